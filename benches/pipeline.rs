@@ -0,0 +1,64 @@
+// Criterion benchmarks for the shaping, wrapping and SVG emission stages.
+// Uses the first installed font family; run with `cargo bench`.
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use text2svg::color::Color;
+use text2svg::font::{fonts, FontConfig, FontStyle};
+use text2svg::render::{build_line_layouts_document, layout_lines, wrap_paragraph, RenderConfig};
+
+const PARAGRAPH: &str = "The quick brown fox jumps over the lazy dog. \
+    Pack my box with five dozen liquor jugs, then sphinx of black quartz, judge my vow.";
+
+fn font_config() -> FontConfig {
+    let font_name = fonts().first().cloned().expect("no installed fonts to benchmark with");
+    FontConfig::new(font_name, 16, Color::BLACK, Color::BLACK, false)
+        .expect("Failed to create font config with system font")
+}
+
+fn render_config() -> RenderConfig {
+    let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+    render_config.set_max_pixel_width(Some(320.0));
+    render_config
+}
+
+fn bench_shape(c: &mut Criterion) {
+    let font_config = font_config();
+    c.bench_function("shape paragraph (cold)", |b| {
+        b.iter(|| {
+            font_config.clear_caches();
+            font_config.shape(black_box(PARAGRAPH), &FontStyle::Regular)
+        })
+    });
+    c.bench_function("shape paragraph (cached)", |b| {
+        b.iter(|| font_config.shape(black_box(PARAGRAPH), &FontStyle::Regular))
+    });
+}
+
+fn bench_wrap(c: &mut Criterion) {
+    let font_config = font_config();
+    let render_config = render_config();
+    c.bench_function("wrap paragraph by pixel width", |b| {
+        b.iter(|| {
+            font_config.clear_caches();
+            wrap_paragraph(black_box(PARAGRAPH), &font_config, &render_config)
+        })
+    });
+}
+
+fn bench_emit(c: &mut Criterion) {
+    let font_config = font_config();
+    let render_config = render_config();
+    let lines: Vec<String> = (0..32)
+        .flat_map(|_| wrap_paragraph(PARAGRAPH, &font_config, &render_config))
+        .collect();
+    c.bench_function("layout and emit 32 paragraphs", |b| {
+        b.iter(|| {
+            let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+            build_line_layouts_document(line_layouts, &lines, &[], &glyph_defs, &font_config, &render_config).unwrap().to_string()
+        })
+    });
+}
+
+criterion_group!(benches, bench_shape, bench_wrap, bench_emit);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+// labelling documents for assistive technology, so an SVG inlined in HTML is
+// read as the text it renders rather than skipped as unnamed graphics
+use svg::node::element::{Description, Title};
+use svg::node::Node;
+use svg::Document;
+
+use crate::markup::{parse_markup, plain_text};
+use crate::utils::cluster_starts;
+
+/// Characters (clusters) kept of the text in a derived title.
+pub const MAX_TITLE_CHARS: usize = 80;
+
+/// How a document describes itself to assistive technology.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Accessibility {
+    /// Label read instead of the rendered text
+    pub title: Option<String>,
+    /// Longer description, read after the label
+    pub description: Option<String>,
+    /// Hidden from assistive technology, for text that is only decoration
+    pub decorative: bool,
+}
+
+/// Title read for the rendered `lines`: their text on one line, without markup
+/// tags, and cut after `MAX_TITLE_CHARS` characters.
+pub fn derive_title(lines: &[String], markup: bool) -> String {
+    let text: Vec<String> = lines
+        .iter()
+        .map(|line| match markup {
+            true => parse_markup(line).map_or_else(|_| line.clone(), |spans| plain_text(&spans)),
+            false => line.clone(),
+        })
+        .collect();
+    let text = text.join(" ").split_whitespace().collect::<Vec<_>>().join(" ");
+    let cut = cluster_starts(&text).nth(MAX_TITLE_CHARS).map(|(cut, _)| cut);
+    match cut {
+        Some(cut) => format!("{}…", text[..cut].trim_end()),
+        None => text,
+    }
+}
+
+/// `doc` labelled as an image of `lines`: `role="img"`, an `aria-label` and a
+/// `<title>` (and `<desc>`) as its first children, or hidden when decorative.
+pub fn label_document(mut doc: Document, lines: &[String], markup: bool, accessibility: &Accessibility) -> Document {
+    if accessibility.decorative {
+        doc.assign("aria-hidden", "true");
+        return doc;
+    }
+    let title = accessibility.title.clone().unwrap_or_else(|| derive_title(lines, markup));
+    if title.is_empty() {
+        return doc;
+    }
+    doc.assign("role", "img");
+    doc.assign("aria-label", title.clone());
+    let mut labels: Vec<Box<dyn Node>> = vec![Box::new(Title::new(title))];
+    if let Some(description) = &accessibility.description {
+        labels.push(Box::new(Description::new().add(svg::node::Text::new(description.clone()))));
+    }
+    if let Some(children) = doc.get_children_mut() {
+        children.splice(0..0, labels);
+    }
+    doc
+}
+
+#[cfg(test)]
+mod test_a11y {
+    use super::*;
+
+    #[test]
+    fn test_derive_title() {
+        let lines = vec![r#"Hello <span letter-spacing="2px">wide</span>"#.to_string(), "  world ".to_string()];
+        assert_eq!(derive_title(&lines, true), "Hello wide world");
+        assert_eq!(derive_title(&lines[1..], false), "world");
+        let long = vec!["word ".repeat(30)];
+        let title = derive_title(&long, false);
+        assert!(title.ends_with("word…") && title.chars().count() == MAX_TITLE_CHARS, "{}", title);
+    }
+
+    #[test]
+    fn test_label_document() {
+        let lines = vec!["Sale \"50%\" off".to_string()];
+        let doc = Document::new().add(svg::node::element::Group::new());
+        let accessibility = Accessibility { description: Some("A banner".to_string()), ..Default::default() };
+        let xml = crate::xml::to_xml(&label_document(doc.clone(), &lines, false, &accessibility));
+        assert!(xml.contains(r#"aria-label="Sale &quot;50%&quot; off""#) && xml.contains(r#"role="img""#), "{}", xml);
+        assert!(xml.find("<title>").unwrap() < xml.find("<desc>").unwrap());
+        assert!(xml.find("<desc>").unwrap() < xml.find("<g").unwrap());
+
+        let decorative = Accessibility { decorative: true, ..Default::default() };
+        let xml = label_document(doc, &lines, false, &decorative).to_string();
+        assert!(xml.contains(r#"aria-hidden="true""#) && !xml.contains("<title>"), "{}", xml);
+    }
+}
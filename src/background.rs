@@ -0,0 +1,400 @@
+// backgrounds and frames drawn around the whole text block, across any effect padding
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use svg::node::element::{ClipPath, Group, Image, LinearGradient, Rectangle, Stop};
+
+use crate::color::{parse_colors, split_colors, Color};
+use crate::cssvars::set_paints;
+use crate::length::Length;
+use crate::preview::base64;
+
+pub const BACKGROUND_GRADIENT_ID: &str = "background-gradient";
+pub const BACKGROUND_CLIP_ID: &str = "background-clip";
+
+/// Alternating full-width bands behind the lines, one color per line in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stripes {
+    pub colors: Vec<Color>,
+}
+
+impl std::str::FromStr for Stripes {
+    type Err = String;
+
+    /// Parses comma separated colors, e.g. "#fff,#eee".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = parse_colors(s, ',')
+            .map_err(|e| format!("Invalid stripe colors '{}': {}", s, e))?;
+        Ok(Stripes { colors })
+    }
+}
+
+impl Stripes {
+    /// Bands behind `lines` lines of `line_height`, the first starting at
+    /// `first_line_y`, spanning the width of `view_box` (x, y, width, height).
+    /// The first and last bands reach the top and bottom of the view box.
+    /// With `css_vars` the colors are exported as --text2svg-stripe-1, -2 and so on.
+    pub fn bands(&self, first_line_y: f32, lines: usize, line_height: f32, view_box: (f32, f32, f32, f32), css_vars: bool) -> Group {
+        let (x, view_top, width, height) = view_box;
+        let view_bottom = view_top + height;
+        let mut group = Group::new().set("stroke", "none");
+        for line in 0..lines {
+            let mut top = first_line_y + line as f32 * line_height;
+            let mut bottom = top + line_height;
+            if line == 0 {
+                top = top.min(view_top);
+            }
+            if line + 1 == lines {
+                bottom = bottom.max(view_bottom);
+            }
+            let color = line % self.colors.len();
+            let band = Rectangle::new().set("x", x).set("y", top).set("width", width).set("height", bottom - top);
+            let name = format!("stripe-{}", color + 1);
+            group = group.add(set_paints(band, &[("fill", &name, &self.colors[color])], css_vars));
+        }
+        group
+    }
+}
+
+/// Line style of a border, like CSS border-style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum BorderStyle {
+    #[default]
+    Solid,
+    /// Two parallel lines, each a third of the width
+    Double,
+    Dashed,
+    Dotted,
+}
+
+/// A frame around the whole composition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Border {
+    pub width: f32,
+    pub color: Color,
+    pub style: BorderStyle,
+    /// Corner radius of the outer edge
+    pub radius: f32,
+    /// Space between the text (with its effects) and the border
+    pub padding: f32,
+}
+
+impl std::str::FromStr for Border {
+    type Err = String;
+
+    /// Parses "width,color[,style]", e.g. "4,#c90,double".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid border '{}': expected \"width,color[,style]\", e.g. \"4,#c90,double\"", s);
+        let parts: Vec<&str> = split_colors(s, ',').into_iter().map(str::trim).collect();
+        let (width, color, style) = match parts.as_slice() {
+            [width, color] => (width, color, BorderStyle::default()),
+            [width, color, style] => (width, color, BorderStyle::from_str(style, true).map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        };
+        let width: f32 = width.parse().ok().filter(|width: &f32| *width > 0.0).ok_or_else(invalid)?;
+        let color = color.parse().map_err(|e| format!("Invalid border '{}': {}", s, e))?;
+        Ok(Border { width, color, style, radius: 0.0, padding: 0.0 })
+    }
+}
+
+impl Border {
+    pub fn set_radius(&mut self, radius: f32) -> &mut Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    pub fn set_padding(&mut self, padding: f32) -> &mut Self {
+        self.padding = padding.max(0.0);
+        self
+    }
+
+    /// `view_box` grown by the padding, the area inside the border.
+    pub fn inner_box(&self, view_box: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        grow(view_box, self.padding)
+    }
+
+    /// `view_box` grown by the padding and the border itself.
+    pub fn outer_box(&self, view_box: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        grow(view_box, self.padding + self.width)
+    }
+
+    /// The border lines, drawn just inside `outer_box`. With `css_vars` the
+    /// color is exported as --text2svg-border.
+    pub fn frame(&self, outer_box: (f32, f32, f32, f32), css_vars: bool) -> Group {
+        let mut group = set_paints(Group::new().set("fill", "none"), &[("stroke", "border", &self.color)], css_vars);
+        // Strokes are centered on their rectangles, so each line is inset by half its width
+        let lines: Vec<(f32, f32)> = match self.style {
+            BorderStyle::Double => {
+                let line = self.width / 3.0;
+                vec![(line / 2.0, line), (self.width - line / 2.0, line)]
+            }
+            _ => vec![(self.width / 2.0, self.width)],
+        };
+        for (inset, line_width) in lines {
+            let (x, y, width, height) = grow(outer_box, -inset);
+            let mut rect = Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", width)
+                .set("height", height)
+                .set("stroke-width", line_width);
+            let radius = (self.radius - inset).max(0.0);
+            if radius > 0.0 {
+                rect = rect.set("rx", radius).set("ry", radius);
+            }
+            rect = match self.style {
+                BorderStyle::Dashed => rect.set("stroke-dasharray", format!("{} {}", self.width * 3.0, self.width * 2.0)),
+                // Zero length dashes with round caps draw dots
+                BorderStyle::Dotted => rect
+                    .set("stroke-dasharray", format!("0 {}", self.width * 2.0))
+                    .set("stroke-linecap", "round"),
+                _ => rect,
+            };
+            group = group.add(rect);
+        }
+        group
+    }
+}
+
+/// What fills the area behind the text: a color, a linear gradient or an image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    Color(Color),
+    /// Colors spread evenly along a line at `angle` degrees, CSS style:
+    /// 0 points up, 90 right and 180, the default, down
+    Gradient { angle: f32, colors: Vec<Color> },
+    /// Image file embedded in the svg, covering the area
+    Image(PathBuf),
+}
+
+impl std::str::FromStr for Background {
+    type Err = String;
+
+    /// Parses a color, "linear-gradient([angle deg,] color, color...)" or an image file path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(args) = s.strip_prefix("linear-gradient(").and_then(|args| args.strip_suffix(')')) {
+            let invalid = |e: String| format!("Invalid background gradient '{}': {}", s, e);
+            let mut parts = split_colors(args, ',');
+            let angle = match parts.first().and_then(|first| first.trim().strip_suffix("deg")) {
+                Some(angle) => {
+                    let angle = angle.trim().parse::<f32>().map_err(|_| invalid(format!("bad angle '{}'", parts[0].trim())))?;
+                    parts.remove(0);
+                    angle
+                }
+                None => 180.0,
+            };
+            let colors = parts.into_iter().map(|color| color.trim().parse()).collect::<Result<Vec<Color>, String>>().map_err(invalid)?;
+            if colors.len() < 2 {
+                return Err(invalid("expected at least two colors".to_string()));
+            }
+            return Ok(Background::Gradient { angle, colors });
+        }
+        if let Ok(color) = s.parse() {
+            return Ok(Background::Color(color));
+        }
+        let path = Path::new(s);
+        match (path.is_file(), image_mime_type(path)) {
+            (true, Some(_)) => Ok(Background::Image(path.to_path_buf())),
+            (true, None) => Err(format!("Invalid background '{}': images must be png, jpeg, gif, webp or svg files", s)),
+            (false, _) => Err(format!(
+                "Invalid background '{}': expected a color, a gradient like linear-gradient(90deg, #f06, #48f) or an image file",
+                s
+            )),
+        }
+    }
+}
+
+// Media type of an image file by its extension, for data URLs
+fn image_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+impl Background {
+    /// Fills `area` (x, y, width, height) with corners rounded by `radius`.
+    /// Images are read and embedded here. With `css_vars` the colors are
+    /// exported as --text2svg-background, or -background-1, -2 and so on for gradients.
+    pub fn fill(&self, area: (f32, f32, f32, f32), radius: f32, css_vars: bool) -> std::io::Result<Group> {
+        let (x, y, width, height) = area;
+        let mut rect = Rectangle::new().set("x", x).set("y", y).set("width", width).set("height", height);
+        if radius > 0.0 {
+            rect = rect.set("rx", radius).set("ry", radius);
+        }
+        let group = Group::new().set("stroke", "none");
+        Ok(match self {
+            Background::Color(color) => group.add(set_paints(rect, &[("fill", "background", color)], css_vars)),
+            Background::Gradient { angle, colors } => {
+                // The gradient line runs through the center, long enough for the corners to get the end colors
+                let radians = angle.to_radians();
+                let (dx, dy) = (radians.sin(), -radians.cos());
+                let half = (width * dx.abs() + height * dy.abs()) / 2.0;
+                let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+                let round = |value: f32| (value * 100.0).round() / 100.0;
+                let mut gradient = LinearGradient::new()
+                    .set("id", BACKGROUND_GRADIENT_ID)
+                    .set("gradientUnits", "userSpaceOnUse")
+                    .set("x1", round(cx - dx * half))
+                    .set("y1", round(cy - dy * half))
+                    .set("x2", round(cx + dx * half))
+                    .set("y2", round(cy + dy * half));
+                for (i, color) in colors.iter().enumerate() {
+                    let offset = i as f32 / (colors.len() - 1) as f32;
+                    let name = format!("background-{}", i + 1);
+                    gradient = gradient.add(set_paints(Stop::new().set("offset", offset), &[("stop-color", &name, color)], css_vars));
+                }
+                group.add(gradient).add(rect.set("fill", format!("url(#{})", BACKGROUND_GRADIENT_ID)))
+            }
+            Background::Image(path) => {
+                let data = std::fs::read(path)?;
+                let mime = image_mime_type(path).unwrap_or("image/png");
+                // Scaled to cover the area like CSS background-size: cover, cropping the overflow
+                let image = Image::new()
+                    .set("x", x)
+                    .set("y", y)
+                    .set("width", width)
+                    .set("height", height)
+                    .set("preserveAspectRatio", "xMidYMid slice")
+                    .set("href", format!("data:{};base64,{}", mime, base64(&data)));
+                group
+                    .add(ClipPath::new().set("id", BACKGROUND_CLIP_ID).add(rect))
+                    .add(image.set("clip-path", format!("url(#{})", BACKGROUND_CLIP_ID)))
+            }
+        })
+    }
+}
+
+/// Space on each side of a box, like CSS padding and margin: em lengths are
+/// relative to the font size.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Edges {
+    pub top: Length,
+    pub right: Length,
+    pub bottom: Length,
+    pub left: Length,
+}
+
+impl std::str::FromStr for Edges {
+    type Err = String;
+
+    /// Parses 1 to 4 lengths in CSS order, e.g. "20", "20 40", "1em 2em 0" or
+    /// "10 20 10 20". Bare numbers are pixels.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid edges '{}': expected 1 to 4 lengths such as 20, 20px or 1.5em, e.g. \"20 40\"", s);
+        let lengths = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|length| !length.is_empty())
+            .map(|length| Length::parse(length, Length::Px).ok().filter(|length| length.value() >= 0.0).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (top, right, bottom, left) = match lengths[..] {
+            [all] => (all, all, all, all),
+            [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+            [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+            [top, right, bottom, left] => (top, right, bottom, left),
+            _ => return Err(invalid()),
+        };
+        Ok(Edges { top, right, bottom, left })
+    }
+}
+
+impl Edges {
+    /// (top, right, bottom, left) in pixels for text of `font_size`.
+    pub fn to_pixels(&self, font_size: f32) -> (f32, f32, f32, f32) {
+        let pixels = |length: Length| length.to_pixels(font_size);
+        (pixels(self.top), pixels(self.right), pixels(self.bottom), pixels(self.left))
+    }
+
+    /// The edges with left and right swapped, for right-to-left text where
+    /// the left edge given is the one lines start on.
+    pub fn mirrored(&self) -> Edges {
+        Edges { left: self.right, right: self.left, ..*self }
+    }
+
+    /// `view_box` grown by the edges.
+    pub fn outer_box(&self, view_box: (f32, f32, f32, f32), font_size: f32) -> (f32, f32, f32, f32) {
+        let (x, y, width, height) = view_box;
+        let (top, right, bottom, left) = self.to_pixels(font_size);
+        (x - left, y - top, width + left + right, height + top + bottom)
+    }
+}
+
+fn grow(view_box: (f32, f32, f32, f32), by: f32) -> (f32, f32, f32, f32) {
+    let (x, y, width, height) = view_box;
+    (x - by, y - by, width + 2.0 * by, height + 2.0 * by)
+}
+
+#[cfg(test)]
+mod test_background {
+    use super::*;
+
+    #[test]
+    fn test_stripe_bands() {
+        let stripes: Stripes = "#fff, rgb(238 238 238)".parse().unwrap();
+        assert_eq!(stripes.colors, vec!["#fff".parse().unwrap(), "#eee".parse().unwrap()]);
+        assert!("#fff,".parse::<Stripes>().is_err());
+
+        let bands = stripes.bands(0.0, 3, 10.0, (-4.0, -4.0, 50.0, 38.0), false).to_string();
+        assert_eq!(bands.matches("<rect").count(), 3);
+        // The padding around the text is covered by the outer bands
+        assert!(bands.contains(r##"fill="#ffffff" height="14" width="50" x="-4" y="-4""##));
+        assert!(bands.contains(r##"fill="#eeeeee" height="10" width="50" x="-4" y="10""##));
+        assert!(bands.contains(r##"fill="#ffffff" height="14" width="50" x="-4" y="20""##));
+    }
+
+    #[test]
+    fn test_background_kinds() {
+        assert_eq!("#fff".parse::<Background>(), Ok(Background::Color("#fff".parse().unwrap())));
+        let gradient: Background = "linear-gradient(90deg, #f00, rgb(0, 0, 255))".parse().unwrap();
+        assert_eq!(gradient, Background::Gradient { angle: 90.0, colors: vec!["#f00".parse().unwrap(), "#00f".parse().unwrap()] });
+        assert!("linear-gradient(#f00)".parse::<Background>().is_err());
+        assert!("no/such/image.png".parse::<Background>().is_err());
+
+        // A left to right gradient spans the width through the middle
+        let fill = gradient.fill((0.0, 0.0, 100.0, 40.0), 0.0, false).unwrap().to_string();
+        assert!(fill.contains(r#"x1="0" x2="100" y1="20" y2="20""#), "{}", fill);
+        assert!(fill.contains(r#"fill="url(#background-gradient)""#));
+
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("background.png");
+        std::fs::write(&image, b"png").unwrap();
+        let background: Background = image.to_string_lossy().parse().unwrap();
+        let fill = background.fill((0.0, 0.0, 10.0, 10.0), 2.0, false).unwrap().to_string();
+        assert!(fill.contains("data:image/png;base64,cG5n") && fill.contains(r#"rx="2""#));
+    }
+
+    #[test]
+    fn test_parse_edges() {
+        let edges: Edges = "20 1.5em".parse().unwrap();
+        assert_eq!(edges.to_pixels(10.0), (20.0, 15.0, 20.0, 15.0));
+        let edges: Edges = "1px 2px 3px".parse().unwrap();
+        assert_eq!(edges.to_pixels(10.0), (1.0, 2.0, 3.0, 2.0));
+        let edges: Edges = "1 2 3 4".parse().unwrap();
+        assert_eq!(edges.outer_box((0.0, 0.0, 100.0, 50.0), 10.0), (-4.0, -1.0, 106.0, 54.0));
+        assert_eq!("8".parse::<Edges>().unwrap().to_pixels(10.0), (8.0, 8.0, 8.0, 8.0));
+        assert!("".parse::<Edges>().is_err());
+        assert!("1 2 3 4 5".parse::<Edges>().is_err());
+        assert!("-1".parse::<Edges>().is_err());
+    }
+
+    #[test]
+    fn test_border_frame() {
+        let mut border: Border = "6,#c90,double".parse().unwrap();
+        border.set_padding(4.0).set_radius(8.0);
+        assert_eq!(border.style, BorderStyle::Double);
+        assert_eq!(border.outer_box((0.0, 0.0, 100.0, 50.0)), (-10.0, -10.0, 120.0, 70.0));
+        assert!("0,#000".parse::<Border>().is_err());
+        assert!("2,#000,wavy".parse::<Border>().is_err());
+
+        let frame = border.frame((0.0, 0.0, 120.0, 70.0), false).to_string();
+        assert_eq!(frame.matches("<rect").count(), 2);
+        assert!(frame.contains(r#"height="68" rx="7" ry="7" stroke-width="2" width="118" x="1" y="1""#));
+        assert!(frame.contains(r#"height="60" rx="3" ry="3" stroke-width="2" width="110" x="5" y="5""#));
+    }
+}
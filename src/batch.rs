@@ -0,0 +1,234 @@
+// batch mode: render every text file of a directory, spreading the files
+// over worker threads that share one FontConfig and its caches
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::a11y::label_document;
+use crate::error::{invalid_option, io_error};
+use crate::font::FontConfig;
+use crate::naming::OutputTemplate;
+use crate::progress::Progress;
+use crate::output::OutputConfig;
+use crate::render::{build_line_layouts_document, layout_lines, limit_lines, save_svg, wrap_paragraphs, RenderConfig};
+use crate::svg::OutputFormat;
+
+/// Outcome of a batch render.
+#[derive(Debug, Clone, Default)]
+pub struct BatchReport {
+    pub rendered: usize,
+    pub failed: Vec<(PathBuf, String)>,
+    /// Outputs written, with the number of lines in each
+    pub outputs: Vec<(PathBuf, usize)>,
+}
+
+impl BatchReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl Display for BatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} rendered, {} failed", self.rendered, self.failed.len())?;
+        for (input, error) in self.failed.iter() {
+            write!(f, "\n  {}: {}", input.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Files directly inside `dir`, sorted by name. Hidden files and files in any
+/// output format, e.g. svg or png, are skipped.
+pub fn collect_batch_inputs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir).map_err(|e| io_error(dir, e))?;
+    let mut inputs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            let output = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(OutputFormat::from_extension)
+                .is_some();
+            !hidden && !output
+        })
+        .collect();
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// `out_dir/<input file stem>.<extension of format>`
+pub fn batch_output_path(input: &Path, out_dir: &Path, format: OutputFormat) -> PathBuf {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "output".to_string());
+    out_dir.join(format!("{}.{}", stem, format.extension()))
+}
+
+/// Outputs of `inputs` named by `template`, inside `out_dir`, with `{n}`
+/// numbering the inputs in order. Fails when two inputs would share an output.
+pub fn templated_output_paths(inputs: &[PathBuf], out_dir: &Path, template: &OutputTemplate) -> Result<Vec<PathBuf>> {
+    let mut outputs: Vec<PathBuf> = Vec::with_capacity(inputs.len());
+    for (i, input) in inputs.iter().enumerate() {
+        let output = out_dir.join(template.expand(Some(input), i + 1, &HashMap::new()).map_err(invalid_option)?);
+        if let Some(other) = outputs.iter().position(|previous| *previous == output) {
+            return Err(invalid_option(format!(
+                "{} and {} would both be written to {}; use {{stem}} or {{n}} in the output",
+                inputs[other].display(),
+                input.display(),
+                output.display()
+            )));
+        }
+        outputs.push(output);
+    }
+    Ok(outputs)
+}
+
+/// Renders one plain text file to `output`. Returns the number of lines rendered.
+pub fn render_batch_file(
+    input: &Path,
+    output: &Path,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+) -> Result<usize> {
+    let text = std::fs::read_to_string(input)?;
+    let render_config = &*render_config.with_direction_of(text.lines());
+    let (mut lines, paragraph_starts) = wrap_paragraphs(text.lines(), font_config, render_config);
+    limit_lines(&mut lines, font_config, render_config);
+    let (line_layouts, glyph_defs) = layout_lines(&lines, font_config, render_config);
+    let doc = build_line_layouts_document(line_layouts, &lines, &paragraph_starts, &glyph_defs, font_config, render_config)?;
+    let paragraphs: Vec<String> = text.lines().map(str::to_string).collect();
+    let doc = label_document(doc, &paragraphs, render_config.get_markup(), render_config.get_accessibility());
+    save_svg(output, &doc, &glyph_defs, font_config, output_config)?;
+    Ok(lines.len())
+}
+
+/// Renders every input into `out_dir` on `jobs` worker threads.
+///
+/// Workers pull the next file from a shared counter, so a few large files
+/// don't hold up the rest. A failing file is recorded and the batch goes on.
+/// With `progress`, a progress bar is drawn on stderr if it is a terminal,
+/// and failing files are reported as they finish.
+pub fn render_batch(
+    inputs: &[PathBuf],
+    out_dir: &Path,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    jobs: usize,
+    progress: bool,
+) -> BatchReport {
+    let format = output_config.get_format().unwrap_or_default();
+    let outputs: Vec<PathBuf> = inputs.iter().map(|input| batch_output_path(input, out_dir, format)).collect();
+    render_batch_to(inputs, &outputs, font_config, render_config, output_config, jobs, progress)
+}
+
+/// Renders every input to the output at the same index, as `render_batch` does.
+pub fn render_batch_to(
+    inputs: &[PathBuf],
+    outputs: &[PathBuf],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    jobs: usize,
+    progress: bool,
+) -> BatchReport {
+    let next = AtomicUsize::new(0);
+    let report = Mutex::new(BatchReport::default());
+    let bar = Mutex::new(Progress::new(inputs.len() as u64, progress));
+    let jobs = jobs.clamp(1, inputs.len().max(1));
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(input) = inputs.get(index) else {
+                    break;
+                };
+                let output = &outputs[index];
+                let result = render_batch_file(input, output, font_config, render_config, output_config);
+
+                let mut report = report.lock().unwrap();
+                let mut bar = bar.lock().unwrap();
+                match result {
+                    Ok(lines) => {
+                        report.rendered += 1;
+                        report.outputs.push((output.clone(), lines));
+                    }
+                    Err(e) => {
+                        if progress {
+                            bar.println(&format!("{}: {}", input.display(), e));
+                        }
+                        report.failed.push((input.clone(), e.to_string()));
+                    }
+                }
+                let finished = report.rendered + report.failed.len();
+                bar.set(finished as u64, &format!("{}/{} files", finished, inputs.len()));
+            });
+        }
+    });
+
+    bar.into_inner().unwrap().finish();
+    let mut report = report.into_inner().unwrap();
+    report.failed.sort_by(|a, b| a.0.cmp(&b.0));
+    report.outputs.sort_by(|a, b| a.0.cmp(&b.0));
+    report
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+
+    #[test]
+    fn test_render_batch_across_workers() {
+        let font_config = test_support::font_config(16);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for i in 0..6 {
+            std::fs::write(dir.join(format!("note{}.txt", i)), format!("note number {}\nsecond line", i)).unwrap();
+        }
+        std::fs::write(dir.join("broken.txt"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let inputs = collect_batch_inputs(dir).unwrap();
+        assert_eq!(inputs.len(), 7);
+        let report = render_batch(&inputs, dir, &font_config, &render_config, &OutputConfig::new(), 3, false);
+        assert_eq!(report.rendered, 6);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, dir.join("broken.txt"));
+        assert!(dir.join("note5.svg").exists());
+        assert_eq!(report.outputs[0], (dir.join("note0.svg"), 2));
+
+        // Rendered outputs are not picked up as inputs on the next run
+        assert_eq!(collect_batch_inputs(dir).unwrap().len(), 7);
+
+        // Outputs are named for the format they are written in
+        let mut output_config = OutputConfig::new();
+        output_config.set_output_format(Some(OutputFormat::Html));
+        let report = render_batch(&inputs[1..2], dir, &font_config, &render_config, &output_config, 1, false);
+        assert_eq!(report.outputs[0].0, dir.join("note0.html"));
+        assert!(std::fs::read_to_string(dir.join("note0.html")).unwrap().starts_with("<!DOCTYPE html>"));
+        assert_eq!(batch_output_path(Path::new("a/b.txt"), dir, OutputFormat::Jpeg), dir.join("b.jpg"));
+        assert_eq!(collect_batch_inputs(dir).unwrap().len(), 7);
+
+        let template: OutputTemplate = "{n:02}-{stem}.svg".parse().unwrap();
+        let outputs = templated_output_paths(&inputs[..2], dir, &template).unwrap();
+        assert_eq!(outputs, vec![dir.join("01-broken.svg"), dir.join("02-note0.svg")]);
+        let template: OutputTemplate = "same.svg".parse().unwrap();
+        assert!(templated_output_paths(&inputs, dir, &template).is_err());
+    }
+}
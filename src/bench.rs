@@ -0,0 +1,106 @@
+// `bench` subcommand: time each stage of the rendering pipeline on a user's input
+use std::fmt::Display;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::error::io_error;
+use crate::font::FontConfig;
+use crate::render::{build_line_layouts_document, layout_lines, wrap_paragraphs, RenderConfig};
+use crate::xml::to_xml;
+
+/// Time spent in each pipeline stage, averaged over the benchmark iterations.
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    pub iterations: u32,
+    pub paragraphs: usize,
+    pub lines: usize,
+    pub glyphs: usize,
+    pub output_bytes: usize,
+    pub wrap: Duration,
+    pub shape: Duration,
+    pub layout: Duration,
+    pub emit: Duration,
+}
+
+impl BenchReport {
+    pub fn total(&self) -> Duration {
+        self.wrap + self.shape + self.layout + self.emit
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} paragraphs, {} lines, {} glyphs, {} bytes of SVG (mean of {} iterations)",
+            self.paragraphs, self.lines, self.glyphs, self.output_bytes, self.iterations
+        )?;
+        writeln!(f, "  wrap    {:>10.3} ms", millis(self.wrap))?;
+        writeln!(f, "  shape   {:>10.3} ms", millis(self.shape))?;
+        writeln!(f, "  layout  {:>10.3} ms", millis(self.layout))?;
+        writeln!(f, "  emit    {:>10.3} ms", millis(self.emit))?;
+        write!(f, "  total   {:>10.3} ms", millis(self.total()))
+    }
+}
+
+/// Runs wrapping, shaping, layout and SVG emission on the text in `input`.
+/// Caches are cleared before each iteration so every stage is measured cold.
+pub fn run_bench(
+    input: &Path,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    iterations: u32,
+) -> Result<BenchReport> {
+    let text = std::fs::read_to_string(input)
+        .map_err(|e| io_error(input, e))?;
+    let iterations = iterations.max(1);
+    let mut report = BenchReport {
+        iterations,
+        paragraphs: text.lines().count(),
+        ..Default::default()
+    };
+
+    for _ in 0..iterations {
+        font_config.clear_caches();
+
+        let started = Instant::now();
+        let (lines, paragraph_starts) = wrap_paragraphs(text.lines(), font_config, render_config);
+        report.wrap += started.elapsed();
+
+        // Wrapping shapes most lines already; start shaping from a cold cache
+        font_config.clear_caches();
+        let started = Instant::now();
+        let mut glyphs = 0;
+        for line in lines.iter() {
+            if let Some(shaped) = font_config.shape(line, render_config.get_font_style()) {
+                glyphs += shaped.len();
+            }
+        }
+        report.shape += started.elapsed();
+
+        let started = Instant::now();
+        let (line_layouts, glyph_defs) = layout_lines(&lines, font_config, render_config);
+        report.layout += started.elapsed();
+
+        let started = Instant::now();
+        let document = build_line_layouts_document(line_layouts, &lines, &paragraph_starts, &glyph_defs, font_config, render_config)?;
+        let output = to_xml(&document);
+        report.emit += started.elapsed();
+
+        report.lines = lines.len();
+        report.glyphs = glyphs;
+        report.output_bytes = output.len();
+    }
+
+    report.wrap /= iterations;
+    report.shape /= iterations;
+    report.layout /= iterations;
+    report.emit /= iterations;
+    Ok(report)
+}
@@ -0,0 +1,176 @@
+// box-drawing characters (and their ASCII stand-ins) drawn as connected lines
+// instead of glyphs, so diagrams pasted from terminals come out as clean vectors
+use svg::node::element::Path;
+
+use crate::svg::format_coord;
+
+/// Arms of a box-drawing cell: the edges of the cell its lines reach.
+pub const UP: u8 = 1;
+pub const DOWN: u8 = 2;
+pub const LEFT: u8 = 4;
+pub const RIGHT: u8 = 8;
+
+/// Stroke width of the lines, in ems.
+pub const BOX_STROKE_EM: f32 = 0.075;
+
+/// Arms of a box-drawing character, None for other characters.
+pub fn box_arms(c: char) -> Option<u8> {
+    match c {
+        '─' => Some(LEFT | RIGHT),
+        '│' => Some(UP | DOWN),
+        '┌' | '╭' => Some(DOWN | RIGHT),
+        '┐' | '╮' => Some(DOWN | LEFT),
+        '└' | '╰' => Some(UP | RIGHT),
+        '┘' | '╯' => Some(UP | LEFT),
+        '├' => Some(UP | DOWN | RIGHT),
+        '┤' => Some(UP | DOWN | LEFT),
+        '┬' => Some(DOWN | LEFT | RIGHT),
+        '┴' => Some(UP | LEFT | RIGHT),
+        '┼' => Some(UP | DOWN | LEFT | RIGHT),
+        _ => None,
+    }
+}
+
+// Arm facing back at a neighbour in direction `arm`
+fn opposite(arm: u8) -> u8 {
+    match arm {
+        UP => DOWN,
+        DOWN => UP,
+        LEFT => RIGHT,
+        _ => LEFT,
+    }
+}
+
+// Arms a character could join with: ASCII stand-ins join any way they run
+fn joins(c: char) -> u8 {
+    match c {
+        '-' => LEFT | RIGHT,
+        '|' => UP | DOWN,
+        '+' => UP | DOWN | LEFT | RIGHT,
+        c => box_arms(c).unwrap_or(0),
+    }
+}
+
+// Arms of an ASCII stand-in at (row, col), None where it is ordinary text:
+// '+' joining at least two neighbours, '|' with another line above or below,
+// and '-' in runs of three or more or running into a joint
+fn ascii_arms(grid: &[Vec<char>], row: usize, col: usize) -> Option<u8> {
+    let at = |row: Option<usize>, col: Option<usize>| row.zip(col).and_then(|(row, col)| grid.get(row)?.get(col).copied());
+    let neighbour = |arm: u8| match arm {
+        UP => at(row.checked_sub(1), Some(col)),
+        DOWN => at(Some(row + 1), Some(col)),
+        LEFT => at(Some(row), col.checked_sub(1)),
+        _ => at(Some(row), Some(col + 1)),
+    };
+    let connects = |arm: u8| neighbour(arm).is_some_and(|c| joins(c) & opposite(arm) != 0);
+    match grid[row][col] {
+        '+' => {
+            let arms = [UP, DOWN, LEFT, RIGHT].into_iter().filter(|arm| connects(*arm)).fold(0, |arms, arm| arms | arm);
+            (arms.count_ones() >= 2).then_some(arms)
+        }
+        '|' => [UP, DOWN]
+            .into_iter()
+            .any(|arm| neighbour(arm).is_some_and(|c| matches!(c, '|' | '+') || box_arms(c).is_some_and(|arms| arms & opposite(arm) != 0)))
+            .then_some(UP | DOWN),
+        '-' => {
+            let line = &grid[row];
+            let start = line[..col].iter().rposition(|c| *c != '-').map_or(0, |i| i + 1);
+            let end = line[col..].iter().position(|c| *c != '-').map_or(line.len(), |i| col + i);
+            let joint = |c: Option<&char>| c.is_some_and(|c| *c == '+' || box_arms(*c).is_some());
+            let runs_into_joint = joint(start.checked_sub(1).and_then(|i| line.get(i))) || joint(line.get(end));
+            (end - start >= 3 || runs_into_joint).then_some(LEFT | RIGHT)
+        }
+        _ => None,
+    }
+}
+
+/// For each of `lines`, the byte offsets of its box-drawing characters with
+/// their arms. Columns are counted in characters, as in a terminal.
+pub fn box_cells(lines: &[String]) -> Vec<Vec<(usize, u8)>> {
+    let grid: Vec<Vec<char>> = lines.iter().map(|line| line.chars().collect()).collect();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(row, line)| {
+            line.char_indices()
+                .enumerate()
+                .filter_map(|(col, (offset, c))| box_arms(c).or_else(|| ascii_arms(&grid, row, col)).map(|arms| (offset, arms)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Path drawing `cells` (x, width, arms) of a line whose cells span `top` to
+/// `top + height`: each arm runs from the middle of its cell to the edge, so
+/// neighbouring cells join up.
+pub fn box_path(cells: &[(f32, f32, u8)], top: f32, height: f32, stroke_width: f32, precision: u8) -> Path {
+    let middle = top + height / 2.0;
+    let mut d = String::new();
+    let mut segment = |from: (f32, f32), to: (f32, f32)| {
+        d.push_str(&format!(
+            "M{} {}L{} {}",
+            format_coord(from.0, precision),
+            format_coord(from.1, precision),
+            format_coord(to.0, precision),
+            format_coord(to.1, precision)
+        ));
+    };
+    for &(x, width, arms) in cells {
+        let center = (x + width / 2.0, middle);
+        // Straight runs are drawn in one piece
+        if arms & (LEFT | RIGHT) == LEFT | RIGHT {
+            segment((x, middle), (x + width, middle));
+        } else if arms & LEFT != 0 {
+            segment((x, middle), center);
+        } else if arms & RIGHT != 0 {
+            segment(center, (x + width, middle));
+        }
+        if arms & (UP | DOWN) == UP | DOWN {
+            segment((center.0, top), (center.0, top + height));
+        } else if arms & UP != 0 {
+            segment((center.0, top), center);
+        } else if arms & DOWN != 0 {
+            segment(center, (center.0, top + height));
+        }
+    }
+    Path::new()
+        .set("class", "box-drawing")
+        .set("d", d)
+        .set("fill", "none")
+        .set("stroke-width", format_coord(stroke_width, precision))
+        .set("stroke-linecap", "square")
+}
+
+#[cfg(test)]
+mod test_boxdraw {
+    use super::*;
+
+    fn cells(text: &str) -> Vec<Vec<(usize, u8)>> {
+        box_cells(&text.lines().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_box_cells() {
+        let unicode = cells("┌─┐\n│x│\n└─┘");
+        assert_eq!(unicode[0], [(0, DOWN | RIGHT), (3, LEFT | RIGHT), (6, DOWN | LEFT)]);
+        assert_eq!(unicode[1], [(0, UP | DOWN), (4, UP | DOWN)]);
+
+        let ascii = cells("+--+\n|ok|\n+--+");
+        assert_eq!(ascii[0], [(0, DOWN | RIGHT), (1, LEFT | RIGHT), (2, LEFT | RIGHT), (3, DOWN | LEFT)]);
+        assert_eq!(ascii[1], [(0, UP | DOWN), (3, UP | DOWN)]);
+        assert_eq!(ascii[2][0], (0, UP | RIGHT));
+    }
+
+    #[test]
+    fn test_ascii_in_text_stays_text() {
+        let text = cells("C++ and a+b, --verbose | grep x-y\n----");
+        assert!(text[0].is_empty(), "{:?}", text[0]);
+        assert_eq!(text[1].len(), 4);
+    }
+
+    #[test]
+    fn test_box_path() {
+        let path = box_path(&[(0.0, 10.0, DOWN | RIGHT), (10.0, 10.0, LEFT | RIGHT)], 0.0, 20.0, 1.0, 2).to_string();
+        assert!(path.contains(r#"d="M5 10L10 10M5 10L5 20M10 10L20 10""#), "{}", path);
+    }
+}
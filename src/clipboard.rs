@@ -0,0 +1,193 @@
+// copying the rendered output to the system clipboard, so it can be pasted
+// straight into a design tool without writing a file first
+use std::borrow::Cow;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use resvg::tiny_skia::Pixmap;
+use svg::Document;
+
+use crate::preview::render_pixmap;
+use crate::raster::straight_rgba;
+use crate::xml::to_xml;
+
+// Set in the background process keeping the clipboard contents on Linux
+pub const CLIPBOARD_OWNER_ENV: &str = "TEXT2SVG_CLIPBOARD_OWNER";
+
+// Line the background process writes once it holds the clipboard
+const OWNER_READY: &str = "ready";
+
+/// What goes onto the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ClipboardFormat {
+    /// The SVG markup, as text
+    #[default]
+    Svg,
+    /// The rendered image, with a transparent background
+    Png,
+}
+
+impl std::fmt::Display for ClipboardFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ClipboardFormat::Svg => "SVG markup",
+            ClipboardFormat::Png => "image",
+        })
+    }
+}
+
+/// Contents copied for one document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardContent {
+    Text(String),
+    /// Straight (not premultiplied) RGBA pixels, row by row
+    Image { width: usize, height: usize, rgba: Vec<u8> },
+}
+
+impl ClipboardContent {
+    /// The contents copied for `doc` in `format`, images rendered at `scale`.
+    pub fn of(doc: &Document, format: ClipboardFormat, scale: f32) -> Result<Self> {
+        match format {
+            ClipboardFormat::Svg => Ok(ClipboardContent::Text(to_xml(doc))),
+            ClipboardFormat::Png => Ok(image_of(&render_pixmap(doc, scale, None)?)),
+        }
+    }
+
+    fn set(&self, clipboard: arboard::Set<'_>) -> Result<(), arboard::Error> {
+        match self {
+            ClipboardContent::Text(text) => clipboard.text(text.as_str()),
+            ClipboardContent::Image { width, height, rgba } => {
+                clipboard.image(arboard::ImageData { width: *width, height: *height, bytes: Cow::Borrowed(rgba) })
+            }
+        }
+    }
+}
+
+// The clipboard takes straight alpha while tiny-skia keeps it premultiplied
+fn image_of(pixmap: &Pixmap) -> ClipboardContent {
+    ClipboardContent::Image { width: pixmap.width() as usize, height: pixmap.height() as usize, rgba: straight_rgba(pixmap) }
+}
+
+/// Puts `content` onto the system clipboard.
+///
+/// On Linux the clipboard is served by the program that set it, so a copy of
+/// this program is left running in the background until something else is copied.
+/// This returns once that copy holds the clipboard, or with the error it exited with.
+pub fn copy(content: &ClipboardContent) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| anyhow!("Clipboard unavailable: {}", e))?;
+    if cfg!(target_os = "linux") {
+        return spawn_owner(content);
+    }
+    content.set(clipboard.set()).map_err(|e| anyhow!("Failed to copy to the clipboard: {}", e))
+}
+
+// The contents are handed to the background copy on its stdin, after a header
+// line of "text" or "image <width> <height>". It answers on stdout once it
+// holds the clipboard, or exits with the reason on stderr.
+fn spawn_owner(content: &ClipboardContent) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the running executable")?;
+    let mut child = std::process::Command::new(exe)
+        .env(CLIPBOARD_OWNER_ENV, "1")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("Failed to start the clipboard owner")?;
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("Failed to start the clipboard owner"))?;
+    // A write fails if the owner already exited; its status says why
+    let written = match content {
+        ClipboardContent::Text(text) => stdin.write_all(b"text\n").and_then(|_| stdin.write_all(text.as_bytes())),
+        ClipboardContent::Image { width, height, rgba } => stdin
+            .write_all(format!("image {} {}\n", width, height).as_bytes())
+            .and_then(|_| stdin.write_all(rgba)),
+    };
+    drop(stdin);
+
+    let mut ready = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        BufReader::new(stdout).read_line(&mut ready)?;
+    }
+    if written.is_ok() && ready.trim_end() == OWNER_READY && child.try_wait()?.is_none() {
+        return Ok(());
+    }
+    let status = child.wait().context("Failed to wait for the clipboard owner")?;
+    let mut error = String::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let _ = stderr.read_to_string(&mut error);
+    }
+    match (error.trim(), written) {
+        ("", Err(e)) => bail!("Failed to copy to the clipboard: {}", e),
+        ("", Ok(())) => bail!("Failed to copy to the clipboard: the clipboard owner exited with {}", status),
+        (error, _) => bail!("Failed to copy to the clipboard: {}", error),
+    }
+}
+
+/// Run in the background copy started by [`copy`]: reads the contents from
+/// stdin, sets them, and serves them until another program takes the clipboard.
+pub fn serve_clipboard() -> Result<()> {
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input)?;
+    let content = parse_owner_input(&input)?;
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| anyhow!("Clipboard unavailable: {}", e))?;
+    content.set(clipboard.set()).map_err(|e| anyhow!("Failed to copy to the clipboard: {}", e))?;
+    // The copy waiting on this returns now; setting the same contents again
+    // with wait serves them until another program takes the clipboard
+    let mut stdout = std::io::stdout();
+    writeln!(stdout, "{}", OWNER_READY)?;
+    stdout.flush()?;
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::SetExtLinux;
+        content.set(clipboard.set().wait()).map_err(|e| anyhow!("Failed to copy to the clipboard: {}", e))?;
+    }
+    Ok(())
+}
+
+fn parse_owner_input(input: &[u8]) -> Result<ClipboardContent> {
+    let split = input.iter().position(|&b| b == b'\n').ok_or_else(|| anyhow!("Missing clipboard header"))?;
+    let header = std::str::from_utf8(&input[..split])?;
+    let body = &input[split + 1..];
+    match header.split(' ').collect::<Vec<_>>()[..] {
+        ["text"] => Ok(ClipboardContent::Text(String::from_utf8(body.to_vec())?)),
+        ["image", width, height] => {
+            let (width, height): (usize, usize) = (width.parse()?, height.parse()?);
+            if body.len() != width * height * 4 {
+                bail!("Expected {} bytes of {}x{} pixels, got {}", width * height * 4, width, height, body.len());
+            }
+            Ok(ClipboardContent::Image { width, height, rgba: body.to_vec() })
+        }
+        _ => bail!("Invalid clipboard header: {}", header),
+    }
+}
+
+#[cfg(test)]
+mod test_clipboard {
+    use super::*;
+    use svg::node::element::Rectangle;
+
+    #[test]
+    fn test_image_content() {
+        let doc = Document::new()
+            .set("viewBox", (0, 0, 4, 2))
+            .set("width", 4)
+            .set("height", 2)
+            .add(Rectangle::new().set("width", 2).set("height", 2).set("fill", "red").set("fill-opacity", 0.5));
+        let ClipboardContent::Image { width, height, rgba } = ClipboardContent::of(&doc, ClipboardFormat::Png, 1.0).unwrap() else {
+            panic!("expected an image");
+        };
+        assert_eq!((width, height, rgba.len()), (4, 2, 32));
+        // Half transparent red stays full red, the uncovered right half is transparent
+        assert_eq!(&rgba[..4], &[255, 0, 0, 128]);
+        assert_eq!(rgba[15], 0);
+    }
+
+    #[test]
+    fn test_owner_input() {
+        assert_eq!(parse_owner_input(b"text\n<svg/>").unwrap(), ClipboardContent::Text("<svg/>".to_string()));
+        let image = parse_owner_input(b"image 1 1\n\x01\x02\x03\x04").unwrap();
+        assert_eq!(image, ClipboardContent::Image { width: 1, height: 1, rgba: vec![1, 2, 3, 4] });
+        assert!(parse_owner_input(b"image 2 1\n\x01\x02\x03\x04").is_err());
+        assert!(parse_owner_input(b"text").is_err());
+    }
+}
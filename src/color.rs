@@ -0,0 +1,369 @@
+// colors as written in CSS: names, hex, rgb()/rgba() and hsl()/hsla(),
+// all parsed into one type and written back as hex
+use std::fmt::Display;
+
+use crate::validate::closest;
+
+/// An RGBA color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
+
+impl std::str::FromStr for Rgba {
+    type Err = String;
+
+    /// Parses a CSS color: a name such as "teal", "#rgb", "#rgba", "#rrggbb",
+    /// "#rrggbbaa", "rgb(255, 0, 0)", "rgba(255 0 0 / 50%)" or "hsl(120, 100%, 25%)".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid color '{}': expected a CSS color name, #rgb, #rgba, #rrggbb, #rrggbbaa, rgb(), rgba(), hsl() or hsla()",
+                s
+            )
+        };
+        let color = s.trim();
+        if let Some(hex) = color.strip_prefix('#') {
+            return parse_hex(hex).ok_or_else(invalid);
+        }
+        if let Some((function, args)) = color.strip_suffix(')').and_then(|color| color.split_once('(')) {
+            return parse_function(&function.trim().to_ascii_lowercase(), args).ok_or_else(invalid);
+        }
+        let name = color.to_ascii_lowercase();
+        if name == "transparent" {
+            return Ok(Rgba(0, 0, 0, 0));
+        }
+        NAMED_COLORS
+            .binary_search_by(|(known, _)| known.cmp(&name.as_str()))
+            .map(|i| {
+                let rgb = NAMED_COLORS[i].1;
+                Rgba((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255)
+            })
+            .map_err(|_| match closest(&name, NAMED_COLORS.iter().map(|(known, _)| *known)) {
+                Some(known) => format!("Invalid color '{}': did you mean '{}'?", s, known),
+                None => invalid(),
+            })
+    }
+}
+
+impl Display for Rgba {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)?;
+        if self.3 != 255 {
+            write!(f, "{:02x}", self.3)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = match hex.len() {
+        3 | 4 => hex.chars().map(|c| c.to_digit(16).unwrap() as u8 * 17).collect(),
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect(),
+        _ => return None,
+    };
+    Some(Rgba(digits[0], digits[1], digits[2], digits.get(3).copied().unwrap_or(255)))
+}
+
+// rgb(), rgba(), hsl() or hsla() arguments, comma separated or in the
+// space separated form with an optional "/ alpha"
+fn parse_function(function: &str, args: &str) -> Option<Rgba> {
+    let (channels, alpha) = match args.split_once('/') {
+        Some((channels, alpha)) => (channels, Some(alpha.trim())),
+        None => (args, None),
+    };
+    let mut values: Vec<&str> = channels.split([',', ' ']).map(str::trim).filter(|value| !value.is_empty()).collect();
+    let alpha = match (alpha, values.len()) {
+        (Some(alpha), 3) => alpha,
+        (None, 4) => values.pop().unwrap(),
+        (None, 3) => "1",
+        _ => return None,
+    };
+    let alpha = (parse_fraction(alpha, 1.0)? * 255.0).round() as u8;
+    match function {
+        "rgb" | "rgba" => {
+            let channel = |value: &str| parse_fraction(value, 255.0).map(|c| (c * 255.0).round() as u8);
+            Some(Rgba(channel(values[0])?, channel(values[1])?, channel(values[2])?, alpha))
+        }
+        "hsl" | "hsla" => {
+            let hue = values[0].strip_suffix("deg").unwrap_or(values[0]).parse::<f32>().ok()?;
+            let (saturation, lightness) = (parse_percent(values[1])?, parse_percent(values[2])?);
+            let (r, g, b) = hsl_to_rgb(hue, saturation, lightness);
+            Some(Rgba(r, g, b, alpha))
+        }
+        _ => None,
+    }
+}
+
+// "50%" or a number out of `max`, as a fraction clamped to 0..=1
+fn parse_fraction(value: &str, max: f32) -> Option<f32> {
+    let fraction = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok()? / 100.0,
+        None => value.parse::<f32>().ok()? / max,
+    };
+    fraction.is_finite().then(|| fraction.clamp(0.0, 1.0))
+}
+
+// Saturation and lightness must be percentages
+fn parse_percent(value: &str) -> Option<f32> {
+    value.ends_with('%').then(|| parse_fraction(value, 1.0)).flatten()
+}
+
+/// RGB of a hue in degrees with saturation and lightness from 0 to 1.
+pub fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (u8, u8, u8) {
+    // As in the CSS Color specification
+    let a = saturation * lightness.min(1.0 - lightness);
+    let channel = |n: f32| {
+        let k = (n + hue.rem_euclid(360.0) / 30.0) % 12.0;
+        let value = lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0);
+        (value * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+    (channel(0.0), channel(8.0), channel(4.0))
+}
+
+/// A fill or stroke paint: no paint, the inherited `currentColor`, or a color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    None,
+    CurrentColor,
+    Rgba(Rgba),
+}
+
+impl Color {
+    pub const BLACK: Color = Color::Rgba(Rgba(0, 0, 0, 255));
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    /// Parses "none", "currentColor" or any color `Rgba` accepts.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            paint if paint.eq_ignore_ascii_case("none") => Ok(Color::None),
+            paint if paint.eq_ignore_ascii_case("currentcolor") => Ok(Color::CurrentColor),
+            color => color.parse().map(Color::Rgba),
+        }
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Color::None => write!(f, "none"),
+            Color::CurrentColor => write!(f, "currentColor"),
+            Color::Rgba(rgba) => write!(f, "{}", rgba),
+        }
+    }
+}
+
+/// Splits `s` at each `separator` outside parentheses, so "rgb(0, 0, 0)" stays whole.
+pub fn split_colors(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Parses a `separator` separated list of colors, e.g. "#fff, rgb(0 0 0)".
+pub fn parse_colors(s: &str, separator: char) -> Result<Vec<Color>, String> {
+    split_colors(s, separator).into_iter().map(str::parse).collect()
+}
+
+// CSS named colors as 0xrrggbb, sorted by name
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod test_color {
+    use super::*;
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!("#f00".parse::<Rgba>(), Ok(Rgba(255, 0, 0, 255)));
+        assert_eq!("#00ff0080".parse::<Rgba>(), Ok(Rgba(0, 255, 0, 128)));
+        assert_eq!(Rgba(0, 255, 0, 128).to_string(), "#00ff0080");
+        assert_eq!("RebeccaPurple".parse::<Rgba>(), Ok(Rgba(0x66, 0x33, 0x99, 255)));
+        assert_eq!("rgb(255, 0, 0)".parse::<Rgba>(), Ok(Rgba(255, 0, 0, 255)));
+        assert_eq!("rgba(0 0 100% / 50%)".parse::<Rgba>(), Ok(Rgba(0, 0, 255, 128)));
+        assert_eq!("rgba(0, 0, 0, 0.25)".parse::<Rgba>(), Ok(Rgba(0, 0, 0, 64)));
+        assert_eq!("hsl(120, 100%, 25%)".parse::<Rgba>(), Ok(Rgba(0, 128, 0, 255)));
+        assert_eq!("hsla(240deg 100% 50% / 0)".parse::<Rgba>(), Ok(Rgba(0, 0, 255, 0)));
+        assert!("reddish".parse::<Rgba>().is_err());
+        assert_eq!("tomatoe".parse::<Rgba>(), Err("Invalid color 'tomatoe': did you mean 'tomato'?".to_string()));
+        assert!("rgb(1, 2)".parse::<Rgba>().is_err());
+        assert!("hsl(0, 1, 2)".parse::<Rgba>().is_err());
+
+        assert_eq!("none".parse::<Color>(), Ok(Color::None));
+        assert_eq!("currentColor".parse::<Color>().unwrap().to_string(), "currentColor");
+        assert_eq!("white".parse::<Color>().unwrap().to_string(), "#ffffff");
+        assert_eq!(
+            parse_colors("#fff, rgb(0, 0, 0)", ','),
+            Ok(vec![Color::Rgba(Rgba(255, 255, 255, 255)), Color::BLACK])
+        );
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
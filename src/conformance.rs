@@ -0,0 +1,82 @@
+// parsing written SVGs back with a strict parser in debug mode, so emitter
+// bugs show up here rather than in Inkscape or a browser
+use std::str::FromStr;
+
+use resvg::usvg;
+use resvg::usvg::roxmltree;
+
+// Elements whose `fill` says how an animation ends rather than giving a paint
+const ANIMATION_ELEMENTS: [&str; 4] = ["animate", "animateMotion", "animateTransform", "set"];
+
+// Whether `value` reads as the attribute `name` of an element `element`, by the
+// same parsers usvg uses. Attributes text2svg doesn't write are taken as they are
+fn is_valid_value(element: &str, name: &str, value: &str) -> bool {
+    match name {
+        "transform" | "gradientTransform" | "patternTransform" => svgtypes::Transform::from_str(value).is_ok(),
+        "d" => svgtypes::PathParser::from(value).all(|segment| segment.is_ok()),
+        "fill" if ANIMATION_ELEMENTS.contains(&element) => true,
+        "fill" | "stroke" => svgtypes::Paint::from_str(value).is_ok(),
+        "stop-color" | "flood-color" | "lighting-color" => value == "currentColor" || svgtypes::Color::from_str(value).is_ok(),
+        "viewBox" => svgtypes::ViewBox::from_str(value).is_ok(),
+        "width" | "height" | "rx" | "ry" | "cx" | "cy" | "r" | "x1" | "y1" | "x2" | "y2" | "stroke-width" | "stroke-dashoffset"
+        | "font-size" => svgtypes::Length::from_str(value).is_ok(),
+        // Text positions may list one per character
+        "x" | "y" | "dx" | "dy" => svgtypes::LengthListParser::from(value).all(|length| length.is_ok()),
+        "stroke-dasharray" => value == "none" || svgtypes::LengthListParser::from(value).all(|length| length.is_ok()),
+        "opacity" | "fill-opacity" | "stroke-opacity" | "stop-opacity" | "flood-opacity" | "stroke-miterlimit" => {
+            svgtypes::Number::from_str(value).is_ok()
+        }
+        _ => true,
+    }
+}
+
+/// What a strict parser rejects in the SVG `xml`: well-formedness errors, then
+/// each attribute value it can't read and whether usvg fails on the document.
+/// Empty when the document is clean.
+pub fn svg_problems(xml: &str) -> Vec<String> {
+    let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+    let document = match roxmltree::Document::parse_with_options(xml, options) {
+        Ok(document) => document,
+        Err(e) => return vec![format!("not well-formed XML: {}", e)],
+    };
+    let mut problems: Vec<String> = Vec::new();
+    for node in document.descendants().filter(roxmltree::Node::is_element) {
+        let element = node.tag_name().name();
+        for attribute in node.attributes() {
+            if !is_valid_value(element, attribute.name(), attribute.value()) {
+                // Each distinct problem once, however many elements have it
+                let problem = format!("<{}> has an invalid {} value '{}'", element, attribute.name(), attribute.value());
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        }
+    }
+    if let Err(e) = usvg::Tree::from_xmltree(&document, &usvg::Options::default()) {
+        problems.push(format!("usvg rejects the document: {}", e));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test_conformance {
+    use super::*;
+
+    #[test]
+    fn test_svg_problems() {
+        let clean = r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="5" height="5" fill="red"/></svg>"#;
+        assert!(svg_problems(clean).is_empty(), "{:?}", svg_problems(clean));
+
+        let broken = r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><g class="a"b"/></svg>"#;
+        assert!(svg_problems(broken)[0].starts_with("not well-formed XML"));
+
+        let rejected = r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="5" height="5" transform="scale(oops)"/></svg>"#;
+        let problems = svg_problems(rejected);
+        assert_eq!(problems, vec!["<rect> has an invalid transform value 'scale(oops)'".to_string()]);
+
+        let animated = r##"<svg xmlns="http://www.w3.org/2000/svg"><path d="M0 0L1 1" fill="#000"><animate attributeName="opacity" fill="freeze"/></path><text x="1 2 3">ab</text></svg>"##;
+        assert!(svg_problems(animated).is_empty(), "{:?}", svg_problems(animated));
+        let bad_path = r##"<svg xmlns="http://www.w3.org/2000/svg"><path d="M0 0L1" stroke="#12"/></svg>"##;
+        assert_eq!(svg_problems(bad_path).len(), 2);
+    }
+}
@@ -0,0 +1,51 @@
+// colors exported as CSS custom properties, so pages embedding the svg can
+// retheme it without regenerating: fill="#000" becomes
+// style="fill: var(--text2svg-fill, #000)"
+use std::fmt::Display;
+
+use svg::Node;
+
+/// Prefix of the exported variable names.
+pub const CSS_VAR_PREFIX: &str = "--text2svg-";
+
+/// `var(--text2svg-<name>, <color>)`, falling back to `color` where the variable isn't set.
+pub fn css_var(name: &str, color: impl Display) -> String {
+    format!("var({}{}, {})", CSS_VAR_PREFIX, name, color)
+}
+
+/// Attributes painting with (property, variable name, color) triples: the
+/// plain colors, or with `css_vars` a single style reading the variables.
+pub fn paint_attributes<C: Display>(paints: &[(&str, &str, C)], css_vars: bool) -> Vec<(String, String)> {
+    if !css_vars {
+        return paints.iter().map(|(property, _, color)| (property.to_string(), color.to_string())).collect();
+    }
+    let style = paints
+        .iter()
+        .map(|(property, name, color)| format!("{}: {}", property, css_var(name, color)))
+        .collect::<Vec<_>>()
+        .join("; ");
+    vec![("style".to_string(), style)]
+}
+
+/// `node` painted with the (property, variable name, color) triples.
+pub fn set_paints<T: Node, C: Display>(mut node: T, paints: &[(&str, &str, C)], css_vars: bool) -> T {
+    for (name, value) in paint_attributes(paints, css_vars) {
+        node.assign(&name, value);
+    }
+    node
+}
+
+#[cfg(test)]
+mod test_cssvars {
+    use super::*;
+    use svg::node::element::Group;
+
+    #[test]
+    fn test_set_paints() {
+        let paints = [("fill", "fill", "none"), ("stroke", "fg", "#000")];
+        let plain = set_paints(Group::new(), &paints, false).to_string();
+        assert_eq!(plain, r##"<g fill="none" stroke="#000"/>"##);
+        let themed = set_paints(Group::new(), &paints, true).to_string();
+        assert_eq!(themed, r##"<g style="fill: var(--text2svg-fill, none); stroke: var(--text2svg-fg, #000)"/>"##);
+    }
+}
@@ -0,0 +1,167 @@
+// text decorations drawn as filled paths rather than strokes or css, so every
+// viewer shows them the same way
+use clap::ValueEnum;
+use svg::node::element::Path;
+
+use crate::color::Color;
+use crate::font::FaceMetrics;
+use crate::svg::write_coord;
+
+/// Line style of an underline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum UnderlineStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Wavy,
+}
+
+/// An underline below every line of text. Unset metrics come from the font.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Underline {
+    pub style: UnderlineStyle,
+    /// Defaults to the text color
+    pub color: Option<Color>,
+    /// Thickness in pixels
+    pub thickness: Option<f32>,
+    /// Distance in pixels from the baseline down to the middle of the line
+    pub offset: Option<f32>,
+}
+
+impl Underline {
+    pub fn new(style: UnderlineStyle) -> Self {
+        Self { style, ..Default::default() }
+    }
+
+    pub fn set_color(&mut self, color: Option<Color>) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    pub fn set_thickness(&mut self, thickness: Option<f32>) -> &mut Self {
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn set_offset(&mut self, offset: Option<f32>) -> &mut Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Thickness and offset below the baseline in pixels, for a face with
+    /// `metrics` scaled by `scale`. Fonts without underline metrics get ones
+    /// proportional to `font_size`.
+    pub fn resolve_metrics(&self, metrics: &FaceMetrics, scale: f32, font_size: f32) -> (f32, f32) {
+        let (font_thickness, font_offset) = if metrics.underline_thickness > 0.0 {
+            // The font gives the top of the line, above the baseline when positive
+            let thickness = metrics.underline_thickness * scale;
+            (thickness, -metrics.underline_position * scale + thickness / 2.0)
+        } else {
+            (font_size / 14.0, font_size / 10.0)
+        };
+        (
+            self.thickness.unwrap_or(font_thickness).max(0.1),
+            self.offset.unwrap_or(font_offset),
+        )
+    }
+
+    /// How far the line reaches above and below its middle.
+    pub fn reach(&self, thickness: f32) -> f32 {
+        match self.style {
+            UnderlineStyle::Wavy => thickness / 2.0 + thickness * 1.5,
+            _ => thickness / 2.0,
+        }
+    }
+
+    /// Filled path of the underline from `x0` to `x1`, centered on `y`.
+    pub fn path(&self, x0: f32, x1: f32, y: f32, thickness: f32, precision: u8) -> Path {
+        let mut d = String::new();
+        let point = |d: &mut String, command: char, x: f32, y: f32| {
+            d.push(command);
+            d.push(' ');
+            write_coord(d, x, precision);
+            d.push(' ');
+            write_coord(d, y, precision);
+        };
+        let (top, bottom) = (y - thickness / 2.0, y + thickness / 2.0);
+        match self.style {
+            UnderlineStyle::Solid => {
+                for (command, x, y) in [('M', x0, top), ('L', x1, top), ('L', x1, bottom), ('L', x0, bottom)] {
+                    point(&mut d, command, x, y);
+                }
+                d.push('Z');
+            }
+            UnderlineStyle::Dashed => {
+                let (dash, gap) = (thickness * 4.0, thickness * 2.0);
+                let mut x = x0;
+                while x < x1 {
+                    let end = (x + dash).min(x1);
+                    for (command, x, y) in [('M', x, top), ('L', end, top), ('L', end, bottom), ('L', x, bottom)] {
+                        point(&mut d, command, x, y);
+                    }
+                    d.push('Z');
+                    x += dash + gap;
+                }
+            }
+            UnderlineStyle::Wavy => {
+                // A band of the line's thickness following a sine wave, sampled along its length
+                let wavelength = thickness * 8.0;
+                let amplitude = self.reach(thickness) - thickness / 2.0;
+                let steps = (((x1 - x0) / wavelength * 8.0).ceil() as usize).max(1);
+                let wave: Vec<(f32, f32)> = (0..=steps)
+                    .map(|i| {
+                        let x = x0 + (x1 - x0) * i as f32 / steps as f32;
+                        (x, amplitude * (std::f32::consts::TAU * (x - x0) / wavelength).sin())
+                    })
+                    .collect();
+                for (i, (x, dy)) in wave.iter().enumerate() {
+                    point(&mut d, if i == 0 { 'M' } else { 'L' }, *x, top + dy);
+                }
+                for (x, dy) in wave.iter().rev() {
+                    point(&mut d, 'L', *x, bottom + dy);
+                }
+                d.push('Z');
+            }
+        }
+        Path::new().set("d", d)
+    }
+}
+
+#[cfg(test)]
+mod test_decoration {
+    use super::*;
+
+    #[test]
+    fn test_underline_paths() {
+        let solid = Underline::new(UnderlineStyle::Solid).path(0.0, 10.0, 5.0, 2.0, 2).to_string();
+        assert!(solid.contains(r#"d="M 0 4L 10 4L 10 6L 0 6Z""#));
+
+        // Dashes of four thicknesses with gaps of two, the last one cut at the end
+        let dashed = Underline::new(UnderlineStyle::Dashed).path(0.0, 14.0, 5.0, 1.0, 2).to_string();
+        assert!(dashed.contains(r#"d="M 0 4.5L 4 4.5L 4 5.5L 0 5.5ZM 6 4.5L 10 4.5L 10 5.5L 6 5.5ZM 12 4.5L 14 4.5L 14 5.5L 12 5.5Z""#));
+
+        let wavy = Underline::new(UnderlineStyle::Wavy).path(0.0, 16.0, 0.0, 1.0, 2).to_string();
+        assert_eq!(wavy.matches('L').count(), 2 * 17 - 1);
+    }
+
+    #[test]
+    fn test_underline_metrics() {
+        let metrics = FaceMetrics {
+            units_per_em: 1000,
+            ascent: 800.0,
+            descent: -200.0,
+            line_gap: 0.0,
+            underline_position: -100.0,
+            underline_thickness: 50.0,
+            cap_height: 700.0,
+            x_height: 500.0,
+            typo: None,
+            hhea: None,
+            win: None,
+        };
+        let mut underline = Underline::new(UnderlineStyle::Solid);
+        assert_eq!(underline.resolve_metrics(&metrics, 0.1, 100.0), (5.0, 12.5));
+        underline.set_thickness(Some(3.0)).set_offset(Some(8.0));
+        assert_eq!(underline.resolve_metrics(&metrics, 0.1, 100.0), (3.0, 8.0));
+    }
+}
@@ -0,0 +1,118 @@
+// the base direction of the text and alignment relative to it, so right-to-left
+// text starts on the right and is framed the way its readers expect
+use clap::ValueEnum;
+
+/// Base direction of the text: the side its lines start on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Direction {
+    /// From the first letter of the text with a direction of its own
+    #[default]
+    Auto,
+    /// Left to right
+    Ltr,
+    /// Right to left, e.g. Hebrew or Arabic
+    Rtl,
+}
+
+/// Horizontal alignment of the lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Align {
+    /// The side lines start on: left, or right for right-to-left text
+    #[default]
+    Start,
+    /// The side lines end on
+    End,
+    Left,
+    Right,
+    Center,
+}
+
+/// Whether `c` is a letter of a right-to-left script (bidi class R or AL).
+pub fn is_rtl_letter(c: char) -> bool {
+    c.is_alphabetic()
+        && matches!(c,
+            '\u{0590}'..='\u{08FF}'
+            | '\u{FB1D}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}'
+            | '\u{10800}'..='\u{10FFF}'
+            | '\u{1E800}'..='\u{1EFFF}')
+}
+
+/// Direction of the first letter in `text`, None when it has no letters.
+pub fn text_direction(text: &str) -> Option<Direction> {
+    let first = text.chars().find(|c| c.is_alphabetic())?;
+    Some(if is_rtl_letter(first) { Direction::Rtl } else { Direction::Ltr })
+}
+
+impl Direction {
+    /// This direction, or for `Auto` the one of the first of `lines` with a letter,
+    /// left to right when none has.
+    pub fn resolve<'a>(self, lines: impl IntoIterator<Item = &'a str>) -> Direction {
+        match self {
+            Direction::Auto => lines.into_iter().find_map(text_direction).unwrap_or(Direction::Ltr),
+            direction => direction,
+        }
+    }
+
+    pub fn is_rtl(self) -> bool {
+        self == Direction::Rtl
+    }
+}
+
+impl Align {
+    /// Distance from the left of a line `width` wide in `available` pixels, for text going in `direction`.
+    pub fn offset(self, direction: Direction, width: f32, available: f32) -> f32 {
+        let free = (available - width).max(0.0);
+        match (self, direction.is_rtl()) {
+            (Align::Left, _) | (Align::Start, false) | (Align::End, true) => 0.0,
+            (Align::Right, _) | (Align::End, false) | (Align::Start, true) => free,
+            (Align::Center, _) => free / 2.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_direction {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_resolve_direction() {
+        assert_eq!(Direction::Auto.resolve(["123 ", "שלום world"]), Direction::Rtl);
+        assert_eq!(Direction::Auto.resolve(["Hello مرحبا"]), Direction::Ltr);
+        assert_eq!(Direction::Auto.resolve(["42"]), Direction::Ltr);
+        assert_eq!(Direction::Rtl.resolve(["Hello"]), Direction::Rtl);
+    }
+
+    #[test]
+    fn test_align_offset() {
+        assert_eq!(Align::Start.offset(Direction::Ltr, 30.0, 100.0), 0.0);
+        assert_eq!(Align::Start.offset(Direction::Rtl, 30.0, 100.0), 70.0);
+        assert_eq!(Align::End.offset(Direction::Rtl, 30.0, 100.0), 0.0);
+        assert_eq!(Align::Left.offset(Direction::Rtl, 30.0, 100.0), 0.0);
+        assert_eq!(Align::Center.offset(Direction::Ltr, 30.0, 100.0), 35.0);
+    }
+
+    #[test]
+    fn test_rtl_layout() {
+        use crate::font::FontStyle;
+        use crate::render::{build_text_svg, RenderConfig};
+
+        let font_config = test_support::font_config(20);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_padding("0 0 0 10".parse().unwrap());
+        let translations = |xml: &str| xml.match_indices("translate(").map(|(at, _)| xml[at..].split([',', '(']).nth(1).unwrap().to_string()).collect::<Vec<_>>();
+
+        let (doc, _) = build_text_svg("שלום עולם רב\nשלום", &font_config, &render_config).unwrap();
+        let xml = doc.to_string();
+        // The short line ends at the right, and the padding given for the start goes on the right
+        assert_eq!(translations(&xml)[0], "0", "{}", xml);
+        assert_ne!(translations(&xml)[1], "0", "{}", xml);
+        assert!(xml.contains(r#"viewBox="0 "#), "{}", xml);
+
+        let (doc, _) = build_text_svg("Hello world\nHi", &font_config, &render_config).unwrap();
+        let xml = doc.to_string();
+        assert_eq!(translations(&xml), ["0", "0"], "{}", xml);
+        assert!(xml.contains(r#"viewBox="-10 "#), "{}", xml);
+    }
+}
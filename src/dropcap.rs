@@ -0,0 +1,215 @@
+// drop caps: the first letter of the first paragraph spans several lines and
+// the text beside it is wrapped narrower so it flows around the letter
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use svg::node::element::{Element, Group};
+use svg::{Document, Node};
+
+use crate::a11y::label_document;
+use crate::direction::Direction;
+use crate::font::{FontConfig, FontStyle};
+use crate::output::OutputConfig;
+use crate::render::{
+    build_text_document, layout_lines, line_top, render_text_line, save_svg, stack_line_layouts, wrap_paragraph, LineLayout,
+    RenderConfig,
+};
+use crate::svg::{GlyphCache, GlyphDefs};
+use crate::utils::{wrap_text_by_pixel_widths, wrap_text_by_width, WrapMode};
+
+// The cap's outlines are scaled differently from the same glyphs in the text
+const CAP_ID_SUFFIX: &str = "-cap";
+
+// Copy of a node with its id and glyph references renamed to the cap definitions
+fn rename_glyph_ids(node: &dyn Node) -> Box<dyn Node> {
+    let mut element = Element::new(node.get_name());
+    for (name, value) in node.get_attributes().into_iter().flatten() {
+        match name.as_str() {
+            "id" | "href" => element.assign(name.as_str(), format!("{}{}", value, CAP_ID_SUFFIX)),
+            _ => element.assign(name.as_str(), value.clone()),
+        }
+    }
+    for child in node.get_children().into_iter().flatten() {
+        element.append(rename_glyph_ids(child.as_ref()));
+    }
+    Box::new(element)
+}
+
+/// Font size at which a capital spans `lines` lines of text `line_height`
+/// apart: its top lines up with the capitals of the first line and it sits
+/// on the baseline of the last one.
+pub fn cap_font_size(font_config: &FontConfig, line_height: f32, lines: usize) -> Option<u32> {
+    let metrics = font_config.face_metrics(font_config.get_font_by_style(&FontStyle::Regular)?);
+    let em_height = metrics.size_units(font_config.get_size_mode());
+    let cap_height = if metrics.cap_height > 0.0 { metrics.cap_height } else { metrics.units_per_em as f32 * 0.7 };
+    let font_size = font_config.get_size() as f32;
+    let span = (lines.saturating_sub(1)) as f32 * line_height + cap_height * font_size / em_height;
+    Some((span * em_height / cap_height).round() as u32)
+}
+
+/// Splits off the letter to drop: the first non-whitespace character with
+/// the combining marks following it. Returns (text before and including it, the rest).
+pub fn split_cap(paragraph: &str) -> Option<(&str, &str)> {
+    let start = paragraph.find(|c: char| !c.is_whitespace())?;
+    let mut chars = paragraph[start..].char_indices().skip(1);
+    let end = chars
+        .find(|(_, c)| !matches!(*c as u32, 0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x20D0..=0x20FF))
+        .map(|(i, _)| start + i)
+        .unwrap_or(paragraph.len());
+    Some((&paragraph[start..end], &paragraph[end..]))
+}
+
+/// Document of `text` with the first letter of its first paragraph dropped
+/// `lines` lines deep. With a pixel width, the lines beside the letter are
+/// wrapped to the width left next to it.
+pub fn build_drop_cap_document(
+    text: &str,
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+    lines: usize,
+) -> Result<(Document, GlyphDefs)> {
+    // The letter is dropped on the left, so the lines beside it start there too
+    let mut left_to_right;
+    let render_config = match render_config.get_direction() {
+        Direction::Rtl => {
+            crate::log_warn!("Drop caps are placed on the left; laying out the text left to right");
+            left_to_right = render_config.clone();
+            left_to_right.set_direction(Direction::Ltr);
+            &left_to_right
+        }
+        _ => render_config,
+    };
+    let paragraphs: Vec<&str> = text.lines().collect();
+    let first = paragraphs
+        .iter()
+        .position(|paragraph| !paragraph.trim().is_empty())
+        .ok_or_else(|| anyhow!("There is no letter to drop"))?;
+    let (cap, rest) = split_cap(paragraphs[first]).ok_or_else(|| anyhow!("There is no letter to drop"))?;
+
+    // Lay out the letter on its own at its size, with definitions of its own
+    let font_size = font_config.get_size();
+    let line_height = render_config.line_height_pixels(font_size) as f32;
+    let cap_size = cap_font_size(font_config, line_height, lines).ok_or_else(|| anyhow!("Font has no regular face"))?;
+    font_config.set_size(cap_size);
+    let mut cap_cache: GlyphCache = HashMap::new();
+    let mut cap_defs: GlyphDefs = HashMap::new();
+    let cap_layout = render_text_line(0.0, 0.0, cap, 0, &[], None, font_config, render_config, &mut cap_cache, &mut cap_defs);
+    font_config.set_size(font_size);
+    let (cap_group, cap_bbox) = cap_layout.ok_or_else(|| anyhow!("Failed to shape the drop cap '{}'", cap))?;
+
+    let face = font_config
+        .get_font_by_style(&FontStyle::Regular)
+        .ok_or_else(|| anyhow!("Font has no regular face"))?;
+    let metrics = font_config.face_metrics(face);
+    let em_height = metrics.size_units(font_config.get_size_mode());
+    let indent = cap_bbox.x_max as f32 + font_size as f32 * 0.25;
+
+    // Lines next to the letter are indented, across paragraphs if the first one is short
+    let beside = first..first + lines;
+    let mut wrapped: Vec<String> = vec![String::new(); first];
+    let mut paragraph_starts: Vec<usize> = (0..first).collect();
+    for (i, paragraph) in paragraphs.iter().enumerate().skip(first) {
+        let paragraph = if i == first { rest } else { paragraph };
+        let start = wrapped.len();
+        paragraph_starts.push(start);
+        let paragraph_lines = match (render_config.get_wrap(), render_config.get_max_pixel_width(), render_config.get_max_width()) {
+            (WrapMode::Pixel, Some(width), _) => wrap_text_by_pixel_widths(
+                paragraph,
+                |line| if beside.contains(&(start + line)) { width - indent } else { width },
+                font_config,
+                render_config.get_font_style(),
+            ),
+            (WrapMode::Word, _, Some(width)) => wrap_text_by_width(paragraph, width, true, render_config.get_east_asian_width()),
+            (WrapMode::Char, _, Some(width)) => wrap_text_by_width(paragraph, width, false, render_config.get_east_asian_width()),
+            _ => wrap_paragraph(paragraph, font_config, render_config),
+        };
+        wrapped.extend(paragraph_lines);
+    }
+
+    let (line_layouts, mut glyph_defs) = layout_lines(&wrapped, font_config, render_config);
+    let line_layouts: Vec<LineLayout> = line_layouts
+        .into_iter()
+        .enumerate()
+        .map(|(i, layout)| match layout {
+            Some((group, bbox)) if beside.contains(&i) => {
+                let group = Group::new().set("transform", format!("translate({}, 0)", indent)).add(group);
+                let x_max = bbox.x_max.saturating_add(indent.ceil() as i16);
+                Some((group, rustybuzz::ttf_parser::Rect { x_max, ..bbox }))
+            }
+            layout => layout,
+        })
+        .collect();
+    let (mut content, width, height) = stack_line_layouts(line_layouts, &paragraph_starts, font_config, render_config);
+
+    // The letter sits on the baseline of the last line beside it
+    let cap_scale = cap_size as f32 / em_height;
+    let last_beside = line_top(first + lines - 1, &paragraph_starts, font_config, render_config) as f32;
+    let baseline = last_beside + metrics.ascent * font_size as f32 / em_height;
+    let cap_y = baseline - metrics.ascent * cap_scale;
+    let mut cap_content = Group::new();
+    for (name, value) in Node::get_attributes(&cap_group).into_iter().flatten() {
+        cap_content.assign(name.as_str(), value.clone());
+    }
+    for child in Node::get_children(&cap_group).into_iter().flatten() {
+        cap_content.append(rename_glyph_ids(child.as_ref()));
+    }
+    content = content.add(cap_content.set("transform", format!("translate(0, {})", cap_y)));
+    for (id, node) in cap_defs {
+        glyph_defs.insert(format!("{}{}", id, CAP_ID_SUFFIX), rename_glyph_ids(node.as_ref()));
+    }
+
+    let width = width.max(cap_bbox.x_max.max(0) as u32);
+    let height = (height as f32).max((baseline - metrics.descent * cap_scale).ceil());
+    let doc = build_text_document(content, &wrapped, &glyph_defs, (0.0, 0.0, width as f32, height), font_config, render_config)?;
+    let text_lines: Vec<String> = paragraphs.iter().map(|paragraph| paragraph.to_string()).collect();
+    let doc = label_document(doc, &text_lines, render_config.get_markup(), render_config.get_accessibility());
+    Ok((doc, glyph_defs))
+}
+
+/// Renders `text` with a drop cap `lines` lines deep and saves it.
+pub fn render_drop_cap(
+    text: &str,
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    lines: usize,
+    output: &Path,
+) -> Result<()> {
+    let (doc, glyph_defs) = build_drop_cap_document(text, font_config, render_config, lines)?;
+    save_svg(output, &doc, &glyph_defs, font_config, output_config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_dropcap {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_split_cap() {
+        assert_eq!(split_cap("  Once upon"), Some(("O", "nce upon")));
+        assert_eq!(split_cap("E\u{301}te"), Some(("E\u{301}", "te")));
+        assert_eq!(split_cap("   "), None);
+    }
+
+    #[test]
+    fn test_drop_cap_flows_text_around_letter() {
+        let mut font_config = test_support::font_config(16);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_max_pixel_width(Some(200.0));
+        let text = "Once upon a time there was a drop cap that spanned three lines of text, with words flowing around it.";
+
+        let cap_size = cap_font_size(&font_config, 16.0, 3).unwrap();
+        assert!(cap_size > 16 * 2 && cap_size < 16 * 4);
+
+        let (doc, glyph_defs) = build_drop_cap_document(text, &mut font_config, &render_config, 3).unwrap();
+        let doc = doc.to_string();
+        assert_eq!(font_config.get_size(), 16);
+        assert!(glyph_defs.keys().any(|id| id.ends_with(CAP_ID_SUFFIX)));
+        assert!(doc.contains(&format!("{}\"", CAP_ID_SUFFIX)));
+        // The three lines beside the letter are indented, the ones below are not
+        let indented = doc.match_indices("translate(").filter(|(i, _)| !doc[i + 10..].starts_with("0,")).count();
+        assert_eq!(indented, 3);
+    }
+}
@@ -0,0 +1,616 @@
+// visual effects applied to the rendered text as a whole, built from svg filters
+use clap::ValueEnum;
+use svg::Node;
+use svg::node::element::{
+    Element, Filter, Group, Use, FilterEffectComposite, FilterEffectFlood, FilterEffectGaussianBlur, FilterEffectMerge,
+    FilterEffectMergeNode, FilterEffectMorphology, FilterEffectOffset,
+};
+
+use crate::color::{parse_colors, split_colors, Color};
+
+/// Id of the filter drawing the text shadows.
+pub const SHADOW_FILTER_ID: &str = "text-shadow";
+
+/// Id of the filter drawing the neon glow.
+pub const NEON_FILTER_ID: &str = "text-neon";
+
+/// Id of the filter pressing the text into the page.
+pub const LETTERPRESS_FILTER_ID: &str = "text-letterpress";
+
+/// Id of the filter softening the whole text layer.
+pub const BLUR_FILTER_ID: &str = "text-blur";
+
+/// Id of the group holding the text face, referenced by the extrusion layers.
+pub const TEXT_FACE_ID: &str = "text-face";
+
+/// Effect presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Effect {
+    /// Glowing tubes, best with a stroke on a dark background
+    Neon,
+    /// Inset look: lit top edges and shaded bottom edges inside the glyphs, best filled
+    Letterpress,
+}
+
+/// Neon glow around the text strokes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neon {
+    pub color: Color,
+    // how far the outer glow reaches, in pixels
+    pub radius: f32,
+}
+
+impl Neon {
+    /// Glow tuned for text of `font_size` pixels.
+    pub fn new(color: Color, font_size: f32) -> Self {
+        Self {
+            color,
+            radius: (font_size * 0.25).max(1.0),
+        }
+    }
+
+    /// Space the glow reaches beyond the text on every side.
+    pub fn padding(&self) -> f32 {
+        // The widest blur has a standard deviation of radius / 2 and fades out within three
+        (self.radius * 1.5).ceil()
+    }
+}
+
+/// Inset edges inside the glyphs: a highlight along their tops and a shadow along their bottoms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Letterpress {
+    // how far the edges reach into the glyphs, in pixels
+    pub depth: f32,
+    pub highlight: String,
+    pub shadow: String,
+}
+
+impl Letterpress {
+    /// Edges tuned for text of `font_size` pixels.
+    pub fn new(font_size: f32) -> Self {
+        Self {
+            depth: (font_size * 0.04).max(1.0),
+            highlight: "#ffffffcc".to_string(),
+            shadow: "#00000099".to_string(),
+        }
+    }
+}
+
+/// One text shadow, as in CSS `text-shadow`: offset, blur radius and color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shadow {
+    pub dx: f32,
+    pub dy: f32,
+    pub blur: f32,
+    pub color: Color,
+}
+
+// Parses a length such as "3" or "3px"
+fn parse_length(value: &str) -> Option<f32> {
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .parse::<f32>()
+        .ok()
+        .filter(|value| value.is_finite())
+}
+
+impl std::str::FromStr for Shadow {
+    type Err = String;
+
+    /// Parses "dx dy [blur] [color]"; the color defaults to black.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shadow '{}': expected \"dx dy [blur] [color]\", e.g. \"2 2 4 #0008\"", s.trim());
+        // Lengths come first; the rest, which may contain spaces as in "rgb(0 0 0)", is the color
+        let mut lengths = Vec::new();
+        let mut rest = s.trim();
+        while let Some(length) = rest.split_whitespace().next().and_then(parse_length) {
+            lengths.push(length);
+            rest = rest.split_once(char::is_whitespace).map_or("", |(_, rest)| rest.trim_start());
+        }
+        let color = match rest {
+            "" => Color::BLACK,
+            color => color.parse().map_err(|e| format!("Invalid shadow '{}': {}", s.trim(), e))?,
+        };
+        let (dx, dy, blur) = match lengths.as_slice() {
+            [dx, dy] => (*dx, *dy, 0.0),
+            [dx, dy, blur] if *blur >= 0.0 => (*dx, *dy, *blur),
+            _ => return Err(invalid()),
+        };
+        Ok(Shadow {
+            dx,
+            dy,
+            blur,
+            color,
+        })
+    }
+}
+
+/// Deepest extrusion, in pixels.
+pub const MAX_EXTRUDE_DEPTH: f32 = 500.0;
+// Most layers drawn; deeper extrusions space theirs more than a pixel apart
+const MAX_EXTRUDE_LAYERS: usize = 128;
+
+/// 3D extrusion: copies of the text stacked behind it along a direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extrude {
+    // length of the extrusion, in pixels
+    pub depth: f32,
+    // direction in degrees, clockwise from the right (45 extrudes down and to the right)
+    pub angle: f32,
+    pub color: Color,
+}
+
+impl std::str::FromStr for Extrude {
+    type Err = String;
+
+    /// Parses "depth,angle,color", e.g. "8,45,#333".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid extrusion '{}': expected \"depth,angle,color\", e.g. \"8,45,#333\"", s.trim());
+        let [depth, angle, color] = s.splitn(3, ',').map(str::trim).collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+        let depth = parse_length(depth).filter(|depth| *depth > 0.0).ok_or_else(invalid)?;
+        if depth > MAX_EXTRUDE_DEPTH {
+            return Err(format!("Invalid extrusion '{}': depth is at most {}px", s.trim(), MAX_EXTRUDE_DEPTH));
+        }
+        let angle = angle
+            .strip_suffix("deg")
+            .unwrap_or(angle)
+            .parse::<f32>()
+            .ok()
+            .filter(|angle| angle.is_finite())
+            .ok_or_else(invalid)?;
+        let color = color.parse().map_err(|e| format!("Invalid extrusion '{}': {}", s.trim(), e))?;
+        Ok(Extrude { depth, angle, color })
+    }
+}
+
+impl Extrude {
+    /// Offset (dx, dy) of the deepest layer.
+    pub fn offset(&self) -> (f32, f32) {
+        let angle = self.angle.to_radians();
+        (round_offset(self.depth * angle.cos()), round_offset(self.depth * angle.sin()))
+    }
+
+    /// Space the extrusion reaches beyond the text: (left, top, right, bottom).
+    pub fn padding(&self) -> (f32, f32, f32, f32) {
+        let (dx, dy) = self.offset();
+        ((-dx).max(0.0).ceil(), (-dy).max(0.0).ceil(), dx.max(0.0).ceil(), dy.max(0.0).ceil())
+    }
+
+    /// Layers behind the text face, one per pixel of depth up to 128 and
+    /// deepest first, each a `<use>` of the group with id `TEXT_FACE_ID`.
+    pub fn layers(&self) -> Group {
+        let (dx, dy) = self.offset();
+        let steps = (self.depth.ceil() as usize).min(MAX_EXTRUDE_LAYERS);
+        let mut layers = Group::new()
+            .set("fill", self.color.to_string())
+            .set("stroke", self.color.to_string());
+        for step in (1..=steps).rev() {
+            let t = step as f32 / steps as f32;
+            layers = layers.add(
+                Use::new()
+                    .set("href", format!("#{}", TEXT_FACE_ID))
+                    .set("x", round_offset(dx * t))
+                    .set("y", round_offset(dy * t)),
+            );
+        }
+        layers
+    }
+}
+
+// Layer offsets to a hundredth of a pixel, without float noise like 4.3e-8
+fn round_offset(value: f32) -> f32 {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded == 0.0 { 0.0 } else { rounded }
+}
+
+/// Corner style of contour rings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ContourJoin {
+    #[default]
+    Round,
+    Miter,
+    Bevel,
+}
+
+impl ContourJoin {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ContourJoin::Round => "round",
+            ContourJoin::Miter => "miter",
+            ContourJoin::Bevel => "bevel",
+        }
+    }
+}
+
+/// Sticker style outline: rings around the glyphs, each `offset` pixels
+/// wider than the previous one, innermost color first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    pub offset: f32,
+    pub colors: Vec<Color>,
+    pub join: ContourJoin,
+}
+
+impl Contour {
+    pub fn new(offset: f32, colors: Vec<Color>, join: ContourJoin) -> Self {
+        Self { offset, colors, join }
+    }
+
+    /// Space the outermost ring reaches beyond the text on every side.
+    pub fn padding(&self) -> f32 {
+        (self.offset * self.colors.len() as f32).ceil()
+    }
+
+    /// Rings drawn behind `content`, outermost first. Each one is a copy of the
+    /// text stroked as wide as the ring reaches on both sides of the outlines.
+    pub fn rings(&self, content: &Group) -> Group {
+        let mut rings = Group::new();
+        for (i, color) in self.colors.iter().enumerate().rev() {
+            let mut ring = Group::new()
+                .set("fill", color.to_string())
+                .set("stroke", color.to_string())
+                .set("stroke-width", 2.0 * self.offset * (i + 1) as f32)
+                .set("stroke-linejoin", self.join.as_str());
+            if self.join == ContourJoin::Round {
+                ring = ring.set("stroke-linecap", "round");
+            }
+            for child in Node::get_children(content).into_iter().flatten() {
+                ring = ring.add(strip_paint(child.as_ref()));
+            }
+            rings = rings.add(ring);
+        }
+        rings
+    }
+}
+
+/// Parses a comma separated list of colors, e.g. "#fff,#000".
+pub fn parse_contour_colors(colors: &str) -> Result<Vec<Color>, String> {
+    parse_colors(colors, ',').map_err(|e| format!("Invalid contour colors '{}': {}", colors, e))
+}
+
+// Attributes a copy of the text takes from its new parent instead
+fn is_paint_attribute(name: &str) -> bool {
+    name == "fill" || name == "stroke" || name.starts_with("stroke-")
+}
+
+/// Copy of a node tree without fill and stroke attributes, so it is painted
+/// entirely by the group it is added to, and without ids, which stay unique.
+pub fn strip_paint(node: &dyn Node) -> Box<dyn Node> {
+    let (Some(attributes), Some(children)) = (node.get_attributes(), node.get_children()) else {
+        return node.clone();
+    };
+    let mut copy = Element::new(node.get_name());
+    for (name, value) in attributes.iter() {
+        if !is_paint_attribute(name) && name != "id" {
+            copy.assign(name.as_str(), value.clone());
+        }
+    }
+    for child in children.iter() {
+        copy.append(strip_paint(child.as_ref()));
+    }
+    Box::new(copy)
+}
+
+/// Parses a comma separated list of shadows, e.g. "1 1 0 #000, 3 3 6 #0008".
+pub fn parse_shadows(shadows: &str) -> Result<Vec<Shadow>, String> {
+    split_colors(shadows, ',').into_iter().map(str::parse).collect()
+}
+
+/// Space the shadows reach beyond the text on each side: (left, top, right, bottom).
+pub fn shadow_padding(shadows: &[Shadow]) -> (f32, f32, f32, f32) {
+    let mut padding = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for shadow in shadows {
+        // A gaussian blur of standard deviation blur / 2 fades out within three deviations
+        let spread = shadow.blur * 1.5;
+        padding.0 = padding.0.max(spread - shadow.dx);
+        padding.1 = padding.1.max(spread - shadow.dy);
+        padding.2 = padding.2.max(spread + shadow.dx);
+        padding.3 = padding.3.max(spread + shadow.dy);
+    }
+    (padding.0.ceil(), padding.1.ceil(), padding.2.ceil(), padding.3.ceil())
+}
+
+/// Filter drawing `shadows` under the filtered content, covering the region
+/// (x, y, width, height) in user space. The first shadow is drawn on top,
+/// as in CSS.
+pub fn shadow_filter(shadows: &[Shadow], region: (f32, f32, f32, f32)) -> Filter {
+    let (x, y, width, height) = region;
+    let mut filter = Filter::new()
+        .set("id", SHADOW_FILTER_ID)
+        .set("filterUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", width)
+        .set("height", height);
+
+    for (i, shadow) in shadows.iter().enumerate() {
+        let offset_result = if shadow.blur > 0.0 { format!("shadow{}-offset", i) } else { format!("shadow{}", i) };
+        filter = filter
+            .add(
+                FilterEffectFlood::new()
+                    .set("flood-color", shadow.color.to_string())
+                    .set("result", format!("shadow{}-color", i)),
+            )
+            .add(
+                FilterEffectComposite::new()
+                    .set("in", format!("shadow{}-color", i))
+                    .set("in2", "SourceAlpha")
+                    .set("operator", "in")
+                    .set("result", format!("shadow{}-shape", i)),
+            )
+            .add(
+                FilterEffectOffset::new()
+                    .set("in", format!("shadow{}-shape", i))
+                    .set("dx", shadow.dx)
+                    .set("dy", shadow.dy)
+                    .set("result", offset_result.clone()),
+            );
+        if shadow.blur > 0.0 {
+            filter = filter.add(
+                FilterEffectGaussianBlur::new()
+                    .set("in", offset_result)
+                    .set("stdDeviation", shadow.blur / 2.0)
+                    .set("result", format!("shadow{}", i)),
+            );
+        }
+    }
+
+    // Later shadows go below earlier ones, and the text above them all
+    let mut merge = FilterEffectMerge::new();
+    for i in (0..shadows.len()).rev() {
+        merge = merge.add(FilterEffectMergeNode::new().set("in", format!("shadow{}", i)));
+    }
+    merge = merge.add(FilterEffectMergeNode::new().set("in", "SourceGraphic"));
+    filter.add(merge)
+}
+
+/// Space a gaussian blur of standard deviation `deviation` reaches beyond the content.
+pub fn blur_padding(deviation: f32) -> f32 {
+    // The blur fades out within three deviations
+    (deviation * 3.0).ceil()
+}
+
+/// Filter blurring the filtered content, covering the region (x, y, width, height) in user space.
+pub fn blur_filter(deviation: f32, region: (f32, f32, f32, f32)) -> Filter {
+    let (x, y, width, height) = region;
+    Filter::new()
+        .set("id", BLUR_FILTER_ID)
+        .set("filterUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", width)
+        .set("height", height)
+        .add(FilterEffectGaussianBlur::new().set("in", "SourceGraphic").set("stdDeviation", deviation))
+}
+
+/// Filter drawing a neon glow around the filtered content, covering the
+/// region (x, y, width, height) in user space.
+///
+/// The strokes are thickened, tinted with the glow color and blurred at
+/// three widths; the tightest blur is merged twice so the glow is bright
+/// near the tubes, then the content is drawn on top as their core.
+pub fn neon_filter(neon: &Neon, region: (f32, f32, f32, f32)) -> Filter {
+    let (x, y, width, height) = region;
+    let mut filter = Filter::new()
+        .set("id", NEON_FILTER_ID)
+        .set("filterUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", width)
+        .set("height", height)
+        .add(
+            FilterEffectMorphology::new()
+                .set("in", "SourceAlpha")
+                .set("operator", "dilate")
+                .set("radius", (neon.radius / 8.0).max(0.5))
+                .set("result", "neon-thick"),
+        )
+        .add(FilterEffectFlood::new().set("flood-color", neon.color.to_string()).set("result", "neon-color"))
+        .add(
+            FilterEffectComposite::new()
+                .set("in", "neon-color")
+                .set("in2", "neon-thick")
+                .set("operator", "in")
+                .set("result", "neon-tube"),
+        );
+
+    let blurs = [("neon-near", neon.radius / 8.0), ("neon-mid", neon.radius / 4.0), ("neon-far", neon.radius / 2.0)];
+    for (result, deviation) in blurs {
+        filter = filter.add(
+            FilterEffectGaussianBlur::new()
+                .set("in", "neon-tube")
+                .set("stdDeviation", deviation)
+                .set("result", result),
+        );
+    }
+
+    let mut merge = FilterEffectMerge::new();
+    for input in ["neon-far", "neon-mid", "neon-near", "neon-near", "SourceGraphic"] {
+        merge = merge.add(FilterEffectMergeNode::new().set("in", input));
+    }
+    filter.add(merge)
+}
+
+/// Filter drawing inset edges on the filtered content, covering the region
+/// (x, y, width, height) in user space. Nothing is drawn outside the glyphs.
+///
+/// Shifting the glyph shapes down and cutting them out of themselves leaves
+/// a band along the top of every stroke, and shifting them up one along the
+/// bottom. The bands are softened, clipped back to the glyphs and tinted.
+pub fn letterpress_filter(letterpress: &Letterpress, region: (f32, f32, f32, f32)) -> Filter {
+    let (x, y, width, height) = region;
+    let mut filter = Filter::new()
+        .set("id", LETTERPRESS_FILTER_ID)
+        .set("filterUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", width)
+        .set("height", height);
+
+    let edges = [
+        ("letterpress-top", letterpress.depth, letterpress.highlight.as_str()),
+        ("letterpress-bottom", -letterpress.depth, letterpress.shadow.as_str()),
+    ];
+    for (result, dy, color) in edges {
+        filter = filter
+            .add(
+                FilterEffectOffset::new()
+                    .set("in", "SourceAlpha")
+                    .set("dy", dy)
+                    .set("result", format!("{}-shifted", result)),
+            )
+            .add(
+                FilterEffectComposite::new()
+                    .set("in", "SourceAlpha")
+                    .set("in2", format!("{}-shifted", result))
+                    .set("operator", "out")
+                    .set("result", format!("{}-band", result)),
+            )
+            .add(
+                FilterEffectGaussianBlur::new()
+                    .set("in", format!("{}-band", result))
+                    .set("stdDeviation", letterpress.depth / 2.0)
+                    .set("result", format!("{}-soft", result)),
+            )
+            .add(FilterEffectFlood::new().set("flood-color", color).set("result", format!("{}-color", result)))
+            .add(
+                FilterEffectComposite::new()
+                    .set("in", format!("{}-color", result))
+                    .set("in2", format!("{}-soft", result))
+                    .set("operator", "in")
+                    .set("result", format!("{}-tinted", result)),
+            )
+            .add(
+                FilterEffectComposite::new()
+                    .set("in", format!("{}-tinted", result))
+                    .set("in2", "SourceAlpha")
+                    .set("operator", "in")
+                    .set("result", result),
+            );
+    }
+
+    let mut merge = FilterEffectMerge::new();
+    for input in ["SourceGraphic", "letterpress-top", "letterpress-bottom"] {
+        merge = merge.add(FilterEffectMergeNode::new().set("in", input));
+    }
+    filter.add(merge)
+}
+
+#[cfg(test)]
+mod test_effects {
+    use super::*;
+
+    #[test]
+    fn test_parse_shadows() {
+        let shadows = parse_shadows("1 1 0 #000, 3px 3px 6px #0008,-2 4").unwrap();
+        assert_eq!(shadows, vec![
+            Shadow { dx: 1.0, dy: 1.0, blur: 0.0, color: Color::BLACK },
+            Shadow { dx: 3.0, dy: 3.0, blur: 6.0, color: "#0008".parse().unwrap() },
+            Shadow { dx: -2.0, dy: 4.0, blur: 0.0, color: Color::BLACK },
+        ]);
+        assert!(parse_shadows("1 #000").is_err());
+        assert!(parse_shadows("1 1 -3 red").is_err());
+        assert!(parse_shadows("1 1 red 2").is_err());
+
+        assert_eq!(shadow_padding(&shadows), (6.0, 6.0, 12.0, 12.0));
+    }
+
+    #[test]
+    fn test_first_shadow_is_drawn_on_top() {
+        let shadows = parse_shadows("1 1 #f00, 2 2 4 #00f").unwrap();
+        let filter = shadow_filter(&shadows, (0.0, 0.0, 100.0, 50.0)).to_string();
+        let merge = &filter[filter.find("<feMerge>").unwrap()..];
+        let order: Vec<usize> = ["\"shadow1\"", "\"shadow0\"", "\"SourceGraphic\""]
+            .iter()
+            .map(|input| merge.find(input).unwrap())
+            .collect();
+        assert!(order[0] < order[1] && order[1] < order[2]);
+        assert!(filter.contains("stdDeviation=\"2\""));
+    }
+
+    #[test]
+    fn test_blur() {
+        assert_eq!(blur_padding(2.5), 8.0);
+        let filter = blur_filter(2.5, (-8.0, -8.0, 116.0, 66.0)).to_string();
+        assert!(filter.contains(r#"<feGaussianBlur in="SourceGraphic" stdDeviation="2.5"/>"#));
+        assert!(filter.contains(r#"id="text-blur""#));
+    }
+
+    #[test]
+    fn test_extrude() {
+        let extrude: Extrude = "4, 90, #333".parse().unwrap();
+        assert_eq!(extrude.color.to_string(), "#333333");
+        let (dx, dy) = extrude.offset();
+        assert!(dx.abs() < 1e-4 && (dy - 4.0).abs() < 1e-4);
+        assert_eq!(extrude.padding(), (0.0, 0.0, 0.0, 4.0));
+
+        let layers = extrude.layers().to_string();
+        assert_eq!(layers.matches("<use").count(), 4);
+        // The deepest layer comes first so nearer ones are drawn over it
+        assert!(layers.find("y=\"4\"").unwrap() < layers.find("y=\"1\"").unwrap());
+
+        assert!("4,45".parse::<Extrude>().is_err());
+        assert!("0,45,red".parse::<Extrude>().is_err());
+        assert!("4,down,red".parse::<Extrude>().is_err());
+        assert!("1e9,45,red".parse::<Extrude>().unwrap_err().contains("at most 500px"));
+
+        // Deep extrusions keep to the layer cap, still reaching the full depth
+        let deep: Extrude = "500,0,red".parse().unwrap();
+        let layers = deep.layers().to_string();
+        assert_eq!(layers.matches("<use").count(), MAX_EXTRUDE_LAYERS);
+        assert!(layers.contains("x=\"500\""));
+    }
+
+    #[test]
+    fn test_contour_rings() {
+        let content = Group::new().add(
+            Group::new()
+                .set("stroke-width", 1)
+                .add(Use::new().set("href", "#g1").set("fill", "#f00").set("x", 3)),
+        );
+        let contour = Contour::new(4.0, parse_contour_colors("#fff, #000").unwrap(), ContourJoin::Miter);
+        assert_eq!(contour.padding(), 8.0);
+
+        let rings = contour.rings(&content).to_string();
+        // The outer ring comes first so the inner one is drawn over it
+        assert!(rings.find(r#"stroke-width="16""#).unwrap() < rings.find(r#"stroke-width="8""#).unwrap());
+        assert!(rings.contains(r#"stroke-linejoin="miter""#));
+        // Paint set inside the text would override the ring's
+        assert!(!rings.contains("#f00"));
+        assert!(!rings.contains(r#"stroke-width="1""#));
+        assert_eq!(rings.matches(r#"x="3""#).count(), 2);
+
+        assert!(parse_contour_colors("#fff,,#000").is_err());
+    }
+
+    #[test]
+    fn test_letterpress_edges() {
+        let letterpress = Letterpress::new(50.0);
+        assert_eq!(letterpress.depth, 2.0);
+
+        let filter = letterpress_filter(&letterpress, (0.0, 0.0, 100.0, 50.0)).to_string();
+        assert!(filter.contains(r#"dy="2" in="SourceAlpha" result="letterpress-top-shifted""#));
+        assert!(filter.contains(r#"dy="-2" in="SourceAlpha" result="letterpress-bottom-shifted""#));
+        // Both edges are clipped to the glyphs
+        assert_eq!(filter.matches(r#"in2="SourceAlpha" operator="in""#).count(), 2);
+        let merge = &filter[filter.find("<feMerge").unwrap()..];
+        assert!(merge.find("\"SourceGraphic\"").unwrap() < merge.find("\"letterpress-top\"").unwrap());
+    }
+
+    #[test]
+    fn test_neon_glow() {
+        let neon = Neon::new("#0ff".parse().unwrap(), 64.0);
+        assert_eq!(neon.radius, 16.0);
+        assert_eq!(neon.padding(), 24.0);
+
+        let filter = neon_filter(&neon, (-24.0, -24.0, 148.0, 112.0)).to_string();
+        assert!(filter.contains("flood-color=\"#00ffff\""));
+        assert!(filter.contains("stdDeviation=\"8\""));
+        // The glow goes under the text
+        let merge = &filter[filter.find("<feMerge>").unwrap()..];
+        assert!(merge.find("\"neon-far\"").unwrap() < merge.find("\"SourceGraphic\"").unwrap());
+    }
+}
@@ -0,0 +1,38 @@
+// telling emoji apart from text, so they can come from an emoji font even
+// when the fonts of the text have no emoji of their own
+
+/// Emoji families tried in turn when no emoji font is given: those of macOS,
+/// Windows and most Linux desktops.
+pub const EMOJI_FAMILIES: [&str; 5] = ["Apple Color Emoji", "Segoe UI Emoji", "Noto Color Emoji", "Noto Emoji", "Twemoji Mozilla"];
+
+/// Whether `c` is drawn as an emoji, given the character after it: pictographs
+/// always, other symbols when the emoji variation selector asks for it.
+pub fn is_emoji(c: char, next: Option<char>) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF) || (next == Some('\u{FE0F}') && !c.is_ascii())
+}
+
+/// Whether `text` has any emoji.
+pub fn has_emoji(text: &str) -> bool {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if is_emoji(c, chars.peek().copied()) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod test_emoji {
+    use super::*;
+
+    #[test]
+    fn test_is_emoji() {
+        assert!(has_emoji("Launch 🚀 today"));
+        assert!(has_emoji("flag 🇯🇵"));
+        // Symbols are text unless followed by the emoji variation selector
+        assert!(!has_emoji("★ ✓ ♥ é 1"));
+        assert!(has_emoji("I \u{2665}\u{FE0F} it"));
+        assert!(!has_emoji("1\u{FE0F}"));
+    }
+}
@@ -0,0 +1,138 @@
+// regex emphasis: spans of each line matching a pattern are recolored or
+// marked with a background, e.g. to make ERROR stand out in a rendered log
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::color::Rgba;
+use crate::gradient::{cluster_color, Gradient};
+
+/// A pattern whose matches get a text or background color.
+#[derive(Debug, Clone)]
+pub struct Emphasis {
+    pub regex: Regex,
+    pub color: Rgba,
+    /// Mark the matches with a background rather than coloring the text
+    pub background: bool,
+}
+
+impl std::str::FromStr for Emphasis {
+    type Err = String;
+
+    /// Parses "pattern:color" or "pattern:bg=color"; the pattern may contain colons.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, color) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("Invalid highlight '{}': expected \"pattern:color\" or \"pattern:bg=color\"", s))?;
+        let (color, background) = match color.trim().strip_prefix("bg=") {
+            Some(color) => (color, true),
+            None => (color, false),
+        };
+        let regex = Regex::new(pattern).map_err(|e| format!("Invalid highlight pattern '{}': {}", pattern, e))?;
+        if regex.as_str().is_empty() {
+            return Err(format!("Invalid highlight '{}': the pattern is empty", s));
+        }
+        Ok(Emphasis { regex, color: color.parse()?, background })
+    }
+}
+
+/// Colors of one line: text colors by the byte offset they start at (`None`
+/// going back to the text's own color) and byte ranges with a background.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinePaint {
+    pub colors: Vec<(usize, Option<Rgba>)>,
+    pub backgrounds: Vec<(Range<usize>, Rgba)>,
+}
+
+impl LinePaint {
+    /// The paint of bytes `start..end`, with offsets relative to `start`.
+    pub fn slice(&self, start: usize, end: usize) -> LinePaint {
+        let mut colors: Vec<(usize, Option<Rgba>)> = cluster_color(&self.colors, start).map(|color| (0, color)).into_iter().collect();
+        colors.extend(
+            self.colors
+                .iter()
+                .filter(|(offset, _)| *offset > start && *offset < end)
+                .map(|(offset, color)| (offset - start, *color)),
+        );
+        let backgrounds = self
+            .backgrounds
+            .iter()
+            .filter(|(range, _)| range.start < end && range.end > start)
+            .map(|(range, color)| (range.start.max(start) - start..range.end.min(end) - start, *color))
+            .collect();
+        LinePaint { colors, backgrounds }
+    }
+}
+
+/// Colors `line` along the gradient, if any, with the colored `words` and
+/// then the matches of `emphases` on top. Later patterns win where matches overlap.
+pub fn line_paint(line: &str, gradient: Option<&Gradient>, words: Vec<(Range<usize>, Rgba)>, emphases: &[Emphasis]) -> LinePaint {
+    let base: Vec<(usize, Option<Rgba>)> = gradient
+        .map(|gradient| gradient.line_colors(line).into_iter().map(|(start, color)| (start, Some(color))).collect())
+        .unwrap_or_default();
+
+    let mut text_matches = words;
+    let mut backgrounds = Vec::new();
+    for emphasis in emphases {
+        for found in emphasis.regex.find_iter(line).filter(|found| !found.is_empty()) {
+            if emphasis.background {
+                backgrounds.push((found.range(), emphasis.color));
+            } else {
+                text_matches.push((found.range(), emphasis.color));
+            }
+        }
+    }
+    if text_matches.is_empty() {
+        return LinePaint { colors: base, backgrounds };
+    }
+
+    // The color changes only where a gradient step or a match starts or ends
+    let mut boundaries: Vec<usize> = base.iter().map(|(start, _)| *start).collect();
+    boundaries.extend(text_matches.iter().flat_map(|(range, _)| [range.start, range.end]));
+    boundaries.push(0);
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    let colors = boundaries
+        .into_iter()
+        .filter(|boundary| *boundary < line.len())
+        .map(|boundary| {
+            let color = match text_matches.iter().rev().find(|(range, _)| range.contains(&boundary)) {
+                Some((_, color)) => Some(*color),
+                None => cluster_color(&base, boundary).flatten(),
+            };
+            (boundary, color)
+        })
+        .collect();
+    LinePaint { colors, backgrounds }
+}
+
+#[cfg(test)]
+mod test_emphasis {
+    use super::*;
+
+    #[test]
+    fn test_parse_emphasis() {
+        let emphasis: Emphasis = "a:b:#f00".parse().unwrap();
+        assert_eq!(emphasis.regex.as_str(), "a:b");
+        assert_eq!(emphasis.color, Rgba(255, 0, 0, 255));
+        assert!(!emphasis.background);
+        assert!("ERROR:bg=#ff08".parse::<Emphasis>().unwrap().background);
+        assert!("ERROR".parse::<Emphasis>().is_err());
+        assert!("(:#f00".parse::<Emphasis>().is_err());
+        assert!(":#f00".parse::<Emphasis>().is_err());
+    }
+
+    #[test]
+    fn test_line_paint() {
+        let red = Rgba(255, 0, 0, 255);
+        let yellow = Rgba(255, 255, 0, 255);
+        let emphases: Vec<Emphasis> = vec!["ERROR:#f00".parse().unwrap(), r"\d+:bg=#ff0".parse().unwrap()];
+        let paint = line_paint("an ERROR at 42", None, Vec::new(), &emphases);
+        assert_eq!(paint.colors, vec![(0, None), (3, Some(red)), (8, None)]);
+        assert_eq!(paint.backgrounds, vec![(12..14, yellow)]);
+
+        let sliced = paint.slice(5, 14);
+        assert_eq!(sliced.colors, vec![(0, Some(red)), (3, None)]);
+        assert_eq!(sliced.backgrounds, vec![(7..9, yellow)]);
+    }
+}
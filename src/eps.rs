@@ -0,0 +1,229 @@
+// writing the rendered document as Encapsulated PostScript for print
+// workflows, each path of the svg turned into PostScript path operators
+use std::fmt::Write;
+
+use resvg::tiny_skia::{PathSegment, Point, Transform};
+use resvg::usvg;
+
+use crate::svg::write_coord;
+
+/// PostScript points per svg pixel, at the 96 pixels per inch of CSS.
+pub const POINTS_PER_PIXEL: f32 = 0.75;
+
+// Decimal places of coordinates: a hundredth of a point is well below any printer's dot
+const PRECISION: u8 = 2;
+
+// Short names for the path operators, which make up most of the file
+const PROLOG: &str = "/m { moveto } bind def\n/l { lineto } bind def\n/c { curveto } bind def\n/h { closepath } bind def\n";
+
+// Appends the numbers separated by spaces
+fn numbers(out: &mut String, values: &[f32]) {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        write_coord(out, *value, PRECISION);
+    }
+}
+
+// Appends the operands, then `operator` on the same line
+fn op(out: &mut String, values: &[f32], operator: &str) {
+    numbers(out, values);
+    out.push(' ');
+    out.push_str(operator);
+    out.push('\n');
+}
+
+// Color a paint is drawn in: gradients and patterns get their first color
+fn paint_color(paint: &usvg::Paint) -> usvg::Color {
+    match paint {
+        usvg::Paint::Color(color) => *color,
+        usvg::Paint::LinearGradient(gradient) => gradient.stops().first().map_or(usvg::Color::black(), |stop| stop.color()),
+        usvg::Paint::RadialGradient(gradient) => gradient.stops().first().map_or(usvg::Color::black(), |stop| stop.color()),
+        usvg::Paint::Pattern(_) => usvg::Color::black(),
+    }
+}
+
+fn set_color(out: &mut String, paint: &usvg::Paint) {
+    let color = paint_color(paint);
+    for channel in [color.red, color.green, color.blue] {
+        write_coord(out, channel as f32 / 255.0, 3);
+        out.push(' ');
+    }
+    out.push_str("setrgbcolor\n");
+}
+
+// Appends the outline of `path`, mapped by `transform` into points with y going up
+fn write_path(out: &mut String, path: &usvg::Path, transform: Transform, height: f32) {
+    let map = |point: Point| {
+        let mut point = point;
+        transform.map_point(&mut point);
+        [point.x * POINTS_PER_PIXEL, (height - point.y) * POINTS_PER_PIXEL]
+    };
+    let (mut start, mut last) = (Point::zero(), Point::zero());
+    for segment in path.data().segments() {
+        match segment {
+            PathSegment::MoveTo(p) => {
+                op(out, &map(p), "m");
+                (start, last) = (p, p);
+            }
+            PathSegment::LineTo(p) => {
+                op(out, &map(p), "l");
+                last = p;
+            }
+            // PostScript only has cubic curves, which take quadratic ones exactly
+            PathSegment::QuadTo(q, p) => {
+                let c1 = Point::from_xy(last.x + 2.0 / 3.0 * (q.x - last.x), last.y + 2.0 / 3.0 * (q.y - last.y));
+                let c2 = Point::from_xy(p.x + 2.0 / 3.0 * (q.x - p.x), p.y + 2.0 / 3.0 * (q.y - p.y));
+                op(out, &[map(c1), map(c2), map(p)].concat(), "c");
+                last = p;
+            }
+            PathSegment::CubicTo(c1, c2, p) => {
+                op(out, &[map(c1), map(c2), map(p)].concat(), "c");
+                last = p;
+            }
+            PathSegment::Close => {
+                out.push_str("h\n");
+                last = start;
+            }
+        }
+    }
+}
+
+fn write_stroke(out: &mut String, stroke: &usvg::Stroke, transform: Transform) {
+    // Stroke widths and dashes scale with the path
+    let scale = (transform.sx * transform.sy - transform.kx * transform.ky).abs().sqrt() * POINTS_PER_PIXEL;
+    set_color(out, stroke.paint());
+    op(out, &[stroke.width().get() * scale], "setlinewidth");
+    let cap = match stroke.linecap() {
+        usvg::LineCap::Butt => 0.0,
+        usvg::LineCap::Round => 1.0,
+        usvg::LineCap::Square => 2.0,
+    };
+    op(out, &[cap], "setlinecap");
+    let join = match stroke.linejoin() {
+        usvg::LineJoin::Miter | usvg::LineJoin::MiterClip => 0.0,
+        usvg::LineJoin::Round => 1.0,
+        usvg::LineJoin::Bevel => 2.0,
+    };
+    op(out, &[join], "setlinejoin");
+    op(out, &[stroke.miterlimit().get()], "setmiterlimit");
+    if let Some(dashes) = stroke.dasharray() {
+        out.push('[');
+        numbers(out, &dashes.iter().map(|dash| dash * scale).collect::<Vec<_>>());
+        out.push_str("] ");
+        op(out, &[stroke.dashoffset() * scale], "setdash");
+    }
+    out.push_str("stroke\n");
+}
+
+fn write_group(out: &mut String, group: &usvg::Group, height: f32, skipped_images: &mut usize) {
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => write_group(out, group, height, skipped_images),
+            usvg::Node::Text(text) => write_group(out, text.flattened(), height, skipped_images),
+            usvg::Node::Image(_) => *skipped_images += 1,
+            usvg::Node::Path(path) if path.is_visible() => {
+                let transform = path.abs_transform();
+                write_path(out, path, transform, height);
+                let fill = |out: &mut String| {
+                    if let Some(fill) = path.fill() {
+                        out.push_str("gsave\n");
+                        set_color(out, fill.paint());
+                        out.push_str(match fill.rule() {
+                            usvg::FillRule::NonZero => "fill\n",
+                            usvg::FillRule::EvenOdd => "eofill\n",
+                        });
+                        out.push_str("grestore\n");
+                    }
+                };
+                let stroke = |out: &mut String| {
+                    if let Some(stroke) = path.stroke() {
+                        out.push_str("gsave\n");
+                        write_stroke(out, stroke, transform);
+                        out.push_str("grestore\n");
+                    }
+                };
+                match path.paint_order() {
+                    usvg::PaintOrder::FillAndStroke => {
+                        fill(out);
+                        stroke(out);
+                    }
+                    usvg::PaintOrder::StrokeAndFill => {
+                        stroke(out);
+                        fill(out);
+                    }
+                }
+                out.push_str("newpath\n");
+            }
+            usvg::Node::Path(_) => {}
+        }
+    }
+}
+
+/// The svg document `content` as an EPS file the size of the document in
+/// points. Paths keep their fill and stroke colors; gradients and patterns get
+/// their first color, and opacity and images are left out, as PostScript has
+/// no transparency.
+pub fn svg_to_eps(content: &str) -> Result<String, usvg::Error> {
+    let tree = usvg::Tree::from_str(content, &usvg::Options::default())?;
+    let (width, height) = (tree.size().width(), tree.size().height());
+    let mut body = String::new();
+    let mut skipped_images = 0;
+    write_group(&mut body, tree.root(), height, &mut skipped_images);
+    if skipped_images > 0 {
+        crate::log_warn!("EPS output leaves out {} image(s), such as bitmap emoji or background images", skipped_images);
+    }
+
+    let mut out = String::with_capacity(body.len() + 512);
+    out.push_str("%!PS-Adobe-3.0 EPSF-3.0\n%%Creator: text2svg\n");
+    let (points_width, points_height) = (width * POINTS_PER_PIXEL, height * POINTS_PER_PIXEL);
+    writeln!(out, "%%BoundingBox: 0 0 {} {}", points_width.ceil(), points_height.ceil()).unwrap();
+    out.push_str("%%HiResBoundingBox: 0 0 ");
+    numbers(&mut out, &[points_width, points_height]);
+    out.push_str("\n%%LanguageLevel: 2\n%%Pages: 1\n%%EndComments\n%%BeginProlog\n");
+    out.push_str(PROLOG);
+    out.push_str("%%EndProlog\n%%Page: 1 1\nsave\n");
+    out.push_str(&body);
+    out.push_str("restore\nshowpage\n%%Trailer\n%%EOF\n");
+    Ok(out)
+}
+
+/// Width and height in svg pixels of an EPS file written by `svg_to_eps`,
+/// from its bounding box.
+pub fn eps_size(content: &str) -> Option<(f32, f32)> {
+    let line = content.lines().find_map(|line| line.strip_prefix("%%HiResBoundingBox:"))?;
+    let bounds: Vec<f32> = line.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+    // Rounded to hundredths, undoing the rounding of the points
+    let pixels = |points: f32| (points / POINTS_PER_PIXEL * 100.0).round() / 100.0;
+    match bounds[..] {
+        [x0, y0, x1, y1] => Some((pixels(x1 - x0), pixels(y1 - y0))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test_eps {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+    use crate::render::{build_text_svg, RenderConfig};
+    use crate::xml::to_xml;
+
+    #[test]
+    fn test_svg_to_eps() {
+        let font_config = test_support::font_config(20);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let (doc, _) = build_text_svg("Hi", &font_config, &render_config).unwrap();
+        let (width, height) = crate::render::document_size(&doc);
+        let eps = svg_to_eps(&to_xml(&doc)).unwrap();
+
+        assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n"));
+        assert!(eps.contains(&format!("%%BoundingBox: 0 0 {} {}\n", (width * 0.75).ceil(), (height * 0.75).ceil())));
+        assert_eq!(eps_size(&eps), Some((width, height)));
+        // Two glyphs, H in straight lines and i with its dot
+        assert!(eps.matches(" m\n").count() >= 3 && eps.contains(" l\n"));
+        assert!(eps.contains("0 0 0 setrgbcolor\nfill\n"));
+        assert!(eps.ends_with("showpage\n%%Trailer\n%%EOF\n"));
+    }
+}
@@ -0,0 +1,156 @@
+// error kinds and exit codes, so scripts can tell a missing font from a
+// missing file without parsing messages
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::font::FontError;
+
+/// How errors are reported on stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// "Error: message"
+    #[default]
+    Text,
+    /// One JSON object: {"kind", "exit_code", "message", "path", "span"}
+    Json,
+}
+
+/// What went wrong, each with its own exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Anything else, e.g. text overflowing the canvas: exit code 1
+    Other,
+    /// Invalid or conflicting options: exit code 2, as for usage errors
+    InvalidOption,
+    /// The font family can't be found or loaded: exit code 3
+    FontNotFound,
+    /// The font has no glyphs for some characters: exit code 4
+    GlyphMissing,
+    /// Reading input or writing output failed: exit code 5
+    Io,
+}
+
+impl ErrorKind {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::InvalidOption => 2,
+            ErrorKind::FontNotFound => 3,
+            ErrorKind::GlyphMissing => 4,
+            ErrorKind::Io => 5,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::InvalidOption => "invalid_option",
+            ErrorKind::FontNotFound => "font_not_found",
+            ErrorKind::GlyphMissing => "glyph_missing",
+            ErrorKind::Io => "io",
+        }
+    }
+}
+
+/// An error of a known kind, with the file and byte range of the input it is about.
+#[derive(Debug)]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    pub span: Option<Range<usize>>,
+}
+
+impl CliError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CliError { kind, message: message.into(), path: None, span: None }
+    }
+
+    pub fn set_path(mut self, path: Option<&Path>) -> Self {
+        self.path = path.map(Path::to_path_buf);
+        self
+    }
+
+    pub fn set_span(mut self, span: Option<Range<usize>>) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// An IO error on `path`, shown as "path: error".
+pub fn io_error(path: &Path, e: impl Display) -> anyhow::Error {
+    CliError::new(ErrorKind::Io, format!("{}: {}", path.display(), e)).set_path(Some(path)).into()
+}
+
+/// An invalid or conflicting option.
+pub fn invalid_option(message: impl Display) -> anyhow::Error {
+    CliError::new(ErrorKind::InvalidOption, message.to_string()).into()
+}
+
+/// The kind of `err`, from the first error of a known kind in its chain.
+pub fn error_kind(err: &anyhow::Error) -> ErrorKind {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<CliError>() {
+            return e.kind;
+        }
+        if let Some(e) = cause.downcast_ref::<FontError>() {
+            return match e {
+                FontError::IoError(_) => ErrorKind::Io,
+                _ => ErrorKind::FontNotFound,
+            };
+        }
+        if cause.is::<std::io::Error>() {
+            return ErrorKind::Io;
+        }
+        if cause.is::<clap::Error>() {
+            return ErrorKind::InvalidOption;
+        }
+    }
+    ErrorKind::Other
+}
+
+/// `err` as a JSON object on one line.
+pub fn error_json(err: &anyhow::Error) -> String {
+    let kind = error_kind(err);
+    let cli_error = err.chain().find_map(|cause| cause.downcast_ref::<CliError>());
+    let path = cli_error.and_then(|e| e.path.as_ref()).map(|path| path.display().to_string());
+    let span = cli_error.and_then(|e| e.span.clone()).map(|span| json!({"start": span.start, "end": span.end}));
+    json!({
+        "kind": kind.as_str(),
+        "exit_code": kind.exit_code(),
+        "message": err.to_string(),
+        "path": path,
+        "span": span,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+
+    #[test]
+    fn test_error_json() {
+        let err = CliError::new(ErrorKind::GlyphMissing, "1 characters missing").set_path(Some(Path::new("in.txt"))).set_span(Some(3..6));
+        assert_eq!(
+            error_json(&err.into()),
+            r#"{"exit_code":4,"kind":"glyph_missing","message":"1 characters missing","path":"in.txt","span":{"end":6,"start":3}}"#
+        );
+        let io = std::fs::read("/nonexistent/input.txt").map_err(|e| io_error(Path::new("/nonexistent/input.txt"), e)).unwrap_err();
+        assert_eq!(error_kind(&io), ErrorKind::Io);
+        assert!(io.to_string().starts_with("/nonexistent/input.txt: "));
+        assert_eq!(error_kind(&anyhow::anyhow!("overflow")), ErrorKind::Other);
+    }
+}
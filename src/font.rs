@@ -1,42 +1,190 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
 
 use clap::ValueEnum;
 use font_kit::error::{FontLoadingError, SelectionError};
-use font_kit::font::Font;
 use font_kit::handle::Handle;
-use font_kit::properties::{Style, Weight};
+use font_kit::properties::Weight;
 use font_kit::source::SystemSource;
-use rustybuzz::{Feature, ttf_parser::Tag};
+use memmap2::Mmap;
+use ouroboros::self_referencing;
+use rustybuzz::ttf_parser::{self, name_id, Tag};
+use rustybuzz::{Face, Feature, Variation};
 use std::error::Error;
 use std::fmt::Display;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::color::Color;
+use crate::emoji::{has_emoji, is_emoji, EMOJI_FAMILIES};
+use crate::font_cache::FontMetadataCache;
+use crate::length::Length;
+use crate::validate::closest;
+use crate::shape::{monospace_advance, shape_with_reused_buffer, AsciiWidthTable, ShapeCache, ShapeCacheStats, ShapeKey, ShapedText, WordWidthCache};
+use crate::svg::{GlyphSnap, OutlineCache, DEFAULT_PATH_PRECISION, MAX_PATH_PRECISION};
 
 /// names of installed fonts
 pub fn fonts() -> Vec<String> {
+    let mut cache = FontMetadataCache::open();
+    if let Some(families) = cache.as_ref().and_then(|cache| cache.get_families()) {
+        return families.clone();
+    }
+
     let arr: Vec<String> = Vec::new();
     let sys_fonts = SystemSource::new();
     let _families = sys_fonts.all_families();
     match _families {
-        Ok(families) => families,
+        Ok(families) => {
+            if let Some(cache) = cache.as_mut() {
+                cache.set_families(families.clone());
+                let _ = cache.save();
+            }
+            families
+        }
         Err(_) => arr,
     }
 }
 
-#[derive(ValueEnum, Debug, PartialEq, Clone, Eq, Hash)]
+// Faces of a family by style, from the metadata cache when it is still valid
+fn family_faces(font_name: &str, debug: bool) -> Result<Vec<(FontStyle, Handle)>, FontError> {
+    let mut cache = FontMetadataCache::open();
+    if let Some(faces) = cache.as_ref().and_then(|cache| cache.get_family_faces(font_name)) {
+        if debug {
+            crate::log_debug!("font faces of {:?} loaded from the font cache", font_name);
+        }
+        return Ok(faces);
+    }
+
+    let faces = classify_family(font_name, debug)?;
+    if let Some(cache) = cache.as_mut() {
+        cache.set_family_faces(font_name, &faces);
+        if let Err(e) = cache.save() {
+            if debug {
+                crate::log_debug!("failed to write the font cache: {}", e);
+            }
+        }
+    }
+    Ok(faces)
+}
+
+/// Families of a CSS style font list, e.g. `Inter, "Noto Sans CJK SC", Noto Color Emoji`, in order.
+pub fn parse_font_list(fonts: &str) -> Vec<String> {
+    fonts
+        .split(',')
+        .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\'').trim().to_string())
+        .filter(|family| !family.is_empty())
+        .collect()
+}
+
+// Selects a system family and classifies each of its faces by style.
+// Later faces win when two map to the same style.
+fn classify_family(font_name: &str, debug: bool) -> Result<Vec<(FontStyle, Handle)>, FontError> {
+    let font_family = SystemSource::new().select_family_by_name(font_name)?;
+    let mut faces = Vec::new();
+
+    // Only classify faces here; each one is parsed for shaping on first use
+    for handle in font_family.fonts() {
+        let summary = FaceSummary::read(handle)?;
+        let weight = Weight(summary.weight.to_number() as f32);
+        let style = summary.style;
+
+        if debug {
+            crate::log_debug!("font name:\n {:?}", summary.full_name);
+            crate::log_debug!("font properties:\n weight: {:?}, style: {:?}", weight, style);
+        }
+
+        // Weight and slant are classified separately, so e.g. Bold Oblique
+        // is bold-italic rather than taking the place of Bold
+        let full_name = summary.full_name.to_lowercase();
+        let italic = style != ttf_parser::Style::Normal || full_name.contains("italic") || full_name.contains("oblique");
+        let weight = font_full_name_to_weight(full_name).unwrap_or_else(|| approximate_font_weight(weight));
+        faces.push((weight.with_italic(italic), handle.clone()));
+    }
+    Ok(faces)
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Clone, Eq, Hash, PartialOrd, Ord)]
 #[value(rename_all="lower")]
 pub enum FontStyle {
     // Weight
     Thin,
+    #[value(alias = "extra-light")]
     ExtraLight,
     Light,
     Regular,
     Medium,
+    #[value(alias = "semi-bold")]
     SemiBold,
     Bold,
+    #[value(alias = "extra-bold")]
     ExtraBold,
     Black,
     // Style
+    #[value(alias = "regular-italic")]
     Italic,
+    // Weight and style
+    #[value(name = "thin-italic")]
+    ThinItalic,
+    #[value(name = "extralight-italic", alias = "extra-light-italic")]
+    ExtraLightItalic,
+    #[value(name = "light-italic")]
+    LightItalic,
+    #[value(name = "medium-italic")]
+    MediumItalic,
+    #[value(name = "semibold-italic", alias = "semi-bold-italic")]
+    SemiBoldItalic,
+    #[value(name = "bold-italic")]
+    BoldItalic,
+    #[value(name = "extrabold-italic", alias = "extra-bold-italic")]
+    ExtraBoldItalic,
+    #[value(name = "black-italic")]
+    BlackItalic,
+}
+
+impl FontStyle {
+    pub fn is_italic(&self) -> bool {
+        matches!(
+            self,
+            FontStyle::Italic
+                | FontStyle::ThinItalic
+                | FontStyle::ExtraLightItalic
+                | FontStyle::LightItalic
+                | FontStyle::MediumItalic
+                | FontStyle::SemiBoldItalic
+                | FontStyle::BoldItalic
+                | FontStyle::ExtraBoldItalic
+                | FontStyle::BlackItalic
+        )
+    }
+
+    /// The style of the same weight, slanted or upright.
+    pub fn with_italic(&self, italic: bool) -> FontStyle {
+        const PAIRS: [(FontStyle, FontStyle); 9] = [
+            (FontStyle::Thin, FontStyle::ThinItalic),
+            (FontStyle::ExtraLight, FontStyle::ExtraLightItalic),
+            (FontStyle::Light, FontStyle::LightItalic),
+            (FontStyle::Regular, FontStyle::Italic),
+            (FontStyle::Medium, FontStyle::MediumItalic),
+            (FontStyle::SemiBold, FontStyle::SemiBoldItalic),
+            (FontStyle::Bold, FontStyle::BoldItalic),
+            (FontStyle::ExtraBold, FontStyle::ExtraBoldItalic),
+            (FontStyle::Black, FontStyle::BlackItalic),
+        ];
+        let (upright, slanted) = PAIRS
+            .into_iter()
+            .find(|(upright, slanted)| upright == self || slanted == self)
+            .expect("every style has a weight");
+        if italic { slanted } else { upright }
+    }
+
+    /// Name the style is given by on the command line, e.g. bold-italic.
+    pub fn cli_name(&self) -> String {
+        self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -55,10 +203,116 @@ impl Display for FontStyle {
             FontStyle::ExtraBold => write!(f, "extra_bold"),
             FontStyle::Black => write!(f, "black"),
             FontStyle::Italic => write!(f, "italic"),
+            FontStyle::ThinItalic => write!(f, "thin_italic"),
+            FontStyle::ExtraLightItalic => write!(f, "extra_light_italic"),
+            FontStyle::LightItalic => write!(f, "light_italic"),
+            FontStyle::MediumItalic => write!(f, "medium_italic"),
+            FontStyle::SemiBoldItalic => write!(f, "semi_bold_italic"),
+            FontStyle::BoldItalic => write!(f, "bold_italic"),
+            FontStyle::ExtraBoldItalic => write!(f, "extra_bold_italic"),
+            FontStyle::BlackItalic => write!(f, "black_italic"),
         }
     }
 }
 
+/// Registered OpenType feature tags with readable names; numbered stylistic
+/// sets (ss01-ss20) and character variants (cv01-cv99) are not listed.
+pub const FEATURE_NAMES: &[(&str, &str)] = &[
+    ("aalt", "Access all alternates"),
+    ("afrc", "Alternative fractions"),
+    ("c2sc", "Small capitals from capitals"),
+    ("calt", "Contextual alternates"),
+    ("case", "Case-sensitive forms"),
+    ("ccmp", "Glyph composition/decomposition"),
+    ("clig", "Contextual ligatures"),
+    ("cpsp", "Capital spacing"),
+    ("dlig", "Discretionary ligatures"),
+    ("dnom", "Denominators"),
+    ("frac", "Fractions"),
+    ("hist", "Historical forms"),
+    ("fina", "Terminal forms"),
+    ("hlig", "Historical ligatures"),
+    ("init", "Initial forms"),
+    ("isol", "Isolated forms"),
+    ("kern", "Kerning"),
+    ("liga", "Standard ligatures"),
+    ("lnum", "Lining figures"),
+    ("locl", "Localized forms"),
+    ("mark", "Mark positioning"),
+    ("medi", "Medial forms"),
+    ("mkmk", "Mark to mark positioning"),
+    ("numr", "Numerators"),
+    ("onum", "Oldstyle figures"),
+    ("ordn", "Ordinals"),
+    ("pnum", "Proportional figures"),
+    ("rlig", "Required ligatures"),
+    ("salt", "Stylistic alternates"),
+    ("sinf", "Scientific inferiors"),
+    ("smcp", "Small capitals"),
+    ("subs", "Subscript"),
+    ("sups", "Superscript"),
+    ("swsh", "Swash"),
+    ("titl", "Titling"),
+    ("tnum", "Tabular figures"),
+    ("zero", "Slashed zero"),
+];
+
+/// Readable name of a registered OpenType feature tag.
+pub fn feature_name(tag: &str) -> Option<String> {
+    if let Some((_, name)) = FEATURE_NAMES.iter().find(|(known, _)| *known == tag) {
+        return Some(name.to_string());
+    }
+    // Numbered stylistic sets and character variants
+    let number = tag.get(2..).and_then(|n| n.parse::<u8>().ok())?;
+    match &tag[..2] {
+        "ss" if (1..=20).contains(&number) => Some(format!("Stylistic set {}", number)),
+        "cv" if (1..=99).contains(&number) => Some(format!("Character variant {}", number)),
+        _ => None,
+    }
+}
+
+/// Parses a feature list such as "cv01=1,calt=0,liga" into (tag, value) pairs,
+/// a value of 0 disabling the feature and a missing value meaning 1.
+pub fn parse_feature_list(features_str: &str) -> Result<Vec<(String, u32)>, String> {
+    let mut features = Vec::new();
+    for feature_str in features_str.split(',').map(str::trim).filter(|feature| !feature.is_empty()) {
+        let (tag, value) = match feature_str.split_once('=') {
+            Some((tag, value)) => {
+                let tag = tag.trim();
+                let value = value.trim().parse::<u32>().map_err(|_| {
+                    format!("Invalid feature value '{}' for feature '{}': expected a whole number, 0 to disable", value.trim(), tag)
+                })?;
+                (tag, value)
+            }
+            None => (feature_str, 1),
+        };
+        // OpenType feature tags are exactly 4 printable ASCII characters
+        if tag.len() != 4 || !tag.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+            let known = FEATURE_NAMES.iter().map(|(known, _)| *known);
+            let hint = closest(tag, known).map_or_else(String::new, |known| format!(" (did you mean '{}'?)", known));
+            return Err(format!("Invalid feature tag '{}': feature tags are exactly 4 characters, e.g. \"liga\" or \"ss01\"{}", tag, hint));
+        }
+        features.push((tag.to_string(), value));
+    }
+    Ok(features)
+}
+
+// Features on unless turned off, as in browsers
+const DEFAULT_FEATURES: [&str; 4] = ["kern", "liga", "calt", "clig"];
+
+/// Tab stops are this many spaces apart by default.
+pub const DEFAULT_TAB_WIDTH: u32 = 4;
+
+/// How far a tab advances the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TabMode {
+    /// As wide as the tab width in spaces, wherever it is
+    Spaces,
+    /// To the next stop, every tab width in spaces from the start of the line
+    #[default]
+    Stops,
+}
+
 #[derive(Debug)]
 pub enum FontError {
     SelectionError(SelectionError),
@@ -102,22 +356,408 @@ impl From<std::io::Error> for FontError {
     }
 }
 
+/// Raw font bytes. Fonts on disk are memory-mapped rather than read,
+/// so selecting a 20-40 MB CJK font doesn't copy it into memory.
+pub enum FontData {
+    Mapped(Mmap),
+    Owned(Arc<Vec<u8>>),
+}
+
+impl FontData {
+    pub fn map<P: AsRef<Path>>(path: P) -> Result<Self, FontError> {
+        let file = File::open(path)?;
+        // SAFETY: font files are opened read-only and are not expected to be
+        // modified while we render; this is the usual trade-off for mmap'd fonts.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(FontData::Mapped(mmap))
+    }
+
+    /// Data and face index behind a font_kit handle, mapping it from disk when possible.
+    pub fn from_handle(handle: &Handle) -> Result<(Self, u32), FontError> {
+        match handle {
+            Handle::Path { path, font_index } => Ok((Self::map(path)?, *font_index)),
+            Handle::Memory { bytes, font_index } => Ok((FontData::Owned(bytes.clone()), *font_index)),
+        }
+    }
+}
+
+impl Deref for FontData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FontData::Mapped(mmap) => mmap,
+            FontData::Owned(data) => data,
+        }
+    }
+}
+
+/// A rustybuzz face together with the font data it borrows from,
+/// so it is parsed once instead of on every shaping call.
+#[self_referencing]
+pub struct ShapingFace {
+    data: FontData,
+    #[borrows(data)]
+    #[covariant]
+    face: Face<'this>,
+}
+
+impl ShapingFace {
+    pub fn from_data(data: FontData, font_index: u32) -> Result<Self, FontError> {
+        ShapingFaceTryBuilder {
+            data,
+            face_builder: |data: &FontData| {
+                Face::from_slice(data, font_index)
+                    .ok_or(FontError::FontLoadingError(FontLoadingError::Parse))
+            },
+        }
+        .try_build()
+    }
+
+    pub fn face(&self) -> &Face<'_> {
+        self.borrow_face()
+    }
+
+    pub fn set_variations(&mut self, variations: &[Variation]) {
+        self.with_face_mut(|face| face.set_variations(variations));
+    }
+}
+
+impl std::fmt::Debug for ShapingFace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.borrow_data() {
+            FontData::Mapped(_) => "mapped",
+            FontData::Owned(_) => "owned",
+        };
+        write!(f, "ShapingFace({} bytes, {})", self.borrow_data().len(), kind)
+    }
+}
+
+/// How the font size maps to a face's units.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeMode {
+    /// The size is the em square, as in browsers and design tools
+    #[default]
+    Em,
+    /// The size is the height from descent to ascent, as text2svg used to size text
+    CapToBox,
+}
+
+/// Which of a face's tables the ascent, descent and line gap come from. Fonts
+/// often disagree between them, and each platform reads its own.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetricsSource {
+    /// OS/2 typographic metrics when the font asks for them, else hhea, as browsers do
+    #[default]
+    Auto,
+    /// OS/2 typographic ascender, descender and line gap, as Figma and CSS `font-metrics` use
+    Typo,
+    /// The hhea table, as macOS uses
+    Hhea,
+    /// OS/2 usWinAscent and usWinDescent, as Windows uses; no line gap
+    Win,
+}
+
+/// Ascent, descent and line gap of one table, in font units.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct VerticalMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+impl VerticalMetrics {
+    fn new(ascent: i16, descent: i16, line_gap: i16) -> Option<Self> {
+        // A table of zeros is as good as missing
+        (ascent > 0 && ascent > descent).then_some(Self { ascent: ascent as f32, descent: descent as f32, line_gap: line_gap as f32 })
+    }
+}
+
+/// Face-wide metrics in font units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceMetrics {
+    pub units_per_em: u32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub cap_height: f32,
+    pub x_height: f32,
+    /// Each table's vertical metrics, when the face has usable ones
+    pub typo: Option<VerticalMetrics>,
+    pub hhea: Option<VerticalMetrics>,
+    pub win: Option<VerticalMetrics>,
+}
+
+impl FaceMetrics {
+    fn from_face(face: &ttf_parser::Face, name: &str) -> Self {
+        let underline = face.underline_metrics();
+        let bbox = face.global_bounding_box();
+        let (os2, hhea) = (face.tables().os2, face.tables().hhea);
+        let (metrics, problems) = Self {
+            units_per_em: face.units_per_em() as u32,
+            ascent: face.ascender() as f32,
+            descent: face.descender() as f32,
+            line_gap: face.line_gap() as f32,
+            underline_position: underline.map(|u| u.position as f32).unwrap_or(0.0),
+            underline_thickness: underline.map(|u| u.thickness as f32).unwrap_or(0.0),
+            cap_height: face.capital_height().unwrap_or(0) as f32,
+            x_height: face.x_height().unwrap_or(0) as f32,
+            typo: os2.and_then(|os2| VerticalMetrics::new(os2.typographic_ascender(), os2.typographic_descender(), os2.typographic_line_gap())),
+            hhea: VerticalMetrics::new(hhea.ascender, hhea.descender, hhea.line_gap),
+            win: os2.and_then(|os2| VerticalMetrics::new(os2.windows_ascender(), os2.windows_descender(), 0)),
+        }
+        .with_fallbacks(bbox.y_max as f32, bbox.y_min as f32);
+        if !problems.is_empty() {
+            crate::log_warn!("{} has {}; using estimates instead", name, problems.join(" and "));
+        }
+        metrics
+    }
+
+    /// Metrics with the ascent, descent and line gap of `source`. A face without
+    /// usable metrics in that table keeps its own.
+    pub fn with_source(mut self, source: MetricsSource) -> Self {
+        let table = match source {
+            MetricsSource::Auto => None,
+            MetricsSource::Typo => self.typo,
+            MetricsSource::Hhea => self.hhea,
+            MetricsSource::Win => self.win,
+        };
+        if let Some(table) = table {
+            (self.ascent, self.descent, self.line_gap) = (table.ascent, table.descent, table.line_gap);
+        }
+        self
+    }
+
+    /// Font units that make up the font size in `mode`.
+    pub fn size_units(&self, mode: SizeMode) -> f32 {
+        match mode {
+            SizeMode::Em => self.units_per_em.max(1) as f32,
+            SizeMode::CapToBox => (self.ascent - self.descent).max(1.0),
+        }
+    }
+
+    /// Metrics with sane values in place of a zero em size and missing or inverted
+    /// ascent and descent, which would otherwise scale glyphs by NaN or infinity.
+    /// The ascent and descent come from the glyph bounds `y_max` and `y_min` when
+    /// those are usable, else from the em size. Also returns what was replaced.
+    pub fn with_fallbacks(mut self, y_max: f32, y_min: f32) -> (Self, Vec<&'static str>) {
+        let mut problems = Vec::new();
+        if self.units_per_em == 0 {
+            self.units_per_em = 1000;
+            problems.push("no units per em");
+        }
+        let usable = |ascent: f32, descent: f32| ascent.is_finite() && descent.is_finite() && ascent > 0.0 && ascent > descent;
+        if !usable(self.ascent, self.descent) {
+            let em = self.units_per_em as f32;
+            (self.ascent, self.descent) = match usable(y_max, y_min.min(0.0)) {
+                true => (y_max, y_min.min(0.0)),
+                false => (em * 0.8, -em * 0.2),
+            };
+            problems.push("no usable ascent and descent");
+        }
+        (self, problems)
+    }
+}
+
+/// Everything the renderer needs from a loaded font face.
+/// Unlike font_kit's `Font` it is `Send + Sync`, so worker threads can share it.
+#[derive(Debug)]
+pub struct FontFace {
+    shaping_face: ShapingFace,
+    metrics: FaceMetrics,
+    full_name: String,
+}
+
+impl FontFace {
+    /// Loads the face behind a font_kit handle, mapping it from disk when possible.
+    pub fn load(handle: &Handle) -> Result<Self, FontError> {
+        let (data, font_index) = FontData::from_handle(handle)?;
+        Self::from_data(data, font_index)
+    }
+
+    pub fn from_data(data: FontData, font_index: u32) -> Result<Self, FontError> {
+        let shaping_face = ShapingFace::from_data(data, font_index)?;
+        let full_name = face_full_name(shaping_face.face().names());
+        let metrics = FaceMetrics::from_face(shaping_face.face(), &full_name);
+        Ok(Self {
+            shaping_face,
+            metrics,
+            full_name,
+        })
+    }
+
+    pub fn face(&self) -> &Face<'_> {
+        self.shaping_face.face()
+    }
+
+    /// Sets the coordinates of a variable font's axes used for shaping and outlines.
+    pub fn set_variations(&mut self, variations: &[Variation]) {
+        self.shaping_face.set_variations(variations);
+    }
+
+    pub fn metrics(&self) -> FaceMetrics {
+        self.metrics
+    }
+
+    pub fn full_name(&self) -> &str {
+        &self.full_name
+    }
+}
+
+// Full name from the name table, as font_kit reports it
+fn face_full_name(names: ttf_parser::name::Names) -> String {
+    names
+        .into_iter()
+        .filter(|name| name.name_id == name_id::FULL_NAME)
+        .find_map(|name| name.to_string())
+        .unwrap_or_default()
+}
+
+// Name, weight and style of a face, read from its name and OS/2 tables only
+struct FaceSummary {
+    full_name: String,
+    weight: ttf_parser::Weight,
+    style: ttf_parser::Style,
+}
+
+impl FaceSummary {
+    fn read(handle: &Handle) -> Result<Self, FontError> {
+        let (data, font_index) = FontData::from_handle(handle)?;
+        let raw_face = ttf_parser::RawFace::parse(&data, font_index)
+            .map_err(|_| FontError::FontLoadingError(FontLoadingError::Parse))?;
+        let full_name = raw_face
+            .table(Tag::from_bytes(b"name"))
+            .and_then(ttf_parser::name::Table::parse)
+            .map(|table| face_full_name(table.names))
+            .unwrap_or_default();
+        let os2 = raw_face
+            .table(Tag::from_bytes(b"OS/2"))
+            .and_then(ttf_parser::os2::Table::parse);
+        Ok(Self {
+            full_name,
+            weight: os2.map(|os2| os2.weight()).unwrap_or_default(),
+            style: os2.map(|os2| os2.style()).unwrap_or_default(),
+        })
+    }
+}
+
+/// A face of the family that is only parsed for shaping the first time it is used,
+/// so families with many weights don't pay for faces that are never rendered.
+#[derive(Debug)]
+pub struct LazyFontFace {
+    handle: Handle,
+    face: OnceLock<Option<FontFace>>,
+    variations: Vec<Variation>,
+}
+
+impl LazyFontFace {
+    pub fn new(handle: Handle) -> Self {
+        Self {
+            handle,
+            face: OnceLock::new(),
+            variations: Vec::new(),
+        }
+    }
+
+    /// Variations for the face, applied now if it is loaded or else once it is.
+    pub fn set_variations(&mut self, variations: &[Variation]) {
+        self.variations = variations.to_vec();
+        if let Some(Some(face)) = self.face.get_mut() {
+            face.set_variations(variations);
+        }
+    }
+
+    /// Loads the face on first call; a face that fails to load is reported once and stays missing.
+    pub fn get(&self) -> Option<&FontFace> {
+        self.face
+            .get_or_init(|| match FontFace::load(&self.handle) {
+                Ok(mut face) => {
+                    if !self.variations.is_empty() {
+                        face.set_variations(&self.variations);
+                    }
+                    Some(face)
+                }
+                Err(e) => {
+                    crate::log_warn!("failed to load font face {:?}: {}", self.handle, e);
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.face.get().is_some()
+    }
+}
+
+/// A family glyphs missing from the main font are taken from.
+#[derive(Debug)]
+pub struct FallbackFont {
+    name: String,
+    faces: Vec<(FontStyle, LazyFontFace)>,
+}
+
+impl FallbackFont {
+    pub fn new(font_name: &str, debug: bool) -> Result<Self, FontError> {
+        let faces = family_faces(font_name, debug)?
+            .into_iter()
+            .map(|(style, handle)| (style, LazyFontFace::new(handle)))
+            .collect();
+        Ok(Self { name: font_name.to_string(), faces })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The face for `style`, else the Regular one, else the first the family has.
+    pub fn get_face(&self, style: &FontStyle) -> Option<&FontFace> {
+        let find = |style: &FontStyle| self.faces.iter().find(|(face_style, _)| face_style == style);
+        find(style)
+            .or_else(|| find(&FontStyle::Regular))
+            .or_else(|| self.faces.first())
+            .and_then(|(_, face)| face.get())
+    }
+}
+
 #[derive(Debug)]
 pub struct FontConfig {
     font_name: String,
     size: u32,
     feature_map: HashMap<String,Feature>,
     features: Vec<Feature>,
-    faces: HashMap<FontStyle, Font>,
-    letter_space: f32,
-    fill_color: String,
-    color: String,
+    faces: HashMap<FontStyle, LazyFontFace>,
+    letter_space: Length,
+    fill_color: Color,
+    color: Color,
     debug: bool,
+    shape_cache: Mutex<ShapeCache>,
+    outline_cache: OutlineCache,
+    word_width_cache: WordWidthCache,
+    ascii_width_tables: Mutex<HashMap<FontStyle, Option<Arc<AsciiWidthTable>>>>,
+    monospace_advances: Mutex<HashMap<FontStyle, Option<u16>>>,
+    variations: Vec<Variation>,
+    path_precision: u8,
+    quantize: Option<f32>,
+    hinting: Option<f32>,
+    glyph_snap: GlyphSnap,
+    annotate_shaping: bool,
+    fast_measure: bool,
+    tab_width: u32,
+    tab_mode: TabMode,
+    fallbacks: Vec<FallbackFont>,
+    // Emoji come from this font first, found among EMOJI_FAMILIES on first use unless given
+    emoji_font: OnceLock<Option<FallbackFont>>,
+    auto_emoji_font: bool,
+    verbose: bool,
+    size_mode: SizeMode,
+    metrics_source: MetricsSource,
 }
 
-// Get font style from keywords in its full name
+// Get font weight from keywords in its lowercase full name
 fn font_full_name_to_weight(name: String) -> Option<FontStyle> {
-    let name = name.to_lowercase();
     // Search longer patterns first
     if name.contains("extralight") {
         return Some(FontStyle::ExtraLight);
@@ -172,25 +812,61 @@ fn approximate_font_weight(weight: Weight) -> FontStyle {
     FontStyle::Black
 }
 
+// An empty font of size 0 with the default features off; constructors fill in the rest
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            font_name: String::new(),
+            size: 0,
+            feature_map: HashMap::new(),
+            features: Vec::new(),
+            faces: HashMap::new(),
+            letter_space: Length::default(),
+            fill_color: Color::BLACK,
+            color: Color::BLACK,
+            debug: false,
+            shape_cache: Mutex::new(ShapeCache::default()),
+            outline_cache: OutlineCache::default(),
+            word_width_cache: WordWidthCache::default(),
+            ascii_width_tables: Mutex::new(HashMap::new()),
+            monospace_advances: Mutex::new(HashMap::new()),
+            variations: Vec::new(),
+            path_precision: DEFAULT_PATH_PRECISION,
+            quantize: None,
+            hinting: None,
+            glyph_snap: GlyphSnap::None,
+            annotate_shaping: false,
+            fast_measure: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            tab_mode: TabMode::default(),
+            fallbacks: Vec::new(),
+            emoji_font: OnceLock::new(),
+            auto_emoji_font: true,
+            verbose: false,
+            size_mode: SizeMode::default(),
+            metrics_source: MetricsSource::default(),
+        }
+    }
+}
+
 impl FontConfig {
     pub fn from_file(
         font_path: &str,
         size: u32,
-        fill_color: String,
-        color: String,
+        fill_color: Color,
+        color: Color,
         debug: bool,
     ) -> Result<Self, FontError> {
-        use std::collections::HashMap;
-        use font_kit::font::Font;
-        use rustybuzz::Feature;
-        use std::str::FromStr;
-
-        // Read font data from file
-        let data = std::fs::read(font_path)?; // Will return FontError::IoError on failure
-        let font = Font::from_bytes(data.into(), 0).map_err(FontError::FontLoadingError)?;
+        // Map font data from file
+        let data = FontData::map(font_path)?; // Will return FontError::IoError on failure
+        let face = FontFace::from_data(data, 0)?;
 
         let mut faces = HashMap::new();
-        faces.insert(FontStyle::Regular, font);
+        faces.insert(FontStyle::Regular, LazyFontFace {
+            handle: Handle::from_path(font_path.into(), 0),
+            face: OnceLock::from(Some(face)),
+            variations: Vec::new(),
+        });
 
         let mut feature_map = HashMap::new();
         feature_map.insert("kern".to_owned(), Feature::from_str("kern").unwrap());
@@ -207,47 +883,41 @@ impl FontConfig {
             fill_color,
             color,
             faces,
-            letter_space: 0.0,
             debug,
+            ..Default::default()
         })
     }
 
     pub fn new(
         font_name: String,
         size: u32,
-        fill_color: String,
-        color: String,
+        fill_color: Color,
+        color: Color,
         debug: bool,
     ) -> Result<Self, FontError> {
-        let font_family = SystemSource::new().select_family_by_name(&font_name)?;
+        // A list is a CSS style font-family: the first family installed is the
+        // font, and the ones after it fill in the characters it lacks
+        let families = parse_font_list(&font_name);
+        if families.len() > 1 {
+            return Self::from_font_list(&families, size, fill_color, color, debug);
+        }
+        let font_name = families.into_iter().next().unwrap_or(font_name);
 
         let mut faces = HashMap::new();
-
-        for handle in font_family.fonts() {
-            let font = handle.load()?;
-            let properties = font.properties();
-
-            if debug {
-                println!("font name:\n {:?}", font.full_name());
-                println!("font properties:\n {:?}", properties);
-            }
-
-            if let Some(style) = font_full_name_to_weight(font.full_name()) {
-                faces.insert(style, font);
-                continue;
-            }
-
-            match properties.style {
-                Style::Normal => {
-                    let weight = approximate_font_weight(properties.weight);
-                    faces.insert(weight, font);
-                },
-                Style::Italic => {
-                    faces.insert(FontStyle::Italic, font);
-                }
-                _ => {
-                    eprintln!("Unsupported font style\n {:?}", properties);
-                },
+        for (style, handle) in family_faces(&font_name, debug)? {
+            faces.insert(style, LazyFontFace::new(handle));
+        }
+        // Everything falls back to Regular, so a family without it (e.g. only Bold)
+        // has its closest face, upright first, stand in
+        if !faces.contains_key(&FontStyle::Regular) {
+            let closest = faces
+                .keys()
+                .min_by_key(|style| (style.is_italic(), (style.with_italic(false) as i32 - FontStyle::Regular as i32).abs(), (*style).clone()))
+                .cloned();
+            if let Some(style) = closest {
+                crate::log_warn!("{} has no Regular face; its {} face is used in its place", font_name, style.cli_name());
+                let handle = faces[&style].handle.clone();
+                faces.insert(FontStyle::Regular, LazyFontFace::new(handle));
             }
         }
         let mut feature_map = HashMap::new();
@@ -258,7 +928,7 @@ impl FontConfig {
         let features = feature_map.values().cloned().collect();
 
         if debug {
-            println!("faces:\n {:?}", faces);
+            crate::log_debug!("faces:\n {:?}", faces);
         }
 
         // now only supports horizontal writing mode default features
@@ -270,46 +940,46 @@ impl FontConfig {
             fill_color,
             color,
             faces,
-            letter_space:0.0,
             debug,
+            ..Default::default()
         })
     }
 
+    // The first family of `families` that is installed, with the later ones as fallbacks.
+    // Families that aren't installed are skipped with a warning.
+    fn from_font_list(families: &[String], size: u32, fill_color: Color, color: Color, debug: bool) -> Result<Self, FontError> {
+        let mut first_error = None;
+        for (i, family) in families.iter().enumerate() {
+            match Self::new(family.clone(), size, fill_color, color, debug) {
+                Ok(mut font_config) => {
+                    for fallback in &families[i + 1..] {
+                        if let Err(e) = font_config.add_fallback(fallback) {
+                            crate::log_warn!("skipping fallback font '{}': {}", fallback, e);
+                        }
+                    }
+                    return Ok(font_config);
+                }
+                Err(e) => {
+                    crate::log_warn!("font '{}' not found, trying the next one in the list", family);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.expect("font list has at least two families"))
+    }
+
     /// Parse and set font features from a string like "cv01=1,calt=0,liga=1"
     /// This will override existing features for the same tags, but keeps defaults for unspecified features
     pub fn set_features_from_string(&mut self, features_str: &str) -> Result<(), String> {
         // Don't clear existing features - we want to override/add to defaults
         
-        // Parse the features string
-        for feature_str in features_str.split(',') {
-            let feature_str = feature_str.trim();
-            if feature_str.is_empty() {
-                continue;
-            }
-
-            // Parse "feature=value" or just "feature" (defaults to 1)
-            let (tag, value) = if let Some(eq_pos) = feature_str.find('=') {
-                let tag = &feature_str[..eq_pos].trim();
-                let value_str = &feature_str[eq_pos + 1..].trim();
-                let value = value_str.parse::<u32>()
-                    .map_err(|_| format!("Invalid feature value '{}' for feature '{}'", value_str, tag))?;
-                (tag.to_string(), value)
-            } else {
-                // Default value is 1 if not specified
-                (feature_str.to_string(), 1)
-            };
-
-            // Validate tag length (OpenType feature tags are exactly 4 characters)
-            if tag.len() != 4 {
-                return Err(format!("Invalid feature tag '{}': feature tags must be exactly 4 characters", tag));
-            }
-
+        for (tag, value) in parse_feature_list(features_str)? {
             // Handle feature enable/disable
             if value == 0 {
                 // Remove feature when value is 0 (disable)
                 self.feature_map.remove(&tag);
                 if self.debug {
-                    println!("Disabled font feature: {}", tag);
+                    crate::log_debug!("Disabled font feature: {}", tag);
                 }
             } else {
                 // Add/enable feature when value > 0
@@ -326,21 +996,61 @@ impl FontConfig {
                 );
                 self.feature_map.insert(tag.clone(), feature);
                 if self.debug {
-                    println!("Enabled font feature: {}={}", tag, value);
+                    crate::log_debug!("Enabled font feature: {}={}", tag, value);
                 }
             }
         }
 
-        // Update the features vector
+        // Update the features vector; measured widths depend on it
         self.features = self.feature_map.values().cloned().collect();
+        self.word_width_cache.clear();
+        self.ascii_width_tables.lock().unwrap().clear();
         
         if self.debug {
-            println!("Set font features: {:?}", self.feature_map.keys().collect::<Vec<_>>());
+            crate::log_debug!("Set font features: {:?}", self.feature_map.keys().collect::<Vec<_>>());
         }
 
         Ok(())
     }
 
+    /// OpenType feature tags the face for `style` implements in its GSUB or
+    /// GPOS tables, sorted. A legacy kern table counts as "kern".
+    pub fn font_feature_tags(&self, style: &FontStyle) -> Vec<String> {
+        let Some(face) = self.get_shaping_face_by_style(style) else {
+            return Vec::new();
+        };
+        let tables = face.tables();
+        let mut tags: Vec<String> = [tables.gsub, tables.gpos]
+            .into_iter()
+            .flatten()
+            .flat_map(|table| table.features.into_iter().map(|feature| feature.tag.to_string()))
+            // FontForge marks required lookups with a " RQD" feature nobody can request
+            .filter(|tag| tag != " RQD")
+            .collect();
+        if tables.kern.is_some() {
+            tags.push("kern".to_string());
+        }
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Tags enabled by `features_str` (as for `set_features_from_string`) that the
+    /// face for `style` doesn't implement, so requesting them has no effect.
+    pub fn unsupported_features(&self, features_str: &str, style: &FontStyle) -> Vec<String> {
+        let implemented = self.font_feature_tags(style);
+        parse_feature_list(features_str)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(tag, value)| *value != 0 && !implemented.contains(tag))
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+
+    pub fn is_feature_enabled(&self, tag: &str) -> bool {
+        self.feature_map.contains_key(tag)
+    }
+
     /// Get a summary of currently active features
     pub fn get_features_summary(&self) -> String {
         if self.feature_map.is_empty() {
@@ -358,16 +1068,451 @@ impl FontConfig {
         &self.features
     }
 
-    pub fn get_font_by_style(&self, style: &FontStyle) -> Option<&Font> {
-        self.faces.get(style)
+    /// CSS `font-feature-settings` value turning the features on and off as
+    /// they are here, e.g. `'liga' 0, 'ss01' 1`. None with the default features.
+    pub fn css_feature_settings(&self) -> Option<String> {
+        let mut settings: Vec<(&str, u32)> = DEFAULT_FEATURES
+            .into_iter()
+            .filter(|tag| !self.feature_map.contains_key(*tag))
+            .map(|tag| (tag, 0))
+            .chain(
+                self.feature_map
+                    .iter()
+                    .filter(|(tag, feature)| !DEFAULT_FEATURES.contains(&tag.as_str()) || feature.value != 1)
+                    .map(|(tag, feature)| (tag.as_str(), feature.value)),
+            )
+            .collect();
+        settings.sort();
+        let settings: Vec<String> = settings.into_iter().map(|(tag, value)| format!("'{}' {}", tag, value)).collect();
+        (!settings.is_empty()).then(|| settings.join(", "))
+    }
+
+    // Features in a stable order, used as part of the shape cache key
+    fn features_key(&self) -> String {
+        let mut tags: Vec<String> = self.feature_map
+            .iter()
+            .map(|(tag, feature)| format!("{}={}", tag, feature.value))
+            .collect();
+        tags.sort();
+        tags.join(",")
+    }
+
+    /// Shape text with the face of the given style, falling back to Regular.
+    /// Results are cached, so shaping the same run again is a lookup.
+    pub fn shape(&self, text: &str, font_style: &FontStyle) -> Option<Arc<ShapedText>> {
+        let key = ShapeKey::new(
+            text,
+            &self.font_name,
+            font_style,
+            self.size,
+            self.features_key(),
+            self.letter_space.key(),
+        );
+        if let Some(shaped) = self.shape_cache.lock().unwrap().get(&key) {
+            return Some(shaped);
+        }
+
+        // Attempt to get the specific style, fall back to regular if not found
+        let hb_face = self.get_shaping_face_by_style(font_style)
+            .or_else(|| {
+                if self.debug && *font_style != FontStyle::Regular {
+                    crate::log_warn!("Font style {:?} not found, falling back to Regular.", font_style);
+                }
+                self.get_shaping_face_by_style(&FontStyle::Regular)
+            });
+        let Some(hb_face) = hb_face else {
+            crate::log_warn!("Font '{}' has no regular style to fall back to", self.font_name);
+            return None;
+        };
+
+        let mut shaped = shape_with_reused_buffer(hb_face, &self.features, text, |glyph_buffer| {
+            if self.debug {
+                let format_flags = rustybuzz::SerializeFlags::default();
+                crate::log_debug!("rustybuzz shape output:\n {:?}", glyph_buffer.serialize(hb_face, format_flags));
+            }
+            ShapedText::from_glyph_buffer(glyph_buffer)
+        });
+        // Characters the font has no glyph for are taken from the fallbacks, and emoji from the emoji font
+        let missing = shaped.glyph_infos().iter().any(|info| info.glyph_id == 0);
+        if (!self.fallbacks.is_empty() && missing) || (has_emoji(text) && self.get_emoji_font().is_some()) {
+            shaped = self.shape_with_fallbacks(text, font_style);
+        }
+        if text.contains('\t') {
+            self.expand_tabs(&mut shaped, text, hb_face);
+        }
+        let shaped = Arc::new(shaped);
+        self.shape_cache.lock().unwrap().insert(key, shaped.clone());
+        Some(shaped)
+    }
+
+    // Shapes each run of characters with the first font that has them, positions
+    // scaled to this font's units. Emoji try the emoji font before the others.
+    // Spaces stay with the run before them, and tabs and control characters go
+    // to this font, where tabs are laid out.
+    fn shape_with_fallbacks(&self, text: &str, font_style: &FontStyle) -> ShapedText {
+        let faces: Vec<(usize, &FontFace)> = (0..=self.emoji_font_index())
+            .filter_map(|index| Some((index, self.get_face_by_index(index, font_style)?)))
+            .collect();
+        let emoji_face = faces.last().filter(|(index, _)| *index == self.emoji_font_index());
+        let font_for = |c: char, emoji: bool| {
+            emoji_face
+                .filter(|_| emoji)
+                .into_iter()
+                .chain(&faces)
+                .find(|(_, face)| face.face().glyph_index(c).is_some())
+                .map_or(0, |&(index, _)| index)
+        };
+
+        // (start, end, font index) of each run; a grapheme cluster goes to the font
+        // of its first character, so its marks and joiners are shaped with it
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+        for (i, cluster) in text.grapheme_indices(true) {
+            let mut chars = cluster.chars();
+            let Some(c) = chars.next() else { continue };
+            let previous = runs.last().map_or(0, |run| run.2);
+            let index = if c.is_control() {
+                0
+            } else if c.is_whitespace() || cluster.width() == 0 {
+                previous
+            } else {
+                font_for(c, is_emoji(c, chars.next()))
+            };
+            match runs.last_mut() {
+                Some(run) if run.2 == index => run.1 = i + cluster.len(),
+                _ => runs.push((i, i + cluster.len(), index)),
+            }
+        }
+
+        let height = |face: &FontFace| self.face_metrics(face).size_units(self.size_mode);
+        let main_height = faces.first().map_or(1.0, |&(_, face)| height(face));
+        let mut shaped = ShapedText::default();
+        for (start, end, index) in runs {
+            let Some(&(_, face)) = faces.iter().find(|(face_index, _)| *face_index == index) else {
+                continue;
+            };
+            shape_with_reused_buffer(face.face(), &self.features, &text[start..end], |glyph_buffer| {
+                shaped.push_run(glyph_buffer, start as u32, main_height / height(face), index);
+            });
+        }
+        shaped
+    }
+
+    // Gives tabs the glyph of a space, so they draw nothing, and the advance
+    // of the tab mode. Stops are counted from the start of the run.
+    fn expand_tabs(&self, shaped: &mut ShapedText, text: &str, face: &Face) {
+        let Some(space) = face.glyph_index(' ') else {
+            return;
+        };
+        let space_advance = face.glyph_hor_advance(space).unwrap_or(0) as f32;
+        // Letter spacing between clusters moves the text along, so it counts towards the stops
+        let size_units = match self.size_mode {
+            SizeMode::Em => face.units_per_em().max(1) as f32,
+            SizeMode::CapToBox => (face.ascender() as f32 - face.descender() as f32).max(1.0),
+        };
+        let size = self.size.max(1) as f32;
+        let letter_space = self.letter_space.to_pixels(size) * size_units / size;
+        let mut pen = 0.0;
+        let mut last_cluster = None;
+        for (info, position) in shaped.glyphs_mut() {
+            if last_cluster.is_some_and(|cluster| cluster != info.cluster) {
+                pen += letter_space;
+            }
+            last_cluster = Some(info.cluster);
+            if text[info.cluster as usize..].starts_with('\t') {
+                info.glyph_id = space.0 as u32;
+                position.x_offset = 0;
+                position.x_advance = self.tab_advance(pen, space_advance).round() as i32;
+            }
+            pen += position.x_advance as f32;
+        }
+    }
+
+    /// Advance in font units of a tab with the pen `pen` font units into the
+    /// line, for a face whose space is `space_advance` wide.
+    pub fn tab_advance(&self, pen: f32, space_advance: f32) -> f32 {
+        let tab = space_advance * self.tab_width as f32;
+        match self.tab_mode {
+            TabMode::Stops if tab > 0.0 => ((pen / tab).floor() + 1.0) * tab - pen,
+            _ => tab,
+        }
+    }
+
+    /// Shaped width of printable ASCII text in font units, computed from cached
+    /// advances and pair kerning without shaping the whole run.
+    /// None if the text isn't printable ASCII or the face may substitute one of its glyphs.
+    pub fn ascii_width(&self, text: &str, font_style: &FontStyle) -> Option<i32> {
+        let face = self.get_shaping_face_by_style(font_style)
+            .or_else(|| self.get_shaping_face_by_style(&FontStyle::Regular))?;
+        let table = self.ascii_width_tables
+            .lock()
+            .unwrap()
+            .entry(font_style.clone())
+            .or_insert_with(|| AsciiWidthTable::new(face, &self.features).map(Arc::new))
+            .clone()?;
+        table.width(text, face, &self.features)
+    }
+
+    /// Advance shared by every printable ASCII glyph of a monospace face, in font units.
+    /// None for proportional faces.
+    pub fn monospace_advance(&self, font_style: &FontStyle) -> Option<u16> {
+        let face = self.get_shaping_face_by_style(font_style)
+            .or_else(|| self.get_shaping_face_by_style(&FontStyle::Regular))?;
+        *self.monospace_advances
+            .lock()
+            .unwrap()
+            .entry(font_style.clone())
+            .or_insert_with(|| monospace_advance(face))
+    }
+
+    /// Sets the axes of a variable font, e.g. wght=700, on every style.
+    /// Cached shapes and outlines are dropped since they depend on the axes.
+    pub fn set_variations(&mut self, variations: Vec<Variation>) -> &mut Self {
+        for face in self.faces.values_mut() {
+            face.set_variations(&variations);
+        }
+        self.variations = variations;
+        self.clear_caches();
+        self.ascii_width_tables.lock().unwrap().clear();
+        self.monospace_advances.lock().unwrap().clear();
+        self
+    }
+
+    pub fn get_variations(&self) -> &[Variation] {
+        &self.variations
+    }
+
+    /// Suffix telling glyph definitions of different variations apart, e.g. "-wght700".
+    pub fn variations_id_suffix(&self) -> String {
+        self.variations
+            .iter()
+            .map(|variation| format!("-{}{}", variation.tag, variation.value).replace(['.', ' '], "_"))
+            .collect()
+    }
+
+    /// Whether the Regular face is a variable font with the given axis.
+    pub fn has_variation_axis(&self, tag: Tag) -> bool {
+        self.get_shaping_face_by_style(&FontStyle::Regular)
+            .is_some_and(|face| face.variation_axes().into_iter().any(|axis| axis.tag == tag))
+    }
+
+    /// Drops all cached shaping results and glyph outlines.
+    pub fn clear_caches(&self) {
+        self.shape_cache.lock().unwrap().clear();
+        self.outline_cache.clear();
+        self.word_width_cache.clear();
+    }
+
+    pub fn get_shape_cache_stats(&self) -> ShapeCacheStats {
+        self.shape_cache.lock().unwrap().stats()
+    }
+
+    pub fn get_outline_cache(&self) -> &OutlineCache {
+        &self.outline_cache
+    }
+
+    pub fn get_word_width_cache(&self) -> &WordWidthCache {
+        &self.word_width_cache
+    }
+
+    /// The face for a style, loading it the first time the style is requested.
+    pub fn get_font_by_style(&self, style: &FontStyle) -> Option<&FontFace> {
+        self.faces.get(style).and_then(|face| face.get())
+    }
+
+    /// The pre-parsed rustybuzz face for a style, shared by shaping and outlining.
+    pub fn get_shaping_face_by_style(&self, style: &FontStyle) -> Option<&Face<'_>> {
+        self.get_font_by_style(style).map(|face| face.face())
+    }
+
+    /// Adds a family to take the glyphs this font and the fallbacks added
+    /// before it lack from. Cached shapes and widths are dropped.
+    pub fn add_fallback(&mut self, font_name: &str) -> Result<&mut Self, FontError> {
+        self.fallbacks.push(FallbackFont::new(font_name, self.debug)?);
+        self.clear_caches();
+        Ok(self)
+    }
+
+    pub fn get_fallbacks(&self) -> &[FallbackFont] {
+        &self.fallbacks
+    }
+
+    /// Takes emoji from `font_name` before this font and its fallbacks.
+    pub fn set_emoji_font(&mut self, font_name: &str) -> Result<&mut Self, FontError> {
+        self.emoji_font = OnceLock::from(Some(FallbackFont::new(font_name, self.debug)?));
+        self.clear_caches();
+        Ok(self)
+    }
+
+    /// Whether emoji are taken from the first installed of the usual emoji
+    /// fonts, replacing any emoji font set before. On by default.
+    pub fn set_auto_emoji_font(&mut self, auto_emoji_font: bool) -> &mut Self {
+        self.auto_emoji_font = auto_emoji_font;
+        self.emoji_font = OnceLock::new();
+        self.clear_caches();
+        self
+    }
+
+    /// The font emoji are taken from, looked up among the usual emoji fonts the
+    /// first time it is needed unless one was set.
+    pub fn get_emoji_font(&self) -> Option<&FallbackFont> {
+        self.emoji_font
+            .get_or_init(|| {
+                let font = self.auto_emoji_font.then(|| EMOJI_FAMILIES.iter().find_map(|family| FallbackFont::new(family, self.debug).ok())).flatten();
+                if let Some(font) = &font {
+                    crate::log_verbose!("Taking emoji from {}", font.get_name());
+                }
+                font
+            })
+            .as_ref()
+    }
+
+    // Face index of glyphs from the emoji font, after the fallbacks
+    fn emoji_font_index(&self) -> usize {
+        self.fallbacks.len() + 1
+    }
+
+    /// The font's name followed by its fallbacks', comma separated.
+    pub fn get_font_list(&self) -> String {
+        std::iter::once(self.font_name.as_str())
+            .chain(self.fallbacks.iter().map(FallbackFont::get_name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The face glyphs of shaped text with face index `index` come from for
+    /// `style`: this font's for 0, else the fallback's at `index - 1`, and the
+    /// emoji font's for the index after the fallbacks.
+    pub fn get_face_by_index(&self, index: usize, style: &FontStyle) -> Option<&FontFace> {
+        match index {
+            0 => self.get_font_by_style(style).or_else(|| self.get_font_by_style(&FontStyle::Regular)),
+            index if index == self.emoji_font_index() => self.get_emoji_font()?.get_face(style),
+            _ => self.fallbacks.get(index - 1)?.get_face(style),
+        }
+    }
+
+    /// Characters of `text` neither the face for `style`, a fallback nor the emoji font has a glyph
+    /// for, each once in order of appearance. Whitespace and control characters are not checked.
+    pub fn missing_chars(&self, text: &str, style: &FontStyle) -> Vec<char> {
+        let face = self.get_shaping_face_by_style(style);
+        let emoji_font = has_emoji(text).then(|| self.get_emoji_font()).flatten();
+        let fallbacks: Vec<&FontFace> = self.fallbacks.iter().chain(emoji_font).filter_map(|fallback| fallback.get_face(style)).collect();
+        let mut missing = Vec::new();
+        for c in text.chars().filter(|c| !c.is_whitespace() && !c.is_control()) {
+            let found = face.and_then(|face| face.glyph_index(c)).is_some()
+                || fallbacks.iter().any(|fallback| fallback.face().glyph_index(c).is_some());
+            if !found && !missing.contains(&c) {
+                missing.push(c);
+            }
+        }
+        missing
+    }
+
+    /// Styles the family provides, whether or not they have been loaded yet, lightest first.
+    pub fn get_available_styles(&self) -> Vec<FontStyle> {
+        let mut styles: Vec<FontStyle> = self.faces.keys().cloned().collect();
+        styles.sort();
+        styles
+    }
+
+    /// Styles whose faces have been loaded so far.
+    pub fn get_loaded_styles(&self) -> Vec<FontStyle> {
+        self.faces
+            .iter()
+            .filter(|(_, face)| face.is_loaded())
+            .map(|(style, _)| style.clone())
+            .collect()
+    }
+
+    pub fn set_letter_space(&mut self, space: impl Into<Length>) -> &mut Self {
+        self.letter_space = space.into();
+        // Measured widths include letter spacing
+        self.word_width_cache.clear();
+        self
+    }
+
+    /// Decimal places kept for path coordinates, capped at `MAX_PATH_PRECISION`.
+    pub fn set_path_precision(&mut self, precision: u8) -> &mut Self {
+        self.path_precision = precision.min(MAX_PATH_PRECISION);
+        self
+    }
+
+    pub fn get_path_precision(&self) -> u8 {
+        self.path_precision
+    }
+
+    /// Snap outline and glyph position coordinates to multiples of `grid` pixels.
+    /// None or a non-positive grid keeps coordinates as is.
+    pub fn set_quantize(&mut self, grid: Option<f32>) -> &mut Self {
+        self.quantize = grid.filter(|&grid| grid > 0.0);
+        self
+    }
+
+    pub fn get_quantize(&self) -> Option<f32> {
+        self.quantize
+    }
+
+    /// Snap baselines, glyph positions and the straight and extreme edges of outlines
+    /// to whole pixels of `pixel` units, so small raster output is crisp.
+    /// None or a non-positive pixel leaves them where the font puts them.
+    pub fn set_hinting(&mut self, pixel: Option<f32>) -> &mut Self {
+        self.hinting = pixel.filter(|&pixel| pixel > 0.0);
+        self
+    }
+
+    pub fn get_hinting(&self) -> Option<f32> {
+        self.hinting
+    }
+
+    /// Round glyph x positions to half or whole pixels rather than keeping fractional advances.
+    pub fn set_glyph_snap(&mut self, glyph_snap: GlyphSnap) -> &mut Self {
+        self.glyph_snap = glyph_snap;
+        self
+    }
+
+    pub fn get_glyph_snap(&self) -> GlyphSnap {
+        self.glyph_snap
     }
 
-    pub fn set_letter_space(&mut self, space: f32) -> &mut Self {
-        self.letter_space = space;
+    /// Draw cluster boundaries and the kerning applied between glyphs over the text.
+    pub fn set_annotate_shaping(&mut self, annotate_shaping: bool) -> &mut Self {
+        self.annotate_shaping = annotate_shaping;
         self
     }
 
-    pub fn get_letter_space(&self) -> f32 {
+    pub fn get_annotate_shaping(&self) -> bool {
+        self.annotate_shaping
+    }
+
+    /// Measure text for wrapping by summing nominal glyph advances instead of shaping.
+    /// Ignores kerning and ligatures; rendered lines are still shaped exactly.
+    pub fn set_fast_measure(&mut self, fast_measure: bool) -> &mut Self {
+        self.fast_measure = fast_measure;
+        self.word_width_cache.clear();
+        self
+    }
+
+    pub fn get_fast_measure(&self) -> bool {
+        self.fast_measure
+    }
+
+    /// Width of a tab in spaces and how it advances the text.
+    /// Cached shapes and widths are dropped since tabs are laid out while shaping.
+    pub fn set_tabs(&mut self, tab_width: u32, tab_mode: TabMode) -> &mut Self {
+        self.tab_width = tab_width;
+        self.tab_mode = tab_mode;
+        self.clear_caches();
+        self
+    }
+
+    pub fn get_tab_width(&self) -> u32 {
+        self.tab_width
+    }
+
+    pub fn get_tab_mode(&self) -> TabMode {
+        self.tab_mode
+    }
+
+    pub fn get_letter_space(&self) -> Length {
         self.letter_space
     }
 
@@ -375,11 +1520,21 @@ impl FontConfig {
         &self.font_name
     }
 
-    pub fn get_color(&self) -> &String {
+    pub fn set_color(&mut self, color: Color) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    pub fn get_color(&self) -> &Color {
         &self.color
     }
 
-    pub fn get_fill_color(&self) -> &String {
+    pub fn set_fill_color(&mut self, fill_color: Color) -> &mut Self {
+        self.fill_color = fill_color;
+        self
+    }
+
+    pub fn get_fill_color(&self) -> &Color {
         &self.fill_color
     }
 
@@ -387,9 +1542,64 @@ impl FontConfig {
         self.size
     }
 
+    /// Changes the font size; outlines are cached per size, measured widths are dropped.
+    pub fn set_size(&mut self, size: u32) -> &mut Self {
+        if size != self.size {
+            self.size = size;
+            self.word_width_cache.clear();
+        }
+        self
+    }
+
+    /// How the size maps to font units. Cached widths are dropped.
+    pub fn set_size_mode(&mut self, size_mode: SizeMode) -> &mut Self {
+        if size_mode != self.size_mode {
+            self.size_mode = size_mode;
+            self.clear_caches();
+        }
+        self
+    }
+
+    pub fn get_size_mode(&self) -> SizeMode {
+        self.size_mode
+    }
+
+    /// Which table vertical metrics are read from. Cached widths are dropped.
+    pub fn set_metrics_source(&mut self, metrics_source: MetricsSource) -> &mut Self {
+        if metrics_source != self.metrics_source {
+            self.metrics_source = metrics_source;
+            self.clear_caches();
+        }
+        self
+    }
+
+    pub fn get_metrics_source(&self) -> MetricsSource {
+        self.metrics_source
+    }
+
+    /// Metrics of `face` with the vertical metrics of the chosen table.
+    pub fn face_metrics(&self, face: &FontFace) -> FaceMetrics {
+        face.metrics().with_source(self.metrics_source)
+    }
+
+    /// Pixels per font unit of a face with `metrics` at this size.
+    pub fn pixel_scale(&self, metrics: FaceMetrics) -> f32 {
+        self.size as f32 / metrics.size_units(self.size_mode)
+    }
+
     pub fn get_debug(&self) -> bool {
         self.debug
     }
+
+    /// Report output statistics such as the written file size.
+    pub fn set_verbose(&mut self, verbose: bool) -> &mut Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn get_verbose(&self) -> bool {
+        self.verbose
+    }
 }
 
 #[cfg(test)]
@@ -402,8 +1612,8 @@ mod test_font_features {
         FontConfig::new(
             "NonExistentTestFont".to_string(),
             16,
-            "#000".to_string(),
-            "#000".to_string(),
+            Color::BLACK,
+            Color::BLACK,
             false // debug off for cleaner tests
         ).unwrap_or_else(|_| {
             // Create a mock config by directly constructing the struct for testing
@@ -423,11 +1633,10 @@ mod test_font_features {
                 size: 16,
                 feature_map,
                 features,
-                fill_color: "#000".to_string(),
-                color: "#000".to_string(),
+                fill_color: Color::BLACK,
+                color: Color::BLACK,
                 faces: HashMap::new(), // Empty faces for testing
-                letter_space: 0.0,
-                debug: false,
+                ..Default::default()
             }
         })
     }
@@ -532,6 +1741,14 @@ mod test_font_features {
         assert!(!summary.contains("liga=")); // Should be disabled
     }
 
+    #[test]
+    fn test_css_feature_settings() {
+        let mut font_config = create_test_font_config();
+        assert_eq!(font_config.css_feature_settings(), None);
+        font_config.set_features_from_string("liga=0,ss01,kern=2").unwrap();
+        assert_eq!(font_config.css_feature_settings().as_deref(), Some("'kern' 2, 'liga' 0, 'ss01' 1"));
+    }
+
     #[test]
     fn test_get_features_summary_empty() {
         let mut font_config = create_test_font_config();
@@ -558,3 +1775,204 @@ mod test_font_features {
         assert!(summary.contains("liga=2"));
     }
 }
+
+#[cfg(test)]
+mod test_font_faces {
+    use super::*;
+    use crate::test_support;
+
+    // A face of a bundled font, loaded on first use like those found by family
+    fn lazy_face(path: &str) -> LazyFontFace {
+        LazyFontFace::new(Handle::from_path(path.into(), 0))
+    }
+
+    #[test]
+    fn test_size_modes() {
+        let metrics = FaceMetrics {
+            units_per_em: 2048,
+            ascent: 1901.0,
+            descent: -483.0,
+            line_gap: 0.0,
+            underline_position: -130.0,
+            underline_thickness: 90.0,
+            cap_height: 1493.0,
+            x_height: 1120.0,
+            typo: None,
+            hhea: None,
+            win: None,
+        };
+        assert_eq!(metrics.size_units(SizeMode::Em), 2048.0);
+        assert_eq!(metrics.size_units(SizeMode::CapToBox), 2384.0);
+
+        let mut font_config = test_support::font_config(64);
+        // A 64px em is 64px wide for an em-wide glyph, as in browsers
+        let em = font_config.get_font_by_style(&FontStyle::Regular).unwrap().metrics();
+        assert_eq!(font_config.pixel_scale(em), 64.0 / em.units_per_em as f32);
+        let width = crate::utils::calculate_text_width("Hamburgefonstiv", &font_config, &FontStyle::Regular).unwrap();
+        font_config.set_size_mode(SizeMode::CapToBox);
+        let boxed = crate::utils::calculate_text_width("Hamburgefonstiv", &font_config, &FontStyle::Regular).unwrap();
+        assert!(boxed < width);
+        assert!((boxed / width - em.units_per_em as f32 / (em.ascent - em.descent)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_metrics_sources() {
+        let typo = VerticalMetrics { ascent: 1556.0, descent: -492.0, line_gap: 410.0 };
+        let metrics = FaceMetrics {
+            units_per_em: 2048,
+            ascent: 1901.0,
+            descent: -483.0,
+            line_gap: 0.0,
+            underline_position: -130.0,
+            underline_thickness: 90.0,
+            cap_height: 1493.0,
+            x_height: 1120.0,
+            typo: Some(typo),
+            hhea: Some(VerticalMetrics { ascent: 1901.0, descent: -483.0, line_gap: 0.0 }),
+            win: None,
+        };
+        let typo_metrics = metrics.with_source(MetricsSource::Typo);
+        assert_eq!((typo_metrics.ascent, typo_metrics.descent, typo_metrics.line_gap), (1556.0, -492.0, 410.0));
+        // Missing tables leave the metrics as they are
+        assert_eq!(metrics.with_source(MetricsSource::Win), metrics);
+        assert_eq!(metrics.with_source(MetricsSource::Auto), metrics);
+        assert_eq!(VerticalMetrics::new(0, 0, 0), None);
+    }
+
+    #[test]
+    fn test_metric_fallbacks() {
+        let metrics = FaceMetrics {
+            units_per_em: 2048,
+            ascent: 1900.0,
+            descent: -500.0,
+            line_gap: 0.0,
+            underline_position: -200.0,
+            underline_thickness: 100.0,
+            cap_height: 1400.0,
+            x_height: 1000.0,
+            typo: None,
+            hhea: None,
+            win: None,
+        };
+        assert_eq!(metrics.with_fallbacks(2000.0, -600.0), (metrics, Vec::new()));
+
+        // Missing ascent and descent come from the glyph bounds, or else the em size
+        let broken = FaceMetrics { units_per_em: 0, ascent: 0.0, descent: 0.0, ..metrics };
+        let (fixed, problems) = broken.with_fallbacks(2000.0, -600.0);
+        assert_eq!((fixed.units_per_em, fixed.ascent, fixed.descent), (1000, 2000.0, -600.0));
+        assert_eq!(problems.len(), 2);
+        let (fixed, _) = FaceMetrics { ascent: -10.0, ..metrics }.with_fallbacks(0.0, 0.0);
+        assert_eq!((fixed.ascent, fixed.descent), (2048.0 * 0.8, -2048.0 * 0.2));
+    }
+
+    #[test]
+    fn test_faces_load_on_first_use() {
+        let font_config = FontConfig {
+            size: 16,
+            faces: HashMap::from([(FontStyle::Regular, lazy_face(test_support::FONT_PATH))]),
+            ..Default::default()
+        };
+        assert!(font_config.get_loaded_styles().is_empty());
+
+        let style = font_config.get_available_styles().into_iter().next().expect("family has no faces");
+        assert!(font_config.get_font_by_style(&style).is_some());
+        assert_eq!(font_config.get_loaded_styles(), vec![style]);
+    }
+
+    #[test]
+    fn test_unsupported_features() {
+        assert_eq!(feature_name("liga").as_deref(), Some("Standard ligatures"));
+        assert_eq!(feature_name("ss03").as_deref(), Some("Stylistic set 3"));
+        assert_eq!(feature_name("ss21"), None);
+        let font_config = test_support::font_config(16);
+        let style = font_config.get_available_styles().into_iter().next().expect("family has no faces");
+        let implemented = font_config.font_feature_tags(&style);
+        let mut features = implemented.iter().map(|tag| format!("{}=1", tag)).collect::<Vec<_>>();
+        features.push("zzzz=1".to_string());
+        features.push("yyyy=0".to_string());
+        // Only enabled tags the font lacks are reported
+        assert_eq!(font_config.unsupported_features(&features.join(","), &style), vec!["zzzz"]);
+    }
+
+    #[test]
+    fn test_missing_chars() {
+        let font_config = test_support::font_config(16);
+        let style = font_config.get_available_styles().into_iter().next().expect("family has no faces");
+        // Private use characters have no glyphs in regular fonts
+        assert_eq!(font_config.missing_chars("a\u{10fffd}\tb \u{10fffd}", &style), vec!['\u{10fffd}']);
+    }
+
+    #[test]
+    fn test_combined_styles() {
+        assert_eq!(FontStyle::from_str("bold-italic", false), Ok(FontStyle::BoldItalic));
+        assert_eq!(FontStyle::from_str("semi-bold", false), Ok(FontStyle::SemiBold));
+        assert_eq!(FontStyle::from_str("semibold", false), Ok(FontStyle::SemiBold));
+        assert_eq!(FontStyle::SemiBold.with_italic(true), FontStyle::SemiBoldItalic);
+        assert_eq!(FontStyle::Italic.with_italic(false), FontStyle::Regular);
+        assert_eq!(FontStyle::BoldItalic.cli_name(), "bold-italic");
+    }
+
+    #[test]
+    #[ignore = "needs the DejaVu Sans family installed"]
+    fn test_installed_bold_faces() {
+        // Bold Oblique faces no longer take the place of Bold ones
+        let font_config = FontConfig::new("DejaVu Sans".to_string(), 16, Color::BLACK, Color::BLACK, false).unwrap();
+        let styles = font_config.get_available_styles();
+        assert!(styles.contains(&FontStyle::Bold) && styles.contains(&FontStyle::BoldItalic), "{:?}", styles);
+        let bold = font_config.get_font_by_style(&FontStyle::Bold).unwrap();
+        assert!(!bold.full_name().to_lowercase().contains("oblique"));
+    }
+
+    #[test]
+    fn test_parse_font_list() {
+        assert_eq!(parse_font_list(r#"Inter, "Noto Sans CJK SC",, 'Noto Color Emoji' "#), vec!["Inter", "Noto Sans CJK SC", "Noto Color Emoji"]);
+    }
+
+    #[test]
+    #[ignore = "needs the DejaVu Math TeX Gyre and DejaVu Sans families installed"]
+    fn test_installed_font_list() {
+        let font_config = FontConfig::new("Missing Family, DejaVu Math TeX Gyre, DejaVu Sans".to_string(), 16, Color::BLACK, Color::BLACK, false).unwrap();
+        assert_eq!(font_config.get_font_list(), "DejaVu Math TeX Gyre, DejaVu Sans");
+        assert!(font_config.missing_chars("aЖ", &FontStyle::Regular).is_empty());
+
+        // The letter the math font lacks comes from the fallback, scaled to the same size
+        let shaped = font_config.shape("aЖ", &FontStyle::Regular).unwrap();
+        assert_eq!((shaped.face_index(0), shaped.face_index(1)), (0, 1));
+        assert!(shaped.glyph_infos().iter().all(|info| info.glyph_id != 0));
+        assert_eq!(shaped.glyph_infos()[1].cluster, 1);
+    }
+
+    #[test]
+    fn test_emoji_font() {
+        let mut font_config = FontConfig::from_file(test_support::MONO_FONT_PATH, 16, Color::BLACK, Color::BLACK, false).unwrap();
+        let emoji_font = FallbackFont { name: "DejaVu Sans".to_string(), faces: vec![(FontStyle::Regular, lazy_face(test_support::FONT_PATH))] };
+        font_config.emoji_font = OnceLock::from(Some(emoji_font));
+        assert!(font_config.missing_chars("a😀", &FontStyle::Regular).is_empty());
+        // The emoji comes from the emoji font, past the fallbacks; the text stays in the font
+        let shaped = font_config.shape("a😀 b", &FontStyle::Regular).unwrap();
+        assert_eq!((0..shaped.len()).map(|i| shaped.face_index(i)).collect::<Vec<_>>(), [0, 1, 1, 0]);
+        assert!(shaped.glyph_infos().iter().all(|info| info.glyph_id != 0));
+
+        font_config.set_auto_emoji_font(false);
+        assert_eq!(font_config.missing_chars("a😀", &FontStyle::Regular), ['😀']);
+    }
+
+    #[test]
+    fn test_tab_advances() {
+        let mut font_config = test_support::font_config(16);
+        let style = font_config.get_available_styles().into_iter().next().expect("family has no faces");
+        let advances = |font_config: &FontConfig, text: &str| -> Vec<i32> {
+            font_config.shape(text, &style).unwrap().glyph_positions().iter().map(|position| position.x_advance).collect()
+        };
+        let space = advances(&font_config, " ")[0];
+
+        // The tab reaches the first stop, 4 spaces in
+        let stops = advances(&font_config, "a\tb");
+        assert!((stops[0] + stops[1] - 4 * space).abs() <= 1);
+        let tab_glyph = font_config.shape("\t", &style).unwrap().glyph_infos()[0].glyph_id;
+        assert_eq!(tab_glyph, font_config.shape(" ", &style).unwrap().glyph_infos()[0].glyph_id);
+
+        font_config.set_tabs(2, TabMode::Spaces);
+        assert_eq!(advances(&font_config, "a\tb")[1], 2 * space);
+    }
+}
@@ -0,0 +1,258 @@
+// on-disk cache of system font metadata: installed families and, per family,
+// the files behind each style, so repeated runs skip font enumeration
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use font_kit::handle::Handle;
+
+use crate::font::FontStyle;
+
+const CACHE_HEADER: &str = "text2svg-font-cache 2";
+
+/// Environment variable overriding the cache file location; "off" disables the cache.
+pub const FONT_CACHE_ENV: &str = "TEXT2SVG_FONT_CACHE";
+
+/// Cached font metadata, valid as long as the font directories are unchanged.
+#[derive(Debug, Default)]
+pub struct FontMetadataCache {
+    path: PathBuf,
+    fingerprint: u64,
+    families: Option<Vec<String>>,
+    faces: HashMap<String, Vec<(FontStyle, PathBuf, u32)>>,
+    dirty: bool,
+}
+
+impl FontMetadataCache {
+    /// Where the cache lives unless overridden by `TEXT2SVG_FONT_CACHE`.
+    pub fn default_path() -> Option<PathBuf> {
+        cache_path(|name| std::env::var_os(name))
+    }
+
+    /// Opens the cache at its default location. Tests never touch the user's cache.
+    pub fn open() -> Option<Self> {
+        if cfg!(test) {
+            return None;
+        }
+        Self::default_path().map(|path| Self::load(path, font_dirs_fingerprint()))
+    }
+
+    /// Loads the cache at `path`. A missing, unreadable or stale cache
+    /// (written for a different `fingerprint`) loads empty.
+    pub fn load(path: PathBuf, fingerprint: u64) -> Self {
+        let mut cache = Self {
+            path,
+            fingerprint,
+            ..Default::default()
+        };
+        let Ok(content) = std::fs::read_to_string(&cache.path) else {
+            return cache;
+        };
+
+        let mut lines = content.lines();
+        if lines.next() != Some(&format!("{} {}", CACHE_HEADER, fingerprint)) {
+            return cache;
+        }
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["L", family] => cache.families.get_or_insert_with(Vec::new).push(family.to_string()),
+                ["S", family, style, index, path] => {
+                    let (Ok(style), Ok(index)) = (FontStyle::from_str(style, false), index.parse()) else {
+                        continue;
+                    };
+                    cache.faces
+                        .entry(family.to_string())
+                        .or_default()
+                        .push((style, PathBuf::from(path), index));
+                }
+                _ => {}
+            }
+        }
+        cache
+    }
+
+    pub fn get_families(&self) -> Option<&Vec<String>> {
+        self.families.as_ref()
+    }
+
+    pub fn set_families(&mut self, families: Vec<String>) {
+        self.families = Some(families);
+        self.dirty = true;
+    }
+
+    /// Classified faces of a family, as font_kit handles.
+    pub fn get_family_faces(&self, family: &str) -> Option<Vec<(FontStyle, Handle)>> {
+        let faces = self.faces.get(family)?;
+        Some(
+            faces
+                .iter()
+                .map(|(style, path, index)| (style.clone(), Handle::from_path(path.clone(), *index)))
+                .collect(),
+        )
+    }
+
+    /// Remembers the classified faces of a family. Families loaded from memory
+    /// rather than files can't be cached and are skipped.
+    pub fn set_family_faces(&mut self, family: &str, faces: &[(FontStyle, Handle)]) {
+        let mut entries = Vec::with_capacity(faces.len());
+        for (style, handle) in faces {
+            match handle {
+                Handle::Path { path, font_index } if is_storable(path) => {
+                    entries.push((style.clone(), path.clone(), *font_index));
+                }
+                _ => return,
+            }
+        }
+        self.faces.insert(family.to_string(), entries);
+        self.dirty = true;
+    }
+
+    /// Writes the cache back if anything was added since it was loaded.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut content = format!("{} {}\n", CACHE_HEADER, self.fingerprint);
+        for family in self.families.iter().flatten() {
+            if !family.contains(['\t', '\n']) {
+                content.push_str(&format!("L\t{}\n", family));
+            }
+        }
+        for (family, faces) in self.faces.iter() {
+            if family.contains(['\t', '\n']) {
+                continue;
+            }
+            for (style, path, index) in faces {
+                let style = style.cli_name();
+                content.push_str(&format!("S\t{}\t{}\t{}\t{}\n", family, style, index, path.display()));
+            }
+        }
+
+        // Write to a temporary file first so concurrent runs never read a partial cache,
+        // named for this process so they never write to the same one either
+        let tmp_path = self.path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+// Cache file location from the environment variables looked up by `var`
+fn cache_path(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    match var(FONT_CACHE_ENV) {
+        Some(path) if path == "off" => return None,
+        Some(path) if !path.is_empty() => return Some(PathBuf::from(path)),
+        _ => {}
+    }
+    let cache_dir = var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("text2svg").join("fonts.cache"))
+}
+
+fn is_storable(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| !path.contains(['\t', '\n']))
+}
+
+// Directories fonts are installed to on this platform
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "macos") {
+        dirs.push("/System/Library/Fonts".into());
+        dirs.push("/Library/Fonts".into());
+        dirs.extend(home.map(|home| home.join("Library/Fonts")));
+    } else if cfg!(target_os = "windows") {
+        let windir = std::env::var_os("WINDIR").map(PathBuf::from).unwrap_or_else(|| "C:\\Windows".into());
+        dirs.push(windir.join("Fonts"));
+        dirs.extend(std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Microsoft\\Windows\\Fonts")));
+    } else {
+        dirs.push("/usr/share/fonts".into());
+        dirs.push("/usr/local/share/fonts".into());
+        if let Some(home) = home {
+            dirs.push(home.join(".fonts"));
+            dirs.push(home.join(".local/share/fonts"));
+        }
+    }
+    dirs
+}
+
+/// Hash of the modification times of every font directory and its subdirectories.
+/// Installing or removing a font changes the mtime of the directory it lives in.
+pub fn font_dirs_fingerprint() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let mut pending = font_dirs();
+    while let Some(dir) = pending.pop() {
+        let Ok(metadata) = dir.metadata() else {
+            continue;
+        };
+        dir.hash(&mut hasher);
+        metadata.modified().ok().hash(&mut hasher);
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    pending.push(entry.path());
+                }
+            }
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test_font_cache {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip_and_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fonts.cache");
+
+        let mut cache = FontMetadataCache::load(path.clone(), 42);
+        assert!(cache.get_families().is_none());
+        cache.set_families(vec!["Test Sans".to_string()]);
+        cache.set_family_faces("Test Sans", &[
+            (FontStyle::Regular, Handle::from_path("/fonts/TestSans.ttf".into(), 0)),
+            (FontStyle::SemiBold, Handle::from_path("/fonts/TestSans.ttc".into(), 2)),
+        ]);
+        cache.save().unwrap();
+
+        let cache = FontMetadataCache::load(path.clone(), 42);
+        assert_eq!(cache.get_families(), Some(&vec!["Test Sans".to_string()]));
+        let faces = cache.get_family_faces("Test Sans").unwrap();
+        assert_eq!(faces.len(), 2);
+        assert!(faces.iter().any(|(style, handle)| {
+            *style == FontStyle::SemiBold
+                && matches!(handle, Handle::Path { path, font_index: 2 } if path == Path::new("/fonts/TestSans.ttc"))
+        }));
+
+        // Font directories changed since the cache was written
+        let stale = FontMetadataCache::load(path.clone(), 43);
+        assert!(stale.get_families().is_none());
+        assert!(stale.get_family_faces("Test Sans").is_none());
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| OsString::from(value))
+        };
+        assert_eq!(cache_path(env(&[("HOME", "/home/me")])), Some(PathBuf::from("/home/me/.cache/text2svg/fonts.cache")));
+        assert_eq!(cache_path(env(&[("HOME", "/home/me"), ("XDG_CACHE_HOME", "/xdg")])), Some(PathBuf::from("/xdg/text2svg/fonts.cache")));
+        assert_eq!(cache_path(env(&[("HOME", "/home/me"), (FONT_CACHE_ENV, "/tmp/fonts.cache")])), Some(PathBuf::from("/tmp/fonts.cache")));
+        assert_eq!(cache_path(env(&[("HOME", "/home/me"), (FONT_CACHE_ENV, "off")])), None);
+        assert_eq!(cache_path(env(&[])), None);
+    }
+}
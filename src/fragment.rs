@@ -0,0 +1,107 @@
+// writing the rendered text as a <g> or <symbol> fragment rather than a whole
+// document, for pasting into an existing SVG or HTML page
+use clap::ValueEnum;
+use svg::node::element::Element;
+use svg::{Document, Node};
+
+/// Id given to fragments unless another is chosen.
+pub const DEFAULT_FRAGMENT_ID: &str = "text2svg";
+
+/// Element the fragment is wrapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FragmentKind {
+    /// A group, drawn where it is placed
+    #[value(name = "g")]
+    Group,
+    /// A symbol with the view box, drawn by `<use href="#id">` at any size
+    Symbol,
+}
+
+/// How a fragment is written: its element and id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub kind: FragmentKind,
+    pub id: String,
+}
+
+/// Parses a fragment id: a name without whitespace or quotes that doesn't start with a digit.
+pub fn parse_fragment_id(id: &str) -> Result<String, String> {
+    let valid = !id.is_empty()
+        && !id.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.')
+        && !id.contains(|c: char| c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '<' | '>' | '&' | '#'));
+    match valid {
+        true => Ok(id.to_string()),
+        false => Err(format!("Invalid fragment id '{}': expected a name such as \"title\" or \"logo-text\"", id)),
+    }
+}
+
+// Attributes of the document that only make sense on a whole document
+fn document_attribute(name: &str) -> bool {
+    matches!(name, "width" | "height" | "viewBox" | "version" | "xmlns") || name.starts_with("xmlns:")
+}
+
+/// The contents of `doc` in a `<g>` or `<symbol>` with the fragment's id and
+/// without the document's size. A symbol keeps the view box; a group is moved
+/// so the view box starts at the origin.
+pub fn to_fragment(doc: &Document, fragment: &Fragment) -> Element {
+    let mut doc = Element::from(doc.clone());
+    let mut element = Element::new(match fragment.kind {
+        FragmentKind::Group => "g",
+        FragmentKind::Symbol => "symbol",
+    });
+    element.assign("id", fragment.id.as_str());
+    let view_box = doc.get_attributes().get("viewBox").map(|value| value.to_string());
+    for (name, value) in doc.get_attributes() {
+        if !document_attribute(name) {
+            element.assign(name.as_str(), value.clone());
+        }
+    }
+    match (fragment.kind, view_box) {
+        (FragmentKind::Symbol, Some(view_box)) => element.assign("viewBox", view_box),
+        (FragmentKind::Group, Some(view_box)) => {
+            let origin: Vec<f32> = view_box.split_whitespace().take(2).filter_map(|value| value.parse().ok()).collect();
+            if let [x, y] = origin[..] {
+                if x != 0.0 || y != 0.0 {
+                    // Subtracting from 0 keeps a zero offset from printing as -0
+                    element.assign("transform", format!("translate({} {})", 0.0 - x, 0.0 - y));
+                }
+            }
+        }
+        _ => {}
+    }
+    for child in std::mem::take(doc.get_children_mut()) {
+        element.append(child);
+    }
+    element
+}
+
+#[cfg(test)]
+mod test_fragment {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+    use crate::render::{build_text_svg, RenderConfig};
+    use crate::xml::to_xml;
+
+    #[test]
+    fn test_to_fragment() {
+        let font_config = test_support::font_config(20);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let (doc, _) = build_text_svg("Hi", &font_config, &render_config).unwrap();
+        let view_box = doc.get_attributes().and_then(|attributes| attributes.get("viewBox")).unwrap().to_string();
+
+        let symbol = to_xml(&to_fragment(&doc, &Fragment { kind: FragmentKind::Symbol, id: "title".into() }));
+        assert!(symbol.starts_with("<symbol "), "{}", symbol);
+        assert!(symbol.contains(r#"id="title""#) && symbol.contains(&format!(r#"viewBox="{}""#, view_box)));
+        assert!(!symbol.contains("<svg") && !symbol.contains(" width=") && !symbol.contains("xmlns"));
+        assert!(symbol.contains("<defs>") && symbol.contains("<use"));
+
+        let group = to_xml(&to_fragment(&doc, &Fragment { kind: FragmentKind::Group, id: "title".into() }));
+        assert!(group.starts_with("<g ") && group.ends_with("</g>"));
+        assert!(!group.contains("viewBox") && !group.contains(" height="));
+
+        assert!(parse_fragment_id("logo-text").is_ok());
+        assert!(parse_fragment_id("1st").is_err());
+        assert!(parse_fragment_id("a b").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+// mapping the glyphs of a rendered document back to the text they came from,
+// for tools that select, search or time text over the SVG
+use serde_json::{json, Value};
+use svg::{Document, Node};
+
+use crate::markup::{parse_markup, plain_text};
+
+/// Where the glyphs laid out together come from, so their `<use>` elements
+/// get ids naming the text of their clusters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterIds {
+    /// Index of the rendered (wrapped) line
+    pub line: usize,
+    /// Byte offset of the shaped text in the line
+    pub offset: usize,
+    /// Byte length of the shaped text
+    pub len: usize,
+}
+
+impl ClusterIds {
+    /// Id of glyph `index` of the cluster spanning bytes `start..end` of the shaped text.
+    pub fn id(&self, start: usize, end: usize, index: usize) -> String {
+        let id = format!("t{}-{}-{}", self.line, self.offset + start, self.offset + end);
+        match index {
+            0 => id,
+            index => format!("{}_{}", id, index),
+        }
+    }
+}
+
+// Rendered line and byte range named by a glyph id
+fn parse_id(id: &str) -> Option<(usize, usize, usize)> {
+    let id = id.strip_prefix('t')?;
+    let id = id.split_once('_').map_or(id, |(id, _)| id);
+    let mut parts = id.split('-').map(str::parse::<usize>);
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(line)), Some(Ok(start)), Some(Ok(end)), None) => Some((line, start, end)),
+        _ => None,
+    }
+}
+
+/// Where the text of one glyph is in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlyphSource {
+    pub id: String,
+    /// Source line, from 1
+    pub line: usize,
+    /// Range of characters in the source line
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+// Paragraph and byte offset in it where each rendered line starts. Wrapped
+// lines are pieces of their paragraph, in order, as in wrap_spans.
+fn line_origins(lines: &[String], paragraph_starts: &[usize], paragraphs: &[String]) -> Vec<(usize, usize)> {
+    let mut cursor = 0;
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let paragraph = match paragraph_starts.is_empty() {
+                true => i,
+                false => paragraph_starts.partition_point(|start| *start <= i).saturating_sub(1),
+            };
+            if paragraph_starts.is_empty() || paragraph_starts.binary_search(&i).is_ok() {
+                cursor = 0;
+            }
+            let text = paragraphs.get(paragraph).map_or("", String::as_str);
+            let start = text.get(cursor..).and_then(|rest| rest.find(line.as_str())).map_or(cursor, |found| cursor + found);
+            cursor = start + line.len();
+            (paragraph, start)
+        })
+        .collect()
+}
+
+// Characters in `text` before byte `offset`, which may fall inside one
+fn char_index(text: &str, offset: usize) -> usize {
+    text.char_indices().take_while(|(i, _)| *i < offset).count()
+}
+
+/// Sources of the glyphs of `doc` with ids from [`ClusterIds`], in document
+/// order, given the rendered `lines` (paragraphs starting at `paragraph_starts`)
+/// and the source `paragraphs`. With `markup` the ranges count the text
+/// without its tags.
+pub fn glyph_sources(doc: &Document, lines: &[String], paragraph_starts: &[usize], paragraphs: &[String], markup: bool) -> Vec<GlyphSource> {
+    let plain = |text: &String| match markup.then(|| parse_markup(text).ok()).flatten() {
+        Some(spans) => plain_text(&spans),
+        None => text.clone(),
+    };
+    let lines: Vec<String> = lines.iter().map(plain).collect();
+    let paragraphs: Vec<String> = paragraphs.iter().map(plain).collect();
+    let origins = line_origins(&lines, paragraph_starts, &paragraphs);
+    let mut sources = Vec::new();
+    collect_sources(doc, &origins, &paragraphs, &mut sources);
+    sources
+}
+
+fn collect_sources(node: &dyn Node, origins: &[(usize, usize)], paragraphs: &[String], sources: &mut Vec<GlyphSource>) {
+    let id = node.get_attributes().and_then(|attributes| attributes.get("id")).map(|id| id.to_string());
+    let named = id.as_deref().filter(|_| node.get_name() == "use").and_then(|id| Some((id, parse_id(id)?)));
+    if let Some((id, (line, start, end))) = named {
+        if let Some(&(paragraph, origin)) = origins.get(line) {
+            let text = paragraphs.get(paragraph).map_or("", String::as_str);
+            // Text added when rendering, such as an ellipsis, maps to the end of the line
+            let (start, end) = (char_index(text, origin + start), char_index(text, origin + end));
+            let chars = text.chars().skip(start).take(end - start).collect();
+            sources.push(GlyphSource { id: id.to_string(), line: paragraph + 1, start, end, text: chars });
+        }
+    }
+    for child in node.get_children().into_iter().flatten() {
+        collect_sources(child.as_ref(), origins, paragraphs, sources);
+    }
+}
+
+/// The glyph sources as JSON: `{"glyphs": [{"id", "line", "start", "end", "text"}, ...]}`.
+pub fn glyph_map_json(sources: &[GlyphSource]) -> String {
+    let glyphs: Vec<Value> = sources
+        .iter()
+        .map(|source| json!({ "id": source.id, "line": source.line, "start": source.start, "end": source.end, "text": source.text }))
+        .collect();
+    json!({ "glyphs": glyphs }).to_string()
+}
+
+#[cfg(test)]
+mod test_glyphmap {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+    use crate::render::{build_text_svg, wrap_paragraphs, RenderConfig};
+
+    #[test]
+    fn test_cluster_ids() {
+        let ids = ClusterIds { line: 2, offset: 5, len: 4 };
+        assert_eq!(ids.id(0, 2, 0), "t2-5-7");
+        assert_eq!(ids.id(0, 2, 1), "t2-5-7_1");
+        assert_eq!(parse_id("t2-5-7_1"), Some((2, 5, 7)));
+        assert_eq!(parse_id("g12-Regular"), None);
+    }
+
+    #[test]
+    fn test_glyph_sources() {
+        let font_config = test_support::font_config(20);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_glyph_ids(true).set_max_width(Some(6));
+        let text = "héllo world\nok";
+        let (doc, _) = build_text_svg(text, &font_config, &render_config).unwrap();
+        let paragraphs: Vec<String> = text.lines().map(str::to_string).collect();
+        let (lines, paragraph_starts) = wrap_paragraphs(text.lines(), &font_config, &render_config);
+        let sources = glyph_sources(&doc, &lines, &paragraph_starts, &paragraphs, false);
+
+        let texts: String = sources.iter().map(|source| source.text.as_str()).collect();
+        assert_eq!(texts, "hélloworldok");
+        // The second wrapped line is still in the first source line, in characters
+        let w = sources.iter().find(|source| source.text == "w").unwrap();
+        assert_eq!((w.line, w.start, w.end), (1, 6, 7));
+        let k = sources.last().unwrap();
+        assert_eq!((k.id.as_str(), k.line, k.start, k.end), ("t2-1-2", 2, 1, 2));
+        assert!(glyph_map_json(&sources[..1]).contains(r#""text":"h""#));
+    }
+}
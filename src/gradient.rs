@@ -0,0 +1,124 @@
+// per-character or per-word coloring along a palette, applied to whole
+// glyph clusters so ligatures and combining marks keep a single color
+use clap::ValueEnum;
+
+use crate::color::{split_colors, Rgba};
+
+/// What advances along the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorBy {
+    /// Every visible character
+    #[default]
+    Char,
+    /// Every whitespace separated word
+    Word,
+}
+
+/// Colors spread evenly over the characters or words of each line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub palette: Vec<Rgba>,
+    pub by: ColorBy,
+}
+
+// Red, orange, yellow, green, blue, violet
+const RAINBOW: &str = "#e81416,#ffa500,#faeb36,#79c314,#487de7,#70369d";
+
+/// Parses a comma separated list of colors, or "rainbow".
+pub fn parse_palette(palette: &str) -> Result<Vec<Rgba>, String> {
+    let palette = if palette.trim() == "rainbow" { RAINBOW } else { palette };
+    let colors = split_colors(palette, ',').into_iter().map(str::parse).collect::<Result<Vec<Rgba>, String>>()?;
+    if colors.len() < 2 {
+        return Err("A gradient needs at least two colors".to_string());
+    }
+    Ok(colors)
+}
+
+impl Gradient {
+    pub fn new(palette: Vec<Rgba>, by: ColorBy) -> Self {
+        Self { palette, by }
+    }
+
+    /// Color at `t` between 0 (first color) and 1 (last color).
+    pub fn color_at(&self, t: f32) -> Rgba {
+        let last = self.palette.len() - 1;
+        let position = t.clamp(0.0, 1.0) * last as f32;
+        let index = (position.floor() as usize).min(last.saturating_sub(1));
+        let (from, to) = (self.palette[index], self.palette[(index + 1).min(last)]);
+        let t = position - index as f32;
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgba(mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2), mix(from.3, to.3))
+    }
+
+    /// Colors starting at byte offsets of `line`, sorted by offset. Each
+    /// visible character or word gets the next step along the palette.
+    pub fn line_colors(&self, line: &str) -> Vec<(usize, Rgba)> {
+        let starts: Vec<usize> = match self.by {
+            ColorBy::Char => line
+                .char_indices()
+                .filter(|(_, c)| !c.is_whitespace())
+                .map(|(i, _)| i)
+                .collect(),
+            ColorBy::Word => line
+                .char_indices()
+                .filter(|&(i, c)| !c.is_whitespace() && line[..i].chars().next_back().is_none_or(char::is_whitespace))
+                .map(|(i, _)| i)
+                .collect(),
+        };
+        let steps = starts.len().saturating_sub(1).max(1) as f32;
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| (*start, self.color_at(i as f32 / steps)))
+            .collect()
+    }
+}
+
+/// Color of the cluster starting at byte `cluster`: the last color starting at or before it.
+pub fn cluster_color<T: Copy>(colors: &[(usize, T)], cluster: usize) -> Option<T> {
+    let index = colors.partition_point(|(start, _)| *start <= cluster);
+    index.checked_sub(1).map(|index| colors[index].1)
+}
+
+/// The colors covering bytes `start..end`, with offsets relative to `start`.
+pub fn slice_colors(colors: &[(usize, Rgba)], start: usize, end: usize) -> Vec<(usize, Rgba)> {
+    let mut sliced: Vec<(usize, Rgba)> = cluster_color(colors, start).map(|color| (0, color)).into_iter().collect();
+    sliced.extend(
+        colors
+            .iter()
+            .filter(|(offset, _)| *offset > start && *offset < end)
+            .map(|(offset, color)| (offset - start, *color)),
+    );
+    sliced
+}
+
+#[cfg(test)]
+mod test_gradient {
+    use super::*;
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!(parse_palette("red, hsl(240, 100%, 50%)"), Ok(vec![Rgba(255, 0, 0, 255), Rgba(0, 0, 255, 255)]));
+        assert!(parse_palette("#fff").is_err());
+        assert_eq!(parse_palette("rainbow").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_line_colors() {
+        let gradient = Gradient::new(parse_palette("#000,#fff").unwrap(), ColorBy::Char);
+        let colors = gradient.line_colors("ab c");
+        assert_eq!(colors, vec![
+            (0, Rgba(0, 0, 0, 255)),
+            (1, Rgba(128, 128, 128, 255)),
+            (3, Rgba(255, 255, 255, 255)),
+        ]);
+        // The space and glyphs inside a cluster take the color of where they start
+        assert_eq!(cluster_color(&colors, 2), Some(Rgba(128, 128, 128, 255)));
+        assert_eq!(slice_colors(&colors, 2, 4), vec![(0, Rgba(128, 128, 128, 255)), (1, Rgba(255, 255, 255, 255))]);
+
+        let gradient = Gradient::new(parse_palette("#000,#fff").unwrap(), ColorBy::Word);
+        let colors = gradient.line_colors("one  two three");
+        assert_eq!(colors.iter().map(|(start, _)| *start).collect::<Vec<_>>(), vec![0, 5, 9]);
+        assert_eq!(cluster_color(&colors, 7), Some(Rgba(128, 128, 128, 255)));
+    }
+}
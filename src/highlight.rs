@@ -1,6 +1,7 @@
 use syntect::{highlighting::{Color, FontStyle as SynFontStyle, Theme, ThemeSet}, parsing::SyntaxSet, LoadingError}; // Renamed FontStyle to avoid clash
 use std::{fmt::Display, path::Path};
 
+use crate::direction::Direction;
 use crate::font::FontStyle as AppFontStyle; // Renamed our FontStyle
 
 
@@ -8,6 +9,52 @@ pub struct HighlightSetting {
     pub syntax_set: SyntaxSet,
     pub theme_set: ThemeSet,
     pub theme: String, // Name of the currently selected theme
+    pub chrome: CodeChrome,
+}
+
+/// Decorations around highlighted code, like code screenshot tools draw.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeChrome {
+    /// Number the lines in a gutter on the side lines start on
+    pub line_numbers: bool,
+    /// Corner radius of the background panel; the panel gets a margin around the code
+    pub radius: Option<f32>,
+    /// Draw a window title bar with the three traffic light buttons
+    pub window_bar: bool,
+    /// Title centered in the window bar
+    pub title: Option<String>,
+    /// Right to left aligns the lines and puts the gutter on the right
+    pub direction: Direction,
+}
+
+// Traffic light colors of the window bar buttons: close, minimize, zoom
+pub const WINDOW_BUTTON_COLORS: [&str; 3] = ["#ff5f56", "#ffbd2e", "#27c93f"];
+
+impl CodeChrome {
+    /// Whether the code is drawn on a panel with a margin around it.
+    pub fn has_panel(&self) -> bool {
+        self.radius.is_some() || self.window_bar
+    }
+
+    /// Margin between the panel edges and the code.
+    pub fn padding(&self, font_size: u32) -> u32 {
+        if self.has_panel() { font_size.div_ceil(2) } else { 0 }
+    }
+
+    /// Height of the window bar, nothing without one.
+    pub fn bar_height(&self, font_size: u32) -> u32 {
+        if self.window_bar { (font_size as f32 * 1.5).round() as u32 } else { 0 }
+    }
+
+    /// Radius and centers of the window buttons, relative to the top left of the bar.
+    pub fn buttons(&self, font_size: u32) -> Vec<(f32, f32, f32)> {
+        let bar_height = self.bar_height(font_size) as f32;
+        let radius = bar_height * 0.15;
+        let left = self.padding(font_size) as f32 + radius;
+        (0..WINDOW_BUTTON_COLORS.len())
+            .map(|i| (radius, left + i as f32 * radius * 3.0, bar_height / 2.0))
+            .collect()
+    }
 }
 
 impl Default for HighlightSetting {
@@ -18,6 +65,7 @@ impl Default for HighlightSetting {
             syntax_set: ss,
             theme_set: ts,
             theme: "base16-ocean.dark".to_string(), // Default theme name
+            chrome: CodeChrome::default(),
         }
     }
 }
@@ -41,6 +89,12 @@ impl HighlightSetting {
         self.theme = name.to_string();
         self
     }
+
+    /// Sets the decorations drawn around the code.
+    pub fn set_chrome(&mut self, chrome: CodeChrome) -> &mut Self {
+        self.chrome = chrome;
+        self
+    }
 }
 
 // Wrapper for syntect::highlighting::Color to provide Display impl for rgba()
@@ -92,7 +146,9 @@ impl HighlightFontStyle {
 #[cfg(test)]
 mod test_highlight{
     use super::*;
+    use crate::test_support;
     use crate::font::FontStyle as AppFontStyle; // Use the aliased name
+    use crate::output::OutputConfig;
 
     #[test]
     fn test_font_style_mapping() {
@@ -122,6 +178,21 @@ mod test_highlight{
         assert_eq!(underline_app_style.get_style(), AppFontStyle::Regular);
     }
 
+    #[test]
+    fn test_code_chrome_layout() {
+        let plain = CodeChrome { line_numbers: true, ..Default::default() };
+        assert_eq!(plain.padding(20), 0);
+        assert_eq!(plain.bar_height(20), 0);
+
+        let window = CodeChrome { window_bar: true, ..Default::default() };
+        assert_eq!(window.padding(20), 10);
+        assert_eq!(window.bar_height(20), 30);
+        let buttons = window.buttons(20);
+        assert_eq!(buttons.len(), 3);
+        assert_eq!(buttons[0], (4.5, 14.5, 15.0));
+        assert_eq!(buttons[2].1, 14.5 + 27.0);
+    }
+
      #[test]
     fn test_color_display() {
         // Opaque black
@@ -140,5 +211,20 @@ mod test_highlight{
         let fully_transparent = HighlightColor::new(Color { r: 100, g: 100, b: 100, a: 0 });
         assert_eq!(fully_transparent.to_string(), "rgba(100,100,100,0.000)");
     }
-}
 
+    #[test]
+    fn test_render_file_highlight_errors() {
+        let mut font_config = test_support::font_config(20);
+        let output = std::path::PathBuf::from("/nonexistent/dir/out.svg");
+        let mut setting = HighlightSetting::default();
+
+        // Errors come back instead of panicking
+        let missing = std::path::PathBuf::from("/nonexistent/input.rs");
+        assert!(crate::render::render_file_highlight(&missing, &mut font_config, &setting, &OutputConfig::new(), output.clone()).is_err());
+        let input = std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/highlight.rs"));
+        let unwritable = crate::render::render_file_highlight(&input, &mut font_config, &setting, &OutputConfig::new(), output.clone()).unwrap_err();
+        assert!(unwritable.to_string().starts_with("/nonexistent/dir/out.svg: "));
+        setting.theme = "no-such-theme".to_string();
+        assert!(crate::render::render_file_highlight(&input, &mut font_config, &setting, &OutputConfig::new(), output).unwrap_err().to_string().contains("no-such-theme"));
+    }
+}
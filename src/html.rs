@@ -0,0 +1,63 @@
+// wrapping the rendered svg in an HTML page, a minimal one of its own or an
+// existing template, so a snippet can be previewed or published directly
+use crate::xml::escape_text;
+
+/// Marks where the svg goes in an HTML template.
+pub const HTML_PLACEHOLDER: &str = "{{svg}}";
+
+// Title of pages whose document has no label
+const DEFAULT_TITLE: &str = "text2svg";
+
+/// Reads the HTML template at `path`, which has to hold the placeholder.
+pub fn parse_html_template(path: &str) -> Result<String, String> {
+    let template = std::fs::read_to_string(path).map_err(|e| format!("Can't read HTML template '{}': {}", path, e))?;
+    match template.contains(HTML_PLACEHOLDER) {
+        true => Ok(template),
+        false => Err(format!("HTML template '{}' has no {} placeholder marking where the svg goes", path, HTML_PLACEHOLDER)),
+    }
+}
+
+/// What goes before and after the svg: `template` split at its first
+/// placeholder, or a minimal page titled `title`.
+pub fn html_parts(title: Option<&str>, template: Option<&str>) -> (String, String) {
+    if let Some((before, after)) = template.and_then(|template| template.split_once(HTML_PLACEHOLDER)) {
+        return (before.to_string(), after.to_string());
+    }
+    let before = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n</head>\n<body>\n",
+        escape_text(title.filter(|title| !title.is_empty()).unwrap_or(DEFAULT_TITLE))
+    );
+    (before, "\n</body>\n</html>\n".to_string())
+}
+
+/// `svg` in an HTML page: `template` with it at the placeholder, or a minimal
+/// page titled `title`.
+pub fn html_page(svg: &str, title: Option<&str>, template: Option<&str>) -> String {
+    let (before, after) = html_parts(title, template);
+    [before.as_str(), svg, after.as_str()].concat()
+}
+
+#[cfg(test)]
+mod test_html {
+    use super::*;
+
+    #[test]
+    fn test_html_page() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><path d="M0 0"/></svg>"#;
+        let page = html_page(svg, Some("Fish & chips"), None);
+        assert!(page.starts_with("<!DOCTYPE html>\n") && page.ends_with("</html>\n"));
+        assert!(page.contains("<title>Fish &amp; chips</title>"));
+        assert!(page.contains(&format!("<body>\n{}\n</body>", svg)));
+        assert!(html_page(svg, None, None).contains("<title>text2svg</title>"));
+
+        let template = "<main>{{svg}}</main><!-- {{svg}} -->";
+        assert_eq!(html_page(svg, Some("unused"), Some(template)), format!("<main>{}</main><!-- {{{{svg}}}} -->", svg));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template.html");
+        std::fs::write(&path, "<body></body>").unwrap();
+        assert!(parse_html_template(path.to_str().unwrap()).unwrap_err().contains("no {{svg}} placeholder"));
+        std::fs::write(&path, template).unwrap();
+        assert_eq!(parse_html_template(path.to_str().unwrap()).unwrap(), template);
+    }
+}
@@ -0,0 +1,118 @@
+// the language of the rendered text, recorded in the output for screen readers,
+// hyphenation and font selection downstream since the glyphs themselves don't say
+use std::fmt::Display;
+
+/// A BCP 47 language tag, e.g. "en", "pt-BR" or "zh-Hant".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lang(String);
+
+impl std::str::FromStr for Lang {
+    type Err = String;
+
+    /// Checks the tag's shape: a 2-3 or 5-8 letter language, then subtags of 1-8 letters or digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tag = s.trim();
+        let mut subtags = tag.split('-');
+        let language = subtags.next().unwrap_or_default();
+        let valid = matches!(language.len(), 2..=3 | 5..=8)
+            && language.chars().all(|c| c.is_ascii_alphabetic())
+            && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+        match valid {
+            true => Ok(Lang(tag.to_string())),
+            false => Err(format!("Invalid language '{}': expected a BCP 47 tag, e.g. \"en\" or \"pt-BR\"", s)),
+        }
+    }
+}
+
+impl Display for Lang {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// Languages of the scripts only one common language is written in
+fn script_lang(c: char) -> Option<&'static str> {
+    match c {
+        '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' => Some("ja"),
+        '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => Some("ko"),
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some("el"),
+        '\u{0590}'..='\u{05FF}' => Some("he"),
+        '\u{0530}'..='\u{058F}' => Some("hy"),
+        '\u{10A0}'..='\u{10FF}' => Some("ka"),
+        '\u{0E00}'..='\u{0E7F}' => Some("th"),
+        '\u{0E80}'..='\u{0EFF}' => Some("lo"),
+        '\u{1780}'..='\u{17FF}' => Some("km"),
+        '\u{0F00}'..='\u{0FFF}' => Some("bo"),
+        '\u{0980}'..='\u{09FF}' => Some("bn"),
+        '\u{0A80}'..='\u{0AFF}' => Some("gu"),
+        '\u{0B80}'..='\u{0BFF}' => Some("ta"),
+        '\u{0C00}'..='\u{0C7F}' => Some("te"),
+        '\u{0C80}'..='\u{0CFF}' => Some("kn"),
+        '\u{0D00}'..='\u{0D7F}' => Some("ml"),
+        '\u{0D80}'..='\u{0DFF}' => Some("si"),
+        '\u{1000}'..='\u{109F}' => Some("my"),
+        '\u{1200}'..='\u{139F}' => Some("am"),
+        _ => None,
+    }
+}
+
+/// Language of `text` when its letters are mostly in a script only one common
+/// language is written in, e.g. Hangul or Greek. Scripts shared by many
+/// languages, such as Latin, Cyrillic, Arabic or Han, give None. Kana decides
+/// for Japanese even among more Han characters.
+pub fn detect_lang(text: &str) -> Option<Lang> {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for lang in text.chars().filter_map(script_lang) {
+        match counts.iter_mut().find(|(counted, _)| *counted == lang) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((lang, 1)),
+        }
+    }
+    let (lang, count) = counts.into_iter().max_by_key(|&(_, count)| count)?;
+    let han = text.chars().filter(|c| matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}')).count();
+    let decides = match lang {
+        "ja" => count * 2 > letters - han,
+        _ => count * 2 > letters,
+    };
+    decides.then(|| Lang(lang.to_string()))
+}
+
+#[cfg(test)]
+mod test_lang {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_parse_lang() {
+        assert_eq!("pt-BR".parse::<Lang>().unwrap().to_string(), "pt-BR");
+        assert!("zh-Hant-TW".parse::<Lang>().is_ok());
+        assert!("e".parse::<Lang>().is_err());
+        assert!("en_US".parse::<Lang>().is_err());
+        assert!("en-".parse::<Lang>().is_err());
+    }
+
+    #[test]
+    fn test_detect_lang() {
+        assert_eq!(detect_lang("Καλημέρα κόσμε").unwrap().to_string(), "el");
+        assert_eq!(detect_lang("東京へ行きます").unwrap().to_string(), "ja");
+        assert_eq!(detect_lang("안녕하세요, 친구 hi").unwrap().to_string(), "ko");
+        assert_eq!(detect_lang("Hello, world"), None);
+        assert_eq!(detect_lang("中文文本"), None);
+        assert_eq!(detect_lang("123"), None);
+    }
+
+    #[test]
+    fn test_lang_attributes() {
+        use crate::font::FontStyle;
+        use crate::render::{build_text_svg, RenderConfig};
+
+        let font_config = test_support::font_config(16);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_lang(Some("en".parse().unwrap())).set_detect_lang(true);
+        let (doc, _) = build_text_svg("Hello\nΓειά σου\nworld", &font_config, &render_config).unwrap();
+        let xml = doc.to_string();
+        assert!(xml.starts_with("<svg") && xml[..xml.find('>').unwrap()].contains(r#"xml:lang="en""#), "{}", xml);
+        assert_eq!(xml.matches(r#"xml:lang="el""#).count(), 1, "{}", xml);
+    }
+}
@@ -0,0 +1,116 @@
+// lengths relative to the font size or in pixels, as taken by the spacing,
+// padding and dash options
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A length relative to the font size (em) or in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Em(f32),
+    Px(f32),
+}
+
+impl Length {
+    /// Parses "2px", "1.5em" or a bare number, which `bare` gives the unit of.
+    pub fn parse(s: &str, bare: fn(f32) -> Length) -> Result<Self, String> {
+        let s = s.trim();
+        let (number, unit): (&str, fn(f32) -> Length) = if let Some(px) = s.strip_suffix("px") {
+            (px, Length::Px)
+        } else if let Some(em) = s.strip_suffix("em") {
+            (em, Length::Em)
+        } else {
+            (s, bare)
+        };
+        match number.trim().parse::<f32>() {
+            Ok(value) if value.is_finite() => Ok(unit(value)),
+            _ => Err(format!("Invalid length '{}': expected e.g. 0.1, 0.1em or 2px", s)),
+        }
+    }
+
+    /// Length in pixels at `font_size` pixels, an em being the font size.
+    pub fn to_pixels(&self, font_size: f32) -> f32 {
+        match *self {
+            Length::Em(em) => em * font_size,
+            Length::Px(px) => px,
+        }
+    }
+
+    /// Length in em at `font_size` pixels.
+    pub fn to_em(&self, font_size: f32) -> f32 {
+        match *self {
+            Length::Em(em) => em,
+            Length::Px(px) => px / font_size,
+        }
+    }
+
+    /// The number, whatever its unit.
+    pub fn value(&self) -> f32 {
+        match *self {
+            Length::Em(value) | Length::Px(value) => value,
+        }
+    }
+
+    // Number and unit flag, for cache keys
+    pub(crate) fn key(&self) -> (f32, bool) {
+        match *self {
+            Length::Em(em) => (em, false),
+            Length::Px(px) => (px, true),
+        }
+    }
+}
+
+impl Default for Length {
+    fn default() -> Self {
+        Length::Em(0.0)
+    }
+}
+
+// A bare number is in em, as --space has always been
+impl From<f32> for Length {
+    fn from(em: f32) -> Self {
+        Length::Em(em)
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    /// Parses "0.1", "0.1em" or "2px".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Length::parse(s, Length::Em)
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Length::Em(em) => write!(f, "{}em", em),
+            Length::Px(px) => write!(f, "{}px", px),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_length {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!("0.1".parse::<Length>(), Ok(Length::Em(0.1)));
+        assert_eq!(" 1.5em ".parse::<Length>(), Ok(Length::Em(1.5)));
+        assert_eq!("2px".parse::<Length>(), Ok(Length::Px(2.0)));
+        assert_eq!(Length::parse("20", Length::Px), Ok(Length::Px(20.0)));
+        assert_eq!(Length::parse("-1", Length::Px), Ok(Length::Px(-1.0)));
+        assert!("wide".parse::<Length>().is_err());
+        assert!("infpx".parse::<Length>().is_err());
+    }
+
+    #[test]
+    fn test_to_pixels() {
+        assert_eq!(Length::Em(1.5).to_pixels(20.0), 30.0);
+        assert_eq!(Length::Px(3.0).to_pixels(20.0), 3.0);
+        assert_eq!(Length::Px(5.0).to_em(20.0), 0.25);
+        assert_eq!(Length::Px(-3.0).value(), -3.0);
+        assert_eq!(Length::Em(0.25).to_string().parse::<Length>(), Ok(Length::Em(0.25)));
+    }
+}
@@ -1,5 +1,253 @@
 pub mod font;
+pub mod font_cache;
 pub mod utils;
+pub mod color;
+pub mod validate;
 pub mod highlight;
 pub mod render;
+pub mod markup;
+pub mod effects;
+pub mod gradient;
+pub mod emphasis;
+pub mod wordcolor;
+pub mod background;
+pub mod cssvars;
+pub mod overflow;
+pub mod decoration;
+pub mod dropcap;
 pub mod svg;
+pub mod shape;
+pub mod watch;
+pub mod preview;
+pub mod tune;
+pub mod error;
+pub mod naming;
+pub mod progress;
+pub mod verbosity;
+pub mod profile;
+pub mod metadata;
+pub mod bench;
+pub mod stream;
+pub mod batch;
+pub mod variation;
+pub mod watermark;
+pub mod warp;
+pub mod truncate;
+pub mod xml;
+pub mod conformance;
+pub mod a11y;
+pub mod lang;
+pub mod direction;
+pub mod serve;
+pub mod preprocess;
+pub mod stamp;
+pub mod boxdraw;
+pub mod glyphmap;
+pub mod emoji;
+pub mod page;
+pub mod fragment;
+pub mod woff;
+pub mod subset;
+pub mod textmode;
+pub mod eps;
+pub mod raster;
+pub mod html;
+pub mod length;
+pub mod output;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+#[cfg(test)]
+mod test_support;
+
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use color::Color;
+use font::{FontConfig, FontStyle};
+use length::Length;
+use render::RenderConfig;
+
+pub use utils::{measure_text, TextMetrics};
+
+/// Renders text to SVG markup from code, with the defaults of the command line:
+///
+/// ```no_run
+/// let svg = text2svg::Text2Svg::new().font("Fira Code").size(24).wrap_px(600.0).render("Hello, world")?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Text2Svg {
+    font: Option<String>,
+    font_file: Option<PathBuf>,
+    size: u32,
+    fill: Color,
+    color: Color,
+    style: FontStyle,
+    letter_space: Option<Length>,
+    line_height: Option<Length>,
+    wrap_px: Option<f32>,
+    markup: bool,
+    // Loaded on first use and shared by clones, until an option it depends on changes
+    font_config: OnceLock<Arc<FontConfig>>,
+}
+
+impl Default for Text2Svg {
+    fn default() -> Self {
+        Text2Svg {
+            font: None,
+            font_file: None,
+            size: 64,
+            fill: Color::None,
+            color: Color::BLACK,
+            style: FontStyle::Regular,
+            letter_space: None,
+            line_height: None,
+            wrap_px: None,
+            markup: false,
+            font_config: OnceLock::new(),
+        }
+    }
+}
+
+impl Text2Svg {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Installed font family, or a comma separated list whose later families
+    /// fill in characters the earlier ones lack.
+    pub fn font(mut self, family: &str) -> Self {
+        self.font = Some(family.to_string());
+        self.font_config = OnceLock::new();
+        self
+    }
+
+    /// Font file to render with, instead of an installed family.
+    pub fn font_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.font_file = Some(path.into());
+        self.font_config = OnceLock::new();
+        self
+    }
+
+    /// Font size in pixels.
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = size;
+        self.font_config = OnceLock::new();
+        self
+    }
+
+    /// Fill of the glyphs, none by default.
+    pub fn fill(mut self, fill: Color) -> Self {
+        self.fill = fill;
+        self.font_config = OnceLock::new();
+        self
+    }
+
+    /// Stroke of the glyph outlines, black by default.
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self.font_config = OnceLock::new();
+        self
+    }
+
+    pub fn style(mut self, style: FontStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn letter_space(mut self, space: Length) -> Self {
+        self.letter_space = Some(space);
+        self.font_config = OnceLock::new();
+        self
+    }
+
+    pub fn line_height(mut self, height: Length) -> Self {
+        self.line_height = Some(height);
+        self
+    }
+
+    /// Wraps lines longer than `width` pixels.
+    pub fn wrap_px(mut self, width: f32) -> Self {
+        self.wrap_px = Some(width);
+        self
+    }
+
+    /// Reads `<span>` markup in the text, as --markup does.
+    pub fn markup(mut self, markup: bool) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    // The font is loaded once, on first use, as loading it parses the font files
+    fn font_config(&self) -> anyhow::Result<&FontConfig> {
+        if let Some(font_config) = self.font_config.get() {
+            return Ok(font_config);
+        }
+        let font_config = Arc::new(self.load_font_config()?);
+        Ok(self.font_config.get_or_init(|| font_config))
+    }
+
+    fn load_font_config(&self) -> anyhow::Result<FontConfig> {
+        let mut font_config = match (&self.font_file, &self.font) {
+            (Some(path), _) => FontConfig::from_file(&path.to_string_lossy(), self.size, self.fill, self.color, false)?,
+            (None, Some(family)) => FontConfig::new(family.clone(), self.size, self.fill, self.color, false)?,
+            (None, None) => anyhow::bail!("No font to render with: set one with font() or font_file()"),
+        };
+        if let Some(space) = self.letter_space {
+            font_config.set_letter_space(space);
+        }
+        Ok(font_config)
+    }
+
+    fn render_config(&self) -> RenderConfig {
+        let mut render_config = RenderConfig::new(false, self.style.clone());
+        render_config.set_max_pixel_width(self.wrap_px).set_markup(self.markup);
+        if let Some(height) = self.line_height {
+            render_config.set_line_height(height);
+        }
+        render_config
+    }
+
+    /// `text` rendered as an SVG document.
+    pub fn render_document(&self, text: &str) -> anyhow::Result<::svg::Document> {
+        let (doc, _) = render::build_text_svg(text, self.font_config()?, &self.render_config())?;
+        Ok(doc)
+    }
+
+    /// Size of `text` set as one line, before rendering it.
+    pub fn measure(&self, text: &str) -> anyhow::Result<TextMetrics> {
+        measure_text(text, self.font_config()?, &self.style)
+    }
+
+    /// `text` rendered as SVG markup.
+    pub fn render(&self, text: &str) -> anyhow::Result<String> {
+        Ok(xml::to_xml(&self.render_document(text)?))
+    }
+}
+
+#[cfg(test)]
+mod test_lib {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let builder = Text2Svg::new().font_file(test_support::FONT_PATH).size(20).fill(Color::BLACK);
+        let one_line = builder.render_document("wrap these few words").unwrap();
+        let wrapped = builder.clone().wrap_px(60.0).render_document("wrap these few words").unwrap();
+        let (width, height) = render::document_size(&one_line);
+        let (wrapped_width, wrapped_height) = render::document_size(&wrapped);
+        assert!(wrapped_width < width && wrapped_height > 2.0 * height, "{}x{} wrapped to {}x{}", width, height, wrapped_width, wrapped_height);
+
+        let svg = builder.render("Hi").unwrap();
+        assert!(svg.starts_with("<svg") && svg.contains("<use") && svg.contains(r##"fill="#000000""##), "{}", svg);
+        assert!(Text2Svg::new().render("Hi").is_err());
+        assert!(builder.measure("Hi").unwrap().width > 0.0);
+
+        // The font is loaded once and shared by clones, and reloaded when it changes
+        let loaded: *const FontConfig = builder.font_config().unwrap();
+        assert!(std::ptr::eq(builder.clone().wrap_px(10.0).font_config().unwrap(), loaded));
+        let resized = builder.clone().size(40);
+        assert!(!std::ptr::eq(resized.font_config().unwrap(), loaded));
+        assert_eq!(resized.font_config().unwrap().get_size(), 40);
+    }
+}
@@ -1,114 +1,800 @@
-mod font;
-mod render;
-mod svg;
-mod utils;
-mod highlight;
-
 use anyhow::Error;
-use clap::Parser;
-use font::{FontConfig, FontStyle};
-use highlight::HighlightSetting;
-use render::RenderConfig;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use text2svg::a11y::Accessibility;
+use text2svg::color::{Color, Rgba};
+use text2svg::lang::Lang;
+use text2svg::font::{self, FontConfig, FontStyle, MetricsSource, SizeMode, TabMode};
+use text2svg::length::Length;
+use text2svg::highlight::{CodeChrome, HighlightSetting};
+use text2svg::background::{Background, Border, Edges, Stripes};
+use text2svg::decoration::{Underline, UnderlineStyle};
+use text2svg::direction::{Align, Direction};
+use text2svg::emphasis::Emphasis;
+use text2svg::fragment::{parse_fragment_id, Fragment, FragmentKind, DEFAULT_FRAGMENT_ID};
+use text2svg::error::{error_json, error_kind, invalid_option, io_error, CliError, ErrorFormat, ErrorKind};
+use text2svg::wordcolor::WordColors;
+use text2svg::naming::OutputTemplate;
+use text2svg::output::OutputConfig;
+use text2svg::overflow::{Canvas, Overflow, VAlign};
+use text2svg::gradient::{parse_palette, ColorBy, Gradient};
+use text2svg::render::{self, RenderConfig};
+use text2svg::svg::{self, PathConfig};
+use text2svg::textmode::TextMode;
+use text2svg::raster::{self, RasterOptions};
+use text2svg::utils::WrapMode;
+use text2svg::warp::{Warp, WarpKind};
+use text2svg::verbosity::{self, Verbosity};
+use text2svg::{log_debug, log_info, log_verbose, log_warn};
+#[cfg(feature = "clipboard")]
+use text2svg::clipboard;
+use text2svg::{batch, bench, html, metadata, profile, dropcap, effects, glyphmap, page, preprocess, preview, serve, stamp, stream, truncate, tune, validate, variation, watch, watermark, xml};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Render text or a file to SVG, the same as giving TEXT or --file without a subcommand
+    Render {
+        /// input text string
+        #[arg(conflicts_with = "file")]
+        text: Option<String>,
+    },
+    /// List installed font families
+    Fonts,
+    /// Print the size in pixels the rendered text would have, without writing it
+    Measure {
+        /// input text string
+        #[arg(conflicts_with = "file")]
+        text: Option<String>,
+    },
+    /// List the characters of the text the font has no glyphs for, failing if there are any
+    Coverage {
+        /// input text string
+        #[arg(conflicts_with = "file")]
+        text: Option<String>,
+    },
+    /// Render every file in a directory, each to <name>.svg or the extension of --format
+    Batch {
+        /// directory of input files
+        #[arg(conflicts_with_all = ["file", "highlight", "vary", "drop_cap"])]
+        dir: PathBuf,
+
+        /// Directory outputs are written to (defaults to the input directory)
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+
+        /// Number of files rendered concurrently (defaults to the number of CPUs)
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: Option<usize>,
+    },
+    /// Adjust font, size, spacing and colors with live previews in the terminal, then print the matching options
+    Tune {
+        /// input text string
+        #[arg(conflicts_with = "file")]
+        text: Option<String>,
+
+        /// Inline image protocol (detected from the terminal by default)
+        #[arg(value_enum, long)]
+        protocol: Option<preview::ImageProtocol>,
+    },
+    /// Show the render in the terminal instead of writing it, in terminals supporting inline images
+    Preview {
+        /// input text string
+        #[arg(conflicts_with = "file")]
+        text: Option<String>,
+
+        /// Inline image protocol (detected from the terminal by default)
+        #[arg(value_enum, long)]
+        protocol: Option<preview::ImageProtocol>,
+
+        /// Pixel scale of the preview image
+        #[arg(long, default_value_t = 1.0, value_parser = validate::parse_positive, allow_negative_numbers = true)]
+        scale: f32,
+    },
+    /// Time each rendering stage (wrap, shape, layout, emit) on an input file
+    Bench {
+        /// input text file
+        #[arg(long)]
+        input: PathBuf,
+
+        /// number of timed iterations to average over
+        #[arg(long, default_value_t = 5)]
+        iterations: u32,
+    },
+    /// Run an HTTP service answering GET /render?text=...&font=...&size=...&format=svg|png with the rendered text
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to listen on; 0.0.0.0 accepts requests from other machines
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// Longest text a request may render, in characters
+        #[arg(long, default_value_t = 1000)]
+        max_text_chars: usize,
+
+        /// Largest font size a request may ask for, in pixels
+        #[arg(long, default_value_t = 512)]
+        max_size: u32,
+
+        /// Largest PNG a request may ask for, in pixels of width times height
+        #[arg(long, default_value_t = 16_000_000, value_parser = clap::value_parser!(u64).range(1..))]
+        max_pixels: u64,
+
+        /// Requests handled at once; more are turned away with 503
+        #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+        max_connections: u32,
+    },
+    /// Replace ```text2svg blocks in Markdown with inline SVG: reads mdBook preprocessor JSON on stdin and writes the book
+    Preprocess {
+        /// Read Markdown on stdin and write it with the blocks replaced, for other static site builds
+        #[arg(long)]
+        markdown: bool,
+
+        #[command(subcommand)]
+        check: Option<PreprocessCheck>,
+    },
+    /// Render an SVG written by text2svg again from the options recorded in it, changing the options given here (e.g. --size 64)
+    Rerender {
+        /// SVG file to re-render; it is overwritten unless --output is given
+        svg: PathBuf,
+    },
+    /// Lay text around a circle, along the top and bottom of a ring, with TEXT (e.g. a monogram) in the middle, for seals and logos
+    Stamp {
+        /// text in the middle of the stamp
+        #[arg(conflicts_with = "file")]
+        text: Option<String>,
+
+        /// text along the top of the ring, reading clockwise
+        #[arg(long)]
+        top: Option<String>,
+
+        /// text along the bottom of the ring, reading left to right
+        #[arg(long)]
+        bottom: Option<String>,
+
+        /// outer radius in pixels (fitted to the text by default)
+        #[arg(long, value_parser = validate::parse_positive, allow_negative_numbers = true)]
+        radius: Option<f32>,
+
+        /// ring borders: 0, 1 (outside the text) or 2 (on both sides of it)
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=2))]
+        rings: u8,
+
+        /// stroke width of the rings in pixels
+        #[arg(long, default_value_t = 2.0, value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+        ring_width: f32,
+
+        /// rotation of the ring text in degrees, clockwise
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        rotation: f32,
+
+        /// font size of the middle text in pixels (defaults to --size)
+        #[arg(long, value_parser = validate::parse_font_size)]
+        center_size: Option<u32>,
+    },
+    /// Tile the text diagonally across the --canvas size (e.g. --canvas 1200x800), for overlaying as a watermark
+    Watermark {
+        /// rotation of the rows in degrees, clockwise
+        #[arg(long, default_value_t = -30.0, allow_negative_numbers = true)]
+        rotation: f32,
+
+        /// opacity of the tiled text, from 0 to 1
+        #[arg(long, default_value_t = 0.15, value_parser = validate::parse_opacity, allow_negative_numbers = true)]
+        opacity: f32,
+
+        /// space between neighbouring copies in pixels
+        #[arg(long, default_value_t = 40.0, value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+        gap: f32,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum PreprocessCheck {
+    /// Succeed when mdBook's RENDERER can show the preprocessed book (html can)
+    Supports { renderer: String },
+}
+
+impl Args {
+    // The hidden flags kept from before there were subcommands, turned into the
+    // subcommands they stand for, so only those need handling
+    fn map_legacy_flags(&mut self) -> Result<(), Error> {
+        let legacy = match (self.batch.take(), self.list_fonts) {
+            (Some(dir), _) => Some(Command::Batch { dir, out_dir: self.out_dir.take(), jobs: self.jobs.take() }),
+            (None, true) => Some(Command::Fonts),
+            (None, false) => None,
+        };
+        if let Some(command) = legacy {
+            if self.command.is_some() {
+                return Err(invalid_option("--batch and --list-fonts stand for the batch and fonts subcommands and can't be given with another"));
+            }
+            self.command = Some(command);
+            self.list_fonts = false;
+        }
+        Ok(())
+    }
+}
+
+impl Command {
+    // Text given to the subcommand
+    fn text(&self) -> Option<&str> {
+        match self {
+            Command::Render { text } | Command::Measure { text } | Command::Coverage { text } | Command::Preview { text, .. } | Command::Tune { text, .. }
+            | Command::Stamp { text, .. } => {
+                text.as_deref()
+            }
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Parser)]
-#[command(about,version,long_about=None)]
+// Options given twice take the last value, so the command line overrides a --profile
+#[command(about,version,long_about=None,args_override_self=true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// input text string
-    #[arg(conflicts_with = "file")]
+    #[arg(conflicts_with_all = ["file", "batch"])]
     text: Option<String>,
 
+    /// input paragraph; repeat for more paragraphs, which follow TEXT if it is given too
+    #[arg(long = "text", value_name = "TEXT", global = true, conflicts_with = "file")]
+    paragraphs: Vec<String>,
+
     /// max width per line (characters)
-    #[arg(long, conflicts_with_all = ["highlight", "pixel_width"])]
+    #[arg(long, global = true, conflicts_with_all = ["highlight", "pixel_width"])]
     width: Option<usize>,
 
     /// max width per line (pixels)
-    #[arg(long, conflicts_with_all = ["highlight", "width"])]
+    #[arg(long, global = true, conflicts_with_all = ["highlight", "width"], value_parser = validate::parse_positive, allow_negative_numbers = true)]
     pixel_width: Option<f32>,
 
+    /// Distance between lines: a multiple of the font size (1.4 or 1.2em) or pixels (24px)
+    #[arg(long, global = true, default_value = "1", conflicts_with = "highlight", value_parser = validate::parse_line_height, allow_negative_numbers = true)]
+    line_height: Length,
+
+    /// Extra space between paragraphs (input lines), on top of the line height: a multiple of the font size or pixels
+    #[arg(long, global = true, default_value = "0", conflicts_with = "highlight", value_parser = validate::parse_paragraph_spacing, allow_negative_numbers = true)]
+    paragraph_spacing: Length,
+
+    /// Width of a tab in spaces
+    #[arg(long, global = true, default_value_t = font::DEFAULT_TAB_WIDTH)]
+    tab_width: u32,
+
+    /// How a tab advances the text: to the next stop, every --tab-width spaces, or by --tab-width spaces wherever it is
+    #[arg(value_enum, long, global = true, default_value = "stops")]
+    tab_mode: TabMode,
+
+    /// How to break long lines: none, char or word (by --width) or pixel (by --pixel-width); picked from the width given by default
+    #[arg(long, global = true, value_enum, conflicts_with = "highlight")]
+    wrap: Option<WrapMode>,
+
+    /// Count every character as one column with --width and --max-chars-per-line, rather than wide East Asian characters as two
+    #[arg(long, global = true)]
+    naive_width: bool,
+
     /// input file
-    #[arg(long,short, conflicts_with = "text")]
+    #[arg(long, short, global = true)]
     file: Option<PathBuf>,
 
-    /// output svg file path
-    #[arg(short, long, default_value = "output.svg")]
-    output: Option<PathBuf>,
+    /// Same as the batch subcommand, kept for older scripts
+    #[arg(long, hide = true, conflicts_with_all = ["file", "highlight", "vary", "drop_cap"])]
+    batch: Option<PathBuf>,
+
+    /// --out-dir of the batch subcommand, with --batch
+    #[arg(long, hide = true, requires = "batch")]
+    out_dir: Option<PathBuf>,
+
+    /// --jobs of the batch subcommand, with --batch
+    #[arg(long, hide = true, requires = "batch", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: Option<usize>,
+
+    /// output svg file path, or "-" for stdout; may name files after the input with {stem}, {n} and {date} (e.g. "out/{stem}-{n:03}.svg")
+    #[arg(short, long, global = true, default_value = "output.svg")]
+    output: OutputTemplate,
 
-    /// font family name (e.g., "Arial", "Times New Roman")
-    #[arg(long)]
+    /// Output format: svg, svgz for gzip-compressed SVG, eps for PostScript, a png, webp or jpeg image, or an html page (the default for outputs with those extensions)
+    #[arg(value_enum, long, global = true)]
+    format: Option<svg::OutputFormat>,
+
+    /// HTML file html output is inlined into, at its {{svg}} placeholder, instead of a minimal page
+    #[arg(long, global = true, value_name = "FILE", value_parser = html::parse_html_template)]
+    html_template: Option<String>,
+
+    /// Color under png, webp and jpeg images, which are transparent without it except jpeg, which is white
+    #[arg(long, global = true)]
+    raster_background: Option<Rgba>,
+
+    /// JPEG quality, from 1 to 100
+    #[arg(long, global = true, default_value_t = raster::DEFAULT_QUALITY, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
+
+    /// Width of png, webp and jpeg images in pixels; with --raster-height too, the text is fitted and centered in both
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    raster_width: Option<u32>,
+
+    /// Height of png, webp and jpeg images in pixels
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    raster_height: Option<u32>,
+
+    /// Write only a <g> or <symbol> fragment, without the <svg> wrapper or its size, to paste into another SVG or HTML page
+    #[arg(value_enum, long, global = true, conflicts_with = "chunk_lines")]
+    fragment: Option<FragmentKind>,
+
+    /// id of the fragment's element
+    #[arg(long, global = true, requires = "fragment", default_value = DEFAULT_FRAGMENT_ID, value_parser = parse_fragment_id)]
+    fragment_id: String,
+
+    /// font family name (e.g., "Arial", "Times New Roman"), or a comma separated list whose later families fill in characters the earlier ones lack
+    #[arg(long, global = true)]
     font: Option<String>,
 
     /// font size in pixels
-    #[arg(long, default_value_t = 64)]
+    #[arg(long, global = true, default_value_t = 64, value_parser = validate::parse_font_size, allow_negative_numbers = true)]
     size: u32,
 
-    /// svg fill color (e.g., "#ff0000", "none"). Overridden by highlight.
-    #[arg(long, conflicts_with="highlight", default_value = "none")]
-    fill: String,
+    /// What the font size measures: the em square (em, as browsers do) or the height from descent to ascent (cap-to-box, the sizing of earlier versions)
+    #[arg(value_enum, long, global = true, default_value = "em")]
+    size_mode: SizeMode,
+
+    /// Font table the ascent, descent and line gap are read from, to match how a target places text: typo, hhea, win or auto
+    #[arg(value_enum, long, global = true, default_value = "auto")]
+    metrics: MetricsSource,
 
-    /// font stroke color (e.g., "#000", "currentColor"). Overridden by highlight.
-    #[arg(long, conflicts_with="highlight", default_value = "#000")]
-    color: String,
+    /// svg fill color: a CSS color such as "#ff0000", "red", "rgb(255 0 0)" or "hsl(0, 100%, 50%)", or "none". Overridden by highlight.
+    #[arg(long, global = true, conflicts_with="highlight", default_value = "none")]
+    fill: Color,
+
+    /// font stroke color: a CSS color (e.g., "#000", "black") or "currentColor". Overridden by highlight.
+    #[arg(long, global = true, conflicts_with="highlight", default_value = "#000")]
+    color: Color,
+
+    /// Paint through CSS variables (--text2svg-fg, --text2svg-fill, ...) falling back to the given colors
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    css_vars: bool,
+
+    /// Stroke width in pixels
+    #[arg(long, global = true, conflicts_with = "highlight", default_value = "1", value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+    stroke_width: f32,
+
+    /// Corner style of the stroke: round, miter or bevel
+    #[arg(long, global = true, conflicts_with = "highlight", value_parser = svg::parse_line_join, default_value = "round")]
+    stroke_linejoin: svg::StrokeLineJoin,
+
+    /// How far miter joins may reach, in stroke widths, before they are beveled
+    #[arg(long, global = true, conflicts_with = "highlight", default_value = "4")]
+    stroke_miterlimit: f32,
+
+    /// Dash pattern of the stroke: dashed, dotted, or dash and gap lengths in em (e.g. "0.2,0.1") or pixels ("6px 3px")
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    stroke_dash: Option<svg::StrokeDash>,
+
+    /// Shift of the dash pattern along the stroke, in em or pixels
+    #[arg(long, global = true, requires = "stroke_dash", default_value = "0")]
+    stroke_dashoffset: Length,
+
+    /// Paint the stroke below the fill so heavy outlines don't eat into the glyphs
+    #[arg(value_enum, long, global = true, conflicts_with = "highlight", default_value = "normal")]
+    paint_order: svg::PaintOrder,
 
     /// Add draw animation effect (works best with stroke only)
-    #[arg(long, conflicts_with="highlight")]
+    #[arg(long, global = true, conflicts_with="highlight")]
     animate: bool,
 
-    /// font style (regular, bold, italic, etc.). Overridden by highlight.
-    #[arg(value_enum, long, conflicts_with="highlight", default_value = "regular")]
+    /// font style: a weight (thin, extralight, light, regular, medium, semibold, bold, extrabold, black), italic, or a weight with -italic (e.g. bold-italic). Overridden by highlight.
+    #[arg(value_enum, long, global = true, conflicts_with="highlight", default_value = "regular")]
     style: Option<FontStyle>,
 
-    /// letter spacing (in em units, e.g., 0.1)
-    #[arg(long, default_value_t = 0.0)] // Default to 0 for better compatibility with <use> positioning
-    space: f32,
+    /// letter spacing in em of the font size (e.g., 0.1 or 0.1em; bare numbers are em) or pixels (e.g., 2px)
+    #[arg(long, visible_alias = "letter-space", global = true, default_value = "0", allow_hyphen_values = true)] // Default to 0 for better compatibility with <use> positioning
+    space: Length,
+
+    /// Text shadows "dx dy [blur] [color]", comma separated, first on top (e.g. "1 1 0 #000, 3 3 6 #0008")
+    #[arg(long, global = true)]
+    shadow: Option<String>,
+
+    /// Blur the whole text layer, effects included, by this standard deviation in pixels
+    #[arg(long, global = true, value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+    blur: Option<f32>,
+
+    /// Effect preset applied to the text
+    #[arg(value_enum, long, global = true, conflicts_with = "highlight")]
+    effect: Option<effects::Effect>,
+
+    /// Glow color of the neon effect
+    #[arg(long, global = true, requires = "effect", default_value = "#0ff")]
+    glow_color: Color,
+
+    /// Extrude the text in 3D: "depth,angle,color", angle in degrees clockwise from the right (e.g. "8,45,#333")
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    extrude: Option<effects::Extrude>,
+
+    /// Color each character along a palette: comma separated colors (e.g. "#f00,#00f") or "rainbow"
+    #[arg(long, global = true, conflicts_with_all = ["highlight", "extrude"])]
+    gradient: Option<String>,
+
+    /// Whether the gradient steps per character or per word
+    #[arg(value_enum, long, global = true, requires = "gradient", default_value = "char")]
+    gradient_by: ColorBy,
+
+    /// Color the matches of a regex in each line: "pattern:color", or "pattern:bg=color" for a background (repeatable)
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    highlight_regex: Vec<Emphasis>,
+
+    /// Color words from a JSON or CSV map of words, or word indices counting from 0, to colors
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    word_colors: Option<PathBuf>,
+
+    /// Alternating background bands behind the lines, one color per line in turn (e.g. "#fff,#f4f4f4")
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    stripe_colors: Option<Stripes>,
 
-    /// font features (e.g., "cv01=1,calt=0,liga=1")
-    #[arg(long, conflicts_with="highlight")]
+    /// Fill behind the text, its padding and border: a color, "linear-gradient([angle deg,] colors...)" or an image file (png, jpeg, gif, webp or svg)
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    background: Option<Background>,
+
+    /// Underline every line, drawn as a path [possible values: solid, dashed, wavy]
+    #[arg(value_enum, long, global = true, num_args = 0..=1, default_missing_value = "solid")]
+    underline: Option<UnderlineStyle>,
+
+    /// Underline color (defaults to the text color)
+    #[arg(long, global = true, requires = "underline")]
+    underline_color: Option<Color>,
+
+    /// Underline thickness in pixels (defaults to the font's)
+    #[arg(long, global = true, requires = "underline", value_parser = validate::parse_positive, allow_negative_numbers = true)]
+    underline_thickness: Option<f32>,
+
+    /// Distance in pixels from the baseline down to the underline (defaults to the font's)
+    #[arg(long, global = true, requires = "underline", allow_negative_numbers = true)]
+    underline_offset: Option<f32>,
+
+    /// Render at most this many lines, after wrapping
+    #[arg(long, global = true)]
+    max_lines: Option<usize>,
+
+    /// Split the text into pages this many pixels tall, written to <output>-001.svg, <output>-002.svg, ... (or the files {n} names)
+    #[arg(long, value_parser = parse_page_height,
+        conflicts_with_all = ["batch", "watch", "chunk_lines", "highlight", "vary", "drop_cap", "canvas", "glyph_map"])]
+    page_height: Option<f32>,
+
+    /// Cut lines longer than this many characters, after wrapping, ending them with --ellipsis
+    #[arg(long, global = true, conflicts_with = "highlight", value_parser = clap::value_parser!(u32).range(1..))]
+    max_chars_per_line: Option<u32>,
+
+    /// What ends lines cut by --max-chars-per-line, and with --overflow ellipsis the last line kept by --max-lines; counted in the widths
+    #[arg(long, global = true, default_value = truncate::DEFAULT_ELLIPSIS)]
+    ellipsis: String,
+
+    /// How text that doesn't fit ends: past --max-lines it is cut off, fades out or ends with --ellipsis, past --canvas it is cut off, scaled down or an error
+    #[arg(value_enum, long, global = true, default_value = "clip")]
+    overflow: Overflow,
+
+    /// Exact output size "WIDTHxHEIGHT" in pixels (e.g. "1200x630"), with the text at its top left corner
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    canvas: Option<Canvas>,
+
+    /// Where the text sits vertically on the canvas; baseline puts the first line's baseline on its middle
+    #[arg(value_enum, long, global = true, requires = "canvas", default_value = "top")]
+    valign: VAlign,
+
+    /// Base direction of the text; rtl aligns lines right and swaps left and right padding, margin and gutter
+    #[arg(value_enum, long, global = true, default_value = "auto")]
+    direction: Direction,
+
+    /// Horizontal alignment of the lines; start and end follow the direction
+    #[arg(value_enum, long, global = true, default_value = "start")]
+    align: Align,
+
+    /// Frame around the whole image: "width,color[,style]", style one of solid, double, dashed, dotted (e.g. "4,#c90,double")
+    #[arg(long, global = true)]
+    border: Option<Border>,
+
+    /// Corner radius of the border in pixels
+    #[arg(long, global = true, requires = "border", default_value_t = 0.0, value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+    border_radius: f32,
+
+    /// Space between the text and the border in pixels
+    #[arg(long, global = true, requires = "border", default_value_t = 0.0, value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+    border_padding: f32,
+
+    /// Space around the text inside any background and border, 1 to 4 CSS style lengths in px or em (e.g. "20 40")
+    #[arg(long, global = true, conflicts_with = "highlight", default_value = "0")]
+    padding: Edges,
+
+    /// Space around the whole image outside any border, 1 to 4 CSS style lengths in px or em (e.g. "10 20 10 20")
+    #[arg(long, global = true, conflicts_with = "highlight", default_value = "0")]
+    margin: Edges,
+
+    /// Animate an axis of a variable font, pulsing between two values: "axis=from:to" (e.g. "wght=100:900")
+    #[arg(long, global = true, conflicts_with_all = ["highlight", "chunk_lines", "watch"])]
+    vary: Option<variation::AxisAnimation>,
+
+    /// Number of keyframes outlined for --vary
+    #[arg(long, global = true, requires = "vary", default_value_t = 12, value_parser = clap::value_parser!(u16).range(2..))]
+    keyframes: u16,
+
+    /// Seconds one --vary cycle takes
+    #[arg(long, global = true, requires = "vary", default_value_t = 2.0, value_parser = validate::parse_positive, allow_negative_numbers = true)]
+    vary_duration: f32,
+
+    /// Drop the first letter of the first paragraph this many lines deep, with the text flowing around it
+    #[arg(long, global = true, value_parser = clap::value_parser!(u16).range(2..), conflicts_with_all = ["highlight", "markup", "vary", "chunk_lines", "watch"])]
+    drop_cap: Option<u16>,
+
+    /// Bend the text like WordArt
+    #[arg(value_enum, long, global = true, conflicts_with = "highlight")]
+    warp: Option<WarpKind>,
+
+    /// Strength of the warp, as a fraction of the text height (negative bends the other way)
+    #[arg(long, global = true, requires = "warp", default_value_t = 0.3, allow_negative_numbers = true, value_parser = parse_warp_amount)]
+    warp_amount: f32,
+
+    /// Sticker style outline: width in pixels of each ring drawn around the glyphs
+    #[arg(long, global = true, conflicts_with = "highlight", value_parser = validate::parse_positive, allow_negative_numbers = true)]
+    contour: Option<f32>,
+
+    /// Colors of the contour rings, innermost first; one ring per color
+    #[arg(long, global = true, requires = "contour", default_value = "#fff")]
+    contour_colors: String,
+
+    /// Corner style of the contour rings
+    #[arg(value_enum, long, global = true, requires = "contour", default_value = "round")]
+    contour_join: effects::ContourJoin,
+
+    /// Interpret the input as markup, e.g. <span letter-spacing="2px">spaced</span>
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    markup: bool,
+
+    /// Draw box-drawing characters (─│┌┐└┘├┤┬┴┼, and +-| where they join up) as connected lines, for diagrams pasted from terminals
+    #[arg(long, global = true, conflicts_with = "highlight")]
+    box_drawing: bool,
+
+    /// How the text is written: glyph outlines (paths), or <text> elements with the font embedded as WOFF, selectable and editable in Inkscape or Figma (text)
+    #[arg(value_enum, long, global = true, default_value = "paths",
+        conflicts_with_all = ["highlight", "chunk_lines", "warp", "vary", "drop_cap", "box_drawing"])]
+    text_mode: TextMode,
+
+    /// decimal places kept for path coordinates
+    #[arg(long, global = true, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=6))]
+    precision: u8,
+
+    /// Snap path coordinates to a grid of this many pixels (e.g. 0.25) for smaller, more compressible output
+    #[arg(long, global = true, value_parser = parse_quantize_grid)]
+    quantize: Option<f32>,
+
+    /// Snap baselines, glyph positions and straight stems to whole pixels, for crisp output rasterized at small sizes (10-14px)
+    #[arg(long, global = true)]
+    hint: bool,
+
+    /// Round glyph x positions: none keeps fractional advances, half or full give crisper glyphs that repeat more often
+    #[arg(value_enum, long, global = true, default_value = "none")]
+    glyph_snap: svg::GlyphSnap,
+
+    /// Draw cluster boundaries and the kerning applied between glyphs, labelled in font units, to check shaping
+    #[arg(long, global = true)]
+    show_shaping: bool,
+
+    /// Label read by screen readers, as <title> and aria-label; defaults to the start of the text
+    #[arg(long, global = true)]
+    title: Option<String>,
+
+    /// Longer description read by screen readers, as <desc>
+    #[arg(long, global = true)]
+    desc: Option<String>,
+
+    /// Hide the SVG from screen readers (aria-hidden), for text that is only decoration
+    #[arg(long, global = true, conflicts_with_all = ["title", "desc"])]
+    decorative: bool,
+
+    /// Language of the text as a BCP 47 tag (e.g. "en", "pt-BR"), set as xml:lang and lang on the root
+    #[arg(long, global = true)]
+    lang: Option<Lang>,
+
+    /// Also tag each line written in a script of its own (e.g. Greek, Hangul, kana) with its language
+    #[arg(long, global = true)]
+    detect_lang: bool,
+
+    /// Estimate widths from glyph advances when wrapping (no kerning or ligatures); faster on huge inputs
+    #[arg(long, global = true)]
+    fast_measure: bool,
+
+    /// Font emoji are taken from before the text's fonts, or "none"; by default the first installed of Apple Color Emoji, Segoe UI Emoji, Noto Color Emoji, Noto Emoji and Twemoji Mozilla
+    #[arg(long, global = true, value_name = "FONT")]
+    emoji_font: Option<String>,
+
+    /// font features (e.g., "cv01=1,calt=0,liga=1"), or "help" to list the features the font implements
+    #[arg(long, global = true, conflicts_with="highlight", value_parser = validate::parse_features)]
     features: Option<String>,
 
     /// Enable syntax highlighting mode for files
-    #[arg(long)]
+    #[arg(long, global = true)]
     highlight: bool,
 
     /// Syntax highlighting theme name or path to .tmTheme file
-    #[arg(long, requires="highlight", default_value="base16-ocean.dark")]
+    #[arg(long, global = true, requires="highlight", default_value="base16-ocean.dark")]
     theme: Option<String>,
 
+    /// Number the lines of highlighted code in a gutter
+    #[arg(long, global = true, requires = "highlight")]
+    line_numbers: bool,
+
+    /// Draw highlighted code on a panel with rounded corners of this radius in pixels
+    #[arg(long, global = true, requires = "highlight", value_parser = validate::parse_non_negative, allow_negative_numbers = true)]
+    rounded: Option<f32>,
+
+    /// Add a window title bar with traffic light buttons above highlighted code
+    #[arg(long, global = true, requires = "highlight")]
+    window_bar: bool,
+
+    /// Title shown in the window bar (e.g. the file name)
+    #[arg(long, global = true, requires = "window_bar")]
+    window_title: Option<String>,
+
     /// List supported file types/syntax for highlighting
-    #[arg(long)]
+    #[arg(long, global = true)]
     list_syntax: bool,
 
     /// List available built-in highlighting themes
-    #[arg(long)]
+    #[arg(long, global = true)]
     list_theme: bool,
 
-    /// Enable debug logging
-    #[arg(short, long)]
+    /// Enable debug logging and check the written SVG with a strict parser, the same as -vv
+    #[arg(short, long, global = true)]
     debug: bool,
 
-    /// List installed font families
-    #[arg(long)]
+    /// Report more: -v adds output statistics such as the size of the written file, -vv debug details
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// After rendering, print "width=W height=H lines=N pages=P output=PATH" for each output, for scripts
+    #[arg(long, global = true)]
+    print_dimensions: bool,
+
+    /// Only report errors: no progress messages, warnings or progress bars (bars are only drawn when stderr is a terminal)
+    #[arg(short, long, global = true, conflicts_with_all = ["verbose", "debug"])]
+    quiet: bool,
+
+    /// Same as the fonts subcommand, kept for older scripts
+    #[arg(long, hide = true)]
     list_fonts: bool,
+
+    /// Render the input file this many lines at a time, streaming the output (for huge files)
+    #[arg(long, global = true, requires = "file", conflicts_with_all = ["highlight", "watch"])]
+    chunk_lines: Option<usize>,
+
+    /// Refuse to render when the estimated memory use exceeds this (e.g. "512M", "2G")
+    #[arg(long, global = true, value_parser = stream::parse_memory_size)]
+    max_memory: Option<u64>,
+
+    /// Keep running and re-render the input file whenever it changes
+    #[arg(long, global = true, requires = "file")]
+    watch: bool,
+
+    /// Copy the output to the system clipboard instead of writing a file: the SVG markup, or the image as png
+    #[cfg(feature = "clipboard")]
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "svg",
+        conflicts_with_all = ["output", "batch", "watch", "chunk_lines", "highlight", "vary", "drop_cap"])]
+    clipboard: Option<clipboard::ClipboardFormat>,
+
+    /// Also write a JSON map from the id of every glyph's <use> element to its source line and character range
+    #[arg(long, value_name = "FILE",
+        conflicts_with_all = ["batch", "watch", "chunk_lines", "highlight", "vary", "drop_cap", "warp", "text_mode"])]
+    glyph_map: Option<PathBuf>,
+
+    /// Load the options of this named profile from the config file (~/.config/text2svg/config, or $TEXT2SVG_CONFIG); options given on the command line override them
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// How errors are reported on stderr: text, or one JSON object with the kind, exit code, message, path and span
+    #[arg(value_enum, long, global = true, default_value = "text")]
+    error_format: ErrorFormat,
 }
 
 fn main() {
+    // The background copy keeping the clipboard contents, started by clipboard::copy
+    #[cfg(feature = "clipboard")]
+    if std::env::var_os(clipboard::CLIPBOARD_OWNER_ENV).is_some() {
+        // The copy waiting on it reports the reason
+        if let Err(e) = clipboard::serve_clipboard() {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let raw_argv: Vec<String> = std::env::args().collect();
+    let requested_format = match json_errors_requested(&raw_argv) {
+        true => ErrorFormat::Json,
+        false => ErrorFormat::Text,
+    };
+    let mut argv = profile::expand_profile_args(raw_argv, None)
+        .unwrap_or_else(|message| exit_with_error(invalid_option(message), requested_format));
+    let mut args = parse_args(argv.clone());
+    // A rerender is the recorded command with the options given to it on top
+    if let Some(Command::Rerender { svg }) = &args.command {
+        argv = rerender_argv(&argv, svg).unwrap_or_else(|e| exit_with_error(e, requested_format));
+        args = parse_args(argv.clone());
+    }
+    let error_format = args.error_format;
+
+    if let Err(e) = run(args, &argv) {
+        exit_with_error(e, error_format);
+    }
+}
 
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        // Consider adding more context, e.g., e.chain()
-        std::process::exit(1);
+fn exit_with_error(e: Error, error_format: ErrorFormat) -> ! {
+    match error_format {
+        ErrorFormat::Text => eprintln!("Error: {}", e),
+        ErrorFormat::Json => eprintln!("{}", error_json(&e)),
     }
+    std::process::exit(error_kind(&e).exit_code());
 }
 
-fn run() -> Result<(),Error> {
-    let args = Args::parse();
+// Arguments to run the command recorded in `svg` with, followed by the ones
+// given after `rerender`
+fn rerender_argv(argv: &[String], svg: &Path) -> Result<Vec<String>, Error> {
+    let options = metadata::read_options(svg)?;
+    let start = argv.iter().position(|arg| arg == "rerender").map_or(argv.len(), |i| i + 1);
+    let mut overrides = argv[start..].to_vec();
+    if let Some(i) = overrides.iter().position(|arg| Path::new(arg) == svg) {
+        overrides.remove(i);
+    }
+    Ok(metadata::rerender_args(&argv[0], &options, svg, &overrides))
+}
 
-    if args.debug {
-        println!("Debug Mode Enabled");
-        println!("Args: {:?}", args);
+fn parse_args(argv: Vec<String>) -> Args {
+    let json_errors = json_errors_requested(&argv);
+    match Args::try_parse_from(argv) {
+        Ok(args) => args,
+        // Usage errors are reported by clap itself unless JSON is asked for
+        Err(e) if e.use_stderr() && json_errors => {
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+            let e = Error::new(CliError::new(ErrorKind::InvalidOption, message));
+            eprintln!("{}", error_json(&e));
+            std::process::exit(error_kind(&e).exit_code());
+        }
+        Err(e) => e.exit(),
     }
+}
 
-    if args.list_fonts {
+// Whether the unparsed arguments `args` ask for JSON errors, for errors in parsing them
+fn json_errors_requested(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == "--error-format=json")
+        || args.windows(2).any(|pair| pair[0] == "--error-format" && pair[1] == "json")
+}
+
+fn run(mut args: Args, argv: &[String]) -> Result<(),Error> {
+    args.map_legacy_flags()?;
+    let level = match args.debug {
+        true => Verbosity::Debug,
+        false => Verbosity::from_flags(args.verbose, args.quiet),
+    };
+    verbosity::set_verbosity(level);
+    // With the SVG on stdout, everything else goes to stderr
+    let to_stdout = args.output.expand(args.file.as_deref(), 1, &HashMap::new()).is_ok_and(|path| verbosity::is_stdout(&path));
+    verbosity::set_pipe_mode(to_stdout);
+
+    log_debug!("Debug Mode Enabled");
+    log_debug!("Args: {:?}", args);
+    if let Some(profile) = &args.profile {
+        log_debug!("Options of profile {} loaded", profile);
+    }
+    // mdBook asks before running a preprocessor, with its usual options given
+    if let Some(Command::Preprocess { check: Some(PreprocessCheck::Supports { renderer }), .. }) = &args.command {
+        std::process::exit(if renderer == "html" { 0 } else { 1 });
+    }
+    // Spacing is only checked against the size once both are known
+    validate::validate_spacing(args.space, args.size).map_err(invalid_option)?;
+
+    if matches!(args.command, Some(Command::Fonts)) {
         println!("Installed Font Families:");
         let fonts = font::fonts();
         if fonts.is_empty() {
@@ -132,11 +818,11 @@ fn run() -> Result<(),Error> {
              match highlight_setting.add_theme_from_path("custom-theme", &path) {
                  Ok(_) => {
                      highlight_setting.set_theme("custom-theme");
-                     if args.debug { println!("Loaded custom theme from: {}", path.display()); }
+                     log_debug!("Loaded custom theme from: {}", path.display());
                  }
                  Err(e) => {
-                    // Use eprintln for warnings/errors
-                    eprintln!("Warning: Failed to load theme from path '{}': {}. Using default.", path.display(), e);
+                    // Rendering goes on with the default theme
+                    log_warn!("Failed to load theme from path '{}': {}. Using default.", path.display(), e);
                     // Optionally reset to default theme name if loading failed?
                     // highlight_setting.set_theme("base16-ocean.dark"); // Example reset
                  }
@@ -144,16 +830,25 @@ fn run() -> Result<(),Error> {
         } else {
             // Assume it's a built-in theme name
             if highlight_setting.get_theme(theme_path_or_name).is_none() {
-                 eprintln!("Warning: Theme '{}' not found. Available themes:", theme_path_or_name);
-                 list_themes(&highlight_setting);
-                 eprintln!("Using default theme: {}", highlight_setting.theme);
+                 // Only warnings here, as stdout may carry the SVG
+                 log_warn!(
+                     "Theme '{}' not found, using the default theme {} (see --list-theme)",
+                     theme_path_or_name,
+                     highlight_setting.theme
+                 );
             } else {
                  highlight_setting.set_theme(theme_path_or_name);
-                 if args.debug { println!("Using built-in theme: {}", theme_path_or_name); }
+                 log_debug!("Using built-in theme: {}", theme_path_or_name);
             }
         }
     }
-
+    highlight_setting.set_chrome(CodeChrome {
+        line_numbers: args.line_numbers,
+        radius: args.rounded,
+        window_bar: args.window_bar,
+        title: args.window_title.clone(),
+        direction: args.direction,
+    });
 
     if args.list_syntax {
         println!("Supported Syntaxes (Name, Extensions):");
@@ -168,97 +863,677 @@ fn run() -> Result<(),Error> {
        return Ok(()); // Exit after listing
     }
 
+    // Subcommands taking text read it in place of the top level argument
+    let text = text_paragraphs(args.text.as_deref().or_else(|| args.command.as_ref().and_then(Command::text)), &args.paragraphs);
+
     // --- Font and Render Config ---
     // Require font for actual rendering
-    let font_name = match args.font {
+    let font_name = match args.font.clone() {
         Some(f) => f,
         None => {
             // Don't exit if only listing things, but require for rendering
-            if args.text.is_none() && args.file.is_none() {
+            if text.is_none() && args.file.is_none() && args.command.is_none() {
                  return Ok(()); // Nothing to render, maybe just listed things
             }
-            return Err(anyhow::anyhow!("--font option is required for rendering"));
+            return Err(invalid_option("--font option is required for rendering"));
         }
     };
 
-    let output_path = args.output.unwrap_or_else(|| PathBuf::from("output.svg"));
+    let output_path = args.output.expand(args.file.as_deref(), 1, &HashMap::new()).map_err(invalid_option)?;
+    if args.output.has_placeholders() {
+        if let Some(parent) = output_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+        }
+    }
 
     // Create FontConfig
-    let mut font_config = FontConfig::new(
-        font_name,
-        args.size,
-        args.fill.clone(), // Clone needed as args might be used later
-        args.color.clone(),
-        args.debug
-    )?;
-    font_config.set_letter_space(args.space);
+    let font_load_started = Instant::now();
+    let mut font_config = font_config_from_args(font_name, &args)?;
 
-    // Apply font features if specified
+    let style = args.style.clone().unwrap_or(FontStyle::Regular);
+    // A style the family lacks would otherwise render as Regular without a word
+    let available = font_config.get_available_styles();
+    if !args.highlight && style != FontStyle::Regular && !available.contains(&style) {
+        let available: Vec<String> = available.iter().map(FontStyle::cli_name).collect();
+        return Err(invalid_option(format!(
+            "{} has no {} face; its styles are {}",
+            font_config.get_font_name(),
+            style.cli_name(),
+            available.join(", ")
+        )));
+    }
+    if args.features.as_deref() == Some("help") {
+        print_font_features(&font_config, &style);
+        return Ok(());
+    }
     if let Some(features_str) = &args.features {
-        if let Err(err) = font_config.set_features_from_string(features_str) {
-            return Err(anyhow::anyhow!("Failed to parse font features '{}': {}", features_str, err));
-        }
-        if args.debug {
-            println!("Applied font features: {}", font_config.get_features_summary());
+        let unsupported = font_config.unsupported_features(features_str, &style);
+        if !unsupported.is_empty() {
+            let implemented = font_config.font_feature_tags(&style);
+            let unsupported: Vec<String> = unsupported
+                .iter()
+                .map(|tag| match validate::closest(tag, implemented.iter().map(String::as_str)) {
+                    Some(known) => format!("{} (did you mean {}?)", tag, known),
+                    None => tag.clone(),
+                })
+                .collect();
+            log_warn!(
+                "{} doesn't implement the font features {}; they have no effect (see --features help)",
+                font_config.get_font_name(),
+                unsupported.join(", ")
+            );
         }
     }
 
-    if args.debug {
-        println!("Font Config: {:?}", font_config);
-        println!("Active font features: {}", font_config.get_features_summary());
-    }
+    log_debug!("Font Config: {:?}", font_config);
+    log_debug!("Active font features: {}", font_config.get_features_summary());
 
     // Create RenderConfig (for non-highlight mode)
-    let mut render_config = RenderConfig::new(args.animate, args.style.unwrap_or(FontStyle::Regular));
+    let mut render_config = RenderConfig::new(args.animate, args.style.clone().unwrap_or(FontStyle::Regular));
     render_config.set_max_width(args.width);
     render_config.set_max_pixel_width(args.pixel_width);
+    match (args.wrap, args.width, args.pixel_width) {
+        (Some(WrapMode::Char | WrapMode::Word), None, _) => {
+            return Err(invalid_option("--wrap char and --wrap word need the line length in characters, e.g. --width 40"));
+        }
+        (Some(WrapMode::Pixel), _, None) => {
+            return Err(invalid_option("--wrap pixel needs the line length in pixels, e.g. --pixel-width 400"));
+        }
+        _ => {}
+    }
+    render_config.set_wrap(args.wrap).set_east_asian_width(!args.naive_width);
+    render_config.set_line_height(args.line_height).set_paragraph_spacing(args.paragraph_spacing);
+    render_config.set_markup(args.markup);
+    render_config.set_box_drawing(args.box_drawing);
+    render_config.set_glyph_ids(args.glyph_map.is_some());
+    render_config.set_text_mode(args.text_mode);
+    if let Some(shadow) = &args.shadow {
+        render_config.set_shadows(effects::parse_shadows(shadow).map_err(invalid_option)?);
+    }
+    render_config.set_extrude(args.extrude.clone());
+    render_config.set_warp(args.warp.map(|kind| Warp::new(kind, args.warp_amount)));
+    if let Some(offset) = args.contour.filter(|offset| *offset > 0.0) {
+        let colors = effects::parse_contour_colors(&args.contour_colors).map_err(invalid_option)?;
+        render_config.set_contour(Some(effects::Contour::new(offset, colors, args.contour_join)));
+    }
+    if let Some(palette) = &args.gradient {
+        let palette = parse_palette(palette).map_err(invalid_option)?;
+        render_config.set_gradient(Some(Gradient::new(palette, args.gradient_by)));
+    }
+    render_config.set_emphases(args.highlight_regex.clone());
+    if let Some(path) = &args.word_colors {
+        render_config.set_word_colors(Some(WordColors::load(path).map_err(anyhow::Error::msg)?));
+    }
+    render_config.set_stripes(args.stripe_colors.clone());
+    render_config.set_background(args.background.clone());
+    render_config.set_max_chars_per_line(args.max_chars_per_line.map(|n| n as usize)).set_ellipsis(&args.ellipsis);
+    // A watermark's canvas is the size it tiles, not one the text is fitted into
+    let fit_canvas = args.canvas.filter(|_| !matches!(args.command, Some(Command::Watermark { .. })));
+    render_config.set_max_lines(args.max_lines).set_overflow(args.overflow).set_canvas(fit_canvas.map(|mut canvas| {
+        canvas.set_valign(args.valign);
+        canvas
+    }));
+    render_config.set_underline(args.underline.map(|style| {
+        let mut underline = Underline::new(style);
+        underline
+            .set_color(args.underline_color)
+            .set_thickness(args.underline_thickness)
+            .set_offset(args.underline_offset);
+        underline
+    }));
+    render_config.set_padding(args.padding).set_margin(args.margin);
+    render_config.set_accessibility(Accessibility {
+        title: args.title.clone(),
+        description: args.desc.clone(),
+        decorative: args.decorative,
+    });
+    render_config.set_lang(args.lang.clone()).set_detect_lang(args.detect_lang);
+    render_config.set_direction(args.direction).set_align(args.align);
+    render_config.set_border(args.border.clone().map(|mut border| {
+        border.set_radius(args.border_radius).set_padding(args.border_padding);
+        border
+    }));
+    render_config.set_blur(args.blur);
+    render_config.set_css_vars(args.css_vars);
+    if args.animate && args.stroke_dash.is_some() {
+        // The draw animation runs on the dash pattern itself
+        return Err(invalid_option("--stroke-dash can't be combined with --animate"));
+    }
+    render_config.set_path_config(PathConfig {
+        stroke_width: args.stroke_width.max(0.0),
+        stroke_linejoin: args.stroke_linejoin,
+        stroke_miterlimit: args.stroke_miterlimit.max(1.0),
+        paint_order: args.paint_order,
+        stroke_dash: args.stroke_dash.clone().map(|mut dash| {
+            dash.set_offset(args.stroke_dashoffset);
+            dash
+        }),
+        ..PathConfig::default()
+    });
+    if args.effect == Some(effects::Effect::Letterpress) {
+        render_config.set_letterpress(Some(effects::Letterpress::new(args.size as f32)));
+    }
+    if args.effect == Some(effects::Effect::Neon) {
+        render_config.set_neon(Some(effects::Neon::new(args.glow_color, args.size as f32)));
+    }
+
+    if let Some(Command::Bench { input, iterations }) = &args.command {
+        let font_load = font_load_started.elapsed();
+        println!("Benchmarking {}...", input.display());
+        let report = bench::run_bench(input, &font_config, &render_config, *iterations)?;
+        println!("  load    {:>10.3} ms", font_load.as_secs_f64() * 1000.0);
+        println!("{}", report);
+        return Ok(());
+    }
+
+    if let Some(Command::Preprocess { markdown, .. }) = &args.command {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input).map_err(|e| anyhow::anyhow!("Failed to read stdin: {}", e))?;
+        let mut fonts: HashMap<String, FontConfig> = HashMap::new();
+        let mut render_markdown = |markdown: &str| {
+            let mut count = 0;
+            preprocess::replace_blocks(markdown, |block| {
+                count += 1;
+                let svg = render_block(block, &mut fonts, &args, &render_config)?;
+                Ok(preprocess::inline_svg(&svg, &format!("{}-{}-", preprocess::FENCE_LANG, count)))
+            })
+        };
+        let output = match markdown {
+            true => render_markdown(&input)?,
+            false => preprocess::preprocess_book(&input, |_, content| render_markdown(content))?,
+        };
+        print!("{}", output);
+        return Ok(());
+    }
+
+    if let Some(Command::Serve { port, host, max_text_chars, max_size, max_pixels, max_connections }) = &args.command {
+        let limits = serve::ServeLimits {
+            max_text_chars: *max_text_chars,
+            max_size: *max_size,
+            max_pixels: *max_pixels,
+            max_connections: *max_connections as usize,
+            ..serve::ServeLimits::default()
+        };
+        // Responses cached by clients stay valid as long as the options shared by every request do
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (env!("CARGO_PKG_VERSION"), argv).hash(&mut hasher);
+        let load_font = |name: &str, size: u32| -> anyhow::Result<FontConfig> {
+            let mut font_config = font_config_from_args(name.to_string(), &args)?;
+            font_config.set_size(size);
+            Ok(font_config)
+        };
+        let server = serve::Server::new(font_config.get_font_name().clone(), args.size, render_config, limits, hasher.finish(), load_font);
+        let listener = std::net::TcpListener::bind((host.as_str(), *port)).map_err(|e| anyhow::anyhow!("Failed to listen on {}:{}: {}", host, port, e))?;
+        log_info!("Serving on http://{}:{}/render?text=... (Ctrl-C to stop)", host, port);
+        return server.run(listener);
+    }
 
+    match &args.command {
+        Some(Command::Measure { .. }) => {
+            let (doc, _) = build_document(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?;
+            let (width, height) = render::document_size(&doc);
+            println!("{}x{}", width, height);
+            return Ok(());
+        }
+        Some(Command::Coverage { .. }) => {
+            let input = input_text(text.as_deref(), args.file.as_deref())?;
+            let missing = font_config.missing_chars(&input, render_config.get_font_style());
+            if missing.is_empty() {
+                println!("{} has glyphs for every character", font_config.get_font_list());
+                return Ok(());
+            }
+            for c in &missing {
+                println!("U+{:04X} {}", *c as u32, c);
+            }
+            // Point at the first missing character
+            let span = input.find(missing[0]).map(|start| start..start + missing[0].len_utf8());
+            let message = format!("{} characters missing from {}", missing.len(), font_config.get_font_list());
+            return Err(CliError::new(ErrorKind::GlyphMissing, message).set_path(args.file.as_deref()).set_span(span).into());
+        }
+        Some(Command::Tune { protocol, .. }) => {
+            let protocol = protocol.or_else(preview::ImageProtocol::detect).ok_or_else(|| {
+                anyhow::anyhow!("The terminal doesn't seem to show inline images; pass --protocol kitty or --protocol iterm")
+            })?;
+            let space = args.space.to_em(args.size as f32);
+            let state = tune::TuneState::new(font::fonts(), font_config.get_font_name(), args.size, space, args.fill, args.color);
+            let state = tune::run(state, protocol, |state| {
+                if state.get_font() != font_config.get_font_name().as_str() {
+                    font_config = font_config_from_args(state.get_font().to_string(), &args)?;
+                }
+                font_config.set_size(state.size).set_letter_space(Length::Em(state.space));
+                font_config.set_fill_color(state.fill).set_color(state.color);
+                Ok(build_document(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?.0)
+            })?;
+            println!("{}", state.options());
+            return Ok(());
+        }
+        Some(Command::Preview { protocol, scale, .. }) => {
+            let protocol = protocol.or_else(preview::ImageProtocol::detect).ok_or_else(|| {
+                anyhow::anyhow!("The terminal doesn't seem to show inline images; pass --protocol kitty or --protocol iterm")
+            })?;
+            // Hinting snaps to the pixels of the scaled image
+            let scale = scale.max(0.1);
+            font_config.set_hinting(args.hint.then_some(1.0 / scale));
+            let (doc, _) = build_document(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?;
+            let png = preview::rasterize(&doc, scale)?;
+            println!("{}", protocol.escape(&png));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    #[cfg(feature = "clipboard")]
+    if let Some(format) = args.clipboard {
+        let (doc, _) = build_document(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?;
+        clipboard::copy(&clipboard::ClipboardContent::of(&doc, format, 1.0)?)?;
+        log_info!("Copied the {} to the clipboard", format);
+        return Ok(());
+    }
 
     // --- Rendering Logic ---
-    if let Some(text) = args.text {
+    let batch = match &args.command {
+        Some(Command::Batch { dir, out_dir, jobs }) => Some((dir.clone(), out_dir.clone(), *jobs)),
+        _ => None,
+    };
+    if batch.is_some() && !args.paragraphs.is_empty() {
+        return Err(invalid_option("--text can't be used with batch rendering, which reads its input files"));
+    }
+    // Stdout carries one finished SVG and nothing else
+    if to_stdout {
+        if batch.is_some() || args.watch || args.chunk_lines.is_some() {
+            return Err(invalid_option("--output - writes a single SVG and can't be used with batches, --watch or --chunk-lines"));
+        }
+        if args.print_dimensions {
+            return Err(invalid_option("--print-dimensions reads the written file and can't be used with --output -"));
+        }
+        if args.page_height.is_some() {
+            return Err(invalid_option("--output - writes a single SVG and can't be used with --page-height"));
+        }
+    }
+    // PostScript and images are drawn from the glyph outlines of a whole document
+    let format = args.format.unwrap_or_else(|| svg::OutputFormat::from_path(Path::new(&args.output.pattern)));
+    if (format == svg::OutputFormat::Eps || format.is_raster()) && (args.text_mode == TextMode::Text || args.fragment.is_some()) {
+        return Err(invalid_option(
+            "EPS and image outputs draw the glyph outlines of a whole document and can't be used with --text-mode text or --fragment",
+        ));
+    }
+    if args.html_template.is_some() && format != svg::OutputFormat::Html {
+        return Err(invalid_option("--html-template is for html output; add --format html or write to an .html file"));
+    }
+    if args.print_dimensions && args.fragment == Some(FragmentKind::Group) {
+        return Err(invalid_option("--fragment g leaves out the size --print-dimensions reports; use --fragment symbol"));
+    }
+    // Lines are counted before rendering consumes the input; chunked renders and batches count their own
+    let line_count = match args.print_dimensions && batch.is_none() && args.chunk_lines.is_none() && args.page_height.is_none() {
+        true if args.highlight => Some(input_text(text.as_deref(), args.file.as_deref())?.lines().count()),
+        true => Some(
+            input_lines(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?.0.len(),
+        ),
+        false => None,
+    };
+    // Single renders record their options for `text2svg rerender`; a batch's apply to the whole directory
+    let mut output_config = output_config_from_args(&args);
+    if batch.is_none() {
+        output_config.set_source_options(Some(metadata::render_options(argv)));
+    }
+    if let Some((dir, out_dir, jobs)) = batch {
+        let inputs = batch::collect_batch_inputs(&dir)?;
+        // A templated --output names the files, relative to --out-dir when given
+        let outputs = if args.output.has_placeholders() {
+            batch::templated_output_paths(&inputs, out_dir.as_deref().unwrap_or(Path::new("")), &args.output)?
+        } else {
+            let out_dir = out_dir.unwrap_or_else(|| dir.clone());
+            let format = output_config.get_format().unwrap_or_default();
+            inputs.iter().map(|input| batch::batch_output_path(input, &out_dir, format)).collect()
+        };
+        for parent in outputs.iter().filter_map(|output| output.parent()).filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+        }
+        let jobs = jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        });
+        log_info!("Rendering {} files from {} with {} jobs...", inputs.len(), dir.display(), jobs);
+        let report = batch::render_batch_to(&inputs, &outputs, &font_config, &render_config, &output_config, jobs, verbosity::enabled(Verbosity::Normal));
+        log_info!("{}", report);
+        if args.print_dimensions {
+            for (output, lines) in report.outputs.iter() {
+                print_dimensions(output, *lines, 1)?;
+            }
+        }
+        if !report.is_success() {
+            return Err(anyhow::anyhow!("{} of {} files failed to render", report.failed.len(), inputs.len()));
+        }
+    } else if let Some(Command::Stamp { top, bottom, radius, rings, ring_width, rotation, center_size, .. }) = &args.command {
+        let mut stamp = stamp::StampConfig::new();
+        stamp.top = top.clone();
+        stamp.bottom = bottom.clone();
+        stamp.center = text.clone();
+        stamp.radius = *radius;
+        stamp.rings = *rings;
+        stamp.ring_width = *ring_width;
+        stamp.rotation = *rotation;
+        stamp.center_size = *center_size;
+        log_info!("Rendering stamp to {}...", output_path.display());
+        stamp::render_stamp(&stamp, &mut font_config, &render_config, &output_config, &output_path)?;
+    } else if let Some(Command::Watermark { rotation, opacity, gap }) = &args.command {
+        let canvas = args.canvas.ok_or_else(|| invalid_option("watermark needs the size it tiles, e.g. --canvas 1200x800"))?;
+        let (lines, paragraph_starts) = input_lines(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?;
+        let mut watermark = watermark::WatermarkConfig::new(watermark::Canvas { width: canvas.width, height: canvas.height });
+        watermark.rotation = *rotation;
+        watermark.opacity = opacity.clamp(0.0, 1.0);
+        watermark.gap = gap.max(0.0);
+        log_info!("Rendering watermark to {}...", output_path.display());
+        watermark::render_watermark(&lines, &paragraph_starts, &font_config, &render_config, &output_config, &watermark, &output_path)?;
+    } else if let Some(animation) = &args.vary {
+        let (lines, paragraph_starts) = input_lines(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?;
+        log_info!("Rendering {} keyframes of {} to {}...", args.keyframes, animation.tag, output_path.display());
+        variation::render_axis_animation(
+            &lines,
+            &paragraph_starts,
+            &mut font_config,
+            &render_config,
+            &output_config,
+            animation,
+            args.keyframes as usize,
+            args.vary_duration,
+            &output_path,
+        )?;
+    } else if let Some(lines) = args.drop_cap {
+        let text = input_text(text.as_deref(), args.file.as_deref())?;
+        log_info!("Rendering with a {} line drop cap to {}...", lines, output_path.display());
+        dropcap::render_drop_cap(&text, &mut font_config, &render_config, &output_config, lines as usize, &output_path)?;
+    } else if let Some(page_height) = args.page_height {
+        let (lines, paragraph_starts) = input_lines(text.as_deref(), args.file.as_deref(), &font_config, &render_config)?;
+        let render_config = &*render_config.with_direction_of(lines.iter().map(String::as_str));
+        let pages = page::build_pages(&lines, &paragraph_starts, page_height, &font_config, render_config)?;
+        let page_paths = (1..=pages.len())
+            .map(|n| match args.output.has_placeholders() {
+                true => args.output.expand(args.file.as_deref(), n, &HashMap::new()).map_err(invalid_option),
+                false => Ok(page::page_path(&output_path, n)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        log_info!("Rendering {} pages to {}...", pages.len(), page_paths[0].display());
+        for (path, (_, doc, glyph_defs)) in page_paths.iter().zip(&pages) {
+            render::save_svg(path, doc, glyph_defs, &font_config, &output_config).map_err(|e| io_error(path, e))?;
+        }
+        if args.print_dimensions {
+            for (path, (lines, _, _)) in page_paths.iter().zip(&pages) {
+                print_dimensions(path, lines.len(), pages.len())?;
+            }
+        }
+    } else if let Some(map_path) = &args.glyph_map {
+        log_info!("Rendering to {} with a glyph map in {}...", output_path.display(), map_path.display());
+        // The input is read and wrapped once, so the map matches the document written
+        let input = input_text(text.as_deref(), args.file.as_deref())?;
+        let paragraphs: Vec<String> = input.lines().map(str::to_string).collect();
+        let (lines, paragraph_starts) = wrap_input(&input, &font_config, &render_config);
+        let build = match text {
+            Some(_) => render::build_wrapped_text_svg,
+            None => render::build_wrapped_lines_svg,
+        };
+        let (doc, glyph_defs) = build(lines.clone(), &paragraph_starts, &paragraphs, &font_config, &render_config)?;
+        render::save_svg(&output_path, &doc, &glyph_defs, &font_config, &output_config).map_err(|e| io_error(&output_path, e))?;
+        let sources = glyphmap::glyph_sources(&doc, &lines, &paragraph_starts, &paragraphs, render_config.get_markup());
+        std::fs::write(map_path, glyphmap::glyph_map_json(&sources)).map_err(|e| io_error(map_path, e))?;
+    } else if let Some(text) = text {
         if args.highlight {
-             eprintln!("Warning: Highlight mode is ignored when providing text directly via argument.");
+             log_warn!("Highlight mode is ignored when providing text directly via argument.");
         }
-        println!("Rendering text to {}...", output_path.display());
+        log_info!("Rendering text to {}...", output_path.display());
         render::render_text_to_svg_file(
             &text,
             &mut font_config,
             &render_config,
-            output_path,
-        );
+            &output_config,
+            output_path.clone(),
+        )?;
     } else if let Some(file) = args.file {
         if !file.exists() {
-            return Err(anyhow::anyhow!("Input file not found: {}", file.display()));
+            return Err(CliError::new(ErrorKind::Io, format!("Input file not found: {}", file.display())).set_path(Some(&file)).into());
         }
-        if args.highlight {
-            println!("Rendering file {} with highlighting to {}...", file.display(), output_path.display());
+        if args.watch {
+            log_info!("Watching {} (Ctrl-C to stop)...", file.display());
+            if args.highlight {
+                // Highlighting state carries across lines, so re-render the whole file
+                watch::watch_file(&file, || {
+                    render::render_file_highlight(&file, &mut font_config, &highlight_setting, &output_config, output_path.clone())?;
+                    log_info!("Rendered {}", output_path.display());
+                    Ok(())
+                })?;
+            } else {
+                watch::watch_text_file(&file, &font_config, &render_config, &output_config, output_path.clone())?;
+            }
+        } else if args.highlight {
+            log_info!("Rendering file {} with highlighting to {}...", file.display(), output_path.display());
             render::render_file_highlight(
                 &file,
                 &mut font_config,
                 &highlight_setting, // Pass the configured settings
-                output_path,
-            );
+                &output_config,
+                output_path.clone(),
+            )?;
+        } else if let Some(chunk_lines) = args.chunk_lines {
+            if args.warp.is_some() {
+                return Err(invalid_option("--warp bends the whole text at once and can't be combined with --chunk-lines"));
+            }
+            if args.contour.is_some() {
+                return Err(invalid_option("--contour copies the whole text and can't be combined with --chunk-lines"));
+            }
+            if render_config.get_word_colors().is_some_and(WordColors::has_tokens) {
+                return Err(invalid_option("Word colors by index count words across the whole text and can't be combined with --chunk-lines"));
+            }
+            log_info!("Rendering file {} in chunks of {} lines to {}...", file.display(), chunk_lines, output_path.display());
+            let stats = stream::render_text_file_chunked(
+                &file,
+                &font_config,
+                &render_config,
+                &output_config,
+                &output_path,
+                chunk_lines,
+                args.max_memory,
+                verbosity::enabled(Verbosity::Normal),
+            )?;
+            log_debug!("Chunked render: {:?}", stats);
+            if verbosity::enabled(Verbosity::Verbose) {
+                let written = std::fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0);
+                log_verbose!("Wrote {}: {} bytes, {} lines in {} chunks", output_path.display(), written, stats.lines, stats.chunks);
+            }
+            if args.print_dimensions {
+                print_dimensions(&output_path, stats.lines, 1)?;
+            }
         } else {
-            println!("Rendering file {} as plain text to {}...", file.display(), output_path.display());
+            if let Some(max_memory) = args.max_memory {
+                stream::check_file_fits_in_memory(&file, max_memory)?;
+            }
+            log_info!("Rendering file {} as plain text to {}...", file.display(), output_path.display());
             render::render_text_file_to_svg(
                 &file,
                 &mut font_config,
                 &render_config,
-                output_path,
-            );
+                &output_config,
+                output_path.clone(),
+            )?;
         }
     } else {
         // This case should ideally be caught earlier if font wasn't provided,
-        // but added for completeness if only flags like --list-fonts were used.
-        if !args.list_fonts && !args.list_syntax && !args.list_theme {
-             println!("No input text or file provided. Use --text or --file.");
+        // but added for completeness if only flags like --list-syntax were used.
+        if !args.list_syntax && !args.list_theme {
+             log_info!("No input text or file provided. Use --text or --file.");
              // Potentially print help here
         }
     }
 
+    if let Some(lines) = line_count {
+        print_dimensions(&output_path, lines, 1)?;
+    }
+
+    log_debug!("Shape cache: {}", font_config.get_shape_cache_stats());
+    log_debug!("Outline cache: {} glyphs", font_config.get_outline_cache().len());
+
+    Ok(())
+}
+
+// Output config with the output options of the command line
+fn output_config_from_args(args: &Args) -> OutputConfig {
+    let mut output_config = OutputConfig::new();
+    output_config.set_output_format(args.format);
+    output_config.set_html_template(args.html_template.clone());
+    output_config.set_raster_options(RasterOptions {
+        background: args.raster_background,
+        quality: args.quality,
+        width: args.raster_width,
+        height: args.raster_height,
+    });
+    output_config.set_fragment(args.fragment.map(|kind| Fragment { kind, id: args.fragment_id.clone() }));
+    output_config
+}
+
+// Font config for `font_name` with the font options of the command line
+fn font_config_from_args(font_name: String, args: &Args) -> Result<FontConfig, Error> {
+    let mut font_config = FontConfig::new(
+        font_name,
+        args.size,
+        args.fill,
+        args.color,
+        verbosity::enabled(Verbosity::Debug)
+    )?;
+    font_config.set_letter_space(args.space);
+    font_config.set_path_precision(args.precision);
+    font_config.set_quantize(args.quantize).set_hinting(args.hint.then_some(1.0)).set_glyph_snap(args.glyph_snap)
+        .set_annotate_shaping(args.show_shaping);
+    font_config.set_fast_measure(args.fast_measure);
+    font_config.set_tabs(args.tab_width, args.tab_mode);
+    font_config.set_size_mode(args.size_mode).set_metrics_source(args.metrics);
+    font_config.set_verbose(verbosity::enabled(Verbosity::Verbose));
+    match args.emoji_font.as_deref() {
+        Some("none") => {
+            font_config.set_auto_emoji_font(false);
+        }
+        Some(emoji_font) => {
+            font_config.set_emoji_font(emoji_font).map_err(|e| invalid_option(format!("Emoji font '{}' not found: {}", emoji_font, e)))?;
+        }
+        None => {}
+    }
+
+    // Apply font features if specified
+    if let Some(features_str) = args.features.as_ref().filter(|features| *features != "help") {
+        if let Err(err) = font_config.set_features_from_string(features_str) {
+            return Err(invalid_option(format!("Failed to parse font features '{}': {}", features_str, err)));
+        }
+        log_debug!("Applied font features: {}", font_config.get_features_summary());
+    }
+    Ok(font_config)
+}
+
+// SVG of a ```text2svg block, in the font, size and colors of its info string or else the command line's
+fn render_block(block: &preprocess::Block, fonts: &mut HashMap<String, FontConfig>, args: &Args, render_config: &RenderConfig) -> Result<String, Error> {
+    let option = |key: &str| block.options.get(key).map(String::as_str);
+    if let Some(key) = block.options.keys().find(|key| !["font", "size", "fill", "color"].contains(&key.as_str())) {
+        return Err(invalid_option(format!("Unknown block option '{}': expected font, size, fill or color", key)));
+    }
+    let font_name = option("font").or(args.font.as_deref()).unwrap_or_default().to_string();
+    let font_config = match fonts.entry(font_name.clone()) {
+        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        std::collections::hash_map::Entry::Vacant(entry) => entry.insert(font_config_from_args(font_name, args)?),
+    };
+    font_config.set_size(option("size").map(validate::parse_font_size).transpose().map_err(invalid_option)?.unwrap_or(args.size));
+    font_config.set_fill_color(option("fill").map(str::parse).transpose().map_err(invalid_option)?.unwrap_or(args.fill));
+    font_config.set_color(option("color").map(str::parse).transpose().map_err(invalid_option)?.unwrap_or(args.color));
+    let (doc, _) = render::build_text_svg(block.text.trim_end_matches(['\n', '\r']), font_config, render_config)?;
+    Ok(xml::to_xml(&doc))
+}
+
+// Document rendering the input text or file the way the render mode would, with its glyph definitions
+fn build_document(
+    text: Option<&str>,
+    file: Option<&std::path::Path>,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(::svg::Document, svg::GlyphDefs), Error> {
+    match (text, file) {
+        (Some(text), _) => render::build_text_svg(text, font_config, render_config),
+        (None, Some(file)) => render::build_text_file_svg(file, font_config, render_config),
+        (None, None) => Err(anyhow::anyhow!("No input text or file provided. Use --text or --file.")),
+    }
+}
+
+// The text argument followed by the --text paragraphs, one per line as the
+// paragraphs of a file are
+fn text_paragraphs(text: Option<&str>, paragraphs: &[String]) -> Option<String> {
+    let paragraphs: Vec<&str> = text.into_iter().chain(paragraphs.iter().map(String::as_str)).collect();
+    match paragraphs.is_empty() {
+        true => None,
+        false => Some(paragraphs.join("\n")),
+    }
+}
+
+// The input text, or else the contents of the input file
+fn input_text(text: Option<&str>, file: Option<&std::path::Path>) -> Result<String, Error> {
+    match (text, file) {
+        (Some(text), _) => Ok(text.to_string()),
+        (None, Some(file)) => std::fs::read_to_string(file).map_err(|e| io_error(file, e)),
+        (None, None) => Err(anyhow::anyhow!("No input text or file provided. Use --text or --file.")),
+    }
+}
+
+// Wrapped lines of the input text or file, for modes rendering them in one go
+fn input_lines(
+    text: Option<&str>,
+    file: Option<&std::path::Path>,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(Vec<String>, Vec<usize>), Error> {
+    Ok(wrap_input(&input_text(text, file)?, font_config, render_config))
+}
+
+// Wrapped lines of `input` and where its paragraphs start, up to the maximum number of lines
+fn wrap_input(input: &str, font_config: &FontConfig, render_config: &RenderConfig) -> (Vec<String>, Vec<usize>) {
+    let (mut lines, paragraph_starts) = render::wrap_paragraphs(input.lines(), font_config, render_config);
+    render::limit_lines(&mut lines, font_config, render_config);
+    (lines, paragraph_starts)
+}
+
+// One line per output, `pages` being how many the render wrote; the path goes last as it may contain spaces
+fn print_dimensions(output: &Path, lines: usize, pages: usize) -> Result<(), Error> {
+    let (width, height) = render::svg_file_size(output)?;
+    println!("width={} height={} lines={} pages={} output={}", width, height, lines, pages, output.display());
     Ok(())
 }
 
+fn parse_page_height(height: &str) -> Result<f32, String> {
+    match height.parse::<f32>() {
+        Ok(height) if height > 0.0 && height.is_finite() => Ok(height),
+        _ => Err(format!("Invalid page height '{}': expected a positive number of pixels, e.g. 1100", height)),
+    }
+}
+
+fn parse_quantize_grid(grid: &str) -> Result<f32, String> {
+    match grid.parse::<f32>() {
+        Ok(grid) if grid > 0.0 && grid.is_finite() => Ok(grid),
+        _ => Err(format!("Invalid grid '{}': expected a positive number of pixels, e.g. 0.25", grid)),
+    }
+}
+
+fn parse_warp_amount(amount: &str) -> Result<f32, String> {
+    match amount.parse::<f32>() {
+        Ok(amount) if amount.abs() <= text2svg::warp::MAX_WARP_AMOUNT => Ok(amount),
+        _ => Err(format!("Invalid warp amount '{}': expected a number from -{1} to {1}, e.g. 0.3", amount, text2svg::warp::MAX_WARP_AMOUNT)),
+    }
+}
+
+fn print_font_features(font_config: &FontConfig, style: &FontStyle) {
+    let tags = font_config.font_feature_tags(style);
+    println!("Font features of {} ({}):", font_config.get_font_name(), style);
+    if tags.is_empty() {
+        println!("  (none)");
+    }
+    for tag in tags {
+        // Features shaping applies unless disabled are marked
+        let enabled = if font_config.is_feature_enabled(&tag) { "*" } else { " " };
+        println!("{} {}  {}", enabled, tag, font::feature_name(&tag).unwrap_or_default());
+    }
+    println!("* on by default; enable others with e.g. --features ss01,onum or disable with liga=0");
+}
 
 fn list_themes(settings: &HighlightSetting) {
      println!("Available Themes:");
@@ -267,3 +1542,80 @@ fn list_themes(settings: &HighlightSetting) {
         }
 }
 
+
+#[cfg(test)]
+mod test_main {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        // Catches conflicts between global flags and flags subcommands don't have
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_subcommand_options() {
+        // Shared options are accepted on either side of the subcommand
+        let args = Args::try_parse_from(["text2svg", "--size", "20", "measure", "Hi", "--font", "Arial", "--padding", "4"]).unwrap();
+        assert_eq!(args.command.as_ref().and_then(Command::text), Some("Hi"));
+        assert_eq!((args.size, args.font.as_deref()), (20, Some("Arial")));
+        assert!(Args::try_parse_from(["text2svg", "render", "Hi", "--file", "in.txt"]).is_err());
+
+        // Repeated --text options are paragraphs after the text argument
+        let args = Args::try_parse_from(["text2svg", "Title", "--text", "First", "--font", "Arial", "--text", "Second one"]).unwrap();
+        assert_eq!(text_paragraphs(args.text.as_deref(), &args.paragraphs).as_deref(), Some("Title\nFirst\nSecond one"));
+        assert!(Args::try_parse_from(["text2svg", "--text", "Hi", "--file", "in.txt"]).is_err());
+
+        let warp = |amount: &str| Args::try_parse_from(["text2svg", "Hi", "--warp", "arch", "--warp-amount", amount]).map(|args| args.warp_amount);
+        assert_eq!(warp("-0.5").unwrap(), -0.5);
+        for amount in ["NaN", "inf", "2.5", "-3"] {
+            assert!(warp(amount).is_err());
+        }
+    }
+
+    #[test]
+    fn test_legacy_flags() {
+        // The hidden flags map onto the subcommands they stand for
+        let mut args = Args::try_parse_from(["text2svg", "--batch", "in", "--out-dir", "out", "--jobs", "2", "--font", "Arial"]).unwrap();
+        args.map_legacy_flags().unwrap();
+        assert!(matches!(&args.command, Some(Command::Batch { dir, out_dir: Some(out), jobs: Some(2) }) if dir == Path::new("in") && out == Path::new("out")));
+        let mut args = Args::try_parse_from(["text2svg", "--list-fonts"]).unwrap();
+        args.map_legacy_flags().unwrap();
+        assert!(matches!(args.command, Some(Command::Fonts)));
+        let mut args = Args::try_parse_from(["text2svg", "--batch", "in", "measure", "Hi"]).unwrap();
+        assert!(args.map_legacy_flags().is_err());
+
+        assert!(Args::try_parse_from(["text2svg", "batch", "in", "--jobs", "0"]).is_err());
+        assert!(Args::try_parse_from(["text2svg", "--batch", "in", "--jobs", "0"]).is_err());
+        let help = Args::command().render_help().to_string();
+        assert!(!help.contains("--batch") && !help.contains("--list-fonts"), "{}", help);
+    }
+
+    #[test]
+    fn test_every_subcommand_parses() {
+        // Reading the matches back into Args panics when a global and a subcommand option share an id
+        let commands: &[&[&str]] = &[
+            &["render", "Hi"],
+            &["fonts"],
+            &["measure", "Hi"],
+            &["coverage", "Hi"],
+            &["batch", "in", "--jobs", "2"],
+            &["tune", "Hi"],
+            &["preview", "Hi"],
+            &["bench", "--input", "in.txt"],
+            &["serve", "--port", "8081"],
+            &["preprocess", "supports", "html"],
+            &["rerender", "out.svg"],
+            &["stamp", "AB", "--top", "top", "--rotation", "10"],
+            &["watermark", "--canvas", "300x200", "--rotation", "-20", "--text", "hi"],
+        ];
+        for command in commands {
+            let argv = ["text2svg"].iter().chain(command.iter()).chain(["--font", "DejaVu Sans"].iter());
+            let args = Args::try_parse_from(argv).unwrap_or_else(|e| panic!("{:?}: {}", command, e));
+            assert!(args.command.is_some(), "{:?}", command);
+        }
+        let args = Args::try_parse_from(["text2svg", "watermark", "--canvas", "300x200", "--text", "hi"]).unwrap();
+        assert_eq!(args.canvas.map(|canvas| (canvas.width, canvas.height)), Some((300.0, 200.0)));
+    }
+}
@@ -0,0 +1,162 @@
+// inline markup for styling parts of a line:
+//   plain <span letter-spacing="2px">spaced out</span> plain
+// Spans nest; `&lt;`, `&gt;`, `&quot;` and `&amp;` stand for literal characters.
+use std::str::FromStr;
+
+use crate::length::Length;
+
+/// A run of text sharing the same inline style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    // None keeps the font config's letter spacing
+    pub letter_space: Option<Length>,
+}
+
+impl Span {
+    pub fn new(text: &str, letter_space: Option<Length>) -> Self {
+        Self {
+            text: text.to_string(),
+            letter_space,
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Letter spacing set by an opening tag such as `span letter-spacing="2px"`
+fn parse_open_tag(tag: &str) -> Result<Option<Length>, String> {
+    let Some(attributes) = tag.strip_prefix("span") else {
+        return Err(format!("Unsupported markup tag <{}>", tag));
+    };
+    let attributes = attributes.trim();
+    if attributes.is_empty() {
+        return Ok(None);
+    }
+    let value = attributes
+        .strip_prefix("letter-spacing=")
+        .and_then(|value| value.strip_prefix('"'))
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(|| format!("Unsupported markup attributes in <{}>", tag))?;
+    Length::from_str(&unescape(value)).map(Some)
+}
+
+// Appends text to the last span when it has the same style
+fn push_text(spans: &mut Vec<Span>, text: &str, letter_space: Option<Length>) {
+    if text.is_empty() {
+        return;
+    }
+    let text = unescape(text);
+    match spans.last_mut() {
+        Some(last) if last.letter_space == letter_space => last.text.push_str(&text),
+        _ => spans.push(Span { text, letter_space }),
+    }
+}
+
+/// Splits a line of markup into styled spans. Adjacent text with the same
+/// style is merged, and empty spans are dropped.
+pub fn parse_markup(line: &str) -> Result<Vec<Span>, String> {
+    let mut spans: Vec<Span> = Vec::new();
+    // Letter spacing of every open span, innermost last
+    let mut open: Vec<Option<Length>> = Vec::new();
+    let mut rest = line;
+
+    while let Some(tag_start) = rest.find('<') {
+        let current = open.last().copied().flatten();
+        push_text(&mut spans, &rest[..tag_start], current);
+        let tag_end = rest[tag_start..]
+            .find('>')
+            .map(|i| tag_start + i)
+            .ok_or_else(|| "Unclosed markup tag".to_string())?;
+        let tag = rest[tag_start + 1..tag_end].trim();
+        if tag == "/span" {
+            open.pop().ok_or_else(|| "Unexpected </span>".to_string())?;
+        } else {
+            // Spans without their own spacing inherit the enclosing one
+            let letter_space = parse_open_tag(tag)?.or(current);
+            open.push(letter_space);
+        }
+        rest = &rest[tag_end + 1..];
+    }
+    if !open.is_empty() {
+        return Err("Missing </span>".to_string());
+    }
+    push_text(&mut spans, rest, None);
+    Ok(spans)
+}
+
+/// Markup for `spans`, parsing back to the same spans.
+pub fn to_markup(spans: &[Span]) -> String {
+    let mut markup = String::new();
+    for span in spans {
+        match span.letter_space {
+            Some(letter_space) => {
+                markup.push_str(&format!("<span letter-spacing=\"{}\">{}</span>", letter_space, escape(&span.text)))
+            }
+            None => markup.push_str(&escape(&span.text)),
+        }
+    }
+    markup
+}
+
+/// The text of all spans, without markup.
+pub fn plain_text(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+/// The spans covering bytes `start..end` of their plain text.
+pub fn slice_spans(spans: &[Span], start: usize, end: usize) -> Vec<Span> {
+    let mut sliced = Vec::new();
+    let mut offset = 0;
+    for span in spans {
+        let span_end = offset + span.text.len();
+        let from = start.clamp(offset, span_end) - offset;
+        let to = end.clamp(offset, span_end) - offset;
+        if from < to {
+            sliced.push(Span::new(&span.text[from..to], span.letter_space));
+        }
+        offset = span_end;
+    }
+    sliced
+}
+
+#[cfg(test)]
+mod test_markup {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested_spans() {
+        let spans = parse_markup(r#"a <span letter-spacing="2px">b <span>c</span><span letter-spacing="0.5em">d</span></span> &lt;e&gt;"#).unwrap();
+        assert_eq!(spans, vec![
+            Span::new("a ", None),
+            Span::new("b c", Some(Length::Px(2.0))),
+            Span::new("d", Some(Length::Em(0.5))),
+            Span::new(" <e>", None),
+        ]);
+        assert_eq!(parse_markup(&to_markup(&spans)).unwrap(), spans);
+        assert_eq!(plain_text(&spans), "a b cd <e>");
+
+        assert!(parse_markup("<b>bold</b>").is_err());
+        assert!(parse_markup("<span>open").is_err());
+        assert!(parse_markup(r#"<span letter-spacing="wide">x</span>"#).is_err());
+    }
+
+    #[test]
+    fn test_slice_spans() {
+        let spans = vec![Span::new("hello ", None), Span::new("wide world", Some(Length::Px(3.0)))];
+        assert_eq!(slice_spans(&spans, 3, 10), vec![
+            Span::new("lo ", None),
+            Span::new("wide", Some(Length::Px(3.0))),
+        ]);
+        assert_eq!(slice_spans(&spans, 11, 16), vec![Span::new("world", Some(Length::Px(3.0)))]);
+    }
+}
@@ -0,0 +1,107 @@
+// the options an SVG was rendered with, kept in its <metadata> so
+// `text2svg rerender` can regenerate it with some of them changed
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use svg::node::element::Element;
+use svg::node::{Node, Text};
+use svg::parser::Event;
+
+use crate::error::io_error;
+use crate::xml::strip_invalid;
+
+/// id of the metadata element holding the options.
+pub const OPTIONS_METADATA_ID: &str = "text2svg-options";
+
+// Options that choose where and how loudly to write rather than what is rendered
+const OUTPUT_OPTIONS: [&str; 10] = [
+    "--output", "-o", "--format", "--html-template", "--raster-background", "--quality", "--raster-width", "--raster-height",
+    "--profile", "--error-format",
+];
+const OUTPUT_FLAGS: [&str; 7] = ["--verbose", "--quiet", "--debug", "--print-dimensions", "-v", "-q", "-d"];
+
+/// Command line options without the program name and the ones about output rather
+/// than rendering, e.g. --output and --verbose.
+pub fn render_options(args: &[String]) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if OUTPUT_OPTIONS.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        let is_output_option = OUTPUT_OPTIONS
+            .iter()
+            .any(|option| arg.starts_with(&format!("{}=", option)) || (*option == "-o" && arg.starts_with("-o")));
+        // Short flags may be combined, e.g. -vv
+        let is_flag = OUTPUT_FLAGS.contains(&arg.as_str())
+            || (arg.len() > 1 && arg.starts_with('-') && !arg.starts_with("--") && arg[1..].chars().all(|c| matches!(c, 'v' | 'q' | 'd')));
+        if !is_output_option && !is_flag {
+            options.push(arg.clone());
+        }
+    }
+    options
+}
+
+/// `<metadata>` element recording `options` as a JSON array.
+pub fn options_element(options: &[String]) -> Element {
+    let mut element = Element::new("metadata");
+    element.assign("id", OPTIONS_METADATA_ID);
+    // JSON escapes control characters, but not the noncharacters XML forbids as well
+    element.append(Text::new(strip_invalid(&serde_json::Value::from(options).to_string())));
+    element
+}
+
+/// Options recorded in the SVG at `path`.
+pub fn read_options(path: &Path) -> Result<Vec<String>> {
+    let content = crate::svg::read_svg_file(path).map_err(|e| io_error(path, e))?;
+    let mut in_options = false;
+    for event in svg::read(&content).map_err(|e| io_error(path, e))? {
+        match event {
+            Event::Tag("metadata", svg::node::element::tag::Type::Start, attributes) => {
+                in_options = attributes.get("id").is_some_and(|id| *id == OPTIONS_METADATA_ID);
+            }
+            Event::Text(text) if in_options => {
+                let json = text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+                return serde_json::from_str(&json)
+                    .map_err(|e| anyhow!("{}: the recorded options aren't valid: {}", path.display(), e));
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow!("{}: no text2svg options recorded; only SVGs written by text2svg can be re-rendered", path.display()))
+}
+
+/// Arguments re-rendering `svg` from its `options`: written back to `svg` unless
+/// `overrides` name another --output, and with `overrides` winning over `options`.
+pub fn rerender_args(program: &str, options: &[String], svg: &Path, overrides: &[String]) -> Vec<String> {
+    let mut args = vec![program.to_string()];
+    args.extend(options.iter().cloned());
+    args.push(format!("--output={}", svg.display()));
+    args.extend(overrides.iter().cloned());
+    args
+}
+
+#[cfg(test)]
+mod test_metadata {
+    use super::*;
+
+    #[test]
+    fn test_options_round_trip() {
+        let args: Vec<String> = ["text2svg", "a < b & c", "--font", "DejaVu Sans", "-o", "out.svg", "-vv", "--output=x.svg", "--size", "64", "-q"]
+            .map(String::from)
+            .to_vec();
+        let options = render_options(&args);
+        assert_eq!(options, ["a < b & c", "--font", "DejaVu Sans", "--size", "64"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metadata.svg");
+        let doc = svg::Document::new().set("width", 10).add(options_element(&options));
+        svg::save(&path, &doc).unwrap();
+        assert_eq!(read_options(&path).unwrap(), options);
+
+        let overrides = vec!["--size".to_string(), "20".to_string()];
+        let args = rerender_args("text2svg", &options, Path::new("logo.svg"), &overrides);
+        assert_eq!(args[6..], ["--output=logo.svg", "--size", "20"]);
+    }
+}
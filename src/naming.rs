@@ -0,0 +1,127 @@
+// output file name patterns: "out/{stem}-{n}.svg" names every output of a
+// batch or multi-page render after its input
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Output path with `{name}` placeholders: `{stem}` is the input file name
+/// without extension, `{n}` the number of the output counting from 1 (`{n:03}`
+/// pads it with zeros), `{date}` today's date as YYYY-MM-DD, and other names
+/// are looked up among the values given when expanding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    pub pattern: String,
+}
+
+impl std::str::FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let template = OutputTemplate { pattern: s.to_string() };
+        // Checks the braces; values are only known when expanding
+        template.expand_with(|_, _| Ok(String::new()))?;
+        Ok(template)
+    }
+}
+
+impl OutputTemplate {
+    pub fn has_placeholders(&self) -> bool {
+        let mut found = false;
+        let _ = self.expand_with(|_, _| {
+            found = true;
+            Ok(String::new())
+        });
+        found
+    }
+
+    /// The path for output number `n` of `input` (None for text given directly, named "text").
+    pub fn expand(&self, input: Option<&Path>, n: usize, values: &HashMap<String, String>) -> Result<PathBuf, String> {
+        let stem = input
+            .and_then(Path::file_stem)
+            .map_or_else(|| "text".to_string(), |stem| stem.to_string_lossy().into_owned());
+        let path = self.expand_with(|name, width| match name {
+            "stem" => Ok(stem.clone()),
+            "n" => Ok(format!("{:0width$}", n, width = width)),
+            "date" => Ok(today()),
+            _ => values.get(name).cloned().ok_or_else(|| format!("Unknown placeholder {{{}}} in output '{}'", name, self.pattern)),
+        })?;
+        Ok(PathBuf::from(path))
+    }
+
+    // Replaces every placeholder with `value(name, zero padded width)`; "{{" and "}}" are literal braces
+    fn expand_with(&self, mut value: impl FnMut(&str, usize) -> Result<String, String>) -> Result<String, String> {
+        let invalid = |reason: &str| format!("Invalid output '{}': {}", self.pattern, reason);
+        let mut out = String::with_capacity(self.pattern.len());
+        let mut rest = self.pattern.as_str();
+        while let Some(i) = rest.find(['{', '}']) {
+            out.push_str(&rest[..i]);
+            let brace = &rest[i..];
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                out.push_str(&brace[..1]);
+                rest = &brace[2..];
+                continue;
+            }
+            if brace.starts_with('}') {
+                return Err(invalid("unmatched '}'"));
+            }
+            let end = brace.find('}').ok_or_else(|| invalid("unclosed '{'"))?;
+            let placeholder = &brace[1..end];
+            let (name, width) = match placeholder.split_once(':') {
+                Some((name, width)) => (name, width.parse::<usize>().map_err(|_| invalid("expected a width such as {n:03}"))?),
+                None => (placeholder, 0),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(invalid(&format!("bad placeholder {{{}}}", placeholder)));
+            }
+            out.push_str(&value(name, width)?);
+            rest = &brace[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// Today's date in UTC as YYYY-MM-DD.
+pub fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() / 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Gregorian date of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test_naming {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() {
+        let template: OutputTemplate = "out/{stem}-{n:03}.svg".parse().unwrap();
+        let values = HashMap::new();
+        assert_eq!(template.expand(Some(Path::new("notes/a.b.txt")), 7, &values), Ok(PathBuf::from("out/a.b-007.svg")));
+        assert_eq!(template.expand(None, 1, &values), Ok(PathBuf::from("out/text-001.svg")));
+
+        let template: OutputTemplate = "{{{lang}}}.svg".parse().unwrap();
+        let values = HashMap::from([("lang".to_string(), "de".to_string())]);
+        assert_eq!(template.expand(None, 1, &values), Ok(PathBuf::from("{de}.svg")));
+        assert!(template.expand(None, 1, &HashMap::new()).is_err());
+
+        assert!("out/{stem.svg".parse::<OutputTemplate>().is_err());
+        assert!("out/{n:x}.svg".parse::<OutputTemplate>().is_err());
+        assert!(!"out.svg".parse::<OutputTemplate>().unwrap().has_placeholders());
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+    }
+}
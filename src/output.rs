@@ -0,0 +1,105 @@
+// how rendered documents are written out, kept apart from the font so the
+// same font config can be written to several formats
+use std::path::Path;
+
+use crate::fragment::Fragment;
+use crate::raster::RasterOptions;
+use crate::svg::OutputFormat;
+
+/// Format, page and raster settings for written outputs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputConfig {
+    // None writes what each output's extension asks for
+    format: Option<OutputFormat>,
+    raster_options: RasterOptions,
+    html_template: Option<String>,
+    fragment: Option<Fragment>,
+    source_options: Option<Vec<String>>,
+}
+
+impl OutputConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes every output in `format`, whatever its extension; `None` goes by the extension.
+    pub fn set_output_format(&mut self, format: Option<OutputFormat>) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    /// Format of the output written to `path`.
+    /// The format given with `set_output_format`, if any.
+    pub fn get_format(&self) -> Option<OutputFormat> {
+        self.format
+    }
+
+    pub fn get_output_format(&self, path: &Path) -> OutputFormat {
+        self.format.unwrap_or_else(|| OutputFormat::from_path(path))
+    }
+
+    /// Background, quality and pixel size of png, webp and jpeg outputs.
+    pub fn set_raster_options(&mut self, options: RasterOptions) -> &mut Self {
+        self.raster_options = options;
+        self
+    }
+
+    pub fn get_raster_options(&self) -> &RasterOptions {
+        &self.raster_options
+    }
+
+    /// Page html outputs are inlined into at its placeholder, instead of a minimal one.
+    pub fn set_html_template(&mut self, template: Option<String>) -> &mut Self {
+        self.html_template = template;
+        self
+    }
+
+    pub fn get_html_template(&self) -> Option<&str> {
+        self.html_template.as_deref()
+    }
+
+    /// Writes outputs as a `<g>` or `<symbol>` fragment instead of a whole document.
+    pub fn set_fragment(&mut self, fragment: Option<Fragment>) -> &mut Self {
+        self.fragment = fragment;
+        self
+    }
+
+    pub fn get_fragment(&self) -> Option<&Fragment> {
+        self.fragment.as_ref()
+    }
+
+    /// Records the command line options in each written SVG, for `text2svg rerender`.
+    pub fn set_source_options(&mut self, options: Option<Vec<String>>) -> &mut Self {
+        self.source_options = options;
+        self
+    }
+
+    pub fn get_source_options(&self) -> Option<&[String]> {
+        self.source_options.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test_output {
+    use super::*;
+    use crate::fragment::FragmentKind;
+
+    #[test]
+    fn test_output_format_follows_extension_unless_set() {
+        let mut output_config = OutputConfig::new();
+        assert_eq!(output_config.get_output_format(Path::new("out.png")), OutputFormat::from_path(Path::new("out.png")));
+        output_config.set_output_format(Some(OutputFormat::Html));
+        assert_eq!(output_config.get_output_format(Path::new("out.png")), OutputFormat::Html);
+    }
+
+    #[test]
+    fn test_defaults_write_whole_documents() {
+        let mut output_config = OutputConfig::new();
+        assert_eq!(output_config.get_fragment(), None);
+        assert_eq!(output_config.get_html_template(), None);
+        assert_eq!(output_config.get_source_options(), None);
+        assert_eq!(output_config.get_raster_options(), &RasterOptions::default());
+        output_config.set_fragment(Some(Fragment { kind: FragmentKind::Symbol, id: "logo".to_string() }));
+        assert_eq!(output_config.get_fragment().map(|fragment| fragment.id.as_str()), Some("logo"));
+    }
+}
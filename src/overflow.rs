@@ -0,0 +1,180 @@
+// what happens to text that doesn't fit, in lines or on a fixed canvas: it is
+// cut off, optionally fading out or ending with an ellipsis so readers can tell
+// there is more, scaled down, or refused
+use clap::ValueEnum;
+use svg::node::element::{Group, LinearGradient, Mask, Rectangle, Stop};
+
+pub const OVERFLOW_FADE_ID: &str = "overflow-fade";
+
+/// How text that doesn't fit ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Overflow {
+    /// Lines past the limit are dropped, text past the canvas is cut off
+    #[default]
+    Clip,
+    /// Lines past the limit are dropped and the last one fades out
+    Fade,
+    /// Lines past the limit are dropped and the last one ends with the ellipsis
+    Ellipsis,
+    /// Text larger than the canvas is scaled down to fit it
+    Scale,
+    /// Text larger than the canvas is an error
+    Error,
+}
+
+/// Where the text sits vertically on a canvas taller than it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum VAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+    /// The first line's baseline on the middle of the canvas, whatever glyphs the text has
+    Baseline,
+}
+
+/// Exact output size in pixels, whatever the size of the text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Canvas {
+    pub width: f32,
+    pub height: f32,
+    pub valign: VAlign,
+}
+
+impl std::str::FromStr for Canvas {
+    type Err = String;
+
+    /// Parses "WIDTHxHEIGHT", e.g. "1200x630".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid canvas '{}': expected \"WIDTHxHEIGHT\", e.g. \"1200x630\"", s);
+        let (width, height) = s.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+        let size = |value: &str| value.trim().parse::<f32>().ok().filter(|value| value.is_finite() && *value > 0.0);
+        match (size(width), size(height)) {
+            (Some(width), Some(height)) => Ok(Canvas { width, height, valign: VAlign::default() }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Canvas {
+    pub fn set_valign(&mut self, valign: VAlign) -> &mut Self {
+        self.valign = valign;
+        self
+    }
+
+    /// View box showing `content` (x, y, width, height) on the canvas, at its
+    /// left edge and aligned vertically, `baseline` being the y of the first
+    /// line's baseline. Content larger than the canvas is cut off, scaled down
+    /// to fit, or an error, by `overflow`.
+    pub fn view_box(&self, content: (f32, f32, f32, f32), overflow: Overflow, baseline: f32) -> Result<(f32, f32, f32, f32), String> {
+        let (x, y, width, height) = content;
+        let fits = width <= self.width && height <= self.height;
+        let (view_width, view_height) = match overflow {
+            Overflow::Scale if !fits => {
+                // A larger view box of the canvas' proportions shrinks everything alike
+                let scale = (self.width / width).min(self.height / height);
+                (self.width / scale, self.height / scale)
+            }
+            Overflow::Error if !fits => {
+                return Err(format!(
+                    "The text needs {}x{} pixels but the canvas is {}x{}",
+                    width.ceil(),
+                    height.ceil(),
+                    self.width,
+                    self.height
+                ))
+            }
+            _ => (self.width, self.height),
+        };
+        let top = match self.valign {
+            VAlign::Top => y,
+            VAlign::Middle => y - (view_height - height) / 2.0,
+            VAlign::Bottom => y - (view_height - height),
+            VAlign::Baseline => baseline - view_height / 2.0,
+        };
+        Ok((x, top, view_width, view_height))
+    }
+}
+
+/// Mask fading out the bottom `fade` pixels of text covering `width` x `height`.
+pub fn fade_mask(width: f32, height: f32, fade: f32) -> Mask {
+    // Generous margins so overhanging glyphs are not cut off on the sides or the top
+    let margin = fade.max(1.0);
+    let (x, y, mask_width, mask_height) = (-margin, -margin, width + 2.0 * margin, height + margin);
+    let gradient_id = format!("{}-gradient", OVERFLOW_FADE_ID);
+    let gradient = LinearGradient::new()
+        .set("id", gradient_id.as_str())
+        .set("gradientUnits", "userSpaceOnUse")
+        .set("x1", 0)
+        .set("y1", height - fade)
+        .set("x2", 0)
+        .set("y2", height)
+        .add(Stop::new().set("offset", 0).set("stop-color", "#fff"))
+        .add(Stop::new().set("offset", 1).set("stop-color", "#fff").set("stop-opacity", 0));
+    Mask::new()
+        .set("id", OVERFLOW_FADE_ID)
+        .set("maskUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", mask_width)
+        .set("height", mask_height)
+        .add(gradient)
+        .add(
+            Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", mask_width)
+                .set("height", mask_height)
+                .set("fill", format!("url(#{})", gradient_id)),
+        )
+}
+
+/// `content` of `width` x `height` with its last `fade` pixels faded out.
+/// The mask travels with the content, so no separate definitions are needed.
+pub fn fade_out(content: Group, width: f32, height: f32, fade: f32) -> Group {
+    Group::new()
+        .add(fade_mask(width, height, fade))
+        .add(content.set("mask", format!("url(#{})", OVERFLOW_FADE_ID)))
+}
+
+#[cfg(test)]
+mod test_overflow {
+    use super::*;
+
+    #[test]
+    fn test_canvas_view_box() {
+        let canvas: Canvas = "200x100".parse().unwrap();
+        assert!("200".parse::<Canvas>().is_err());
+        assert!("0x100".parse::<Canvas>().is_err());
+
+        let small = (-2.0, -4.0, 150.0, 50.0);
+        assert_eq!(canvas.view_box(small, Overflow::Error, 30.0), Ok((-2.0, -4.0, 200.0, 100.0)));
+        let wide = (-2.0, -4.0, 400.0, 50.0);
+        assert_eq!(canvas.view_box(wide, Overflow::Clip, 30.0), Ok((-2.0, -4.0, 200.0, 100.0)));
+        assert_eq!(canvas.view_box(wide, Overflow::Scale, 30.0), Ok((-2.0, -4.0, 400.0, 200.0)));
+        assert!(canvas.view_box(wide, Overflow::Error, 30.0).is_err());
+    }
+
+    #[test]
+    fn test_canvas_valign() {
+        let mut canvas: Canvas = "200x100".parse().unwrap();
+        let content = (0.0, -4.0, 150.0, 50.0);
+        canvas.set_valign(VAlign::Middle);
+        assert_eq!(canvas.view_box(content, Overflow::Clip, 30.0), Ok((0.0, -29.0, 200.0, 100.0)));
+        canvas.set_valign(VAlign::Bottom);
+        assert_eq!(canvas.view_box(content, Overflow::Clip, 30.0), Ok((0.0, -54.0, 200.0, 100.0)));
+        canvas.set_valign(VAlign::Baseline);
+        assert_eq!(canvas.view_box(content, Overflow::Clip, 30.0), Ok((0.0, -20.0, 200.0, 100.0)));
+        // Scaled down content fills the canvas in height, so there is nothing to align
+        canvas.set_valign(VAlign::Middle);
+        assert_eq!(canvas.view_box((0.0, 0.0, 100.0, 200.0), Overflow::Scale, 30.0), Ok((0.0, 0.0, 400.0, 200.0)));
+    }
+
+    #[test]
+    fn test_fade_out() {
+        let faded = fade_out(Group::new(), 100.0, 60.0, 20.0).to_string();
+        assert!(faded.contains(r#"<mask height="80" id="overflow-fade" maskUnits="userSpaceOnUse" width="140" x="-20" y="-20">"#));
+        assert!(faded.contains(r#"y1="40" y2="60""#));
+        assert!(faded.contains(r#"<g mask="url(#overflow-fade)"/>"#));
+    }
+}
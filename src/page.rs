@@ -0,0 +1,134 @@
+// splitting long text into pages of a fixed height, each its own svg, so a
+// chapter can be rendered as a sequence of pages
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use svg::Document;
+
+use crate::a11y::label_document;
+use crate::font::FontConfig;
+use crate::overflow::{Canvas, VAlign};
+use crate::render::{
+    build_text_document, effects_view_box, layout_lines, line_top, margin_view_box, padded_view_box, stack_line_layouts,
+    RenderConfig,
+};
+use crate::svg::GlyphDefs;
+
+// Width and height around text of `width` x `height`: effects, padding, border and margin
+fn composition_size(width: f32, height: f32, font_config: &FontConfig, render_config: &RenderConfig) -> (f32, f32) {
+    let view_box = effects_view_box((0.0, 0.0, width, height), render_config);
+    let (_, _, width, height) = margin_view_box(padded_view_box(view_box, font_config, render_config), font_config, render_config);
+    (width, height)
+}
+
+/// Lines on each page of `page_height` pixels, given `line_count` lines with
+/// paragraphs starting at `paragraph_starts`. Each page has room for the
+/// padding, border and margin; paragraph spacing is dropped at the top of a page.
+pub fn paginate(
+    line_count: usize,
+    paragraph_starts: &[usize],
+    page_height: f32,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<Vec<Range<usize>>> {
+    let line_height = render_config.line_height_pixels(font_config.get_size()) as f32;
+    let room = page_height - composition_size(0.0, 0.0, font_config, render_config).1;
+    if room < line_height {
+        bail!(
+            "A page height of {} leaves no room for a line of {} pixels after the padding and margins; it needs at least {}",
+            page_height,
+            line_height,
+            (page_height - room + line_height).ceil()
+        );
+    }
+    let top = |index: usize| line_top(index, paragraph_starts, font_config, render_config) as f32;
+    let mut pages = Vec::new();
+    let mut start = 0;
+    for end in 1..=line_count {
+        if end > start + 1 && top(end - 1) - top(start) + line_height > room {
+            pages.push(start..end - 1);
+            start = end - 1;
+        }
+    }
+    if start < line_count {
+        pages.push(start..line_count);
+    }
+    Ok(pages)
+}
+
+/// The pages `lines` fill, paragraphs starting at `paragraph_starts`: the lines
+/// on each, its document and the glyph definitions it uses. Every page is `page_height` tall and as
+/// wide as the widest, with the text at its top.
+pub fn build_pages(
+    lines: &[String],
+    paragraph_starts: &[usize],
+    page_height: f32,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<Vec<(Range<usize>, Document, GlyphDefs)>> {
+    let pages = paginate(lines.len(), paragraph_starts, page_height, font_config, render_config)?;
+    let laid_out: Vec<_> = pages
+        .iter()
+        .map(|page| {
+            let starts: Vec<usize> = paragraph_starts.iter().copied().filter(|start| page.contains(start)).map(|start| start - page.start).collect();
+            let (line_layouts, glyph_defs) = layout_lines(&lines[page.clone()], font_config, render_config);
+            let (content, width, height) = stack_line_layouts(line_layouts, &starts, font_config, render_config);
+            (content, glyph_defs, width as f32, height as f32)
+        })
+        .collect();
+
+    let widest = laid_out.iter().map(|(_, _, width, _)| *width).fold(0.0, f32::max);
+    let mut page_config = render_config.clone();
+    page_config.set_canvas(Some(Canvas {
+        width: composition_size(widest, 0.0, font_config, render_config).0.ceil(),
+        height: page_height,
+        valign: VAlign::Top,
+    }));
+    pages
+        .into_iter()
+        .zip(laid_out)
+        .map(|(page, (content, glyph_defs, width, height))| {
+            let doc = build_text_document(content, &lines[page.clone()], &glyph_defs, (0.0, 0.0, width, height), font_config, &page_config)?;
+            let doc = label_document(doc, &lines[page.clone()], render_config.get_markup(), render_config.get_accessibility());
+            Ok((page, doc, glyph_defs))
+        })
+        .collect()
+}
+
+/// Path of page `n`, counting from 1: `output` with `-001`, `-002`, ... before its extension.
+pub fn page_path(output: &Path, n: usize) -> PathBuf {
+    let stem = output.file_stem().map_or_else(|| "page".into(), |stem| stem.to_string_lossy().into_owned());
+    let name = match output.extension() {
+        Some(extension) => format!("{}-{:03}.{}", stem, n, extension.to_string_lossy()),
+        None => format!("{}-{:03}", stem, n),
+    };
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test_page {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+    use crate::render::document_size;
+
+    #[test]
+    fn test_paginate() {
+        let font_config = test_support::font_config(20);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        // Lines are 20 pixels apart, paragraphs 10 more; the stroke takes a pixel
+        render_config.set_paragraph_spacing(crate::length::Length::Px(10.0));
+        assert_eq!(paginate(5, &[0, 3], 62.0, &font_config, &render_config).unwrap(), [0..3, 3..5]);
+        assert_eq!(paginate(5, &[0, 2], 62.0, &font_config, &render_config).unwrap(), [0..2, 2..5]);
+        assert!(paginate(5, &[0], 10.0, &font_config, &render_config).is_err());
+
+        let lines: Vec<String> = ["one", "two", "three"].map(str::to_string).to_vec();
+        let pages = build_pages(&lines, &[0, 1, 2], 55.0, &font_config, &render_config).unwrap();
+        assert_eq!((pages[0].0.clone(), pages[1].0.clone()), (0..2, 2..3));
+        let (first, second) = (document_size(&pages[0].1), document_size(&pages[1].1));
+        assert_eq!((first.1, second.1), (55.0, 55.0));
+        assert_eq!(first.0, second.0);
+        assert_eq!(page_path(Path::new("out/ch1.svg"), 2), Path::new("out/ch1-002.svg"));
+    }
+}
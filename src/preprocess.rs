@@ -0,0 +1,185 @@
+// replacing ```text2svg fenced blocks in Markdown with the SVG they render,
+// for mdBook (as a preprocessor) and other static site builds
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+/// Info string word marking a fenced block to render.
+pub const FENCE_LANG: &str = "text2svg";
+
+/// A fenced block to render: its text and the options of its info string,
+/// e.g. ```text2svg size=48 font="DejaVu Serif".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub text: String,
+    pub options: HashMap<String, String>,
+    /// Line of the opening fence, from 1
+    pub line: usize,
+}
+
+// An open fence: its character, length and whether it is a text2svg block
+struct Fence {
+    marker: char,
+    len: usize,
+    options: Option<HashMap<String, String>>,
+    line: usize,
+}
+
+// The fence a line opens or closes: its character, length and the info string after it
+fn fence(line: &str) -> Option<(char, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    let info = rest[len..].trim();
+    // Backtick fences can't have backticks in their info string
+    (indent <= 3 && len >= 3 && !(marker == '`' && info.contains('`'))).then_some((marker, len, info))
+}
+
+/// Options of an info string after the `text2svg` word: `key=value` pairs,
+/// values double quoted when they hold spaces.
+pub fn parse_info(info: &str) -> Result<HashMap<String, String>, String> {
+    let mut options = HashMap::new();
+    let mut rest = info.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=').ok_or_else(|| format!("Expected key=value in '{}'", rest))?;
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').ok_or_else(|| format!("Unclosed quote in '{}'", rest))?,
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        options.insert(key.trim().to_string(), value.to_string());
+        rest = after.trim_start();
+    }
+    Ok(options)
+}
+
+/// `markdown` with every ```text2svg block replaced by what `render` gives for it.
+/// Blocks inside other fenced blocks, such as examples of the syntax, are kept.
+pub fn replace_blocks(markdown: &str, mut render: impl FnMut(&Block) -> Result<String>) -> Result<String> {
+    let mut out = String::with_capacity(markdown.len());
+    let mut open: Option<Fence> = None;
+    let mut body = String::new();
+    for (i, line) in markdown.split_inclusive('\n').enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        match &open {
+            None => match fence(content) {
+                Some((marker, len, info)) => {
+                    let options = match info.split_once(char::is_whitespace).unwrap_or((info, "")) {
+                        (FENCE_LANG, options) => Some(parse_info(options).map_err(|e| anyhow!("Line {}: {}", i + 1, e))?),
+                        _ => None,
+                    };
+                    if options.is_none() {
+                        out.push_str(line);
+                    }
+                    open = Some(Fence { marker, len, options, line: i + 1 });
+                }
+                None => out.push_str(line),
+            },
+            Some(current) => {
+                let closes = fence(content).is_some_and(|(marker, len, info)| marker == current.marker && len >= current.len && info.is_empty());
+                match (&current.options, closes) {
+                    (Some(options), true) => {
+                        let block = Block { text: std::mem::take(&mut body), options: options.clone(), line: current.line };
+                        let rendered = render(&block).map_err(|e| anyhow!("Block at line {}: {}", block.line, e))?;
+                        out.push_str(&rendered);
+                        out.push('\n');
+                        open = None;
+                    }
+                    (Some(_), false) => body.push_str(line),
+                    (None, closes) => {
+                        out.push_str(line);
+                        if closes {
+                            open = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if let Some(Fence { options: Some(_), line, .. }) = open {
+        bail!("Block at line {} has no closing fence", line);
+    }
+    Ok(out)
+}
+
+/// `svg` made safe to inline in Markdown next to others: an HTML block without
+/// blank lines, which would end it, and ids (and references to them) starting
+/// with `id_prefix` so the glyphs of one image don't resolve to another's.
+pub fn inline_svg(svg: &str, id_prefix: &str) -> String {
+    let references = Regex::new(r##"(\sid="|href="#|url\(#)"##).expect("valid regex");
+    let svg = references.replace_all(svg, |caps: &Captures| format!("{}{}", &caps[1], id_prefix));
+    let lines: Vec<&str> = svg.lines().filter(|line| !line.trim().is_empty()).collect();
+    format!("<div class=\"{}\">\n{}\n</div>", FENCE_LANG, lines.join("\n"))
+}
+
+/// mdBook preprocessor input (`[context, book]`, as JSON) turned into the book
+/// it outputs, with `render` applied to the content of every chapter.
+pub fn preprocess_book(input: &str, mut render: impl FnMut(&str, &str) -> Result<String>) -> Result<String> {
+    let input: Value = serde_json::from_str(input).context("Expected mdBook preprocessor input: [context, book] as JSON")?;
+    let mut book = match input {
+        Value::Array(mut parts) if parts.len() == 2 => parts.pop().unwrap_or_default(),
+        _ => bail!("Expected mdBook preprocessor input: [context, book] as JSON"),
+    };
+    render_chapters(&mut book, &mut render)?;
+    Ok(serde_json::to_string(&book)?)
+}
+
+// Chapters nest under "sections" (or "items") and their "sub_items"
+fn render_chapters(value: &mut Value, render: &mut impl FnMut(&str, &str) -> Result<String>) -> Result<()> {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Object(chapter)) = object.get_mut("Chapter") {
+                let name = chapter.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+                if let Some(Value::String(content)) = chapter.get_mut("content") {
+                    *content = render(&name, content).map_err(|e| anyhow!("Chapter '{}': {}", name, e))?;
+                }
+            }
+            object.values_mut().try_for_each(|child| render_chapters(child, render))
+        }
+        Value::Array(items) => items.iter_mut().try_for_each(|child| render_chapters(child, render)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test_preprocess {
+    use super::*;
+
+    #[test]
+    fn test_replace_blocks() {
+        let markdown = "# Title\n\n```text2svg size=48 font=\"DejaVu Serif\"\nHello\nworld\n```\n\n````markdown\n```text2svg\nkept\n```\n````\n```rust\nlet x = 1;\n```\n";
+        let mut blocks = Vec::new();
+        let out = replace_blocks(markdown, |block| {
+            blocks.push(block.clone());
+            Ok("<svg/>".to_string())
+        })
+        .unwrap();
+        assert_eq!(out, "# Title\n\n<svg/>\n\n````markdown\n```text2svg\nkept\n```\n````\n```rust\nlet x = 1;\n```\n");
+        assert_eq!(blocks.len(), 1);
+        assert_eq!((blocks[0].text.as_str(), blocks[0].line), ("Hello\nworld\n", 3));
+        assert_eq!(blocks[0].options["font"], "DejaVu Serif");
+        assert_eq!(blocks[0].options["size"], "48");
+        assert!(replace_blocks("```text2svg\nopen\n", |_| Ok(String::new())).is_err());
+        assert!(replace_blocks("```text2svg size\nx\n```\n", |_| Ok(String::new())).is_err());
+    }
+
+    #[test]
+    fn test_inline_svg() {
+        let svg = "<svg>\n<defs><path id=\"g1\"/></defs>\n\n<use href=\"#g1\" filter=\"url(#shadow)\"/>\n</svg>";
+        let inlined = inline_svg(svg, "b2-");
+        assert_eq!(inlined, "<div class=\"text2svg\">\n<svg>\n<defs><path id=\"b2-g1\"/></defs>\n<use href=\"#b2-g1\" filter=\"url(#b2-shadow)\"/>\n</svg>\n</div>");
+    }
+
+    #[test]
+    fn test_preprocess_book() {
+        let input = r#"[{"root": "."}, {"sections": [{"Chapter": {"name": "Intro", "content": "a", "sub_items": [{"Chapter": {"name": "Nested", "content": "b", "sub_items": []}}]}}, "Separator"], "__non_exhaustive": null}]"#;
+        let book = preprocess_book(input, |name, content| Ok(format!("{}:{}", name, content))).unwrap();
+        let book: Value = serde_json::from_str(&book).unwrap();
+        assert_eq!(book["sections"][0]["Chapter"]["content"], "Intro:a");
+        assert_eq!(book["sections"][0]["Chapter"]["sub_items"][0]["Chapter"]["content"], "Nested:b");
+        assert_eq!(book["sections"][1], "Separator");
+        assert!(preprocess_book("{}", |_, content| Ok(content.to_string())).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+// terminal previews: the rendered svg rasterized and shown inline by
+// terminals speaking the kitty or iTerm2 image protocols
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use resvg::{tiny_skia, usvg};
+use svg::Document;
+
+use crate::xml::to_xml;
+
+// Largest payload of one kitty graphics escape
+const KITTY_CHUNK: usize = 4096;
+
+/// Inline image protocol of a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImageProtocol {
+    /// kitty graphics protocol (kitty, Ghostty, Konsole)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm)
+    Iterm,
+}
+
+impl ImageProtocol {
+    /// The protocol of the running terminal, guessed from its environment.
+    pub fn detect() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let program = var("TERM_PROGRAM");
+        if !var("KITTY_WINDOW_ID").is_empty() || var("TERM").contains("kitty") || program == "ghostty" {
+            Some(ImageProtocol::Kitty)
+        } else if matches!(program.as_str(), "iTerm.app" | "WezTerm") || var("LC_TERMINAL") == "iTerm2" {
+            Some(ImageProtocol::Iterm)
+        } else {
+            None
+        }
+    }
+
+    /// Escape sequence removing the images shown so far, which kitty keeps
+    /// through clearing the screen.
+    pub fn clear(&self) -> &'static str {
+        match self {
+            ImageProtocol::Kitty => "\x1b_Ga=d\x1b\\",
+            ImageProtocol::Iterm => "",
+        }
+    }
+
+    /// Escape sequences showing the `png` image at the cursor.
+    pub fn escape(&self, png: &[u8]) -> String {
+        let data = base64(png);
+        match self {
+            ImageProtocol::Kitty => {
+                let chunks: Vec<&str> = data
+                    .as_bytes()
+                    .chunks(KITTY_CHUNK)
+                    .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+                    .collect();
+                let mut out = String::with_capacity(data.len() + chunks.len() * 16);
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = (i + 1 < chunks.len()) as u8;
+                    // Only the first chunk carries the image format and action
+                    let keys = if i == 0 { "a=T,f=100," } else { "" };
+                    out.push_str(&format!("\x1b_G{}m={};{}\x1b\\", keys, more, chunk));
+                }
+                out
+            }
+            ImageProtocol::Iterm => format!("\x1b]1337;File=inline=1;size={}:{}\x07", png.len(), data),
+        }
+    }
+}
+
+/// `doc` rasterized at `scale` on a white background, as PNG.
+pub fn rasterize(doc: &Document, scale: f32) -> Result<Vec<u8>> {
+    render_pixmap(doc, scale, Some(tiny_skia::Color::WHITE))?.encode_png().map_err(|e| anyhow!("{}", e))
+}
+
+/// `doc` rendered at `scale` onto `background`, transparent when None.
+pub fn render_pixmap(doc: &Document, scale: f32, background: Option<tiny_skia::Color>) -> Result<tiny_skia::Pixmap> {
+    let tree = usvg::Tree::from_str(&to_xml(doc), &usvg::Options::default())?;
+    let size = tree.size().to_int_size().scale_by(scale).ok_or_else(|| anyhow!("Empty image"))?;
+    render_tree(&tree, size, tiny_skia::Transform::from_scale(scale, scale), background)
+}
+
+/// `tree` drawn with `transform` onto a `size` image filled with `background`,
+/// transparent when None.
+pub fn render_tree(
+    tree: &usvg::Tree,
+    size: tiny_skia::IntSize,
+    transform: tiny_skia::Transform,
+    background: Option<tiny_skia::Color>,
+) -> Result<tiny_skia::Pixmap> {
+    let mut pixmap =
+        tiny_skia::Pixmap::new(size.width(), size.height()).ok_or_else(|| anyhow!("Image too large: {}x{}", size.width(), size.height()))?;
+    if let Some(background) = background {
+        pixmap.fill(background);
+    }
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+    Ok(pixmap)
+}
+
+/// Standard base64 with padding.
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let triple = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(triple >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test_preview {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(ImageProtocol::Iterm.escape(b"Ma"), "\x1b]1337;File=inline=1;size=2:TWE=\x07");
+        assert_eq!(ImageProtocol::Kitty.escape(b"Ma"), "\x1b_Ga=T,f=100,m=0;TWE=\x1b\\");
+        // Long images are sent in chunks, all but the last flagged with m=1
+        let kitty = ImageProtocol::Kitty.escape(&[0; 6000]);
+        assert_eq!(kitty.matches("\x1b_G").count(), 2);
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,m=1;"));
+        assert!(kitty.contains("\x1b_Gm=0;"));
+    }
+}
@@ -0,0 +1,147 @@
+// named option sets from the config file, e.g.
+//
+//   [blog-header]
+//   font = "Inter"
+//   size = 48
+//   canvas = 1200x630
+//   padding = 24
+//
+// A profile expands to the command line options it names, placed before the
+// ones given on the command line so those override it
+use std::path::{Path, PathBuf};
+
+use crate::validate::closest;
+
+/// Environment variable overriding the config file location.
+pub const CONFIG_ENV: &str = "TEXT2SVG_CONFIG";
+
+/// Profiles of a config file, in file order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profiles {
+    profiles: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Profiles {
+    /// Where the config file lives unless overridden by `TEXT2SVG_CONFIG`.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("text2svg").join("config"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Can't read the config file {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parses `[name]` sections of `option = value` lines; `#` starts a comment.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut profiles: Vec<(String, Vec<(String, String)>)> = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                profiles.push((name.trim().to_string(), Vec::new()));
+                continue;
+            }
+            let Some((option, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `option = value` or `[profile]`, found '{}'", number + 1, line));
+            };
+            let Some((_, options)) = profiles.last_mut() else {
+                return Err(format!("line {}: option '{}' is outside of a [profile] section", number + 1, option.trim()));
+            };
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            options.push((option.trim().replace('_', "-"), value.to_string()));
+        }
+        Ok(Self { profiles })
+    }
+
+    pub fn get_names(&self) -> Vec<&str> {
+        self.profiles.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Command line options of the profile `name`: `true` makes a flag and `false` leaves it out.
+    pub fn get_args(&self, name: &str) -> Result<Vec<String>, String> {
+        let Some((_, options)) = self.profiles.iter().find(|(profile, _)| profile == name) else {
+            let names = self.get_names();
+            return Err(match closest(name, names.iter().copied()) {
+                Some(known) => format!("Unknown profile '{}': did you mean '{}'?", name, known),
+                None if names.is_empty() => format!("Unknown profile '{}': the config file has no profiles", name),
+                None => format!("Unknown profile '{}'; profiles are {}", name, names.join(", ")),
+            });
+        };
+        let mut args = Vec::new();
+        for (option, value) in options {
+            match value.as_str() {
+                "true" => args.push(format!("--{}", option)),
+                "false" => {}
+                _ => args.push(format!("--{}={}", option, value)),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// `args` with the options of the profile they name with `--profile` inserted
+/// after the program name, so later options override the profile's.
+pub fn expand_profile_args(args: Vec<String>, config: Option<&Path>) -> Result<Vec<String>, String> {
+    let name = args.windows(2).find(|pair| pair[0] == "--profile").map(|pair| pair[1].clone());
+    let Some(name) = name.or_else(|| args.iter().find_map(|arg| arg.strip_prefix("--profile=").map(str::to_string))) else {
+        return Ok(args);
+    };
+    let path = config
+        .map(Path::to_path_buf)
+        .or_else(Profiles::default_path)
+        .ok_or_else(|| format!("--profile {} needs a config file, but there is no config directory", name))?;
+    if !path.exists() {
+        return Err(format!("--profile {} needs the config file {}, which doesn't exist", name, path.display()));
+    }
+    let profile_args = Profiles::load(&path)?.get_args(&name)?;
+    let mut args = args;
+    let at = 1.min(args.len());
+    args.splice(at..at, profile_args);
+    Ok(args)
+}
+
+#[cfg(test)]
+mod test_profile {
+    use super::*;
+
+    const CONFIG: &str = "# recurring styles\n[blog-header]\nfont = \"DejaVu Sans\"\nsize = 48\nstroke_width = 2\nanimate = true\nquiet = false\n\n[code]\nhighlight = true\n";
+
+    #[test]
+    fn test_profile_args() {
+        let profiles = Profiles::parse(CONFIG).unwrap();
+        assert_eq!(profiles.get_names(), vec!["blog-header", "code"]);
+        assert_eq!(
+            profiles.get_args("blog-header").unwrap(),
+            vec!["--font=DejaVu Sans", "--size=48", "--stroke-width=2", "--animate"]
+        );
+        assert!(profiles.get_args("blog-headr").unwrap_err().contains("did you mean 'blog-header'"));
+        assert!(Profiles::parse("size = 48").is_err());
+    }
+
+    #[test]
+    fn test_expand_profile_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profiles.toml");
+        std::fs::write(&path, CONFIG).unwrap();
+        let args: Vec<String> = ["text2svg", "hi", "--profile", "code", "--size", "20"].map(String::from).to_vec();
+        let expanded = expand_profile_args(args.clone(), Some(&path)).unwrap();
+        assert_eq!(expanded, ["text2svg", "--highlight", "hi", "--profile", "code", "--size", "20"]);
+        let without: Vec<String> = vec!["text2svg".into(), "hi".into()];
+        assert_eq!(expand_profile_args(without.clone(), Some(&path)).unwrap(), without);
+    }
+}
@@ -0,0 +1,116 @@
+// progress bar on stderr for long renders (batch mode, chunked files), only
+// drawn when stderr is a terminal
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// Cells of the bar itself
+const BAR_WIDTH: usize = 30;
+// Minimum time between two redraws, so fast steps don't flood the terminal
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress of `total` units of work, drawn on one line of stderr.
+#[derive(Debug)]
+pub struct Progress {
+    pub total: u64,
+    pub done: u64,
+    pub enabled: bool,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    /// A bar that is only drawn if `enabled` and stderr is a terminal.
+    pub fn new(total: u64, enabled: bool) -> Self {
+        Progress {
+            total,
+            done: 0,
+            enabled: enabled && std::io::stderr().is_terminal(),
+            started: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    /// Sets the units done so far, redrawing with `detail` (e.g. "120 lines") after the bar.
+    pub fn set(&mut self, done: u64, detail: &str) {
+        self.done = done.min(self.total);
+        if !self.enabled || self.drawn.is_some_and(|drawn| drawn.elapsed() < REDRAW_INTERVAL && self.done < self.total) {
+            return;
+        }
+        self.drawn = Some(Instant::now());
+        let line = progress_line(self.done, self.total, self.started.elapsed(), detail);
+        let mut err = std::io::stderr();
+        let _ = write!(err, "\r\x1b[2K{}", line);
+        let _ = err.flush();
+    }
+
+    /// Prints `message` on its own line above the bar.
+    pub fn println(&mut self, message: &str) {
+        if self.enabled && self.drawn.is_some() {
+            eprint!("\r\x1b[2K");
+            // Forces a redraw on the next step
+            self.drawn = None;
+        }
+        eprintln!("{}", message);
+    }
+
+    /// Removes the bar, leaving the line free for what follows.
+    pub fn finish(&mut self) {
+        if self.enabled && self.drawn.take().is_some() {
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Time left at the rate so far, once some work is done.
+pub fn eta(done: u64, total: u64, elapsed: Duration) -> Option<Duration> {
+    if done == 0 || done >= total {
+        return None;
+    }
+    Some(elapsed.mul_f64((total - done) as f64 / done as f64))
+}
+
+/// "[=======>      ]  45%  ETA 0:12  detail"
+pub fn progress_line(done: u64, total: u64, elapsed: Duration, detail: &str) -> String {
+    let fraction = if total == 0 { 1.0 } else { done as f64 / total as f64 };
+    let filled = ((fraction * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+    let mut bar = "=".repeat(filled);
+    if filled < BAR_WIDTH {
+        bar.push('>');
+    }
+    let eta = eta(done, total, elapsed).map_or_else(|| "--:--".to_string(), format_duration);
+    format!("[{:<width$}] {:>3}%  ETA {}  {}", bar, (fraction * 100.0) as u32, eta, detail, width = BAR_WIDTH)
+}
+
+/// "m:ss", or "h:mm:ss" past an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod test_progress {
+    use super::*;
+
+    #[test]
+    fn test_progress_line() {
+        assert_eq!(eta(0, 10, Duration::from_secs(5)), None);
+        assert_eq!(eta(5, 10, Duration::from_secs(6)), Some(Duration::from_secs(6)));
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+        assert_eq!(
+            progress_line(3, 10, Duration::from_secs(3), "3/10 files"),
+            format!("[{}>{}]  30%  ETA 0:07  3/10 files", "=".repeat(9), " ".repeat(20))
+        );
+        assert!(progress_line(10, 10, Duration::from_secs(3), "").starts_with(&format!("[{}] 100%  ETA --:--", "=".repeat(30))));
+    }
+}
@@ -0,0 +1,130 @@
+// writing the rendered document as a bitmap: png, webp or jpeg drawn by the
+// same rasterizer as the previews, at a chosen pixel size
+use anyhow::{anyhow, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::ExtendedColorType;
+use resvg::tiny_skia::{self, IntSize, Pixmap, Transform};
+use resvg::usvg;
+
+use crate::color::Rgba;
+use crate::svg::OutputFormat;
+
+/// JPEG quality unless another is chosen.
+pub const DEFAULT_QUALITY: u8 = 90;
+
+/// How raster outputs are drawn and encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RasterOptions {
+    /// Color under the image: transparent when None, or white for jpeg, which has no alpha
+    pub background: Option<Rgba>,
+    /// JPEG quality from 1 to 100; webp is written lossless
+    pub quality: u8,
+    /// Size in pixels: the document's size when neither is given, the other
+    /// following the aspect ratio when one is, and the document fitted and
+    /// centered when both are
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        RasterOptions { background: None, quality: DEFAULT_QUALITY, width: None, height: None }
+    }
+}
+
+// Pixel size of an image of a `width` by `height` document
+fn pixel_size(width: f32, height: f32, options: &RasterOptions) -> Option<IntSize> {
+    let scaled = |length: f32, scale: f32| (length * scale).round().max(1.0) as u32;
+    match (options.width, options.height) {
+        (Some(w), Some(h)) => IntSize::from_wh(w, h),
+        (Some(w), None) => IntSize::from_wh(w, scaled(height, w as f32 / width)),
+        (None, Some(h)) => IntSize::from_wh(scaled(width, h as f32 / height), h),
+        (None, None) => IntSize::from_wh(width.ceil() as u32, height.ceil() as u32),
+    }
+}
+
+/// Straight (not premultiplied) RGBA pixels of `pixmap`, row by row.
+pub fn straight_rgba(pixmap: &Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
+
+/// The svg document `content` rendered and encoded as a png, webp or jpeg image.
+pub fn encode_raster(content: &str, format: OutputFormat, options: &RasterOptions) -> Result<Vec<u8>> {
+    let tree = usvg::Tree::from_str(content, &usvg::Options::default())?;
+    let (width, height) = (tree.size().width(), tree.size().height());
+    let size = pixel_size(width, height, options).ok_or_else(|| anyhow!("Empty image"))?;
+    let scale = (size.width() as f32 / width).min(size.height() as f32 / height);
+    let transform = Transform::from_scale(scale, scale)
+        .post_translate((size.width() as f32 - width * scale) / 2.0, (size.height() as f32 - height * scale) / 2.0);
+
+    let background = match (format, options.background) {
+        // JPEG has no alpha, so the background is made opaque over white
+        (OutputFormat::Jpeg, background) => {
+            let Rgba(r, g, b, a) = background.unwrap_or(Rgba(255, 255, 255, 255));
+            let over_white = |channel: u8| ((channel as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+            Some(Rgba(over_white(r), over_white(g), over_white(b), 255))
+        }
+        (_, background) => background,
+    };
+    let background = background.map(|Rgba(r, g, b, a)| tiny_skia::Color::from_rgba8(r, g, b, a));
+    let pixmap = crate::preview::render_tree(&tree, size, transform, background)?;
+
+    let mut out = Vec::new();
+    match format {
+        OutputFormat::Webp => {
+            WebPEncoder::new_lossless(&mut out).encode(&straight_rgba(&pixmap), size.width(), size.height(), ExtendedColorType::Rgba8)?
+        }
+        OutputFormat::Jpeg => {
+            // Every pixel is opaque over the background
+            let rgb: Vec<u8> = pixmap.pixels().iter().flat_map(|pixel| [pixel.red(), pixel.green(), pixel.blue()]).collect();
+            JpegEncoder::new_with_quality(&mut out, options.quality).encode(&rgb, size.width(), size.height(), ExtendedColorType::Rgb8)?
+        }
+        _ => out = pixmap.encode_png().map_err(|e| anyhow!("{}", e))?,
+    }
+    Ok(out)
+}
+
+/// Width and height of the png, webp or jpeg image at `path`, None when it isn't one.
+pub fn image_size(path: &std::path::Path) -> Option<(u32, u32)> {
+    image::ImageReader::open(path).ok()?.with_guessed_format().ok()?.into_dimensions().ok()
+}
+
+#[cfg(test)]
+mod test_raster {
+    use super::*;
+
+    const SVG: &str = r##"<svg height="50" viewBox="0 0 100 50" width="100" xmlns="http://www.w3.org/2000/svg"><rect fill="#f00" height="50" width="50"/></svg>"##;
+
+    #[test]
+    fn test_encode_raster() {
+        let png = encode_raster(SVG, OutputFormat::Png, &RasterOptions::default()).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (100, 50));
+        assert_eq!(image.get_pixel(10, 10).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(90, 10).0[3], 0);
+
+        // Fitted into the width and centered, with a white background
+        let options = RasterOptions { width: Some(200), height: Some(200), quality: 95, ..RasterOptions::default() };
+        let jpeg = encode_raster(SVG, OutputFormat::Jpeg, &options).unwrap();
+        assert!(jpeg.starts_with(&[0xff, 0xd8]));
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (200, 200));
+        assert!(image.get_pixel(50, 100).0[0] > 240 && image.get_pixel(50, 100).0[1] < 20);
+        assert!(image.get_pixel(150, 100).0.iter().all(|&channel| channel > 240));
+        assert!(image.get_pixel(50, 20).0.iter().all(|&channel| channel > 240));
+
+        let options = RasterOptions { height: Some(25), background: Some(Rgba(0, 0, 255, 255)), ..RasterOptions::default() };
+        let webp = encode_raster(SVG, OutputFormat::Webp, &options).unwrap();
+        let image = image::load_from_memory(&webp).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (50, 25));
+        assert_eq!(image.get_pixel(40, 10).0, [0, 0, 255, 255]);
+    }
+}
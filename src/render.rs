@@ -1,33 +1,101 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use anyhow::{anyhow, Result};
 use resvg::tiny_skia::Point;
 use std::io::BufRead;
-use std::path::PathBuf;
-use svg::node::element::{Rectangle, Definitions};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use svg::node::element::{Circle, Rectangle, Definitions};
 
 use syntect::easy::HighlightFile;
 
 use rustybuzz::ttf_parser::Rect;
-use rustybuzz::Face;
-use rustybuzz::GlyphBuffer;
 
+use crate::a11y::{label_document, Accessibility, MAX_TITLE_CHARS};
+use crate::color::Color;
+use crate::error::io_error;
 use crate::font::{FontConfig, FontStyle};
-use crate::highlight::{HighlightColor, HighlightFontStyle, HighlightSetting};
-use crate::svg::{TextBuilder, GlyphCache, GlyphDefs};
+use crate::length::Length;
+use crate::effects::{
+    blur_filter, blur_padding, letterpress_filter, neon_filter, shadow_filter, shadow_padding, Contour, Extrude, Letterpress, Neon,
+    Shadow, BLUR_FILTER_ID, LETTERPRESS_FILTER_ID, NEON_FILTER_ID, SHADOW_FILTER_ID, TEXT_FACE_ID,
+};
+use svg::node::element::Filter;
+use crate::background::{Background, Border, Edges, Stripes};
+use crate::boxdraw::box_cells;
+use crate::cssvars::set_paints;
+use crate::decoration::Underline;
+use crate::direction::{text_direction, Align, Direction};
+use crate::emphasis::{line_paint, Emphasis, LinePaint};
+use crate::fragment::to_fragment;
+use crate::textmode::{font_face_style, text_line, TextMode};
+use crate::glyphmap::ClusterIds;
+use crate::gradient::Gradient;
+use crate::lang::{detect_lang, Lang};
+use crate::highlight::{HighlightColor, HighlightFontStyle, HighlightSetting, WINDOW_BUTTON_COLORS};
+use crate::overflow::{fade_out, Canvas, Overflow};
+use crate::wordcolor::{tokens, WordColors};
+use crate::markup::{parse_markup, plain_text, slice_spans, to_markup, Span};
+use crate::shape::ShapedText;
+use crate::metadata::options_element;
+use crate::output::OutputConfig;
+use crate::svg::{count_glyph_uses, estimate_svg_size, save_document, TextBuilder, GlyphCache, GlyphDefs, PathConfig};
 use crate::utils::open_file_by_lines;
+use crate::warp::{warp_text, Warp};
 use crate::utils::open_file_by_lines_width;
 use crate::utils::open_file_by_lines_pixel_width;
 use crate::utils::wrap_text_by_pixel_width;
+use crate::utils::wrap_text_by_width;
+use crate::utils::WrapMode;
+use crate::truncate::{Truncation, DEFAULT_ELLIPSIS};
 
 use svg::node::element::{Group, Style};
 use svg::Document;
 use syntect::highlighting::Style as TokenStyle;
 
 // render config for non-highlight mode
+#[derive(Clone)]
 pub struct RenderConfig {
     animate: bool,
     font_style: FontStyle,
     max_width: Option<usize>,
     max_pixel_width: Option<f32>,
+    wrap: Option<WrapMode>,
+    east_asian_width: bool,
+    markup: bool,
+    shadows: Vec<Shadow>,
+    neon: Option<Neon>,
+    letterpress: Option<Letterpress>,
+    blur: Option<f32>,
+    path_config: PathConfig,
+    css_vars: bool,
+    extrude: Option<Extrude>,
+    gradient: Option<Gradient>,
+    warp: Option<Warp>,
+    contour: Option<Contour>,
+    emphases: Vec<Emphasis>,
+    word_colors: Option<WordColors>,
+    stripes: Option<Stripes>,
+    background: Option<Background>,
+    border: Option<Border>,
+    padding: Edges,
+    margin: Edges,
+    line_height: Length,
+    paragraph_spacing: Length,
+    max_lines: Option<usize>,
+    max_chars_per_line: Option<usize>,
+    ellipsis: String,
+    overflow: Overflow,
+    canvas: Option<Canvas>,
+    underline: Option<Underline>,
+    accessibility: Accessibility,
+    lang: Option<Lang>,
+    detect_lang: bool,
+    direction: Direction,
+    align: Align,
+    box_drawing: bool,
+    glyph_ids: bool,
+    text_mode: TextMode,
 }
 
 impl RenderConfig {
@@ -37,6 +105,42 @@ impl RenderConfig {
             font_style: style,
             max_width: None,
             max_pixel_width: None,
+            wrap: None,
+            east_asian_width: true,
+            markup: false,
+            shadows: Vec::new(),
+            neon: None,
+            letterpress: None,
+            blur: None,
+            path_config: PathConfig::default(),
+            css_vars: false,
+            extrude: None,
+            gradient: None,
+            warp: None,
+            contour: None,
+            emphases: Vec::new(),
+            word_colors: None,
+            stripes: None,
+            background: None,
+            border: None,
+            padding: Edges::default(),
+            margin: Edges::default(),
+            line_height: Length::Em(1.0),
+            paragraph_spacing: Length::Px(0.0),
+            max_lines: None,
+            max_chars_per_line: None,
+            ellipsis: DEFAULT_ELLIPSIS.to_string(),
+            overflow: Overflow::Clip,
+            canvas: None,
+            underline: None,
+            accessibility: Accessibility::default(),
+            lang: None,
+            detect_lang: false,
+            direction: Direction::Auto,
+            align: Align::Start,
+            box_drawing: false,
+            glyph_ids: false,
+            text_mode: TextMode::Paths,
         }
     }
 
@@ -50,6 +154,22 @@ impl RenderConfig {
         self
     }
 
+    /// Wrap mode; None picks it from the width limit that is set.
+    pub fn set_wrap(&mut self, wrap: Option<WrapMode>) -> &mut Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Whether character wrapping and truncation count East Asian wide characters as two columns.
+    pub fn set_east_asian_width(&mut self, east_asian_width: bool) -> &mut Self {
+        self.east_asian_width = east_asian_width;
+        self
+    }
+
+    pub fn get_east_asian_width(&self) -> bool {
+        self.east_asian_width
+    }
+
     pub fn get_font_style(&self) -> &FontStyle {
         &self.font_style
     }
@@ -57,6 +177,586 @@ impl RenderConfig {
     pub fn get_animate(&self) -> bool {
         self.animate
     }
+
+    pub fn get_max_width(&self) -> Option<usize> {
+        self.max_width
+    }
+
+    pub fn get_max_pixel_width(&self) -> Option<f32> {
+        self.max_pixel_width
+    }
+
+    /// The wrap mode in effect: the one set, else pixel with a pixel width,
+    /// word with a character width and none without either. A mode whose
+    /// width isn't set wraps nothing.
+    pub fn get_wrap(&self) -> WrapMode {
+        let wrap = self.wrap.unwrap_or(match (self.max_pixel_width, self.max_width) {
+            (Some(_), _) => WrapMode::Pixel,
+            (None, Some(_)) => WrapMode::Word,
+            (None, None) => WrapMode::None,
+        });
+        match wrap {
+            WrapMode::Pixel if self.max_pixel_width.is_none() => WrapMode::None,
+            WrapMode::Char | WrapMode::Word if self.max_width.is_none() => WrapMode::None,
+            wrap => wrap,
+        }
+    }
+
+    /// Interpret input lines as inline markup (see `markup`).
+    pub fn set_markup(&mut self, markup: bool) -> &mut Self {
+        self.markup = markup;
+        self
+    }
+
+    pub fn get_markup(&self) -> bool {
+        self.markup
+    }
+
+    /// Text shadows, the first drawn on top.
+    pub fn set_shadows(&mut self, shadows: Vec<Shadow>) -> &mut Self {
+        self.shadows = shadows;
+        self
+    }
+
+    pub fn get_shadows(&self) -> &[Shadow] {
+        &self.shadows
+    }
+
+    /// Neon glow around the text, drawn below the shadows.
+    pub fn set_neon(&mut self, neon: Option<Neon>) -> &mut Self {
+        self.neon = neon;
+        self
+    }
+
+    pub fn get_neon(&self) -> Option<&Neon> {
+        self.neon.as_ref()
+    }
+
+    /// Inset edges inside the glyphs, drawn below the glow and shadows.
+    pub fn set_letterpress(&mut self, letterpress: Option<Letterpress>) -> &mut Self {
+        self.letterpress = letterpress;
+        self
+    }
+
+    pub fn get_letterpress(&self) -> Option<&Letterpress> {
+        self.letterpress.as_ref()
+    }
+
+    /// Standard deviation of a gaussian blur over the whole text layer, effects included.
+    pub fn set_blur(&mut self, blur: Option<f32>) -> &mut Self {
+        self.blur = blur.filter(|blur| *blur > 0.0);
+        self
+    }
+
+    pub fn get_blur(&self) -> Option<f32> {
+        self.blur
+    }
+
+    /// Stroke width, joins and paint order of the glyphs.
+    pub fn set_path_config(&mut self, path_config: PathConfig) -> &mut Self {
+        self.path_config = path_config;
+        self
+    }
+
+    pub fn get_path_config(&self) -> &PathConfig {
+        &self.path_config
+    }
+
+    /// Paint with CSS variables falling back to the configured colors, so host pages can retheme the svg.
+    pub fn set_css_vars(&mut self, css_vars: bool) -> &mut Self {
+        self.css_vars = css_vars;
+        self
+    }
+
+    pub fn get_css_vars(&self) -> bool {
+        self.css_vars
+    }
+
+    /// 3D extrusion drawn behind the text.
+    pub fn set_extrude(&mut self, extrude: Option<Extrude>) -> &mut Self {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn get_extrude(&self) -> Option<&Extrude> {
+        self.extrude.as_ref()
+    }
+
+    /// Colors characters or words along a palette, per line.
+    pub fn set_gradient(&mut self, gradient: Option<Gradient>) -> &mut Self {
+        self.gradient = gradient;
+        self
+    }
+
+    pub fn get_gradient(&self) -> Option<&Gradient> {
+        self.gradient.as_ref()
+    }
+
+    /// Bends the laid out text, outlining every glyph in place.
+    pub fn set_warp(&mut self, warp: Option<Warp>) -> &mut Self {
+        self.warp = warp;
+        self
+    }
+
+    pub fn get_warp(&self) -> Option<&Warp> {
+        self.warp.as_ref()
+    }
+
+    /// Sticker style rings drawn around the text.
+    pub fn set_contour(&mut self, contour: Option<Contour>) -> &mut Self {
+        self.contour = contour;
+        self
+    }
+
+    pub fn get_contour(&self) -> Option<&Contour> {
+        self.contour.as_ref()
+    }
+
+    pub fn set_emphases(&mut self, emphases: Vec<Emphasis>) -> &mut Self {
+        self.emphases = emphases;
+        self
+    }
+
+    pub fn get_emphases(&self) -> &[Emphasis] {
+        &self.emphases
+    }
+
+    /// Colors for words picked by the caller, by word or by word index.
+    pub fn set_word_colors(&mut self, word_colors: Option<WordColors>) -> &mut Self {
+        self.word_colors = word_colors;
+        self
+    }
+
+    pub fn get_word_colors(&self) -> Option<&WordColors> {
+        self.word_colors.as_ref()
+    }
+
+    pub fn set_stripes(&mut self, stripes: Option<Stripes>) -> &mut Self {
+        self.stripes = stripes;
+        self
+    }
+
+    pub fn get_stripes(&self) -> Option<&Stripes> {
+        self.stripes.as_ref()
+    }
+
+    /// Color, gradient or image behind the text, its padding and border.
+    pub fn set_background(&mut self, background: Option<Background>) -> &mut Self {
+        self.background = background;
+        self
+    }
+
+    pub fn get_background(&self) -> Option<&Background> {
+        self.background.as_ref()
+    }
+
+    pub fn set_border(&mut self, border: Option<Border>) -> &mut Self {
+        self.border = border;
+        self
+    }
+
+    pub fn get_border(&self) -> Option<&Border> {
+        self.border.as_ref()
+    }
+
+    pub fn set_padding(&mut self, padding: Edges) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn get_padding(&self) -> &Edges {
+        &self.padding
+    }
+
+    /// Distance between baselines; em lengths are relative to the font size.
+    pub fn set_line_height(&mut self, line_height: Length) -> &mut Self {
+        self.line_height = line_height;
+        self
+    }
+
+    pub fn get_line_height(&self) -> Length {
+        self.line_height
+    }
+
+    /// Extra space before each paragraph after the first, on top of the line height.
+    pub fn set_paragraph_spacing(&mut self, paragraph_spacing: Length) -> &mut Self {
+        self.paragraph_spacing = paragraph_spacing;
+        self
+    }
+
+    pub fn get_paragraph_spacing(&self) -> Length {
+        self.paragraph_spacing
+    }
+
+    /// Line height in whole pixels for text of `font_size`, at least 1.
+    pub fn line_height_pixels(&self, font_size: u32) -> u32 {
+        (self.line_height.to_pixels(font_size as f32).round() as u32).max(1)
+    }
+
+    /// Paragraph spacing in whole pixels for text of `font_size`.
+    pub fn paragraph_spacing_pixels(&self, font_size: u32) -> u32 {
+        self.paragraph_spacing.to_pixels(font_size as f32).round().max(0.0) as u32
+    }
+
+    pub fn set_margin(&mut self, margin: Edges) -> &mut Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn get_margin(&self) -> &Edges {
+        &self.margin
+    }
+
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) -> &mut Self {
+        self.max_lines = max_lines;
+        self
+    }
+
+    pub fn get_max_lines(&self) -> Option<usize> {
+        self.max_lines
+    }
+
+    pub fn set_overflow(&mut self, overflow: Overflow) -> &mut Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn get_overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    /// Cut lines longer than this many characters, after wrapping, ending them with the ellipsis.
+    pub fn set_max_chars_per_line(&mut self, max_chars: Option<usize>) -> &mut Self {
+        self.max_chars_per_line = max_chars;
+        self
+    }
+
+    pub fn get_max_chars_per_line(&self) -> Option<usize> {
+        self.max_chars_per_line
+    }
+
+    /// What ends cut lines, and with `Overflow::Ellipsis` the last line kept.
+    pub fn set_ellipsis(&mut self, ellipsis: &str) -> &mut Self {
+        self.ellipsis = ellipsis.to_string();
+        self
+    }
+
+    pub fn get_ellipsis(&self) -> &str {
+        &self.ellipsis
+    }
+
+    /// Limits lines are cut to: the characters per line and the pixel width.
+    pub fn truncation(&self) -> Truncation<'_> {
+        Truncation {
+            max_chars: self.max_chars_per_line,
+            east_asian: self.east_asian_width,
+            max_pixel_width: self.max_pixel_width,
+            ellipsis: &self.ellipsis,
+        }
+    }
+
+    /// Exact output size; the overflow policy decides what happens to text that doesn't fit.
+    pub fn set_canvas(&mut self, canvas: Option<Canvas>) -> &mut Self {
+        self.canvas = canvas;
+        self
+    }
+
+    pub fn get_canvas(&self) -> Option<&Canvas> {
+        self.canvas.as_ref()
+    }
+
+    pub fn set_underline(&mut self, underline: Option<Underline>) -> &mut Self {
+        self.underline = underline;
+        self
+    }
+
+    pub fn get_underline(&self) -> Option<&Underline> {
+        self.underline.as_ref()
+    }
+
+    /// Title, description or decorative role for assistive technology; the title
+    /// is taken from the text when not set.
+    pub fn set_accessibility(&mut self, accessibility: Accessibility) -> &mut Self {
+        self.accessibility = accessibility;
+        self
+    }
+
+    pub fn get_accessibility(&self) -> &Accessibility {
+        &self.accessibility
+    }
+
+    /// Language of the text, recorded on the root element.
+    pub fn set_lang(&mut self, lang: Option<Lang>) -> &mut Self {
+        self.lang = lang;
+        self
+    }
+
+    pub fn get_lang(&self) -> Option<&Lang> {
+        self.lang.as_ref()
+    }
+
+    /// Records the language of each line written in a script of its own, e.g.
+    /// Greek or Hangul, where it differs from the document's.
+    pub fn set_detect_lang(&mut self, detect_lang: bool) -> &mut Self {
+        self.detect_lang = detect_lang;
+        self
+    }
+
+    pub fn get_detect_lang(&self) -> bool {
+        self.detect_lang
+    }
+
+    /// Base direction of the text. Right to left aligns lines to the right by
+    /// default and swaps the left and right padding and margin.
+    pub fn set_direction(&mut self, direction: Direction) -> &mut Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Base direction; `Auto` lays out left to right until resolved with `with_direction_of`.
+    pub fn get_direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// This config with an `Auto` direction resolved from the first letter in
+    /// `lines`, markup tags left out.
+    pub fn with_direction_of<'a>(&self, lines: impl IntoIterator<Item = &'a str>) -> Cow<'_, RenderConfig> {
+        if self.direction != Direction::Auto {
+            return Cow::Borrowed(self);
+        }
+        let direction = lines.into_iter().find_map(|line| self.line_direction(line));
+        let mut resolved = self.clone();
+        resolved.direction = direction.unwrap_or(Direction::Ltr);
+        Cow::Owned(resolved)
+    }
+
+    /// Direction of the first letter in `line`, markup tags left out; None when it has no letters.
+    pub fn line_direction(&self, line: &str) -> Option<Direction> {
+        match self.markup {
+            true => parse_markup(line).map_or_else(|_| text_direction(line), |spans| text_direction(&plain_text(&spans))),
+            false => text_direction(line),
+        }
+    }
+
+    pub fn set_align(&mut self, align: Align) -> &mut Self {
+        self.align = align;
+        self
+    }
+
+    pub fn get_align(&self) -> Align {
+        self.align
+    }
+
+    /// Draws box-drawing characters (and ASCII `+`, `-` and `|` where they join
+    /// up) as connected lines instead of glyphs. Lines with markup are left as they are.
+    pub fn set_box_drawing(&mut self, box_drawing: bool) -> &mut Self {
+        self.box_drawing = box_drawing;
+        self
+    }
+
+    pub fn get_box_drawing(&self) -> bool {
+        self.box_drawing
+    }
+
+    /// Gives every glyph's `<use>` element an id naming its line and the bytes
+    /// of its cluster, which a glyph map resolves to the source text.
+    pub fn set_glyph_ids(&mut self, glyph_ids: bool) -> &mut Self {
+        self.glyph_ids = glyph_ids;
+        self
+    }
+
+    pub fn get_glyph_ids(&self) -> bool {
+        self.glyph_ids
+    }
+
+    /// Writes glyph outlines, or `<text>` elements in the embedded font.
+    pub fn set_text_mode(&mut self, text_mode: TextMode) -> &mut Self {
+        self.text_mode = text_mode;
+        self
+    }
+
+    pub fn get_text_mode(&self) -> TextMode {
+        self.text_mode
+    }
+
+    // Padding or margin edges as given for the text's direction, left and right swapped for right to left
+    fn directed_edges(&self, edges: &Edges) -> Edges {
+        match self.direction.is_rtl() {
+            true => edges.mirrored(),
+            false => *edges,
+        }
+    }
+
+    /// Whether colors vary along lines, from a gradient, colored words or highlighted matches.
+    pub fn has_line_paint(&self) -> bool {
+        self.gradient.is_some() || self.word_colors.is_some() || !self.emphases.is_empty()
+    }
+
+    // Colors along `line`, whose first word is word `first_token` of the text
+    fn line_paint(&self, line: &str, first_token: usize) -> LinePaint {
+        let words = self
+            .word_colors
+            .as_ref()
+            .map(|word_colors| word_colors.line_colors(line, first_token))
+            .unwrap_or_default();
+        line_paint(line, self.get_gradient(), words, self.get_emphases())
+    }
+}
+
+/// View box (x, y, width, height) for text covering `content`, grown so the
+/// configured effects are not clipped.
+pub fn effects_view_box(content: (f32, f32, f32, f32), render_config: &RenderConfig) -> (f32, f32, f32, f32) {
+    let (x, y, width, height) = content;
+    // Each effect applies to the result of the previous one, so their reaches add up
+    let extrusion = render_config.get_extrude().map(Extrude::padding).unwrap_or_default();
+    let contour = render_config.get_contour().map(Contour::padding).unwrap_or(0.0);
+    let extrusion = (extrusion.0 + contour, extrusion.1 + contour, extrusion.2 + contour, extrusion.3 + contour);
+    let glow = render_config.get_neon().map(Neon::padding).unwrap_or(0.0);
+    let shadows = shadow_padding(render_config.get_shadows());
+    let (bent_up, bent_down) = render_config.get_warp().map(|warp| warp.padding(height)).unwrap_or_default();
+    let blur = render_config.get_blur().map(blur_padding).unwrap_or(0.0);
+    // Heavy strokes reach beyond the glyph boxes before any effect applies
+    let stroke = render_config.get_path_config().stroke_reach();
+    let left = stroke + extrusion.0 + glow + shadows.0 + blur;
+    let top = stroke + bent_up + extrusion.1 + glow + shadows.1 + blur;
+    let right = stroke + extrusion.2 + glow + shadows.2 + blur;
+    let bottom = stroke + bent_down + extrusion.3 + glow + shadows.3 + blur;
+    (x - left, y - top, width + left + right, height + top + bottom)
+}
+
+/// The effects view box grown by the padding: the area the background and
+/// any border surround.
+pub fn padded_view_box(view_box: (f32, f32, f32, f32), font_config: &FontConfig, render_config: &RenderConfig) -> (f32, f32, f32, f32) {
+    render_config.directed_edges(render_config.get_padding()).outer_box(view_box, font_config.get_size() as f32)
+}
+
+/// The whole composition around `padded_box`: the border, then the margin.
+pub fn margin_view_box(padded_box: (f32, f32, f32, f32), font_config: &FontConfig, render_config: &RenderConfig) -> (f32, f32, f32, f32) {
+    let border_box = render_config.get_border().map_or(padded_box, |border| border.outer_box(padded_box));
+    render_config.directed_edges(render_config.get_margin()).outer_box(border_box, font_config.get_size() as f32)
+}
+
+/// Applies the fill and stroke colors to the laid out text, with the
+/// extrusion layers and contour rings behind it when configured.
+pub fn style_text_group(content: Group, font_config: &FontConfig, render_config: &RenderConfig) -> Group {
+    let paints = text_paints(font_config);
+    let css_vars = render_config.get_css_vars();
+    let extrude = render_config.get_extrude();
+    let contour = render_config.get_contour();
+    if extrude.is_none() && contour.is_none() {
+        return set_paints(content, &paints, css_vars);
+    }
+
+    let mut group = Group::new();
+    if let Some(extrude) = extrude {
+        group = group.add(extrude.layers());
+    }
+    if let Some(contour) = contour {
+        group = group.add(contour.rings(&content));
+    }
+    let face = match extrude {
+        // The layers reuse the uncolored face, so it gets its own group inside the colored one
+        Some(_) => set_paints(Group::new(), &paints, css_vars).add(Group::new().set("id", TEXT_FACE_ID).add(content)),
+        None => set_paints(content, &paints, css_vars),
+    };
+    group.add(face)
+}
+
+/// Fill and stroke of the text as (property, CSS variable name, color):
+/// the fill is exported as --text2svg-fill and the stroke as --text2svg-fg.
+pub fn text_paints(font_config: &FontConfig) -> [(&'static str, &'static str, Color); 2] {
+    [("fill", "fill", *font_config.get_fill_color()), ("stroke", "fg", *font_config.get_color())]
+}
+
+/// Filters of the configured effects with their ids, outermost first.
+/// Each one applies to the result of the ones after it.
+pub fn effect_filters(view_box: (f32, f32, f32, f32), render_config: &RenderConfig) -> Vec<(&'static str, Filter)> {
+    let mut filters = Vec::new();
+    if let Some(blur) = render_config.get_blur() {
+        filters.push((BLUR_FILTER_ID, blur_filter(blur, view_box)));
+    }
+    let shadows = render_config.get_shadows();
+    if !shadows.is_empty() {
+        filters.push((SHADOW_FILTER_ID, shadow_filter(shadows, view_box)));
+    }
+    if let Some(neon) = render_config.get_neon() {
+        filters.push((NEON_FILTER_ID, neon_filter(neon, view_box)));
+    }
+    if let Some(letterpress) = render_config.get_letterpress() {
+        filters.push((LETTERPRESS_FILTER_ID, letterpress_filter(letterpress, view_box)));
+    }
+    filters
+}
+
+/// Adds the filters of the configured effects to `defs` and applies them to the text group.
+pub fn apply_effects(
+    text_group: Group,
+    mut defs: Definitions,
+    view_box: (f32, f32, f32, f32),
+    render_config: &RenderConfig,
+) -> (Group, Definitions) {
+    let mut group = text_group;
+    let filters = effect_filters(view_box, render_config);
+    for (i, (id, filter)) in filters.into_iter().rev().enumerate() {
+        // The innermost filter goes on the text group itself, the others on wrapping groups
+        group = if i == 0 { group } else { Group::new().add(group) }.set("filter", format!("url(#{})", id));
+        defs = defs.add(filter);
+    }
+    (group, defs)
+}
+
+/// View box of a document showing `view_box`: the view box itself, or fitted to the configured canvas.
+pub fn canvas_view_box(
+    view_box: (f32, f32, f32, f32),
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(f32, f32, f32, f32)> {
+    match render_config.get_canvas() {
+        Some(canvas) => {
+            let (x, y, view_width, view_height) = canvas
+                .view_box(view_box, render_config.get_overflow(), first_baseline(font_config))
+                .map_err(|e| anyhow!(e))?;
+            // The text sits on the side its lines start on
+            let x = x - render_config.get_align().offset(render_config.get_direction(), view_box.2, view_width);
+            Ok((x, y, view_width, view_height))
+        }
+        None => Ok(view_box),
+    }
+}
+
+/// Distance from the top of a line down to its baseline.
+pub fn first_baseline(font_config: &FontConfig) -> f32 {
+    let Some(face) = font_config.get_font_by_style(&FontStyle::Regular) else {
+        return font_config.get_size() as f32 * 0.8;
+    };
+    let metrics = font_config.face_metrics(face);
+    metrics.ascent * font_config.pixel_scale(metrics)
+}
+
+// Sets the document size from its view box, or the canvas
+fn set_view_box(
+    doc: Document,
+    view_box: (f32, f32, f32, f32),
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<Document> {
+    let (x, y, view_width, view_height) = canvas_view_box(view_box, font_config, render_config)?;
+    let (width, height) = render_config.get_canvas().map_or((view_width, view_height), |canvas| (canvas.width, canvas.height));
+    let doc = doc
+        .set("height", height)
+        .set("width", width)
+        .set("viewBox", format!("{} {} {} {}", x, y, view_width, view_height));
+    Ok(match render_config.get_lang() {
+        Some(lang) => set_lang(doc, lang),
+        None => doc,
+    })
+}
+
+// Both attributes, as SVG files read xml:lang and HTML pages inlining them read lang
+fn set_lang<T: svg::Node>(mut node: T, lang: &Lang) -> T {
+    node.assign("xml:lang", lang.to_string());
+    node.assign("lang", lang.to_string());
+    node
 }
 
 
@@ -64,8 +764,9 @@ pub fn render_file_highlight(
     file: &PathBuf,
     font_config: &mut FontConfig,
     highlight_setting: &HighlightSetting,
+    output_config: &OutputConfig,
     output: PathBuf,
-) {
+) -> Result<()> {
     let mut max_width: u32 = 0;
     let mut current_height: u32 = 0;
     let line_height = font_config.get_size(); // Use font size as line height
@@ -77,74 +778,164 @@ pub fn render_file_highlight(
     let mut glyph_cache: GlyphCache = HashMap::new();
     let mut glyph_defs: GlyphDefs = HashMap::new(); // Uses Box<dyn Node> now
     let mut main_content = Group::new(); // Group to hold all lines
-
-    if let Some(theme) = theme_set.themes.get(&highlight_setting.theme) {
-        let mut highlighter = HighlightFile::new(file, syntax_set, theme).unwrap();
-
-        // Calculate background color first
-        let background_color = HighlightColor::new(theme.settings.background.unwrap()).to_string();
-
-        for l in highlighter.reader.lines() {
-            let line = l.unwrap();
-            let mut line_group = Group::new().set("transform", format!("translate(0, {})", current_height));
-            let mut current_x: f32 = 0.0;
-            let mut line_max_x: f32 = 0.0;
-
-            if !line.is_empty() {
-                let regions = highlighter
-                    .highlight_lines
-                    .highlight_line(line.as_str(), syntax_set)
-                    .unwrap();
-
-                for region in regions.iter() {
-                    let style = region.0;
-                    let token = region.1;
-                    // Pass glyph_defs as mutable reference
-                    if let Some((token_group, token_bbox)) =
-                        render_token(current_x, 0.0, token, font_config, style, &mut glyph_cache, &mut glyph_defs)
-                    {
-                        // Apply token style (color) to the group containing <use> elements
-                        let foreground_color = HighlightColor::new(style.foreground).to_string();
-                        let styled_token_group = token_group
-                            .set("fill", foreground_color.clone())
-                            .set("stroke", foreground_color); // Or set stroke based on theme?
-
-                        line_group = line_group.add(styled_token_group);
-                        current_x += token_bbox.width() as f32; // Advance x based on calculated width
-                        line_max_x = current_x; // Update max x for this line
-                    }
+    let mut line_count: u32 = 0;
+    // Lines with their top and width, placed once the widest is known
+    let mut lines: Vec<(Group, u32, f32)> = Vec::new();
+    let mut direction = highlight_setting.chrome.direction;
+
+    let theme = theme_set
+        .themes
+        .get(&highlight_setting.theme)
+        .ok_or_else(|| anyhow!("Highlighting theme '{}' not found", highlight_setting.theme))?;
+    let mut highlighter = HighlightFile::new(file, syntax_set, theme).map_err(|e| io_error(file, e))?;
+
+    // Calculate background color first
+    let background_color = HighlightColor::new(theme.settings.background.unwrap_or(syntect::highlighting::Color::BLACK)).to_string();
+
+    for l in highlighter.reader.lines() {
+        let line = l.map_err(|e| io_error(file, e))?;
+        if direction == Direction::Auto {
+            direction = text_direction(&line).unwrap_or(Direction::Auto);
+        }
+        let mut line_group = Group::new();
+        let mut current_x: f32 = 0.0;
+        let mut line_max_x: f32 = 0.0;
+
+        if !line.is_empty() {
+            let regions = highlighter
+                .highlight_lines
+                .highlight_line(line.as_str(), syntax_set)
+                .map_err(|e| anyhow!("Failed to highlight {}: {}", file.display(), e))?;
+
+            for region in regions.iter() {
+                let style = region.0;
+                let token = region.1;
+                // Pass glyph_defs as mutable reference
+                if let Some((token_group, token_bbox)) =
+                    render_token(current_x, 0.0, token, font_config, style, &mut glyph_cache, &mut glyph_defs)
+                {
+                    // Apply token style (color) to the group containing <use> elements
+                    let foreground_color = HighlightColor::new(style.foreground).to_string();
+                    let styled_token_group = token_group
+                        .set("fill", foreground_color.clone())
+                        .set("stroke", foreground_color); // Or set stroke based on theme?
+
+                    line_group = line_group.add(styled_token_group);
+                    current_x += token_bbox.width() as f32; // Advance x based on calculated width
+                    line_max_x = current_x; // Update max x for this line
                 }
             }
-            main_content = main_content.add(line_group);
-            max_width = max_width.max(line_max_x.ceil() as u32);
-            current_height += line_height; // Move to the next line
         }
+        lines.push((line_group, current_height, line_max_x));
+        line_count += 1;
+        max_width = max_width.max(line_max_x.ceil() as u32);
+        current_height += line_height; // Move to the next line
+    }
+
+    // Right-to-left lines end at the left edge of the widest
+    let rtl = direction.is_rtl();
+    for (line_group, y, line_width) in lines {
+        let x = if rtl { max_width as f32 - line_width } else { 0.0 };
+        main_content = main_content.add(line_group.set("transform", format!("translate({}, {})", x, y)));
+    }
 
-        // Add background rectangle
-        let background_rect = Rectangle::new()
-            .set("width", max_width)
-            .set("height", current_height)
-            .set("fill", background_color);
-
-        // Add definitions
-        let mut defs = Definitions::new();
-        // Iterate over the HashMap using .iter() and clone the Box<dyn Node>
-        for (_id, node_box) in glyph_defs.iter() {
-            defs = defs.add(node_box.clone());
+    let chrome = &highlight_setting.chrome;
+    let font_size = font_config.get_size();
+    let padding = chrome.padding(font_size);
+    let bar_height = chrome.bar_height(font_size);
+    let dim_color = theme
+        .settings
+        .gutter_foreground
+        .or(theme.settings.foreground)
+        .unwrap_or(syntect::highlighting::Color::WHITE);
+    let dim_color = HighlightColor::new(dim_color).to_string();
+
+    // Line numbers are aligned towards the code in a gutter one em wider than the widest,
+    // on the left or, for right-to-left code, on the right
+    let mut gutter_width = 0;
+    let mut gutter = Group::new().set("fill", dim_color.clone()).set("stroke", dim_color.clone());
+    if chrome.line_numbers {
+        let numbers: Vec<(Group, f32)> = (1..=line_count)
+            .filter_map(|number| {
+                render_token(0.0, 0.0, &number.to_string(), font_config, TokenStyle::default(), &mut glyph_cache, &mut glyph_defs)
+                    .map(|(group, bbox)| (group, bbox.width() as f32))
+            })
+            .collect();
+        let number_width = numbers.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+        for (i, (number, width)) in numbers.into_iter().enumerate() {
+            let x = match rtl {
+                true => (padding + max_width + font_size) as f32,
+                false => padding as f32 + number_width - width,
+            };
+            gutter = gutter.add(number.set("transform", format!("translate({}, {})", x, bar_height + padding + i as u32 * line_height)));
         }
+        gutter_width = number_width.ceil() as u32 + font_size;
+    }
 
-        // Assemble document
-        doc = doc.add(defs); // Add defs first
-        doc = doc.add(background_rect); // Add background
-        doc = doc.add(main_content); // Add text content
+    let width = max_width + gutter_width + 2 * padding;
+    let height = current_height + bar_height + 2 * padding;
 
-        doc = doc
-            .set("height", current_height)
-            .set("width", max_width)
-            .set("viewBox", format!("0 0 {} {}", max_width, current_height));
+    // Add background rectangle
+    let mut background_rect = Rectangle::new()
+        .set("width", width)
+        .set("height", height)
+        .set("fill", background_color);
+    if let Some(radius) = chrome.radius {
+        background_rect = background_rect.set("rx", radius).set("ry", radius);
+    }
 
-        svg::save(output, &doc).unwrap();
+    // Add definitions
+    let mut defs = Definitions::new();
+
+    let mut window_bar = Group::new();
+    if chrome.window_bar {
+        for ((radius, cx, cy), color) in chrome.buttons(font_size).into_iter().zip(WINDOW_BUTTON_COLORS) {
+            window_bar = window_bar.add(Circle::new().set("cx", cx).set("cy", cy).set("r", radius).set("fill", color));
+        }
+        if let Some(title) = chrome.title.as_deref() {
+            if let Some((title_group, bbox)) =
+                render_token(0.0, 0.0, title, font_config, TokenStyle::default(), &mut glyph_cache, &mut glyph_defs)
+            {
+                let x = (width as f32 - bbox.width() as f32) / 2.0;
+                let y = (bar_height as f32 - line_height as f32) / 2.0;
+                window_bar = window_bar.add(
+                    title_group
+                        .set("transform", format!("translate({}, {})", x, y))
+                        .set("fill", dim_color.clone())
+                        .set("stroke", dim_color),
+                );
+            }
+        }
+    }
+
+    // Iterate over the HashMap using .iter() and clone the Box<dyn Node>
+    for (_id, node_box) in glyph_defs.iter() {
+        defs = defs.add(node_box.clone());
     }
+
+    let code_x = if rtl { padding } else { padding + gutter_width };
+    if code_x + padding + bar_height > 0 {
+        main_content = main_content.set("transform", format!("translate({}, {})", code_x, padding + bar_height));
+    }
+
+    // Assemble document
+    doc = doc.add(defs); // Add defs first
+    doc = doc.add(background_rect); // Add background
+    if chrome.window_bar {
+        doc = doc.add(window_bar);
+    }
+    if chrome.line_numbers {
+        doc = doc.add(gutter);
+    }
+    doc = doc.add(main_content); // Add text content
+
+    doc = doc
+        .set("height", height)
+        .set("width", width)
+        .set("viewBox", format!("0 0 {} {}", width, height));
+
+    save_svg(&output, &doc, &glyph_defs, font_config, output_config).map_err(|e| io_error(&output, e))?;
+    Ok(())
 }
 
 // Renders a token (part of a highlighted line)
@@ -152,7 +943,7 @@ pub fn render_token(
     x: f32,
     y: f32,
     token: &str,
-    font_config: &mut FontConfig,
+    font_config: &FontConfig,
     style: TokenStyle,
     glyph_cache: &mut GlyphCache,
     glyph_defs: &mut GlyphDefs, // Takes mutable reference
@@ -160,7 +951,7 @@ pub fn render_token(
     let font_style = HighlightFontStyle::new(style.font_style).get_style();
 
     if font_config.get_debug() {
-        println!("token: '{}', font style: {:?}", token, font_style);
+        crate::log_debug!("token: '{}', font style: {:?}", token, font_style);
     }
 
     // Shape the token
@@ -175,12 +966,17 @@ pub fn render_token(
     None
 }
 
-// Renders a plain text line
+// Renders a plain text line; `first_token` is the index of its first word in the text,
+// `line_index` its index when its glyphs get ids
+#[allow(clippy::too_many_arguments)]
 pub fn render_text_line(
     x: f32,
     y: f32,
     line: &str,
-    font_config: &mut FontConfig,
+    first_token: usize,
+    box_cells: &[(usize, u8)],
+    line_index: Option<usize>,
+    font_config: &FontConfig,
     render_config: &RenderConfig,
     glyph_cache: &mut GlyphCache,
     glyph_defs: &mut GlyphDefs, // Takes mutable reference
@@ -190,23 +986,95 @@ pub fn render_text_line(
     // Shape the line
     if let Some(glyph_buffer) = text_shape(line, font_config, style) {
         if font_config.get_debug() {
-            println!("shape line: {:?}", line);
+            crate::log_debug!("shape line: {:?}", line);
         }
         let mut svg_builder = TextBuilder::new();
-        svg_builder.set_origin(Point { x, y });
-        // Colors applied later
+        svg_builder
+            .set_origin(Point { x, y })
+            .set_path_config(render_config.get_path_config().clone());
+        // Colors applied later, unless they vary along the line
+        if render_config.has_line_paint() {
+            let paint = render_config.line_paint(line, first_token);
+            svg_builder
+                .set_cluster_colors(paint.colors)
+                .set_cluster_backgrounds(paint.backgrounds);
+        }
+        if !box_cells.is_empty() {
+            svg_builder.set_box_cells(box_cells.to_vec(), render_config.line_height_pixels(font_config.get_size()) as f32);
+        }
+        svg_builder.set_cluster_ids(line_index.map(|index| ClusterIds { line: index, offset: 0, len: line.len() }));
 
         // Pass glyph_defs as mutable reference
         return Some(svg_builder.build(font_config, style, &glyph_buffer, glyph_cache, glyph_defs));
     }
 
     if font_config.get_debug() {
-        eprintln!("failed to shape with harfbuzz:\n{:?}", line);
+        crate::log_debug!("failed to shape with harfbuzz:\n{:?}", line);
     }
     None
 }
 
-fn get_animation_style() -> Style {
+/// Renders a line of styled spans one after another, each with its own letter spacing.
+/// Kerning and ligatures don't apply across span boundaries.
+/// With `line_index` the glyphs get ids by their offsets in the line's plain text.
+#[allow(clippy::too_many_arguments)]
+pub fn render_spans_line(
+    x: f32,
+    y: f32,
+    spans: &[Span],
+    first_token: usize,
+    line_index: Option<usize>,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    glyph_cache: &mut GlyphCache,
+    glyph_defs: &mut GlyphDefs,
+) -> Option<(Group, Rect)> {
+    let style = render_config.get_font_style();
+    let mut line_group = Group::new();
+    let mut line_bbox: Option<Rect> = None;
+    let mut pen_x = x;
+    // Gradients and matches run along the whole line rather than restarting in each span
+    let paint = if render_config.has_line_paint() {
+        render_config.line_paint(&plain_text(spans), first_token)
+    } else {
+        LinePaint::default()
+    };
+    let mut span_start = 0;
+
+    for (i, span) in spans.iter().enumerate() {
+        let glyph_buffer = text_shape(&span.text, font_config, style)?;
+        let span_end = span_start + span.text.len();
+        let span_paint = paint.slice(span_start, span_end);
+        let mut svg_builder = TextBuilder::new();
+        svg_builder
+            .set_origin(Point { x: pen_x, y })
+            .set_path_config(render_config.get_path_config().clone())
+            .set_letter_space(span.letter_space)
+            .set_continues_run(i > 0)
+            .set_cluster_colors(span_paint.colors)
+            .set_cluster_backgrounds(span_paint.backgrounds)
+            .set_cluster_ids(line_index.map(|line| ClusterIds { line, offset: span_start, len: span.text.len() }));
+        span_start = span_end;
+        let (span_group, span_bbox, end_x) =
+            svg_builder.build_with_advance(font_config, style, &glyph_buffer, glyph_cache, glyph_defs);
+
+        line_group = line_group.add(span_group);
+        line_bbox = Some(match line_bbox {
+            Some(bbox) => Rect {
+                x_min: bbox.x_min.min(span_bbox.x_min),
+                y_min: bbox.y_min.min(span_bbox.y_min),
+                x_max: bbox.x_max.max(span_bbox.x_max),
+                y_max: bbox.y_max.max(span_bbox.y_max),
+            },
+            None => span_bbox,
+        });
+        pen_x = end_x;
+    }
+
+    line_bbox.map(|bbox| (line_group, bbox))
+}
+
+pub fn get_animation_style() -> Style {
     Style::new("
   @keyframes draw {
     to {
@@ -222,129 +1090,435 @@ fn get_animation_style() -> Style {
   }")
 }
 
-pub fn render_text_file_to_svg(file: &PathBuf, font_config: &mut FontConfig, render_config: &RenderConfig, output: PathBuf) {
-    let mut max_width: u32 = 0;
-    let mut current_height: u32 = 0;
-    let line_height = font_config.get_size(); // Use font size as line height
-
-    let mut doc = Document::new();
-    let mut glyph_cache: GlyphCache = HashMap::new();
-    let mut glyph_defs: GlyphDefs = HashMap::new(); // Uses Box<dyn Node>
-    // Group for all text content
-    let mut main_group = Group::new();
-    // Apply global fill/stroke to the main group
-    main_group = main_group
-        .set("fill", font_config.get_fill_color().as_str())
-        .set("stroke", font_config.get_color().as_str());
-        // Note: stroke-width might need to be set here or in TextBuilder if needed
-
+pub fn render_text_file_to_svg(
+    file: &Path,
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    output: PathBuf,
+) -> Result<()> {
+    let (doc, glyph_defs) = build_text_file_svg(file, font_config, render_config)?;
+    save_svg(&output, &doc, &glyph_defs, font_config, output_config).map_err(|e| io_error(&output, e))?;
+    Ok(())
+}
 
-    let file_lines = if let Some(pixel_width) = render_config.max_pixel_width {
-        open_file_by_lines_pixel_width(file, pixel_width, font_config, render_config.get_font_style())
-    } else if let Some(char_width) = render_config.max_width {
-        open_file_by_lines_width(file, char_width)
-    } else {
+/// Document rendering the lines of `file`, wrapped as configured, with the glyph definitions it uses.
+pub fn build_text_file_svg(file: &Path, font_config: &FontConfig, render_config: &RenderConfig) -> Result<(Document, GlyphDefs)> {
+    let paragraph_spacing = render_config.paragraph_spacing_pixels(font_config.get_size()) > 0;
+    let file_lines = if render_config.get_markup() || render_config.max_chars_per_line.is_some() || paragraph_spacing {
+        // Markup tags don't take up space, so wrap the text they style instead
         open_file_by_lines(file)
+            .map(|paragraphs| wrap_paragraphs(paragraphs.iter().map(String::as_str), font_config, render_config))
+    } else {
+        let lines = match (render_config.get_wrap(), render_config.max_pixel_width, render_config.max_width) {
+            (WrapMode::Pixel, Some(pixel_width), _) => {
+                open_file_by_lines_pixel_width(file, pixel_width, font_config, render_config.get_font_style())
+            }
+            (WrapMode::Word, _, Some(char_width)) => open_file_by_lines_width(file, char_width, true, render_config.east_asian_width),
+            (WrapMode::Char, _, Some(char_width)) => open_file_by_lines_width(file, char_width, false, render_config.east_asian_width),
+            _ => open_file_by_lines(file),
+        };
+        // Without paragraph spacing where the paragraphs start doesn't matter
+        lines.map(|lines| (lines, Vec::new()))
     };
 
+    let (lines, paragraph_starts) = file_lines?;
     if font_config.get_debug() {
-        println!("file lines : {:?}", file_lines);
+        crate::log_debug!("{} lines read from {}", lines.len(), file.display());
     }
+    let render_config = &*render_config.with_direction_of(lines.iter().map(String::as_str));
+    let (doc, glyph_defs) = build_lines_svg(lines, &paragraph_starts, font_config, render_config)?;
+    Ok((label_file_document(doc, file, render_config)?, glyph_defs))
+}
 
-    if let Ok(lines) = file_lines {
-        for (line_index, line) in lines.iter().enumerate() {
-            let line_group_transform = format!("translate(0, {})", current_height);
-            if line.is_empty() {
-                // Still advance height for empty lines
-            } else if let Some((line_content_group, line_bbox)) =
-                // Pass glyph_defs as mutable reference
-                render_text_line(0.0, 0.0, line, font_config, render_config, &mut glyph_cache, &mut glyph_defs)
-            {
-                // Wrap line content in a group for positioning and animation
-                let mut positioned_line_group = Group::new()
-                    .set("transform", line_group_transform)
-                    .add(line_content_group);
-                
-                // Add animation class and delay for each line
-                if render_config.get_animate() {
-                    let animation_delay = line_index as f32 * 0.8; // 0.8s delay between lines
-                    positioned_line_group = positioned_line_group
-                        .set("class", "text-line")
-                        .set("style", format!("animation-delay: {}s", animation_delay));
-                }
-                
-                main_group = main_group.add(positioned_line_group);
-                // Cast i16 width to u32 for max comparison
-                max_width = max_width.max(line_bbox.width() as u32);
-            }
-            current_height += line_height; // Move to next line position
+/// `doc` labelled for assistive technology with the text of `file`, or as configured.
+pub fn label_file_document(doc: Document, file: &Path, render_config: &RenderConfig) -> Result<Document> {
+    let accessibility = render_config.get_accessibility();
+    if accessibility.title.is_some() || accessibility.decorative {
+        return Ok(label_text_document(doc, &[], render_config));
+    }
+    // The title only takes the start of the text, so only that much of the file is read again
+    let mut title_bytes = 0;
+    let paragraphs: Vec<String> = std::io::BufReader::new(std::fs::File::open(file).map_err(|e| io_error(file, e))?)
+        .lines()
+        .map_while(Result::ok)
+        .take_while(|line| {
+            let more = title_bytes <= MAX_TITLE_CHARS * 4;
+            title_bytes += line.len();
+            more
+        })
+        .collect();
+    Ok(label_text_document(doc, &paragraphs, render_config))
+}
+
+/// Wraps one input paragraph according to the configured width limit.
+/// In markup mode the wrapped lines are markup too.
+/// Lines past `--max-chars-per-line` are cut short.
+pub fn wrap_paragraph(paragraph: &str, font_config: &FontConfig, render_config: &RenderConfig) -> Vec<String> {
+    let mut lines = None;
+    if render_config.get_markup() {
+        match parse_markup(paragraph) {
+            Ok(spans) => lines = Some(wrap_spans(&spans, font_config, render_config)),
+            Err(e) => crate::log_warn!("{}; rendering the line as plain text: {}", e, paragraph),
         }
+    }
+    let lines = lines.unwrap_or_else(|| wrap_plain_paragraph(paragraph, font_config, render_config));
+    if render_config.max_chars_per_line.is_none() {
+        return lines;
+    }
+    let truncation = render_config.truncation();
+    lines
+        .iter()
+        .map(|line| truncation.truncate(line, render_config.get_markup(), font_config, render_config.get_font_style()))
+        .collect()
+}
 
-        // Add definitions
-        let mut defs = Definitions::new();
-        // Iterate over the HashMap using .iter() and clone the Box<dyn Node>
-        for (_id, node_box) in glyph_defs.iter() {
-            defs = defs.add(node_box.clone());
+/// Wraps each of `paragraphs`, returning the lines and the index of the first
+/// line of each paragraph.
+pub fn wrap_paragraphs<'a>(
+    paragraphs: impl IntoIterator<Item = &'a str>,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> (Vec<String>, Vec<usize>) {
+    let mut lines = Vec::new();
+    let mut paragraph_starts = Vec::new();
+    for paragraph in paragraphs {
+        paragraph_starts.push(lines.len());
+        lines.extend(wrap_paragraph(paragraph, font_config, render_config));
+    }
+    (lines, paragraph_starts)
+}
+
+/// Drops lines past `--max-lines`; with `Overflow::Ellipsis` the last line
+/// kept ends with the ellipsis.
+pub fn limit_lines(lines: &mut Vec<String>, font_config: &FontConfig, render_config: &RenderConfig) {
+    let max_lines = render_config.get_max_lines().unwrap_or(usize::MAX);
+    if lines.len() <= max_lines {
+        return;
+    }
+    lines.truncate(max_lines);
+    if render_config.get_overflow() == Overflow::Ellipsis {
+        if let Some(last) = lines.last_mut() {
+            *last = ellipsize_line(last, font_config, render_config);
         }
-        doc = doc.add(defs); // Add defs first
-        doc = doc.add(main_group); // Add text content
+    }
+}
 
-        if render_config.get_animate() {
-            doc = doc.add(get_animation_style());
+/// `line` ending with the ellipsis, cut to fit the truncation limits.
+pub fn ellipsize_line(line: &str, font_config: &FontConfig, render_config: &RenderConfig) -> String {
+    render_config
+        .truncation()
+        .ellipsize(line, render_config.get_markup(), font_config, render_config.get_font_style())
+}
+
+// Wraps the plain text of styled spans and splits the spans along the wrapped lines.
+// Widths are measured with the base letter spacing.
+fn wrap_spans(spans: &[Span], font_config: &FontConfig, render_config: &RenderConfig) -> Vec<String> {
+    let plain = plain_text(spans);
+    let mut cursor = 0;
+    wrap_plain_paragraph(&plain, font_config, render_config)
+        .into_iter()
+        .map(|line| {
+            // Wrapped lines are pieces of the plain text, in order
+            let start = plain[cursor..].find(line.as_str()).map(|i| cursor + i).unwrap_or(cursor);
+            let end = (start + line.len()).min(plain.len());
+            cursor = end;
+            to_markup(&slice_spans(spans, start, end))
+        })
+        .collect()
+}
+
+fn wrap_plain_paragraph(paragraph: &str, font_config: &FontConfig, render_config: &RenderConfig) -> Vec<String> {
+    match (render_config.get_wrap(), render_config.max_pixel_width, render_config.max_width) {
+        (WrapMode::Pixel, Some(pixel_width), _) => {
+            wrap_text_by_pixel_width(paragraph, pixel_width, font_config, render_config.get_font_style())
         }
+        (WrapMode::Word, _, Some(char_width)) => wrap_text_by_width(paragraph, char_width, true, render_config.east_asian_width),
+        (WrapMode::Char, _, Some(char_width)) => wrap_text_by_width(paragraph, char_width, false, render_config.east_asian_width),
+        _ => vec![paragraph.to_string()],
+    }
+}
 
-        doc = doc
-            .set("height", current_height)
-            .set("width", max_width)
-            .set("viewBox", format!("0 0 {} {}", max_width, current_height));
+// Minimum number of lines before layout is spread across threads.
+// Below this, the per-thread glyph caches cost more than they save.
+#[cfg(feature = "parallel")]
+const PARALLEL_LAYOUT_MIN_LINES: usize = 64;
 
-        svg::save(output, &doc).unwrap();
+/// Laid out line content and its bounding box; None for empty or unshapeable lines
+pub type LineLayout = Option<(Group, Rect)>;
+
+/// Lays out every line, returning the line groups in input order
+/// together with the glyph definitions they reference.
+pub fn layout_lines(lines: &[String], font_config: &FontConfig, render_config: &RenderConfig) -> (Vec<LineLayout>, GlyphDefs) {
+    #[cfg(feature = "parallel")]
+    if lines.len() >= PARALLEL_LAYOUT_MIN_LINES {
+        return layout_lines_parallel(lines, font_config, render_config);
     }
+
+    let mut glyph_cache: GlyphCache = HashMap::new();
+    let mut glyph_defs: GlyphDefs = HashMap::new();
+    let layouts = lines
+        .iter()
+        .zip(first_tokens(lines, render_config))
+        .zip(line_box_cells(lines, render_config))
+        .enumerate()
+        .map(|(i, ((line, first_token), box_cells))| {
+            layout_line(line, i, first_token, &box_cells, font_config, render_config, &mut glyph_cache, &mut glyph_defs)
+        })
+        .collect();
+    (layouts, glyph_defs)
+}
+
+// Each worker uses its own glyph cache; glyph ids map to the same svg ids
+// everywhere, so the definitions can simply be merged afterwards.
+#[cfg(feature = "parallel")]
+fn layout_lines_parallel(lines: &[String], font_config: &FontConfig, render_config: &RenderConfig) -> (Vec<LineLayout>, GlyphDefs) {
+    use rayon::prelude::*;
+
+    let laid_out: Vec<(LineLayout, GlyphDefs)> = lines
+        .par_iter()
+        .zip(first_tokens(lines, render_config))
+        .zip(line_box_cells(lines, render_config))
+        .enumerate()
+        .map(|(i, ((line, first_token), box_cells))| {
+            let mut glyph_cache: GlyphCache = HashMap::new();
+            let mut glyph_defs: GlyphDefs = HashMap::new();
+            let layout = layout_line(line, i, first_token, &box_cells, font_config, render_config, &mut glyph_cache, &mut glyph_defs);
+            (layout, glyph_defs)
+        })
+        .collect();
+
+    let mut all_defs: GlyphDefs = HashMap::new();
+    let layouts = laid_out
+        .into_iter()
+        .map(|(layout, glyph_defs)| {
+            for (id, node) in glyph_defs {
+                all_defs.entry(id).or_insert(node);
+            }
+            layout
+        })
+        .collect();
+    (layouts, all_defs)
 }
 
-// Helper function to render multiple text lines to SVG
-fn render_text_lines_to_svg(lines: Vec<String>, font_config: &mut FontConfig, render_config: &RenderConfig, output: PathBuf) {
+// Box-drawing cells of every line, found across lines as vertical lines join up
+fn line_box_cells(lines: &[String], render_config: &RenderConfig) -> Vec<Vec<(usize, u8)>> {
+    match render_config.get_box_drawing() {
+        true => box_cells(lines),
+        false => vec![Vec::new(); lines.len()],
+    }
+}
+
+// Index in the text of the first word of every line, for word colors by index
+fn first_tokens(lines: &[String], render_config: &RenderConfig) -> Vec<usize> {
+    let mut count = 0;
+    lines
+        .iter()
+        .map(|line| {
+            let first = count;
+            if render_config.get_word_colors().is_some_and(WordColors::has_tokens) {
+                count += match render_config.get_markup().then(|| parse_markup(line).ok()).flatten() {
+                    Some(spans) => tokens(&plain_text(&spans)).count(),
+                    None => tokens(line).count(),
+                };
+            }
+            first
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn layout_line(
+    line: &str,
+    line_index: usize,
+    first_token: usize,
+    box_cells: &[(usize, u8)],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    glyph_cache: &mut GlyphCache,
+    glyph_defs: &mut GlyphDefs,
+) -> LineLayout {
+    if line.is_empty() {
+        // Empty lines only advance the height
+        return None;
+    }
+    let spans = render_config.get_markup().then(|| parse_markup(line).ok()).flatten();
+    let glyph_ids = render_config.get_glyph_ids().then_some(line_index);
+    // As text the line is still laid out for its size, but its glyphs aren't kept
+    let text_mode = render_config.get_text_mode() == TextMode::Text;
+    let (mut unused_cache, mut unused_defs) = (GlyphCache::new(), GlyphDefs::new());
+    let (glyph_cache, glyph_defs) = match text_mode {
+        true => (&mut unused_cache, &mut unused_defs),
+        false => (glyph_cache, glyph_defs),
+    };
+    let layout = match &spans {
+        Some(spans) => render_spans_line(0.0, 0.0, spans, first_token, glyph_ids, font_config, render_config, glyph_cache, glyph_defs),
+        None => render_text_line(0.0, 0.0, line, first_token, box_cells, glyph_ids, font_config, render_config, glyph_cache, glyph_defs),
+    };
+    let layout = match text_mode {
+        true => layout.map(|(_, bbox)| (text_line(line, spans.as_deref(), font_config, render_config), bbox)),
+        false => layout,
+    };
+    let line_lang = render_config
+        .get_detect_lang()
+        .then(|| detect_lang(&spans.as_deref().map_or_else(|| line.to_string(), plain_text)))
+        .flatten()
+        .filter(|lang| Some(lang) != render_config.get_lang());
+    let layout = match line_lang {
+        Some(lang) => layout.map(|(group, bbox)| (set_lang(group, &lang), bbox)),
+        None => layout,
+    };
+    match render_config.get_underline() {
+        Some(underline) => layout.map(|(group, bbox)| underline_line(group, bbox, underline, font_config, render_config.get_css_vars())),
+        None => layout,
+    }
+}
+
+// Adds the underline below a laid out line, from its first to its last glyph,
+// growing the line's box when the underline hangs below it
+fn underline_line(line_group: Group, bbox: Rect, underline: &Underline, font_config: &FontConfig, css_vars: bool) -> (Group, Rect) {
+    let Some(face) = font_config.get_font_by_style(&FontStyle::Regular) else {
+        return (line_group, bbox);
+    };
+    let metrics = font_config.face_metrics(face);
+    let font_size = font_config.get_size() as f32;
+    let scale = font_config.pixel_scale(metrics);
+    let (thickness, offset) = underline.resolve_metrics(&metrics, scale, font_size);
+    let baseline = metrics.ascent * scale;
+    // Without a color of its own the underline follows the text, variable included
+    let (name, color) = match underline.color {
+        Some(color) => ("underline", color),
+        None if *font_config.get_fill_color() == Color::None => ("fg", *font_config.get_color()),
+        None => ("fill", *font_config.get_fill_color()),
+    };
+    let path = underline
+        .path(bbox.x_min as f32, bbox.x_max as f32, baseline + offset, thickness, font_config.get_path_precision())
+        .set("stroke", "none");
+    let path = set_paints(path, &[("fill", name, color)], css_vars);
+    let bottom = (baseline + offset + underline.reach(thickness)).ceil() as i16;
+    (Group::new().add(line_group).add(path), Rect { y_max: bbox.y_max.max(bottom), ..bbox })
+}
+
+// Document rendering multiple wrapped text lines, paragraphs starting at `paragraph_starts`
+fn build_lines_svg(
+    mut lines: Vec<String>,
+    paragraph_starts: &[usize],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(Document, GlyphDefs)> {
+    limit_lines(&mut lines, font_config, render_config);
+    let (line_layouts, glyph_defs) = layout_lines(&lines, font_config, render_config);
+    let doc = build_line_layouts_document(line_layouts, &lines, paragraph_starts, &glyph_defs, font_config, render_config)?;
+    Ok((doc, glyph_defs))
+}
+
+// Labels a document with its text as written rather than as wrapped, which may split words
+fn label_text_document(doc: Document, paragraphs: &[String], render_config: &RenderConfig) -> Document {
+    label_document(doc, paragraphs, render_config.get_markup(), render_config.get_accessibility())
+}
+
+/// Wraps line content in a group that moves it to its line and,
+/// when animating, staggers its draw animation.
+pub fn position_line<T: Into<Box<dyn svg::Node>>>(line_content_group: T, line_index: usize, x: f32, y: u32, render_config: &RenderConfig) -> Group {
+    let mut positioned_line_group = Group::new()
+        .set("transform", format!("translate({}, {})", x, y))
+        .add(line_content_group);
+
+    // Add animation class and delay for each line
+    if render_config.get_animate() {
+        let animation_delay = line_index as f32 * 0.8; // 0.8s delay between lines
+        positioned_line_group = positioned_line_group
+            .set("class", "text-line")
+            .set("style", format!("animation-delay: {}s", animation_delay));
+    }
+    positioned_line_group
+}
+
+/// Stacks already laid out `lines` into a document.
+pub fn build_line_layouts_document(
+    line_layouts: Vec<LineLayout>,
+    lines: &[String],
+    paragraph_starts: &[usize],
+    glyph_defs: &GlyphDefs,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<Document> {
+    let (main_group, width, height) = stack_line_layouts(line_layouts, paragraph_starts, font_config, render_config);
+    build_text_document(main_group, lines, glyph_defs, (0.0, 0.0, width as f32, height as f32), font_config, render_config)
+}
+
+/// Moves laid out lines under each other in one group, up to the configured
+/// maximum number of lines, with the paragraph spacing before the lines in the
+/// sorted `paragraph_starts`. Returns the group with the width and height it covers.
+pub fn stack_line_layouts(
+    line_layouts: Vec<LineLayout>,
+    paragraph_starts: &[usize],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> (Group, u32, u32) {
     let mut max_width: u32 = 0;
     let mut current_height: u32 = 0;
-    let line_height = font_config.get_size(); // Use font size as line height
+    let line_height = render_config.line_height_pixels(font_config.get_size());
+    let paragraph_spacing = render_config.paragraph_spacing_pixels(font_config.get_size());
 
-    let mut doc = Document::new();
-    let mut glyph_cache: GlyphCache = HashMap::new();
-    let mut glyph_defs: GlyphDefs = HashMap::new(); // Uses Box<dyn Node>
     // Group for all text content
     let mut main_group = Group::new();
-    // Apply global fill/stroke to the main group
-    main_group = main_group
-        .set("fill", font_config.get_fill_color().as_str())
-        .set("stroke", font_config.get_color().as_str());
-
-    for (line_index, line) in lines.iter().enumerate() {
-        let line_group_transform = format!("translate(0, {})", current_height);
-        if line.is_empty() {
-            // Still advance height for empty lines
-        } else if let Some((line_content_group, line_bbox)) =
-            // Pass glyph_defs as mutable reference
-            render_text_line(0.0, 0.0, line, font_config, render_config, &mut glyph_cache, &mut glyph_defs)
-        {
-            // Wrap line content in a group for positioning and animation
-            let mut positioned_line_group = Group::new()
-                .set("transform", line_group_transform)
-                .add(line_content_group);
-            
-            // Add animation class and delay for each line
-            if render_config.get_animate() {
-                let animation_delay = line_index as f32 * 0.8; // 0.8s delay between lines
-                positioned_line_group = positioned_line_group
-                    .set("class", "text-line")
-                    .set("style", format!("animation-delay: {}s", animation_delay));
-            }
-            
-            main_group = main_group.add(positioned_line_group);
+
+    let max_lines = render_config.get_max_lines().unwrap_or(usize::MAX);
+    let truncated = line_layouts.len() > max_lines;
+    // Lines are placed once the widest is known, as alignment depends on its width
+    let mut placed: Vec<(Group, usize, u32, u32)> = Vec::new();
+    for (line_index, line_layout) in line_layouts.into_iter().take(max_lines).enumerate() {
+        if line_index > 0 && paragraph_starts.binary_search(&line_index).is_ok() {
+            current_height += paragraph_spacing;
+        }
+        if let Some((line_content_group, line_bbox)) = line_layout {
             // Cast i16 width to u32 for max comparison
             max_width = max_width.max(line_bbox.width() as u32);
+            placed.push((line_content_group, line_index, current_height, line_bbox.width() as u32));
         }
         current_height += line_height; // Move to next line position
     }
+    let (align, direction) = (render_config.get_align(), render_config.get_direction());
+    for (line_content_group, line_index, y, width) in placed {
+        let x = align.offset(direction, width as f32, max_width as f32);
+        main_group = main_group.add(position_line(line_content_group, line_index, x, y, render_config));
+    }
+    if truncated && render_config.get_overflow() == Overflow::Fade {
+        main_group = fade_out(main_group, max_width as f32, current_height as f32, line_height as f32);
+    }
+    (main_group, max_width, current_height)
+}
+
+/// Top of line `index` as `stack_line_layouts` places it.
+pub fn line_top(index: usize, paragraph_starts: &[usize], font_config: &FontConfig, render_config: &RenderConfig) -> u32 {
+    let gaps = paragraph_starts.iter().filter(|&&start| start > 0 && start <= index).count() as u32;
+    index as u32 * render_config.line_height_pixels(font_config.get_size())
+        + gaps * render_config.paragraph_spacing_pixels(font_config.get_size())
+}
+
+/// Document holding the uncolored text `content`, set from `lines`, covering the box
+/// (x, y, width, height), with the glyph definitions it uses and the
+/// configured colors and effects. Fails when the text overflows the canvas
+/// and the overflow policy is to fail.
+pub fn build_text_document(
+    content: Group,
+    lines: &[String],
+    glyph_defs: &GlyphDefs,
+    content_box: (f32, f32, f32, f32),
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<Document> {
+    let mut doc = Document::new();
+
+    // Warped glyphs are outlined in place rather than referencing definitions
+    let (content, glyph_defs) = match render_config.get_warp() {
+        Some(warp) => {
+            let warped = warp_text(&content, glyph_defs, warp, content_box, font_config.get_path_precision());
+            (warped, &GlyphDefs::new())
+        }
+        None => (content, glyph_defs),
+    };
 
     // Add definitions
     let mut defs = Definitions::new();
@@ -352,124 +1526,197 @@ fn render_text_lines_to_svg(lines: Vec<String>, font_config: &mut FontConfig, re
     for (_id, node_box) in glyph_defs.iter() {
         defs = defs.add(node_box.clone());
     }
+    if render_config.get_text_mode() == TextMode::Text {
+        if let Some(style) = font_face_style(lines, font_config, render_config) {
+            defs = defs.add(style);
+        }
+    }
+    // Apply global fill/stroke to the main group
+    let main_group = style_text_group(content, font_config, render_config);
+    let view_box = effects_view_box(content_box, render_config);
+    let (main_group, defs) = apply_effects(main_group, defs, view_box, render_config);
     doc = doc.add(defs); // Add defs first
+    let border = render_config.get_border();
+    let padded_box = padded_view_box(view_box, font_config, render_config);
+    if let Some(background) = render_config.get_background() {
+        // The background reaches the outer edge of the border, or fills the whole canvas
+        let (area, radius) = match render_config.get_canvas() {
+            Some(_) => (canvas_view_box(margin_view_box(padded_box, font_config, render_config), font_config, render_config)?, 0.0),
+            None => border.map_or((padded_box, 0.0), |border| (border.outer_box(padded_box), border.radius)),
+        };
+        let fill = background
+            .fill(area, radius, render_config.get_css_vars())
+            .map_err(|e| anyhow!("Failed to read the background image: {}", e))?;
+        doc = doc.add(fill);
+    }
+    if let Some(stripes) = render_config.get_stripes() {
+        let line_height = render_config.line_height_pixels(font_config.get_size()) as f32;
+        let lines = ((content_box.3 / line_height).round() as usize).max(1);
+        let inner_box = border.map_or(padded_box, |border| border.inner_box(padded_box));
+        doc = doc.add(stripes.bands(content_box.1, lines, line_height, inner_box, render_config.get_css_vars()));
+    }
+    if let Some(border) = border {
+        doc = doc.add(border.frame(border.outer_box(padded_box), render_config.get_css_vars()));
+    }
+    let view_box = margin_view_box(padded_box, font_config, render_config);
     doc = doc.add(main_group); // Add text content
 
     if render_config.get_animate() {
         doc = doc.add(get_animation_style());
     }
 
-    doc = doc
-        .set("height", current_height)
-        .set("width", max_width)
-        .set("viewBox", format!("0 0 {} {}", max_width, current_height));
+    set_view_box(doc, view_box, font_config, render_config)
+}
 
-    svg::save(output, &doc).unwrap();
+pub fn render_text_to_svg_file(
+    text: &str,
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    output: PathBuf,
+) -> Result<()> {
+    let (doc, glyph_defs) = build_text_svg(text, font_config, render_config)?;
+    save_svg(&output, &doc, &glyph_defs, font_config, output_config).map_err(|e| io_error(&output, e))?;
+    Ok(())
 }
 
-pub fn render_text_to_svg_file(text: &str, font_config: &mut FontConfig,render_config: &RenderConfig, output: PathBuf) {
-    let mut doc = Document::new();
-    let mut glyph_cache: GlyphCache = HashMap::new();
-    let mut glyph_defs: GlyphDefs = HashMap::new(); // Uses Box<dyn Node>
+/// Document rendering `text`, wrapped as configured, with the glyph definitions it uses.
+pub fn build_text_svg(text: &str, font_config: &FontConfig, render_config: &RenderConfig) -> Result<(Document, GlyphDefs)> {
+    // Wrap the text as configured, each of its lines being a paragraph as in a file
+    let paragraphs: Vec<String> = text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()).collect();
+    let (text_lines, paragraph_starts) = wrap_paragraphs(paragraphs.iter().map(String::as_str), font_config, render_config);
+    build_wrapped_text_svg(text_lines, &paragraph_starts, &paragraphs, font_config, render_config)
+}
 
-    // Handle text wrapping if pixel width is specified
-    let text_lines = if let Some(pixel_width) = render_config.max_pixel_width {
-        wrap_text_by_pixel_width(text, pixel_width, font_config, render_config.get_font_style())
-    } else {
-        vec![text.to_string()]
-    };
+/// Document rendering `lines` wrapped from `paragraphs`, paragraphs starting at
+/// `paragraph_starts`, the way a file is rendered, with the glyph definitions it uses.
+pub fn build_wrapped_lines_svg(
+    lines: Vec<String>,
+    paragraph_starts: &[usize],
+    paragraphs: &[String],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(Document, GlyphDefs)> {
+    let render_config = &*render_config.with_direction_of(paragraphs.iter().map(String::as_str));
+    let (doc, glyph_defs) = build_lines_svg(lines, paragraph_starts, font_config, render_config)?;
+    Ok((label_text_document(doc, paragraphs, render_config), glyph_defs))
+}
 
+/// Like `build_wrapped_lines_svg`, the way text is rendered: a single line is fitted to its glyphs.
+pub fn build_wrapped_text_svg(
+    text_lines: Vec<String>,
+    paragraph_starts: &[usize],
+    paragraphs: &[String],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(Document, GlyphDefs)> {
     // If we have multiple lines, render them like a file
     if text_lines.len() > 1 {
-        render_text_lines_to_svg(text_lines, font_config, render_config, output);
-        return;
+        return build_wrapped_lines_svg(text_lines, paragraph_starts, paragraphs, font_config, render_config);
     }
 
+    let mut glyph_cache: GlyphCache = HashMap::new();
+    let mut glyph_defs: GlyphDefs = HashMap::new(); // Uses Box<dyn Node>
+    let render_config = &*render_config.with_direction_of(paragraphs.iter().map(String::as_str));
+
     // Single line rendering (original logic)
     let text_to_render = &text_lines[0];
     
     // Shape the text
     // Pass glyph_defs as mutable reference
     if let Some((text_content_group, text_bbox)) =
-        render_text_line(0.0, 0.0, text_to_render, font_config, render_config, &mut glyph_cache, &mut glyph_defs)
+        layout_line(text_to_render, 0, 0, &line_box_cells(&text_lines, render_config)[0], font_config, render_config, &mut glyph_cache, &mut glyph_defs)
     {
-        // Cast i16 height/width to u32
-        let height = text_bbox.height() as u32;
-        let width = text_bbox.width() as u32;
-        // Use i16 for viewbox coordinates
-        let view_box = format!("{} {} {} {}", text_bbox.x_min, text_bbox.y_min, text_bbox.width(), text_bbox.height());
-
-        // Apply global fill/stroke and animation class
-        let mut styled_group = text_content_group
-            .set("fill", font_config.get_fill_color().as_str())
-            .set("stroke", font_config.get_color().as_str());
+        let content_box = (
+            text_bbox.x_min as f32,
+            text_bbox.y_min as f32,
+            text_bbox.width() as f32,
+            text_bbox.height() as f32,
+        );
+        // Animation class goes on the single line itself
+        let mut text_content_group = text_content_group;
         if render_config.get_animate() {
-            styled_group = styled_group.set("class", "text-line");
+            text_content_group = text_content_group.set("class", "text-line");
         }
-
-        // Add definitions
-        let mut defs = Definitions::new();
-        // Iterate over the HashMap using .iter() and clone the Box<dyn Node>
-        for (_id, node_box) in glyph_defs.iter() {
-            defs = defs.add(node_box.clone());
-        }
-        doc = doc.add(defs); // Add defs first
-        doc = doc.add(styled_group); // Add text content
-
-        if render_config.get_animate() {
-            doc = doc.add(get_animation_style());
-        }
-
-        doc = doc
-            .set("height", height)
-            .set("width", width)
-            .set("viewBox", view_box);
-
-        svg::save(output, &doc).unwrap();
+        let doc = build_text_document(text_content_group, std::slice::from_ref(text_to_render), &glyph_defs, content_box, font_config, render_config)?;
+        Ok((label_text_document(doc, paragraphs, render_config), glyph_defs))
     } else {
-         eprintln!("Failed to render text to SVG.");
+        Err(anyhow!("Failed to render text to SVG."))
     }
 }
 
-/// Shape text with font default size (units_per_em)
-/// Therefore we need to scale these glyphs later according to the size
-fn text_shape(text: &str, font_config: &mut FontConfig, font_style: &FontStyle) -> Option<GlyphBuffer> {
-    // Attempt to get the specific style, fall back to regular if not found
-    let ft_face = font_config.get_font_by_style(font_style)
-        .or_else(|| {
-            if font_config.get_debug() && *font_style != FontStyle::Regular {
-                 eprintln!("Warning: Font style {:?} not found, falling back to Regular.", font_style);
-            }
-            font_config.get_font_by_style(&FontStyle::Regular)
-        })
-        .or_else(|| {
-             eprintln!("Error: Regular font style not found either for font '{}'.", font_config.get_font_name());
-             None
-        });
-
-    if let Some(ft_face) = ft_face {
-        if let Some(font_data) = ft_face.copy_font_data() {
-            if let Some(hb_face) = Face::from_slice(&font_data, 0) {
-                let mut buffer = rustybuzz::UnicodeBuffer::new();
-                buffer.push_str(text);
-
-                let glyph_buffer = rustybuzz::shape(&hb_face, font_config.get_features(), buffer);
-
-                if font_config.get_debug() {
-                    let format_flags = rustybuzz::SerializeFlags::default();
-                    println!("rustybuzz shape output:\n {:?}", glyph_buffer.serialize(&hb_face, format_flags));
-                }
+/// Width and height of a document in pixels.
+pub fn document_size(doc: &Document) -> (f32, f32) {
+    let attribute = |name: &str| {
+        doc.get_attributes().get(name).and_then(|value| value.to_string().parse::<f32>().ok()).unwrap_or(0.0)
+    };
+    (attribute("width"), attribute("height"))
+}
 
-                return Some(glyph_buffer);
-            } else {
-                eprintln!("Failed to create rustybuzz::Face from font data for font '{}', style {:?}.", font_config.get_font_name(), font_style);
+/// Width and height of a written svg file, from its root element, of an EPS
+/// file from its bounding box, of an image in pixels, or of the svg in an HTML page.
+pub fn svg_file_size(path: &Path) -> Result<(f32, f32)> {
+    if let Some((width, height)) = crate::raster::image_size(path) {
+        return Ok((width as f32, height as f32));
+    }
+    let content = crate::svg::read_svg_file(path).map_err(|e| io_error(path, e))?;
+    if content.starts_with("%!PS") {
+        return crate::eps::eps_size(&content).ok_or_else(|| anyhow!("{}: no bounding box", path.display()));
+    }
+    for event in svg::read(&content).map_err(|e| io_error(path, e))? {
+        // Tags of an HTML page come before the svg inlined into it
+        if let svg::parser::Event::Tag(name, _, attributes) = event {
+            if !matches!(name, "svg" | "symbol" | "g") {
+                continue;
             }
-        } else {
-            eprintln!("Failed to copy font data for font '{}', style {:?}.", font_config.get_font_name(), font_style);
+            let attribute = |name: &str| attributes.get(name).and_then(|value| value.to_string().parse::<f32>().ok()).unwrap_or(0.0);
+            // A symbol fragment keeps only its view box; a group has no size at all
+            let view_box: Vec<f32> = attributes
+                .get("viewBox")
+                .map(|view_box| view_box.split_whitespace().filter_map(|value| value.parse().ok()).collect())
+                .unwrap_or_default();
+            return match (name, &view_box[..]) {
+                ("svg", _) => Ok((attribute("width"), attribute("height"))),
+                ("symbol", [_, _, width, height]) => Ok((*width, *height)),
+                _ => Err(anyhow!("{}: <{}> has no size", path.display(), name)),
+            };
         }
     }
-    // Error messages handled within the if/else blocks
+    Err(anyhow!("{}: no svg element", path.display()))
+}
 
-    None
+/// Writes a document with its output buffer sized up front from the glyph count.
+/// Reports the written size when verbose. Returns the number of bytes written.
+pub fn save_svg(
+    output: &Path,
+    doc: &Document,
+    glyph_defs: &GlyphDefs,
+    font_config: &FontConfig,
+    output_config: &OutputConfig,
+) -> std::io::Result<usize> {
+    let glyph_uses = count_glyph_uses(doc);
+    let estimated = estimate_svg_size(glyph_uses, glyph_defs.len(), font_config.get_path_precision());
+    let format = output_config.get_output_format(output);
+    let raster_options = output_config.get_raster_options();
+    let doc = match output_config.get_source_options() {
+        Some(options) => Cow::Owned(doc.clone().add(options_element(options))),
+        None => Cow::Borrowed(doc),
+    };
+    let written = match output_config.get_fragment() {
+        Some(fragment) => save_document(output, &to_fragment(&doc, fragment), estimated, format, raster_options, output_config.get_html_template())?,
+        None => save_document(output, doc.as_ref(), estimated, format, raster_options, output_config.get_html_template())?,
+    };
+    if font_config.get_verbose() {
+        crate::log_verbose!(
+            "Wrote {}: {} bytes, {} glyphs, {} definitions (estimated {} bytes)",
+            output.display(), written, glyph_uses, glyph_defs.len(), estimated
+        );
+    }
+    Ok(written)
 }
 
+/// Shape text with font default size (units_per_em)
+/// Therefore we need to scale these glyphs later according to the size
+fn text_shape(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<Arc<ShapedText>> {
+    font_config.shape(text, font_style)
+}
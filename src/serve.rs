@@ -0,0 +1,423 @@
+// a small HTTP service rendering text on request, so text2svg can run as a
+// self-hosted image endpoint: GET /render?text=...&font=...&size=...
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use lru::LruCache;
+
+use crate::font::FontConfig;
+use crate::preview::render_pixmap;
+use crate::render::{build_text_svg, document_size, RenderConfig};
+use crate::xml::to_xml;
+
+// Largest request line and headers read from a client
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+// Fonts kept loaded between requests, by family and size
+const FONT_CACHE_SIZE: usize = 16;
+// Responses don't change for the same request to the same server
+const CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Bounds on what a request may ask for.
+#[derive(Debug, Clone)]
+pub struct ServeLimits {
+    /// Longest text rendered, in characters
+    pub max_text_chars: usize,
+    /// Largest font size in pixels
+    pub max_size: u32,
+    /// Largest PNG scale
+    pub max_scale: f32,
+    /// Largest PNG, in pixels of width times height
+    pub max_pixels: u64,
+    /// Requests handled at once; more are answered 503
+    pub max_connections: usize,
+    /// How long a client may take to send its request
+    pub timeout: Duration,
+}
+
+impl Default for ServeLimits {
+    fn default() -> Self {
+        Self {
+            max_text_chars: 1000,
+            max_size: 512,
+            max_scale: 4.0,
+            max_pixels: 16_000_000,
+            max_connections: 32,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Image format of a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Svg => "image/svg+xml",
+            ImageFormat::Png => "image/png",
+        }
+    }
+}
+
+/// What one /render request asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderRequest {
+    pub text: String,
+    /// Font family, the server's default when None
+    pub font: Option<String>,
+    /// Font size in pixels, the server's default when None
+    pub size: Option<u32>,
+    pub format: ImageFormat,
+    /// Pixels per SVG unit of PNG output
+    pub scale: f32,
+}
+
+impl RenderRequest {
+    /// The request given by the `query` string of a URL, checked against `limits`.
+    pub fn from_query(query: &str, limits: &ServeLimits) -> Result<Self, String> {
+        let params = parse_query(query)?;
+        let text = params.get("text").filter(|text| !text.is_empty()).ok_or("Missing text parameter")?.clone();
+        let chars = text.chars().count();
+        if chars > limits.max_text_chars {
+            return Err(format!("Text is {} characters long; the limit is {}", chars, limits.max_text_chars));
+        }
+        let size = match params.get("size") {
+            Some(size) => match size.parse::<u32>() {
+                Ok(size) if (1..=limits.max_size).contains(&size) => Some(size),
+                _ => return Err(format!("Invalid size '{}': expected 1 to {}", size, limits.max_size)),
+            },
+            None => None,
+        };
+        let format = match params.get("format").map(String::as_str) {
+            None | Some("svg") => ImageFormat::Svg,
+            Some("png") => ImageFormat::Png,
+            Some(format) => return Err(format!("Invalid format '{}': expected svg or png", format)),
+        };
+        let scale = match params.get("scale") {
+            Some(scale) => match scale.parse::<f32>() {
+                Ok(scale) if scale > 0.0 && scale <= limits.max_scale => scale,
+                _ => return Err(format!("Invalid scale '{}': expected more than 0 and at most {}", scale, limits.max_scale)),
+            },
+            None => 1.0,
+        };
+        let font = params.get("font").filter(|font| !font.is_empty()).cloned();
+        Ok(Self { text, font, size, format, scale })
+    }
+
+    // Stays the same for the same request, so clients can revalidate with If-None-Match
+    fn etag(&self, seed: u64) -> String {
+        let mut hasher = DefaultHasher::new();
+        (seed, &self.text, &self.font, self.size, self.format, self.scale.to_bits()).hash(&mut hasher);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+}
+
+/// Parameters of a URL query string, with `+` and percent escapes decoded.
+pub fn parse_query(query: &str) -> Result<HashMap<String, String>, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((decode_component(key)?, decode_component(value)?))
+        })
+        .collect()
+}
+
+fn decode_component(s: &str) -> Result<String, String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let byte = s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                out.push(byte.ok_or_else(|| format!("Invalid percent escape in '{}'", s))?);
+                i += 2;
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(out).map_err(|_| format!("'{}' isn't UTF-8", s))
+}
+
+/// An HTTP response, written with `Connection: close`.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Self { status, headers: vec![("Content-Type", content_type.to_string())], body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        let mut body = message.into();
+        body.push('\n');
+        Self::new(status, "text/plain; charset=utf-8", body.into_bytes())
+    }
+
+    fn write_to(&self, stream: &mut impl Write, head_only: bool) -> std::io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", self.body.len()));
+        stream.write_all(head.as_bytes())?;
+        if !head_only {
+            stream.write_all(&self.body)?;
+        }
+        stream.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Renders requests with fonts loaded by `load_font` and the layout options of `render_config`.
+pub struct Server<F> {
+    default_font: String,
+    default_size: u32,
+    render_config: RenderConfig,
+    limits: ServeLimits,
+    load_font: F,
+    fonts: Mutex<LruCache<(String, u32), Arc<FontConfig>>>,
+    etag_seed: u64,
+    active: AtomicUsize,
+}
+
+impl<F> Server<F>
+where
+    F: Fn(&str, u32) -> Result<FontConfig> + Sync,
+{
+    /// A server rendering in `default_font` at `default_size` unless a request names others.
+    /// `etag_seed` should change whenever the options shared by all requests do.
+    pub fn new(default_font: String, default_size: u32, render_config: RenderConfig, limits: ServeLimits, etag_seed: u64, load_font: F) -> Self {
+        let capacity = NonZeroUsize::new(FONT_CACHE_SIZE).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            default_font,
+            default_size,
+            render_config,
+            limits,
+            load_font,
+            fonts: Mutex::new(LruCache::new(capacity)),
+            etag_seed,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Answers connections on `listener` until it fails, each on a thread of its own.
+    pub fn run(&self, listener: TcpListener) -> Result<()> {
+        std::thread::scope(|scope| {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        crate::log_warn!("Failed to accept a connection: {}", e);
+                        continue;
+                    }
+                };
+                if self.active.fetch_add(1, Ordering::SeqCst) >= self.limits.max_connections {
+                    self.active.fetch_sub(1, Ordering::SeqCst);
+                    let _ = Response::error(503, "Too many requests in progress; try again shortly").write_to(&mut stream, false);
+                    continue;
+                }
+                scope.spawn(move || {
+                    self.handle_connection(stream);
+                    self.active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(self.limits.timeout));
+        let _ = stream.set_write_timeout(Some(self.limits.timeout));
+        let (response, head_only) = match read_head(&mut stream) {
+            Ok(head) => {
+                let head_only = head.starts_with("HEAD ");
+                (self.respond(&head), head_only)
+            }
+            Err(response) => (response, false),
+        };
+        crate::log_debug!("{} {}", response.status, reason(response.status));
+        let _ = response.write_to(&mut stream, head_only);
+    }
+
+    /// The response to a request with the request line and headers `head`.
+    pub fn respond(&self, head: &str) -> Response {
+        let mut lines = head.lines();
+        let request_line = lines.next().unwrap_or_default();
+        let (method, target) = match request_line.split(' ').collect::<Vec<_>>()[..] {
+            [method, target, version] if version.starts_with("HTTP/") => (method, target),
+            _ => return Response::error(400, "Malformed request line"),
+        };
+        if method != "GET" && method != "HEAD" {
+            let mut response = Response::error(405, "Only GET and HEAD are supported");
+            response.headers.push(("Allow", "GET, HEAD".to_string()));
+            return response;
+        }
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        match path {
+            "/render" => {}
+            "/health" => return Response::new(200, "text/plain; charset=utf-8", b"ok\n".to_vec()),
+            _ => return Response::error(404, "Not found; use /render?text=..."),
+        }
+        let request = match RenderRequest::from_query(query, &self.limits) {
+            Ok(request) => request,
+            Err(message) => return Response::error(400, message),
+        };
+        let etag = request.etag(self.etag_seed);
+        let cached = lines
+            .filter_map(|line| line.split_once(':'))
+            .any(|(name, value)| name.trim().eq_ignore_ascii_case("if-none-match") && value.split(',').any(|tag| tag.trim() == etag));
+        let mut response = match cached {
+            true => Response { status: 304, headers: Vec::new(), body: Vec::new() },
+            false => {
+                let font_config = match self.font(request.font.as_deref().unwrap_or(&self.default_font), request.size.unwrap_or(self.default_size)) {
+                    Ok(font_config) => font_config,
+                    Err(e) => return Response::error(400, format!("Failed to load the font: {}", e)),
+                };
+                match self.render(&request, &font_config) {
+                    Ok(body) => Response::new(200, request.format.content_type(), body),
+                    Err(response) => return response,
+                }
+            }
+        };
+        response.headers.push(("Cache-Control", CACHE_CONTROL.to_string()));
+        response.headers.push(("ETag", etag));
+        response
+    }
+
+    // The response body, or the error response when the request can't be rendered
+    fn render(&self, request: &RenderRequest, font_config: &FontConfig) -> Result<Vec<u8>, Response> {
+        let failed = |e: &dyn std::fmt::Display| Response::error(500, format!("Failed to render: {}", e));
+        let (doc, _) = build_text_svg(&request.text, font_config, &self.render_config).map_err(|e| failed(&e))?;
+        match request.format {
+            ImageFormat::Svg => Ok(to_xml(&doc).into_bytes()),
+            ImageFormat::Png => {
+                // Checked before rasterizing, as the pixmap is allocated up front
+                let (width, height) = document_size(&doc);
+                let pixels = (width * request.scale).ceil() as u64 * (height * request.scale).ceil() as u64;
+                if pixels > self.limits.max_pixels {
+                    return Err(Response::error(
+                        413,
+                        format!("PNG of {} pixels exceeds the limit of {}; use less text, a smaller size or scale", pixels, self.limits.max_pixels),
+                    ));
+                }
+                let pixmap = render_pixmap(&doc, request.scale, None).map_err(|e| failed(&e))?;
+                pixmap.encode_png().map_err(|e| failed(&e))
+            }
+        }
+    }
+
+    // Loading a font outside the lock, so a slow load doesn't hold up other requests
+    fn font(&self, name: &str, size: u32) -> Result<Arc<FontConfig>> {
+        let key = (name.to_string(), size);
+        if let Some(font) = self.fonts.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            return Ok(font.clone());
+        }
+        let font = Arc::new((self.load_font)(name, size)?);
+        self.fonts.lock().unwrap_or_else(|e| e.into_inner()).put(key, font.clone());
+        Ok(font)
+    }
+}
+
+// The request line and headers, up to the blank line ending them
+fn read_head(stream: &mut impl Read) -> Result<String, Response> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(_) => return Err(Response::error(408, "Timed out reading the request")),
+        };
+        head.extend_from_slice(&buf[..read]);
+        if head.len() > MAX_HEAD_BYTES {
+            return Err(Response::error(431, format!("Request line and headers exceed {} bytes", MAX_HEAD_BYTES)));
+        }
+    }
+    String::from_utf8(head).map_err(|_| Response::error(400, "Request isn't UTF-8"))
+}
+
+#[cfg(test)]
+mod test_serve {
+    use super::*;
+    use crate::test_support;
+    use crate::color::Color;
+    use crate::font::FontStyle;
+
+    #[test]
+    fn test_render_request() {
+        let limits = ServeLimits::default();
+        let request = RenderRequest::from_query("text=Hello+w%C3%B6rld%21&size=32&format=png", &limits).unwrap();
+        assert_eq!(request.text, "Hello wörld!");
+        assert_eq!((request.size, request.format, request.font), (Some(32), ImageFormat::Png, None));
+        assert!(RenderRequest::from_query("size=32", &limits).is_err());
+        assert!(RenderRequest::from_query("text=a&size=4096", &limits).is_err());
+        assert!(RenderRequest::from_query("text=a&format=gif", &limits).is_err());
+        assert!(RenderRequest::from_query("text=%zz", &limits).is_err());
+        let long = format!("text={}", "a".repeat(limits.max_text_chars + 1));
+        assert!(RenderRequest::from_query(&long, &limits).unwrap_err().contains("limit"));
+    }
+
+    #[test]
+    fn test_respond() {
+        let load_font = |_: &str, size: u32| Ok(FontConfig::from_file(test_support::FONT_PATH, size, Color::BLACK, Color::BLACK, false)?);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let server = Server::new("DejaVu Sans".to_string(), 24, render_config, ServeLimits::default(), 0, load_font);
+
+        let response = server.respond("GET /render?text=Hi HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(response.status, 200);
+        assert!(response.headers.contains(&("Content-Type", "image/svg+xml".to_string())));
+        assert!(String::from_utf8_lossy(&response.body).starts_with("<svg"));
+        let (_, etag) = response.headers.iter().find(|(name, _)| *name == "ETag").unwrap();
+
+        let revalidated = server.respond(&format!("GET /render?text=Hi HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n", etag));
+        assert_eq!((revalidated.status, revalidated.body.len()), (304, 0));
+        let png = server.respond("GET /render?text=Hi&format=png HTTP/1.1\r\n\r\n");
+        assert!(png.body.starts_with(b"\x89PNG"));
+        assert_eq!(server.respond("POST /render?text=Hi HTTP/1.1\r\n\r\n").status, 405);
+        assert_eq!(server.respond("GET /other HTTP/1.1\r\n\r\n").status, 404);
+
+        let limits = ServeLimits { max_pixels: 10_000, ..ServeLimits::default() };
+        let server = Server::new("DejaVu Sans".to_string(), 24, RenderConfig::new(false, FontStyle::Regular), limits, 0, load_font);
+        let huge = server.respond("GET /render?text=Hello+world&size=200&scale=4&format=png HTTP/1.1\r\n\r\n");
+        assert_eq!(huge.status, 413);
+        assert!(String::from_utf8_lossy(&huge.body).contains("limit of 10000"));
+        // The svg of the same text isn't rasterized
+        assert_eq!(server.respond("GET /render?text=Hello+world&size=200 HTTP/1.1\r\n\r\n").status, 200);
+    }
+}
@@ -0,0 +1,474 @@
+// text shaping results and the cache that keeps them around between calls
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use rustybuzz::ttf_parser::gsub::SubstitutionSubtable;
+use rustybuzz::ttf_parser::{GlyphId, Tag};
+use rustybuzz::{Face, Feature, GlyphBuffer, GlyphInfo, GlyphPosition, UnicodeBuffer};
+
+use crate::font::FontStyle;
+
+/// Number of shaped runs kept by default.
+pub const DEFAULT_SHAPE_CACHE_SIZE: usize = 1024;
+
+/// Number of measured words kept per style by default.
+pub const DEFAULT_WORD_WIDTH_CACHE_SIZE: usize = 16384;
+
+thread_local! {
+    // Buffer recycled from the previous shaping call on this thread. FontConfig is
+    // shared between workers, so each thread keeps its own instead of locking one.
+    static SHAPE_BUFFER: RefCell<Option<UnicodeBuffer>> = const { RefCell::new(None) };
+}
+
+/// Shapes `text` and hands the result to `f`, reusing the thread's buffer
+/// allocation from the previous call instead of allocating a new one.
+pub fn shape_with_reused_buffer<R>(
+    face: &Face,
+    features: &[Feature],
+    text: &str,
+    f: impl FnOnce(&GlyphBuffer) -> R,
+) -> R {
+    let mut buffer = SHAPE_BUFFER
+        .with(|cell| cell.borrow_mut().take())
+        .unwrap_or_default();
+    buffer.push_str(text);
+    let glyph_buffer = rustybuzz::shape(face, features, buffer);
+    let result = f(&glyph_buffer);
+    SHAPE_BUFFER.with(|cell| *cell.borrow_mut() = Some(glyph_buffer.clear()));
+    result
+}
+
+/// Owned copy of a rustybuzz shaping result.
+/// Unlike `GlyphBuffer` it can be cloned and shared out of the cache.
+#[derive(Debug, Clone, Default)]
+pub struct ShapedText {
+    infos: Vec<GlyphInfo>,
+    positions: Vec<GlyphPosition>,
+    // Font of each glyph when some come from fallbacks, else empty
+    faces: Vec<u16>,
+}
+
+impl ShapedText {
+    pub fn from_glyph_buffer(glyph_buffer: &GlyphBuffer) -> Self {
+        Self {
+            infos: glyph_buffer.glyph_infos().to_vec(),
+            positions: glyph_buffer.glyph_positions().to_vec(),
+            faces: Vec::new(),
+        }
+    }
+
+    /// Appends the glyphs of a run shaped on its own, `offset` bytes into the
+    /// text, from font `face_index` and with positions multiplied by `scale`.
+    pub fn push_run(&mut self, glyph_buffer: &GlyphBuffer, offset: u32, scale: f32, face_index: usize) {
+        if face_index != 0 && self.faces.is_empty() {
+            self.faces = vec![0; self.infos.len()];
+        }
+        let scaled = |value: i32| (value as f32 * scale).round() as i32;
+        for (info, position) in glyph_buffer.glyph_infos().iter().zip(glyph_buffer.glyph_positions()) {
+            let mut info = *info;
+            info.cluster += offset;
+            let mut position = *position;
+            position.x_advance = scaled(position.x_advance);
+            position.y_advance = scaled(position.y_advance);
+            position.x_offset = scaled(position.x_offset);
+            position.y_offset = scaled(position.y_offset);
+            self.infos.push(info);
+            self.positions.push(position);
+            if !self.faces.is_empty() {
+                self.faces.push(face_index as u16);
+            }
+        }
+    }
+
+    /// Font glyph `i` comes from: 0 for the font shaped with, n for its nth fallback.
+    pub fn face_index(&self, i: usize) -> usize {
+        self.faces.get(i).map_or(0, |&face| face as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+
+    pub fn glyph_infos(&self) -> &[GlyphInfo] {
+        &self.infos
+    }
+
+    pub fn glyph_positions(&self) -> &[GlyphPosition] {
+        &self.positions
+    }
+
+    /// Glyphs with their positions, to adjust after shaping.
+    pub fn glyphs_mut(&mut self) -> impl Iterator<Item = (&mut GlyphInfo, &mut GlyphPosition)> {
+        self.infos.iter_mut().zip(self.positions.iter_mut())
+    }
+}
+
+/// Everything that can change the outcome of shaping a run of text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShapeKey {
+    text: String,
+    face: String,
+    style: FontStyle,
+    size: u32,
+    features: String,
+    letter_space: (u32, bool), // f32 bits and whether in px, so the key stays Eq + Hash
+}
+
+impl ShapeKey {
+    pub fn new(text: &str, face: &str, style: &FontStyle, size: u32, features: String, letter_space: (f32, bool)) -> Self {
+        Self {
+            text: text.to_string(),
+            face: face.to_string(),
+            style: style.clone(),
+            size,
+            features,
+            letter_space: (letter_space.0.to_bits(), letter_space.1),
+        }
+    }
+}
+
+/// Hit/miss counters reported in debug output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShapeCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl Display for ShapeCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.hits + self.misses;
+        let ratio = if total == 0 { 0.0 } else { self.hits as f32 / total as f32 * 100.0 };
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate), {}/{} entries",
+            self.hits, self.misses, ratio, self.len, self.capacity
+        )
+    }
+}
+
+/// LRU cache of shaped runs, so repeated words and lines (and the probes
+/// made while wrapping) are only shaped once.
+pub struct ShapeCache {
+    entries: LruCache<ShapeKey, Arc<ShapedText>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for ShapeCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHAPE_CACHE_SIZE)
+    }
+}
+
+impl std::fmt::Debug for ShapeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ShapeCache({})", self.stats())
+    }
+}
+
+impl ShapeCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: LruCache::new(capacity),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Looks up a shaped run, counting the lookup as a hit or a miss.
+    pub fn get(&mut self, key: &ShapeKey) -> Option<Arc<ShapedText>> {
+        match self.entries.get(key) {
+            Some(shaped) => {
+                self.hits += 1;
+                Some(shaped.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: ShapeKey, shaped: Arc<ShapedText>) {
+        self.entries.put(key, shaped);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> ShapeCacheStats {
+        ShapeCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.entries.len(),
+            capacity: self.entries.cap().get(),
+        }
+    }
+}
+
+/// Pixel widths of single words (and whitespace runs) measured while wrapping,
+/// so repeated words are measured once rather than once per candidate line.
+/// Widths depend on font, size, features and letter spacing; the owning
+/// `FontConfig` clears the cache whenever one of those changes.
+pub struct WordWidthCache {
+    capacity: usize,
+    entries: Mutex<HashMap<FontStyle, LruCache<String, f32>>>,
+}
+
+impl Default for WordWidthCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_WORD_WIDTH_CACHE_SIZE)
+    }
+}
+
+impl std::fmt::Debug for WordWidthCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WordWidthCache({} words)", self.len())
+    }
+}
+
+impl WordWidthCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, style: &FontStyle, word: &str) -> Option<f32> {
+        self.entries.lock().unwrap().get_mut(style)?.get(word).copied()
+    }
+
+    pub fn insert(&self, style: &FontStyle, word: &str, width: f32) {
+        let capacity = NonZeroUsize::new(self.capacity).unwrap_or(NonZeroUsize::MIN);
+        self.entries
+            .lock()
+            .unwrap()
+            .entry(style.clone())
+            .or_insert_with(|| LruCache::new(capacity))
+            .put(word.to_string(), width);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().values().map(|words| words.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+// Printable ASCII, the range covered by the ASCII fast path
+const ASCII_FIRST: u8 = b' ';
+const ASCII_LAST: u8 = b'~';
+const ASCII_COUNT: usize = (ASCII_LAST - ASCII_FIRST + 1) as usize;
+
+// Features rustybuzz enables by default for horizontal text that may substitute glyphs
+const DEFAULT_SUBSTITUTION_FEATURES: [&[u8; 4]; 14] = [
+    b"rvrn", b"ltra", b"ltrm", b"ccmp", b"locl", b"rlig", b"calt",
+    b"clig", b"liga", b"rclt", b"frac", b"numr", b"dnom", b"rand",
+];
+
+/// Nominal advances and pair kerning of printable ASCII, in font units.
+/// Text containing a character that a substitution lookup could start at
+/// (e.g. 'f' in a font with fi/fl ligatures) is left to the shaper; for all other
+/// ASCII text shaping amounts to advances plus pair kerning.
+/// Pair kerning is measured with rustybuzz the first time a pair is seen.
+pub struct AsciiWidthTable {
+    advances: [i32; ASCII_COUNT],
+    substituted: [bool; ASCII_COUNT],
+    kerning: Mutex<HashMap<(u8, u8), i32>>,
+}
+
+impl std::fmt::Debug for AsciiWidthTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AsciiWidthTable({} kerning pairs)", self.kerning.lock().unwrap().len())
+    }
+}
+
+impl AsciiWidthTable {
+    /// Returns None when the face doesn't cover all of printable ASCII.
+    pub fn new(face: &Face, features: &[Feature]) -> Option<Self> {
+        let mut glyphs = [GlyphId(0); ASCII_COUNT];
+        let mut advances = [0; ASCII_COUNT];
+        for (i, (glyph, advance)) in glyphs.iter_mut().zip(advances.iter_mut()).enumerate() {
+            *glyph = face.glyph_index((ASCII_FIRST + i as u8) as char)?;
+            *advance = face.glyph_hor_advance(*glyph)? as i32;
+        }
+
+        Some(Self {
+            advances,
+            substituted: substituted_glyphs(face, features, &glyphs),
+            kerning: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Shaped width of `text` in font units, or None if it isn't printable ASCII
+    /// or contains a character that may be substituted.
+    pub fn width(&self, text: &str, face: &Face, features: &[Feature]) -> Option<i32> {
+        let bytes = text.as_bytes();
+        let fast = bytes.iter().all(|&b| {
+            (ASCII_FIRST..=ASCII_LAST).contains(&b) && !self.substituted[(b - ASCII_FIRST) as usize]
+        });
+        if !fast {
+            return None;
+        }
+
+        let mut width: i32 = bytes.iter().map(|&b| self.advance(b)).sum();
+        for pair in bytes.windows(2) {
+            width += self.kerning(pair[0], pair[1], face, features);
+        }
+        Some(width)
+    }
+
+    fn advance(&self, b: u8) -> i32 {
+        self.advances[(b - ASCII_FIRST) as usize]
+    }
+
+    // Kerning between two characters: their shaped width minus their nominal advances
+    fn kerning(&self, left: u8, right: u8, face: &Face, features: &[Feature]) -> i32 {
+        if let Some(&kerning) = self.kerning.lock().unwrap().get(&(left, right)) {
+            return kerning;
+        }
+
+        let pair = String::from_utf8_lossy(&[left, right]).into_owned();
+        let shaped: i32 = shape_with_reused_buffer(face, features, &pair, |glyph_buffer| {
+            glyph_buffer.glyph_positions().iter().map(|pos| pos.x_advance).sum()
+        });
+        let kerning = shaped - self.advance(left) - self.advance(right);
+
+        self.kerning.lock().unwrap().insert((left, right), kerning);
+        kerning
+    }
+}
+
+/// Advance of a monospace face, i.e. the one advance all printable ASCII glyphs share.
+/// None for proportional faces. The post table's fixed pitch flag is not consulted,
+/// since fonts set it inconsistently; the advances themselves are what layout uses.
+pub fn monospace_advance(face: &Face) -> Option<u16> {
+    let mut advances = (ASCII_FIRST..=ASCII_LAST).map(|b| {
+        face.glyph_index(b as char).and_then(|glyph| face.glyph_hor_advance(glyph))
+    });
+    let first = advances.next()??;
+    if advances.all(|advance| advance == Some(first)) && first > 0 {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+// Which of `glyphs` a GSUB lookup enabled by default or by the user can start at.
+// A lookup only fires on glyphs in its subtables' coverage.
+fn substituted_glyphs(face: &Face, features: &[Feature], glyphs: &[GlyphId; ASCII_COUNT]) -> [bool; ASCII_COUNT] {
+    let mut substituted = [false; ASCII_COUNT];
+    let Some(gsub) = face.tables().gsub else {
+        return substituted;
+    };
+    let kern = Tag::from_bytes(b"kern");
+
+    let subtables = gsub.features
+        .into_iter()
+        .filter(|feature| {
+            DEFAULT_SUBSTITUTION_FEATURES.iter().any(|tag| feature.tag == Tag::from_bytes(tag))
+                || features.iter().any(|user| user.tag == feature.tag && user.tag != kern)
+        })
+        .flat_map(|feature| feature.lookup_indices)
+        .filter_map(|index| gsub.lookups.get(index))
+        .flat_map(|lookup| lookup.subtables.into_iter::<SubstitutionSubtable>());
+    for subtable in subtables {
+        let coverage = subtable.coverage();
+        for (flag, &glyph) in substituted.iter_mut().zip(glyphs.iter()) {
+            *flag |= coverage.contains(glyph);
+        }
+    }
+    substituted
+}
+
+#[cfg(test)]
+mod test_shape {
+    use super::*;
+    use crate::test_support;
+
+    fn key(text: &str) -> ShapeKey {
+        ShapeKey::new(text, "TestFont", &FontStyle::Regular, 16, "kern=1".to_string(), (0.0, false))
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let mut cache = ShapeCache::new(4);
+        assert!(cache.get(&key("hello")).is_none());
+        cache.insert(key("hello"), Arc::new(ShapedText::default()));
+        assert!(cache.get(&key("hello")).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.len, 1);
+    }
+
+    #[test]
+    fn test_cache_key_includes_letter_space() {
+        let mut cache = ShapeCache::new(4);
+        cache.insert(key("hello"), Arc::new(ShapedText::default()));
+        let spaced = ShapeKey::new("hello", "TestFont", &FontStyle::Regular, 16, "kern=1".to_string(), (0.1, false));
+        assert!(cache.get(&spaced).is_none());
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = ShapeCache::new(2);
+        cache.insert(key("a"), Arc::new(ShapedText::default()));
+        cache.insert(key("b"), Arc::new(ShapedText::default()));
+        // Touch "a" so "b" becomes the eviction candidate
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("c"), Arc::new(ShapedText::default()));
+
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("c")).is_some());
+    }
+
+    #[test]
+    fn test_word_widths_are_kept_per_style() {
+        let cache = WordWidthCache::new(4);
+        cache.insert(&FontStyle::Regular, "word", 12.5);
+        assert_eq!(cache.get(&FontStyle::Regular, "word"), Some(12.5));
+        assert_eq!(cache.get(&FontStyle::Bold, "word"), None);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_reused_buffer_does_not_leak_between_calls() {
+        let font_config = test_support::font_config(16);
+        let face = font_config.get_shaping_face_by_style(&FontStyle::Regular).unwrap();
+        let features = font_config.get_features();
+
+        let fresh = |text: &str| {
+            let mut buffer = UnicodeBuffer::new();
+            buffer.push_str(text);
+            ShapedText::from_glyph_buffer(&rustybuzz::shape(face, features, buffer)).len()
+        };
+        for text in ["a much longer run of text", "ab", "", "AVATAR"] {
+            let reused = shape_with_reused_buffer(face, features, text, |glyph_buffer| glyph_buffer.len());
+            assert_eq!(reused, fresh(text), "{:?}", text);
+        }
+    }
+}
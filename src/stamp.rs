@@ -0,0 +1,246 @@
+// `stamp` subcommand: text bent around a ring, along its top and bottom, with
+// a line or monogram in the middle, for seals and badge style logos
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use svg::node::element::{Circle, Definitions, Group};
+use svg::Document;
+
+use crate::a11y::label_document;
+use crate::color::Color;
+use crate::font::FontConfig;
+use crate::output::OutputConfig;
+use crate::render::{apply_effects, layout_lines, save_svg, stack_line_layouts, style_text_group, RenderConfig};
+use crate::svg::GlyphDefs;
+use crate::warp::map_text;
+
+// Widest angle a ring text spans when the radius is fitted to it
+const MAX_ARC: f32 = PI * 5.0 / 6.0;
+
+/// What goes on the stamp and how it is framed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StampConfig {
+    /// Text along the top of the ring, reading clockwise
+    pub top: Option<String>,
+    /// Text along the bottom of the ring, reading left to right
+    pub bottom: Option<String>,
+    /// Lines in the middle, e.g. a monogram
+    pub center: Option<String>,
+    /// Outer radius in pixels, fitted to the text when None
+    pub radius: Option<f32>,
+    /// Ring borders drawn: 0, 1 (outside the text) or 2 (on both sides of it)
+    pub rings: u8,
+    pub ring_width: f32,
+    /// Rotation of the ring text in degrees, clockwise
+    pub rotation: f32,
+    /// Font size of the middle lines, the font's size when None
+    pub center_size: Option<u32>,
+}
+
+impl StampConfig {
+    pub fn new() -> Self {
+        Self {
+            top: None,
+            bottom: None,
+            center: None,
+            radius: None,
+            rings: 2,
+            ring_width: 2.0,
+            rotation: 0.0,
+            center_size: None,
+        }
+    }
+}
+
+impl Default for StampConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A line laid out straight, before it is bent: content, glyphs, width and height
+struct StraightLine {
+    content: Group,
+    glyph_defs: GlyphDefs,
+    width: f32,
+    height: f32,
+}
+
+fn layout_straight(lines: &[String], font_config: &FontConfig, render_config: &RenderConfig) -> StraightLine {
+    let paragraph_starts: Vec<usize> = (0..lines.len()).collect();
+    let (line_layouts, glyph_defs) = layout_lines(lines, font_config, render_config);
+    let (content, width, height) = stack_line_layouts(line_layouts, &paragraph_starts, font_config, render_config);
+    StraightLine { content, glyph_defs, width: width as f32, height: height as f32 }
+}
+
+// Paint of the rings: the text's fill, or its outline color when it isn't filled
+fn ring_paint(font_config: &FontConfig) -> Color {
+    match font_config.get_fill_color() {
+        Color::None => *font_config.get_color(),
+        fill => *fill,
+    }
+}
+
+fn round2(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Document of the stamp, centered on the origin, with the glyph definitions
+/// of its middle lines (the ring text is outlined in place). The font size is
+/// changed for the middle lines and restored after.
+pub fn build_stamp_document(
+    stamp: &StampConfig,
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+) -> Result<(Document, GlyphDefs)> {
+    if stamp.top.is_none() && stamp.bottom.is_none() && stamp.center.is_none() {
+        bail!("Nothing to stamp: give the middle text, --top or --bottom");
+    }
+    let size = font_config.get_size() as f32;
+    let arc = |text: &Option<String>| text.as_ref().map(|text| layout_straight(std::slice::from_ref(text), font_config, render_config));
+    let (top, bottom) = (arc(&stamp.top), arc(&stamp.bottom));
+
+    let font_size = font_config.get_size();
+    font_config.set_size(stamp.center_size.unwrap_or(font_size));
+    let center_lines: Option<Vec<String>> = stamp.center.as_ref().map(|center| center.lines().map(str::to_string).collect());
+    let center = center_lines.as_ref().map(|lines| layout_straight(lines, font_config, render_config));
+    font_config.set_size(font_size);
+
+    // The ring text sits in a band between the rings, clear of them by `gap`
+    let gap = size * 0.2 + stamp.ring_width;
+    let text_height = top.iter().chain(&bottom).map(|line| line.height).fold(0.0, f32::max);
+    let band = if text_height > 0.0 { text_height + 2.0 * gap } else { size * 0.25 + stamp.ring_width };
+    let widest = top.iter().chain(&bottom).map(|line| line.width).fold(0.0, f32::max);
+    let center_reach = center.as_ref().map_or(0.0, |center| (center.width.powi(2) + center.height.powi(2)).sqrt() / 2.0 + gap);
+    let radius = stamp
+        .radius
+        .unwrap_or_else(|| (widest / MAX_ARC + band / 2.0).max(center_reach + band).max(size * 2.0));
+    let middle = radius - band / 2.0;
+    if middle <= 0.0 {
+        bail!("A radius of {} leaves no room for the text; it needs more than {}", radius, band / 2.0);
+    }
+    // Top and bottom text meet once each spans half the ring
+    let spans = [&top, &bottom].iter().filter_map(|line| line.as_ref().map(|line| line.width / middle)).collect::<Vec<_>>();
+    let limit = if spans.len() == 2 { PI } else { 2.0 * PI };
+    if spans.iter().any(|span| *span > limit) {
+        crate::log_warn!("The ring text doesn't fit around a radius of {}; it overlaps itself", radius);
+    }
+    if center_reach > radius - band {
+        crate::log_warn!("The middle text reaches past the inner ring; try a smaller --center-size");
+    }
+
+    let rotation = stamp.rotation.to_radians();
+    let precision = font_config.get_path_precision();
+    let mut content = Group::new();
+    if let Some(top) = &top {
+        // Read clockwise with the letters' tops outwards
+        let inset = (text_height - top.height) / 2.0;
+        let map = |(x, y): (f32, f32)| {
+            let angle = -FRAC_PI_2 + rotation + (x - top.width / 2.0) / middle;
+            let r = radius - gap - inset - y;
+            (r * angle.cos(), r * angle.sin())
+        };
+        content = content.add(map_text(&top.content, &top.glyph_defs, &map, precision));
+    }
+    if let Some(bottom) = &bottom {
+        // Read left to right with the letters' tops inwards
+        let inset = (text_height - bottom.height) / 2.0;
+        let map = |(x, y): (f32, f32)| {
+            let angle = FRAC_PI_2 + rotation - (x - bottom.width / 2.0) / middle;
+            let r = radius - band + gap + inset + y;
+            (r * angle.cos(), r * angle.sin())
+        };
+        content = content.add(map_text(&bottom.content, &bottom.glyph_defs, &map, precision));
+    }
+    let mut glyph_defs = GlyphDefs::new();
+    if let Some(center) = center {
+        content = content.add(
+            Group::new()
+                .set("transform", format!("translate({} {})", round2(-center.width / 2.0), round2(-center.height / 2.0)))
+                .add(center.content),
+        );
+        glyph_defs = center.glyph_defs;
+    }
+
+    let paint = ring_paint(font_config).to_string();
+    let mut frame = Group::new().set("class", "stamp-frame").set("fill", "none").set("stroke", paint.as_str()).set("stroke-width", stamp.ring_width);
+    let ring_radii = [radius - stamp.ring_width / 2.0, radius - band + stamp.ring_width / 2.0];
+    for r in ring_radii.into_iter().take(stamp.rings as usize) {
+        frame = frame.add(Circle::new().set("r", round2(r)));
+    }
+    // Dots part the top text from the bottom one on either side
+    if top.is_some() && bottom.is_some() {
+        for angle in [rotation, rotation + PI] {
+            let (x, y) = (middle * angle.cos(), middle * angle.sin());
+            frame = frame.add(Circle::new().set("cx", round2(x)).set("cy", round2(y)).set("r", round2(size * 0.08)).set("fill", paint.as_str()).set("stroke", "none"));
+        }
+    }
+
+    let mut defs = Definitions::new();
+    for node in glyph_defs.values() {
+        defs = defs.add(node.clone());
+    }
+    let extent = round2(radius + 1.0 + render_config.get_path_config().stroke_reach());
+    let view_box = (-extent, -extent, 2.0 * extent, 2.0 * extent);
+    let (text, defs) = apply_effects(style_text_group(content, font_config, render_config), defs, view_box, render_config);
+    let doc = Document::new()
+        .set("width", 2.0 * extent)
+        .set("height", 2.0 * extent)
+        .set("viewBox", format!("{} {} {} {}", -extent, -extent, 2.0 * extent, 2.0 * extent))
+        .add(defs)
+        .add(frame)
+        .add(text);
+    let label: Vec<String> = [&stamp.top, &stamp.center, &stamp.bottom].into_iter().flatten().cloned().collect();
+    let doc = label_document(doc, &label, render_config.get_markup(), render_config.get_accessibility());
+    Ok((doc, glyph_defs))
+}
+
+/// Renders the stamp and saves it to `output`.
+pub fn render_stamp(
+    stamp: &StampConfig,
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    output: &Path,
+) -> Result<()> {
+    let (doc, glyph_defs) = build_stamp_document(stamp, font_config, render_config)?;
+    save_svg(output, &doc, &glyph_defs, font_config, output_config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_stamp {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+
+    #[test]
+    fn test_stamp_document() {
+        let mut font_config = test_support::font_config(20);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let mut stamp = StampConfig::new();
+        stamp.top = Some("QUALITY GOODS".to_string());
+        stamp.bottom = Some("EST 1999".to_string());
+        stamp.center = Some("AB".to_string());
+        stamp.center_size = Some(48);
+
+        let (doc, glyph_defs) = build_stamp_document(&stamp, &mut font_config, &render_config).unwrap();
+        let xml = doc.to_string();
+        // Two rings and the two dots between the ring texts
+        assert_eq!(xml.matches("<circle").count(), 4, "{}", xml);
+        // Only the middle letters are referenced, the ring text is outlined
+        assert_eq!(xml.matches("<use").count(), 2, "{}", xml);
+        assert_eq!(glyph_defs.len(), 2);
+        assert!(xml.contains(r#"aria-label="QUALITY GOODS AB EST 1999""#), "{}", xml);
+        assert_eq!(font_config.get_size(), 20);
+
+        stamp.rings = 1;
+        stamp.radius = Some(30.0);
+        let xml = build_stamp_document(&stamp, &mut font_config, &render_config).unwrap().0.to_string();
+        assert!(xml.contains(r#"r="29""#), "{}", xml);
+        assert_eq!(xml.matches("<circle").count(), 3, "{}", xml);
+
+        assert!(build_stamp_document(&StampConfig::new(), &mut font_config, &render_config).is_err());
+    }
+}
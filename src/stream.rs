@@ -0,0 +1,489 @@
+// chunked rendering: wrap, lay out and write a bounded number of input lines
+// at a time, so huge inputs never have to be held in memory as a whole
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use svg::node::element::{Element, Group};
+use svg::node::{Blob, Node};
+use svg::Document;
+
+use crate::direction::Direction;
+use crate::error::io_error;
+use crate::font::FontConfig;
+use crate::eps::svg_to_eps;
+use crate::html::html_parts;
+use crate::raster::encode_raster;
+use crate::progress::Progress;
+use crate::output::OutputConfig;
+use crate::overflow::{fade_out, Overflow};
+use crate::render::{build_text_document, ellipsize_line, label_file_document, layout_lines, position_line, wrap_paragraphs, RenderConfig};
+use crate::svg::{GlyphDefs, OutputFormat};
+use crate::xml::to_xml;
+
+// Rough memory needed per byte of input text rendered in memory: the wrapped
+// lines, their shaped glyphs and one <use> element node per glyph.
+const MEMORY_PER_INPUT_BYTE: u64 = 512;
+
+/// Parses a memory size such as "512M", "2G", "800k" or a plain byte count.
+pub fn parse_memory_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let digits_end = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits_end);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid memory size '{}': expected e.g. 512M or 2G", size))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("Invalid memory size unit '{}': use K, M or G", unit)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Memory size '{}' is too large", size))
+}
+
+/// Formats a byte count with the largest fitting binary unit, e.g. "1.5 GiB".
+pub fn format_memory_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Estimated memory needed to render `input_bytes` of text in memory.
+pub fn estimate_render_memory(input_bytes: u64) -> u64 {
+    input_bytes.saturating_mul(MEMORY_PER_INPUT_BYTE)
+}
+
+/// Fails with an explanation when rendering a whole file in memory would exceed `max_memory`.
+pub fn check_file_fits_in_memory(file: &Path, max_memory: u64) -> Result<()> {
+    let input_bytes = file
+        .metadata()
+        .map_err(|e| io_error(file, e))?
+        .len();
+    let needed = estimate_render_memory(input_bytes);
+    if needed > max_memory {
+        return Err(anyhow!(
+            "{} is {}; rendering it in memory needs about {}, above --max-memory {}. \
+             Use --chunk-lines to stream it in bounded chunks instead",
+            file.display(),
+            format_memory_size(input_bytes),
+            format_memory_size(needed),
+            format_memory_size(max_memory)
+        ));
+    }
+    Ok(())
+}
+
+/// Summary of a chunked render.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChunkedStats {
+    pub chunks: usize,
+    pub lines: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Input was left over after the maximum number of lines
+    pub truncated: bool,
+}
+
+// Reads up to `max_lines` lines into `chunk`; returns the bytes read, 0 at EOF
+fn read_chunk<R: BufRead>(reader: &mut R, chunk: &mut Vec<String>, max_lines: usize) -> Result<usize> {
+    chunk.clear();
+    let mut bytes = 0;
+    while chunk.len() < max_lines {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        bytes += read;
+        let len = line.trim_end_matches(['\r', '\n']).len();
+        line.truncate(len);
+        chunk.push(line);
+    }
+    Ok(bytes)
+}
+
+/// Renders a plain text file `chunk_lines` input lines at a time.
+///
+/// Line groups are streamed to a temporary file next to `output` as soon as
+/// a chunk is laid out; only the glyph definitions are kept for the whole run.
+/// The document size is known once the last chunk is done, so the final file
+/// is assembled from the header, the definitions and the streamed lines,
+/// each aligned within the widest.
+/// With `progress`, a progress bar over the bytes of `file` is drawn on
+/// stderr if it is a terminal.
+#[allow(clippy::too_many_arguments)]
+pub fn render_text_file_chunked(
+    file: &Path,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    output: &Path,
+    chunk_lines: usize,
+    max_memory: Option<u64>,
+    progress: bool,
+) -> Result<ChunkedStats> {
+    let chunk_lines = chunk_lines.max(1);
+    let render_config = &*with_direction_of_file(file, render_config)?;
+    let input = File::open(file).map_err(|e| io_error(file, e))?;
+    let size = input.metadata().map_or(0, |metadata| metadata.len());
+    let mut reader = BufReader::new(input);
+    let mut progress = Progress::new(size, progress);
+
+    let body_path = body_path(output);
+    let mut body = BufWriter::new(
+        File::create(&body_path).map_err(|e| io_error(&body_path, e))?,
+    );
+
+    let result = stream_chunks(&mut reader, &mut body, font_config, render_config, chunk_lines, max_memory, &mut progress)
+        .and_then(|(stats, glyph_defs)| {
+            progress.finish();
+            body.flush()?;
+            assemble_document(file, output, &body_path, &glyph_defs, font_config, render_config, output_config, stats)?;
+            Ok(stats)
+        });
+    let _ = std::fs::remove_file(&body_path);
+    result
+}
+
+// `render_config` with an `Auto` direction resolved from the first letter of
+// `file`, which is read a line at a time up to that letter
+fn with_direction_of_file<'a>(file: &Path, render_config: &'a RenderConfig) -> Result<Cow<'a, RenderConfig>> {
+    if render_config.get_direction() != Direction::Auto {
+        return Ok(Cow::Borrowed(render_config));
+    }
+    let reader = BufReader::new(File::open(file).map_err(|e| io_error(file, e))?);
+    let mut first = None;
+    for line in reader.lines() {
+        let line = line.map_err(|e| io_error(file, e))?;
+        if render_config.line_direction(&line).is_some() {
+            first = Some(line);
+            break;
+        }
+    }
+    Ok(Cow::Owned(render_config.with_direction_of(first.as_deref()).into_owned()))
+}
+
+// Temporary file holding the streamed line groups
+fn body_path(output: &Path) -> PathBuf {
+    let name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "output.svg".to_string());
+    output.with_file_name(format!(".{}.part", name))
+}
+
+fn stream_chunks<R: BufRead, W: Write>(
+    reader: &mut R,
+    body: &mut W,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    chunk_lines: usize,
+    max_memory: Option<u64>,
+    progress: &mut Progress,
+) -> Result<(ChunkedStats, GlyphDefs)> {
+    let line_height = render_config.line_height_pixels(font_config.get_size());
+    let paragraph_spacing = render_config.paragraph_spacing_pixels(font_config.get_size());
+    let mut stats = ChunkedStats::default();
+    let mut glyph_defs: GlyphDefs = HashMap::new();
+    let mut chunk = Vec::with_capacity(chunk_lines);
+    let max_lines = render_config.get_max_lines().unwrap_or(usize::MAX);
+
+    let mut read = 0;
+    loop {
+        let bytes = read_chunk(reader, &mut chunk, chunk_lines)?;
+        if bytes == 0 {
+            break;
+        }
+        read += bytes as u64;
+        if stats.lines >= max_lines {
+            // Nothing more is rendered, so the rest of the input is never read
+            stats.truncated = true;
+            break;
+        }
+        if let Some(max_memory) = max_memory {
+            let chunk_bytes: u64 = chunk.iter().map(|line| line.len() as u64).sum();
+            let needed = estimate_render_memory(chunk_bytes);
+            if needed > max_memory {
+                return Err(anyhow!(
+                    "a chunk of {} lines ({}) needs about {} to render, above --max-memory {}. \
+                     Lower --chunk-lines",
+                    chunk.len(),
+                    format_memory_size(chunk_bytes),
+                    format_memory_size(needed),
+                    format_memory_size(max_memory)
+                ));
+            }
+        }
+
+        let (mut lines, paragraph_starts) = wrap_paragraphs(chunk.iter().map(String::as_str), font_config, render_config);
+        let remaining = max_lines - stats.lines;
+        // The last line kept ends with the ellipsis, even when the cut falls between chunks
+        let more = lines.len() > remaining || (lines.len() == remaining && !reader.fill_buf()?.is_empty());
+        if more && render_config.get_overflow() == Overflow::Ellipsis {
+            lines.truncate(remaining);
+            if let Some(last) = lines.last_mut() {
+                *last = ellipsize_line(last, font_config, render_config);
+            }
+        }
+        let (line_layouts, chunk_defs) = layout_lines(&lines, font_config, render_config);
+        for (id, node) in chunk_defs {
+            glyph_defs.entry(id).or_insert(node);
+        }
+
+        if more {
+            stats.truncated = true;
+        }
+        for (i, line_layout) in line_layouts.into_iter().take(remaining).enumerate() {
+            if stats.lines > 0 && paragraph_starts.binary_search(&i).is_ok() {
+                stats.height += paragraph_spacing;
+            }
+            if let Some((line_content_group, line_bbox)) = line_layout {
+                // Lines are placed once the widest is known, as alignment depends on its width
+                let line = StreamedLine { index: stats.lines, y: stats.height, width: line_bbox.width() as u32 };
+                line.write(body, &line_content_group.to_string())?;
+                stats.width = stats.width.max(line.width);
+            }
+            stats.height += line_height;
+            stats.lines += 1;
+        }
+        stats.chunks += 1;
+        progress.set(read, &format!("{} lines", stats.lines));
+
+        if font_config.get_debug() {
+            crate::log_debug!("Rendered chunk {} ({} lines so far)", stats.chunks, stats.lines);
+        }
+    }
+
+    Ok((stats, glyph_defs))
+}
+
+// Writes the final document around the streamed line groups, labelled with the text of `file`
+#[allow(clippy::too_many_arguments)]
+fn assemble_document(
+    file: &Path,
+    output: &Path,
+    body_path: &Path,
+    glyph_defs: &GlyphDefs,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    stats: ChunkedStats,
+) -> Result<()> {
+    let doc = label_file_document(document_around_lines(glyph_defs, font_config, render_config, stats)?, file, render_config)?;
+    let mut out = BufWriter::new(File::create(output).map_err(|e| io_error(output, e))?);
+    match output_config.get_output_format(output) {
+        OutputFormat::Svg => write_document(out, body_path, &doc, stats.width, render_config)?.flush()?,
+        OutputFormat::Svgz => {
+            let encoder = BufWriter::new(GzEncoder::new(out, Compression::default()));
+            let encoder = write_document(encoder, body_path, &doc, stats.width, render_config)?;
+            encoder.into_inner().map_err(|e| e.into_error())?.finish()?.flush()?
+        }
+        // The document is streamed into the page like into a file
+        OutputFormat::Html => {
+            // The page is titled like the document is labelled
+            let title = doc.get_attributes().and_then(|attributes| attributes.get("aria-label")).map(|label| label.to_string());
+            let (before, after) = html_parts(title.as_deref(), output_config.get_html_template());
+            out.write_all(before.as_bytes())?;
+            let mut out = write_document(out, body_path, &doc, stats.width, render_config)?;
+            out.write_all(after.as_bytes())?;
+            out.flush()?
+        }
+        // PostScript and images are converted from the whole document
+        format => {
+            let svg = String::from_utf8(write_document(Vec::new(), body_path, &doc, stats.width, render_config)?)?;
+            let converted = match format {
+                OutputFormat::Eps => svg_to_eps(&svg).map(String::into_bytes).map_err(|e| anyhow!("{}: {}", output.display(), e))?,
+                _ => encode_raster(&svg, format, output_config.get_raster_options()).map_err(|e| anyhow!("{}: {}", output.display(), e))?,
+            };
+            out.write_all(&converted)?;
+            out.flush()?
+        }
+    }
+    Ok(())
+}
+
+// Stands in for the streamed lines in the document built around them
+const BODY_PLACEHOLDER: &str = "text2svg-streamed-lines";
+
+// The document of the streamed lines, built as a rendered one is, with a
+// placeholder where the lines go
+fn document_around_lines(glyph_defs: &GlyphDefs, font_config: &FontConfig, render_config: &RenderConfig, stats: ChunkedStats) -> Result<Document> {
+    let (width, height) = (stats.width as f32, stats.height as f32);
+    let mut content = Group::new().add(Element::new(BODY_PLACEHOLDER));
+    if stats.truncated && render_config.get_overflow() == Overflow::Fade {
+        let line_height = render_config.line_height_pixels(font_config.get_size()) as f32;
+        content = fade_out(content, width, height, line_height);
+    }
+    // The lines are only needed by text mode, which isn't streamed
+    build_text_document(content, &[], glyph_defs, (0.0, 0.0, width, height), font_config, render_config)
+}
+
+// A line streamed to the body file: a header with its place and width,
+// then the byte length and markup of its content
+struct StreamedLine {
+    index: usize,
+    y: u32,
+    width: u32,
+}
+
+impl StreamedLine {
+    fn write<W: Write>(&self, body: &mut W, content: &str) -> std::io::Result<()> {
+        writeln!(body, "{} {} {} {}", self.index, self.y, self.width, content.len())?;
+        body.write_all(content.as_bytes())
+    }
+
+    // The next line and its content, None at the end of the body
+    fn read<R: BufRead>(body: &mut R) -> Result<Option<(Self, String)>> {
+        let mut header = String::new();
+        if body.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let fields: Vec<usize> = header
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow!("the streamed lines are corrupt"))?;
+        let [index, y, width, len] = fields[..] else {
+            return Err(anyhow!("the streamed lines are corrupt"));
+        };
+        let mut content = vec![0; len];
+        body.read_exact(&mut content)?;
+        Ok(Some((StreamedLine { index, y: y as u32, width: width as u32 }, String::from_utf8(content)?)))
+    }
+}
+
+// Writes `doc` to `out` with the streamed lines, aligned within `width`, in
+// place of the placeholder, and hands `out` back for finishing
+fn write_document<W: Write>(mut out: W, body_path: &Path, doc: &Document, width: u32, render_config: &RenderConfig) -> Result<W> {
+    let xml = to_xml(doc);
+    let (head, tail) = xml
+        .split_once(&format!("<{}/>", BODY_PLACEHOLDER))
+        .ok_or_else(|| anyhow!("the document has no place for the streamed lines"))?;
+    out.write_all(head.as_bytes())?;
+    let (align, direction) = (render_config.get_align(), render_config.get_direction());
+    let mut body = BufReader::new(File::open(body_path)?);
+    while let Some((line, content)) = StreamedLine::read(&mut body)? {
+        let x = align.offset(direction, line.width as f32, width as f32);
+        writeln!(out, "{}", position_line(Blob::new(content), line.index, x, line.y, render_config))?;
+    }
+    out.write_all(tail.as_bytes())?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test_stream {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+    use crate::length::Length;
+    use crate::render::build_line_layouts_document;
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("1024"), Ok(1024));
+        assert_eq!(parse_memory_size("800k"), Ok(800 << 10));
+        assert_eq!(parse_memory_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_memory_size("2 GiB"), Ok(2 << 30));
+        assert!(parse_memory_size("lots").is_err());
+        assert!(parse_memory_size("12T").is_err());
+    }
+
+    #[test]
+    fn test_chunked_render_matches_in_memory_layout() {
+        let font_config = test_support::font_config(16);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_line_height(Length::Em(1.5)).set_paragraph_spacing(Length::Px(4.0));
+
+        let text = "first line\n\nthird line is longer\nfourth\nfifth";
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("stream.txt");
+        let output = dir.path().join("stream.svg");
+        std::fs::write(&input, text).unwrap();
+
+        let stats = render_text_file_chunked(&input, &font_config, &render_config, &OutputConfig::new(), &output, 2, None, false).unwrap();
+        assert_eq!(stats.chunks, 3);
+        assert_eq!(stats.lines, 5);
+        // Lines 24 pixels apart, with 4 more before each of the last four paragraphs
+        assert_eq!(stats.height, 5 * 24 + 4 * 4);
+        assert!(!body_path(&output).exists());
+
+        let (lines, paragraph_starts) = wrap_paragraphs(text.lines(), &font_config, &render_config);
+        let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+        let document = build_line_layouts_document(line_layouts, &lines, &paragraph_starts, &glyph_defs, &font_config, &render_config).unwrap();
+        let document = label_file_document(document, &input, &render_config).unwrap().to_string();
+        let streamed = std::fs::read_to_string(&output).unwrap();
+        assert!(streamed.contains(&format!(r#"width="{}""#, stats.width)));
+        assert!(document.contains(&format!(r#"width="{}""#, stats.width)));
+        assert!(document.contains(&format!(r#"height="{}""#, stats.height)));
+        // The root is built the same way as the in-memory one
+        assert_eq!(streamed.lines().next(), document.lines().next());
+        assert!(!streamed.contains(BODY_PLACEHOLDER));
+        assert_eq!(streamed.matches("<use").count(), document.matches("<use").count());
+    }
+
+    #[test]
+    fn test_chunked_render_is_labelled() {
+        let font_config = test_support::font_config(16);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        let mut output_config = OutputConfig::new();
+        let accessibility = crate::a11y::Accessibility { description: Some("A & B".to_string()), ..Default::default() };
+        render_config.set_accessibility(accessibility).set_lang(Some("de".parse().unwrap()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("stream.txt");
+        let output = dir.path().join("stream.svg");
+        std::fs::write(&input, "Guten Tag\nWelt").unwrap();
+        render_text_file_chunked(&input, &font_config, &render_config, &output_config, &output, 1, None, false).unwrap();
+        let streamed = std::fs::read_to_string(&output).unwrap();
+        let root = streamed.lines().next().unwrap();
+        assert!(root.contains(r#"aria-label="Guten Tag Welt""#) && root.contains(r#"role="img""#), "{}", root);
+        assert!(root.contains(r#"xml:lang="de""#) && root.contains(r#"lang="de""#), "{}", root);
+        assert!(streamed.contains("<title>Guten Tag Welt</title>\n<desc>A &amp; B</desc>"), "{}", streamed);
+
+        // HTML pages are titled from the label
+        let page = dir.path().join("stream.html");
+        output_config.set_output_format(Some(OutputFormat::Html));
+        render_text_file_chunked(&input, &font_config, &render_config, &output_config, &page, 1, None, false).unwrap();
+        assert!(std::fs::read_to_string(&page).unwrap().contains("<title>Guten Tag Welt</title>\n</head>"));
+    }
+
+    #[test]
+    fn test_chunked_render_is_aligned() {
+        let font_config = test_support::font_config(16);
+        let mut aligned = RenderConfig::new(false, FontStyle::Regular);
+        aligned.set_align(crate::direction::Align::End);
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("stream.svg");
+        let translates = |xml: &str| xml.match_indices("translate(").map(|(i, _)| xml[i..].split(')').next().unwrap().to_string()).collect::<Vec<_>>();
+
+        // Right aligned lines, and right to left ones starting on the right
+        for (text, render_config) in [("short\na much longer line", &aligned), ("שלום\nשלום עולם גדול", &RenderConfig::new(false, FontStyle::Regular))] {
+            let input = dir.path().join("stream.txt");
+            std::fs::write(&input, text).unwrap();
+            render_text_file_chunked(&input, &font_config, render_config, &OutputConfig::new(), &output, 1, None, false).unwrap();
+            let streamed = translates(&std::fs::read_to_string(&output).unwrap());
+            let (document, _) = crate::render::build_text_file_svg(&input, &font_config, render_config).unwrap();
+            let in_memory = translates(&to_xml(&document));
+            assert_eq!(streamed, in_memory);
+            assert_ne!(streamed[0], "translate(0, 0", "{:?}", streamed);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+// cutting an embedded font down to the glyphs a document uses, so embedding a
+// CJK font doesn't add tens of megabytes. Glyph ids are kept as they are: the
+// outlines of unused glyphs are emptied and the character map rebuilt, which
+// leaves the layout tables referring to the same glyphs valid.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+use rustybuzz::ttf_parser::head::IndexToLocationFormat;
+use rustybuzz::ttf_parser::Tag;
+use rustybuzz::{Face, Feature, UnicodeBuffer};
+
+use crate::woff::{face_tables, table_checksum, FontTable};
+
+// Composite glyph component flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+// A format 4 subtable has 16-bit lengths, so it can hold this many segments
+const MAX_FORMAT4_SEGMENTS: usize = (u16::MAX as usize - 16) / 8;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Glyphs `lines` need: the ones their characters map to and the ones
+/// shaping with `features` turns them into, such as ligatures and
+/// alternates. Always has the .notdef glyph.
+pub fn used_glyphs(face: &Face, lines: &[String], features: &[Feature]) -> BTreeSet<u16> {
+    let mut glyphs = BTreeSet::from([0]);
+    for line in lines {
+        glyphs.extend(line.chars().filter_map(|c| face.glyph_index(c)).map(|glyph| glyph.0));
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(line);
+        let shaped = rustybuzz::shape(face, features, buffer);
+        glyphs.extend(shaped.glyph_infos().iter().map(|info| info.glyph_id as u16));
+    }
+    glyphs
+}
+
+// Byte range of each glyph in glyf, from loca
+fn glyph_ranges(loca: &[u8], long_offsets: bool, glyph_count: usize) -> Option<Vec<(usize, usize)>> {
+    let offset = |index: usize| match long_offsets {
+        true => u32_at(loca, index * 4).map(|offset| offset as usize),
+        false => u16_at(loca, index * 2).map(|offset| offset as usize * 2),
+    };
+    (0..glyph_count).map(|glyph| Some((offset(glyph)?, offset(glyph + 1)?))).collect()
+}
+
+// Glyphs a composite glyph is made of
+fn components(glyph: &[u8]) -> Vec<u16> {
+    let mut components = Vec::new();
+    // Simple glyphs have a contour count of 0 or more
+    if glyph.len() < 10 || (glyph[0] as i8) >= 0 {
+        return components;
+    }
+    let mut offset = 10;
+    while let (Some(flags), Some(component)) = (u16_at(glyph, offset), u16_at(glyph, offset + 2)) {
+        components.push(component);
+        offset += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        offset += match flags {
+            flags if flags & WE_HAVE_A_SCALE != 0 => 2,
+            flags if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 => 4,
+            flags if flags & WE_HAVE_A_TWO_BY_TWO != 0 => 8,
+            _ => 0,
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    components
+}
+
+// `glyphs` with the components of the composite glyphs among them, at any depth
+fn with_components(glyphs: &BTreeSet<u16>, glyf: &[u8], ranges: &[(usize, usize)]) -> BTreeSet<u16> {
+    let mut closed = glyphs.clone();
+    let mut pending: Vec<u16> = glyphs.iter().copied().collect();
+    while let Some(glyph) = pending.pop() {
+        let Some(data) = ranges.get(glyph as usize).and_then(|&(start, end)| glyf.get(start..end)) else {
+            continue;
+        };
+        for component in components(data) {
+            if closed.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+    closed
+}
+
+// Runs of consecutive characters mapping to consecutive glyphs: (first, last, first glyph)
+fn cmap_groups(chars: &BTreeMap<u32, u16>) -> Vec<(u32, u32, u16)> {
+    let mut groups: Vec<(u32, u32, u16)> = Vec::new();
+    for (&c, &glyph) in chars {
+        match groups.last_mut() {
+            Some((start, end, first)) if c == *end + 1 && glyph as u32 == *first as u32 + (c - *start) => *end = c,
+            _ => groups.push((c, c, glyph)),
+        }
+    }
+    groups
+}
+
+fn format4(chars: &BTreeMap<u32, u16>) -> Option<Vec<u8>> {
+    let bmp: BTreeMap<u32, u16> = chars.range(..0xFFFF).map(|(&c, &glyph)| (c, glyph)).collect();
+    let mut segments = cmap_groups(&bmp);
+    // The table ends with a segment for 0xFFFF
+    segments.push((0xFFFF, 0xFFFF, 0));
+    if segments.len() > MAX_FORMAT4_SEGMENTS {
+        return None;
+    }
+    let count = segments.len();
+    let search_range = 2 * (1 << count.ilog2());
+    let mut table = Vec::with_capacity(16 + 8 * count);
+    for value in [4, 16 + 8 * count, 0, 2 * count, search_range, search_range.ilog2() as usize - 1, 2 * count - search_range] {
+        table.extend_from_slice(&(value as u16).to_be_bytes());
+    }
+    table.extend(segments.iter().flat_map(|&(_, end, _)| (end as u16).to_be_bytes()));
+    table.extend_from_slice(&[0, 0]);
+    table.extend(segments.iter().flat_map(|&(start, _, _)| (start as u16).to_be_bytes()));
+    table.extend(segments.iter().flat_map(|&(start, end, glyph)| {
+        // The final segment maps 0xFFFF to glyph 0
+        let delta = if end == 0xFFFF { 1 } else { (glyph as u32).wrapping_sub(start) as u16 };
+        delta.to_be_bytes()
+    }));
+    table.extend(segments.iter().flat_map(|_| [0, 0]));
+    Some(table)
+}
+
+fn format12(chars: &BTreeMap<u32, u16>) -> Vec<u8> {
+    let groups = cmap_groups(chars);
+    let mut table = Vec::with_capacity(16 + 12 * groups.len());
+    table.extend_from_slice(&[0, 12, 0, 0]);
+    for value in [16 + 12 * groups.len() as u32, 0, groups.len() as u32] {
+        table.extend_from_slice(&value.to_be_bytes());
+    }
+    for (start, end, glyph) in groups {
+        for value in [start, end, glyph as u32] {
+            table.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+    table
+}
+
+/// A cmap table mapping `chars` to their glyphs: a Windows BMP subtable when
+/// they fit in one, and a full Unicode one.
+pub fn build_cmap(chars: &BTreeMap<u32, u16>) -> Vec<u8> {
+    let subtables: Vec<(u16, Vec<u8>)> = format4(chars).map(|table| (1, table)).into_iter().chain([(10, format12(chars))]).collect();
+    let mut cmap = Vec::new();
+    cmap.extend_from_slice(&[0, 0]);
+    cmap.extend_from_slice(&(subtables.len() as u16).to_be_bytes());
+    let mut offset = 4 + 8 * subtables.len() as u32;
+    for (encoding, table) in &subtables {
+        cmap.extend_from_slice(&3u16.to_be_bytes());
+        cmap.extend_from_slice(&encoding.to_be_bytes());
+        cmap.extend_from_slice(&offset.to_be_bytes());
+        offset += table.len() as u32;
+    }
+    for (_, table) in subtables {
+        cmap.extend(table);
+    }
+    cmap
+}
+
+fn table(tag: &[u8; 4], data: Vec<u8>) -> FontTable<'static> {
+    FontTable { tag: Tag::from_bytes(tag), checksum: table_checksum(&data), data: Cow::Owned(data) }
+}
+
+/// The tables of `face` keeping only what `lines` shaped with `features`
+/// need: the outlines and metrics of their glyphs and a character map of
+/// their characters. None when the face has no TrueType outlines to cut
+/// down, e.g. a CFF font.
+pub fn subset_tables<'a>(face: &Face<'a>, lines: &[String], features: &[Feature]) -> Option<Vec<FontTable<'a>>> {
+    let glyf = face.raw_face().table(Tag::from_bytes(b"glyf"))?;
+    let loca = face.raw_face().table(Tag::from_bytes(b"loca"))?;
+    let long_offsets = face.tables().head.index_to_location_format == IndexToLocationFormat::Long;
+    let glyph_count = face.number_of_glyphs() as usize;
+    let ranges = glyph_ranges(loca, long_offsets, glyph_count)?;
+    let glyphs = with_components(&used_glyphs(face, lines, features), glyf, &ranges);
+
+    // Kept glyphs are copied as they are, padded as their offsets need; the others are left empty
+    let align = if long_offsets { 4 } else { 2 };
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((glyph_count + 1) * align);
+    let push_offset = |loca: &mut Vec<u8>, offset: usize| match long_offsets {
+        true => loca.extend_from_slice(&(offset as u32).to_be_bytes()),
+        false => loca.extend_from_slice(&((offset / 2) as u16).to_be_bytes()),
+    };
+    for (glyph, &(start, end)) in ranges.iter().enumerate() {
+        push_offset(&mut new_loca, new_glyf.len());
+        if glyphs.contains(&(glyph as u16)) {
+            new_glyf.extend_from_slice(glyf.get(start..end)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(align), 0);
+        }
+    }
+    push_offset(&mut new_loca, new_glyf.len());
+
+    // Unused glyphs get no metrics, and glyphs no names (post version 3)
+    let hhea = face.raw_face().table(Tag::from_bytes(b"hhea"))?;
+    let metric_count = u16_at(hhea, 34)? as usize;
+    let mut hmtx = face.raw_face().table(Tag::from_bytes(b"hmtx"))?.to_vec();
+    for glyph in (0..glyph_count).filter(|glyph| !glyphs.contains(&(*glyph as u16))) {
+        // Advance and side bearing, then side bearings only past the metric count
+        let range = match glyph < metric_count {
+            true => glyph * 4..glyph * 4 + 4,
+            false => {
+                let start = metric_count * 4 + (glyph - metric_count) * 2;
+                start..start + 2
+            }
+        };
+        if let Some(metrics) = hmtx.get_mut(range) {
+            metrics.fill(0);
+        }
+    }
+    let mut post = face.raw_face().table(Tag::from_bytes(b"post"))?.get(..32)?.to_vec();
+    post[..4].copy_from_slice(&[0, 3, 0, 0]);
+
+    let chars: BTreeMap<u32, u16> = lines
+        .iter()
+        .flat_map(|line| line.chars())
+        .filter_map(|c| Some((c as u32, face.glyph_index(c)?.0)))
+        .collect();
+    let has_gpos = face.raw_face().table(Tag::from_bytes(b"GPOS")).is_some();
+    let mut subset: Vec<FontTable> = face_tables(face)
+        .into_iter()
+        // The signature wouldn't match the changed font, and kern is only read without GPOS
+        .filter(|table| ![b"glyf", b"loca", b"cmap", b"hmtx", b"post", b"DSIG"].contains(&&table.tag.to_bytes()))
+        .filter(|table| !(has_gpos && &table.tag.to_bytes() == b"kern"))
+        .collect();
+    subset.extend([
+        table(b"glyf", new_glyf),
+        table(b"loca", new_loca),
+        table(b"cmap", build_cmap(&chars)),
+        table(b"hmtx", hmtx),
+        table(b"post", post),
+    ]);
+    Some(subset)
+}
+
+#[cfg(test)]
+mod test_subset {
+    use super::*;
+    use crate::test_support;
+    use rustybuzz::ttf_parser::{self, OutlineBuilder, RawFaceTables};
+
+    struct Ignore;
+
+    impl OutlineBuilder for Ignore {
+        fn move_to(&mut self, _: f32, _: f32) {}
+        fn line_to(&mut self, _: f32, _: f32) {}
+        fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {}
+        fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {}
+        fn close(&mut self) {}
+    }
+
+    #[test]
+    fn test_subset_tables() {
+        let data = test_support::font_data();
+        let face = Face::from_slice(&data, 0).unwrap();
+        let lines = vec!["fé 漢".to_string()];
+        let subset = subset_tables(&face, &lines, &[]).unwrap();
+        let size = |tables: &[FontTable]| tables.iter().map(|table| table.data.len()).sum::<usize>();
+        assert!(size(&subset) * 2 < data.len(), "{} of {} bytes", size(&subset), data.len());
+
+        let find = |tag: &[u8; 4]| subset.iter().find(|table| &table.tag.to_bytes() == tag).map(|table| table.data.as_ref());
+        let raw = RawFaceTables {
+            head: find(b"head").unwrap(),
+            hhea: find(b"hhea").unwrap(),
+            maxp: find(b"maxp").unwrap(),
+            cmap: find(b"cmap"),
+            glyf: find(b"glyf"),
+            loca: find(b"loca"),
+            hmtx: find(b"hmtx"),
+            ..Default::default()
+        };
+        let small = ttf_parser::Face::from_raw_tables(raw).unwrap();
+        let outline = |face: &ttf_parser::Face, c: char| {
+            let glyph = face.glyph_index(c)?;
+            face.outline_glyph(glyph, &mut Ignore)
+        };
+        // Used glyphs keep their ids and outlines, é with the glyphs it's composed of
+        for c in ['f', 'é'] {
+            assert_eq!(small.glyph_index(c), face.glyph_index(c));
+            assert_eq!(outline(&small, c), outline(&face, c));
+        }
+        assert!(outline(&small, 'é').is_some());
+        // Unused characters are gone from the character map and their outlines emptied
+        assert_eq!(small.glyph_index('a'), None);
+        let a = face.glyph_index('a').unwrap();
+        assert!(small.outline_glyph(a, &mut Ignore).is_none());
+        // Characters the face lacks stay unmapped
+        assert_eq!(small.glyph_index('漢'), None);
+    }
+
+    #[test]
+    fn test_used_glyphs_follow_features() {
+        use std::str::FromStr;
+        let data = test_support::font_data();
+        let face = Face::from_slice(&data, 0).unwrap();
+        let lines = vec!["ABCDEFGHIJKLMNOPQRSTUVWXYZ abcdefghijklmnopqrstuvwxyz 0123456789".to_string()];
+        let plain = used_glyphs(&face, &lines, &[]);
+        // Alternates the feature turns the text into are kept too
+        let alternates = used_glyphs(&face, &lines, &[Feature::from_str("salt").unwrap()]);
+        assert!(!alternates.is_subset(&plain), "{:?}", alternates.difference(&plain).collect::<Vec<_>>());
+    }
+}
@@ -1,27 +1,479 @@
 use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 /// StrokeLineCap specifies the shape to be used at the end of open subpaths when stroked
 use resvg::tiny_skia::LineCap as StrokeLineCap;
 /// StrokeLineJoin specifies the shape to be used at the corners of paths when stroked
-use resvg::tiny_skia::LineJoin as StrokeLineJoin;
+pub use resvg::tiny_skia::LineJoin as StrokeLineJoin;
 use resvg::tiny_skia::Point;
 // use resvg::usvg::StrokeWidth; // Removed unused import
 use std::fmt::Write;
 
+use clap::ValueEnum;
+
 use crate::font::{FontConfig, FontStyle};
+use crate::length::Length;
+use crate::color::{Color, Rgba};
+use crate::boxdraw::{box_path, BOX_STROKE_EM};
+use crate::glyphmap::ClusterIds;
+use crate::gradient::cluster_color;
+use crate::shape::ShapedText;
+use crate::xml::{to_xml, write_xml};
+use crate::conformance::svg_problems;
+use crate::eps::svg_to_eps;
+use crate::html::html_page;
+use crate::raster::{encode_raster, RasterOptions};
+use crate::preview::base64;
 use rustybuzz::ttf_parser;
-use rustybuzz::ttf_parser::{GlyphId, Rect};
-use rustybuzz::Face;
-
-use rustybuzz::GlyphBuffer;
-use svg::node::element::{Path, Group, Use}; // Removed Definitions import
+use rustybuzz::ttf_parser::{GlyphId, RasterImageFormat, Rect};
+use svg::node::element::{Image, Path, Group, Line, Rectangle, Text, Use}; // Removed Definitions import
 use svg::Node; // Added Node
 
 
 // --- Glyph Cache and Definitions ---
-pub type GlyphCache = HashMap<u16, String>; // GlyphId -> SVG ID (e.g., "g123")
+pub type GlyphCache = HashMap<(FontStyle, usize, u16), String>; // (Face style, font index, GlyphId) -> SVG ID (e.g., "g123-regular")
 // Store Box<dyn Node> because Node trait object is not Sized
 pub type GlyphDefs = HashMap<String, Box<dyn Node>>; // SVG ID -> Boxed <path> Node for <defs>
 
+// (Face style, font index, GlyphId, scale factor bits, precision, quantization grid bits, hinting pixel and offset bits)
+type OutlineKey = (FontStyle, usize, u16, u32, u8, u32, (u32, u32));
+
+/// Decimal places kept for path coordinates unless configured otherwise.
+pub const DEFAULT_PATH_PRECISION: u8 = 2;
+/// Highest supported path coordinate precision.
+pub const MAX_PATH_PRECISION: u8 = 6;
+
+const POW10: [f64; MAX_PATH_PRECISION as usize + 1] = [1.0, 10.0, 100.0, 1e3, 1e4, 1e5, 1e6];
+
+/// Appends `value` rounded to `precision` decimal places, without trailing zeros.
+/// Rounds through an integer instead of going through `{}` float formatting,
+/// which dominates the time spent outlining long texts.
+pub fn write_coord(out: &mut String, value: f32, precision: u8) {
+    let precision = precision.min(MAX_PATH_PRECISION) as usize;
+    let scaled = (value as f64 * POW10[precision]).round();
+    if !scaled.is_finite() || scaled.abs() >= 1e18 {
+        write!(out, "{}", value).unwrap();
+        return;
+    }
+
+    let mut n = scaled as i64;
+    if n == 0 {
+        out.push('0');
+        return;
+    }
+    if n < 0 {
+        out.push('-');
+        n = -n;
+    }
+
+    // Drop trailing zeros of the fraction
+    let mut fraction_digits = precision;
+    while fraction_digits > 0 && n % 10 == 0 {
+        n /= 10;
+        fraction_digits -= 1;
+    }
+
+    // Digits in reverse order, padded so there is at least one integer digit
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    while n > 0 {
+        digits[len] = b'0' + (n % 10) as u8;
+        n /= 10;
+        len += 1;
+    }
+    while len <= fraction_digits {
+        digits[len] = b'0';
+        len += 1;
+    }
+
+    for i in (0..len).rev() {
+        out.push(digits[i] as char);
+        if i == fraction_digits && i > 0 {
+            out.push('.');
+        }
+    }
+}
+
+// Bytes of a `<use href="#g123-regular" x="" y=""/>` element, without the coordinates
+const USE_ELEMENT_BYTES: usize = 40;
+// Bytes of a `<path d="" id="g123-regular"/>` definition, without the path data
+const PATH_ELEMENT_BYTES: usize = 34;
+// Coordinates in a typical glyph outline, counting both x and y
+const OUTLINE_COORDS_PER_GLYPH: usize = 64;
+// Header, styles and wrapping groups
+const DOCUMENT_OVERHEAD_BYTES: usize = 1024;
+
+// Bytes of one formatted coordinate and its separator: a few integer digits,
+// the decimal point and `precision` decimals
+fn coord_bytes(precision: u8) -> usize {
+    5 + precision as usize
+}
+
+/// Estimated size in bytes of a document placing `glyph_uses` glyphs
+/// that reference `glyph_defs` outlined definitions.
+pub fn estimate_svg_size(glyph_uses: usize, glyph_defs: usize, precision: u8) -> usize {
+    let coord = coord_bytes(precision);
+    let use_bytes = USE_ELEMENT_BYTES + 2 * coord;
+    let def_bytes = PATH_ELEMENT_BYTES + OUTLINE_COORDS_PER_GLYPH * coord;
+    DOCUMENT_OVERHEAD_BYTES + glyph_uses * use_bytes + glyph_defs * def_bytes
+}
+
+/// Number of `<use>` elements in a node tree.
+pub fn count_glyph_uses(node: &dyn Node) -> usize {
+    let own = usize::from(node.get_name() == "use");
+    let children = node
+        .get_children()
+        .map(|children| children.iter().map(|child| count_glyph_uses(child.as_ref())).sum())
+        .unwrap_or(0);
+    own + children
+}
+
+/// How documents are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain SVG text
+    #[default]
+    Svg,
+    /// Gzip-compressed SVG, as .svgz files hold
+    Svgz,
+    /// Encapsulated PostScript of the glyph paths, for print workflows
+    Eps,
+    /// PNG image
+    Png,
+    /// Lossless WebP image
+    Webp,
+    /// JPEG image, on a background as it has no transparency
+    #[value(alias = "jpg")]
+    Jpeg,
+    /// HTML page with the SVG inline
+    Html,
+}
+
+impl OutputFormat {
+    /// The format a file name asks for by its extension, svg when it has no
+    /// other format's.
+    pub fn from_path(path: &std::path::Path) -> Self {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        Self::from_extension(extension).unwrap_or_default()
+    }
+
+    /// The format files with `extension` hold, if any.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "svg" => Some(OutputFormat::Svg),
+            "svgz" => Some(OutputFormat::Svgz),
+            "eps" => Some(OutputFormat::Eps),
+            "png" => Some(OutputFormat::Png),
+            "webp" => Some(OutputFormat::Webp),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "html" | "htm" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// Extension of files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Svg => "svg",
+            OutputFormat::Svgz => "svgz",
+            OutputFormat::Eps => "eps",
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Html => "html",
+        }
+    }
+
+    /// Whether the format is a bitmap image.
+    pub fn is_raster(self) -> bool {
+        matches!(self, OutputFormat::Png | OutputFormat::Webp | OutputFormat::Jpeg)
+    }
+}
+
+// Counts the bytes passed on to `inner`
+struct ByteCount<W> {
+    inner: W,
+    count: usize,
+}
+
+impl<W: std::io::Write> std::io::Write for ByteCount<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes `document` to `out` gzip-compressed, streaming the text through the
+/// encoder. Returns the number of compressed bytes written.
+pub fn write_svgz<N: Node + Clone + std::fmt::Display, W: std::io::Write>(document: &N, out: W) -> std::io::Result<usize> {
+    use std::io::Write as _;
+    let counted = ByteCount { inner: std::io::BufWriter::new(out), count: 0 };
+    let mut encoder = flate2::write::GzEncoder::new(counted, flate2::Compression::default());
+    // The formatter writes in small pieces; the encoder gets them in blocks
+    let mut buffered = std::io::BufWriter::new(&mut encoder);
+    write_xml(document, &mut buffered)?;
+    buffered.flush()?;
+    drop(buffered);
+    let mut counted = encoder.finish()?;
+    counted.flush()?;
+    Ok(counted.count)
+}
+
+/// Text of the svg file at `path`, decompressed when it's gzip-compressed.
+pub fn read_svg_file(path: &std::path::Path) -> std::io::Result<String> {
+    use std::io::Read as _;
+    let data = std::fs::read(path)?;
+    // Gzip streams start with these two bytes, which no XML text does
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return String::from_utf8(data).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+    }
+    let mut content = String::new();
+    flate2::read::GzDecoder::new(&data[..]).read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Serializes `document` into a buffer of `estimated_size` bytes and writes it
+/// to `path` in one go, or to stdout when `path` is "-". Compressed documents
+/// are streamed through the encoder instead, EPS and images are converted from
+/// the serialized text and HTML pages wrap it. Returns the number of bytes written.
+pub fn save_document<P: AsRef<std::path::Path>, N: Node + Clone + std::fmt::Display>(
+    path: P,
+    document: &N,
+    estimated_size: usize,
+    format: OutputFormat,
+    raster: &RasterOptions,
+    html_template: Option<&str>,
+) -> std::io::Result<usize> {
+    // Check the emitter's output with a strict parser when debugging
+    let check = |out: &str| {
+        if crate::verbosity::enabled(crate::verbosity::Verbosity::Debug) {
+            for problem in svg_problems(out) {
+                crate::log_warn!("{}: {}", path.as_ref().display(), problem);
+            }
+        }
+    };
+    if format == OutputFormat::Svgz {
+        if crate::verbosity::enabled(crate::verbosity::Verbosity::Debug) {
+            check(&to_xml(document));
+        }
+        return match crate::verbosity::is_stdout(path.as_ref()) {
+            true => write_svgz(document, std::io::stdout().lock()),
+            false => write_svgz(document, std::fs::File::create(path.as_ref())?),
+        };
+    }
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+    let mut out = Vec::with_capacity(estimated_size);
+    write_xml(document, &mut out)?;
+    let out = String::from_utf8(out).map_err(|e| invalid(e.to_string()))?;
+    check(&out);
+    let out = match format {
+        OutputFormat::Eps => svg_to_eps(&out).map_err(|e| invalid(e.to_string()))?.into_bytes(),
+        format if format.is_raster() => encode_raster(&out, format, raster).map_err(|e| invalid(e.to_string()))?,
+        OutputFormat::Html => {
+            // The page is titled like the document is labelled
+            let title = document.get_attributes().and_then(|attributes| attributes.get("aria-label")).map(|label| label.to_string());
+            html_page(&out, title.as_deref(), html_template).into_bytes()
+        }
+        _ => out.into_bytes(),
+    };
+    if crate::verbosity::is_stdout(path.as_ref()) {
+        use std::io::Write as _;
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&out)?;
+        stdout.flush()?;
+    } else {
+        std::fs::write(path, &out)?;
+    }
+    Ok(out.len())
+}
+
+/// `value` snapped to the nearest multiple of `grid`; unchanged when `grid` is 0.
+pub fn quantize_coord(value: f32, grid: f32) -> f32 {
+    if grid > 0.0 {
+        (value / grid).round() * grid
+    } else {
+        value
+    }
+}
+
+/// Definition id derived from the path data itself, identical for identical outlines.
+pub fn outline_id(d: &str) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::hash::Hash::hash(d, &mut hasher);
+    format!("o{:x}", std::hash::Hasher::finish(&hasher))
+}
+
+/// Definition of a bitmap glyph without outlines, such as a color emoji: its
+/// png scaled from the nearest strike to `scale` pixels per font unit and
+/// placed at the origin like an outline would be.
+pub fn bitmap_glyph(face: &ttf_parser::Face, glyph_id: GlyphId, scale: f32, precision: u8) -> Option<Image> {
+    let pixels_per_em = scale * face.units_per_em() as f32;
+    let image = face.glyph_raster_image(glyph_id, pixels_per_em.round().clamp(1.0, u16::MAX as f32) as u16)?;
+    if image.format != RasterImageFormat::PNG || image.pixels_per_em == 0 {
+        return None;
+    }
+    // Image offsets and sizes are in pixels of the strike, y up from the baseline
+    let pixel = pixels_per_em / image.pixels_per_em as f32;
+    Some(
+        Image::new()
+            .set("x", format_coord(image.x as f32 * pixel, precision))
+            .set("y", format_coord(-(image.y as f32 + image.height as f32) * pixel, precision))
+            .set("width", format_coord(image.width as f32 * pixel, precision))
+            .set("height", format_coord(image.height as f32 * pixel, precision))
+            .set("href", format!("data:image/png;base64,{}", base64(image.data))),
+    )
+}
+
+/// `value` formatted like `write_coord`, for use as an attribute value.
+pub fn format_coord(value: f32, precision: u8) -> String {
+    let mut out = String::new();
+    write_coord(&mut out, value, precision);
+    out
+}
+
+/// Glyph outline path data shared between lines, documents and worker threads,
+/// so each glyph of a face is only outlined once per scale.
+/// An empty path means the glyph has no outline (e.g. a space).
+#[derive(Default)]
+pub struct OutlineCache {
+    entries: Mutex<HashMap<OutlineKey, Arc<str>>>,
+}
+
+impl std::fmt::Debug for OutlineCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OutlineCache({} glyphs)", self.len())
+    }
+}
+
+impl OutlineCache {
+    /// `face` is the index of the font in the fallback order, 0 being the main font.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_or_insert_with<F>(&self, style: &FontStyle, face: usize, glyph_id: u16, scale: f32, precision: u8, grid: f32, hint: (f32, f32), outline: F) -> Arc<str>
+    where
+        F: FnOnce() -> Arc<str>,
+    {
+        let key = (style.clone(), face, glyph_id, scale.to_bits(), precision, grid.to_bits(), (hint.0.to_bits(), hint.1.to_bits()));
+        if let Some(d) = self.entries.lock().unwrap().get(&key) {
+            return d.clone();
+        }
+        // Outline without holding the lock; a racing thread just computes the same path
+        let d = outline();
+        self.entries.lock().unwrap().entry(key).or_insert(d).clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Order the fill and the stroke of the glyphs are painted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum PaintOrder {
+    /// Fill first, with the stroke on top reaching into the glyphs
+    #[default]
+    Normal,
+    /// Stroke first, so only its outer half shows around the fill
+    Stroke,
+}
+
+/// Whether glyph x positions keep their fractional advances or are rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum GlyphSnap {
+    /// Exact positions, for the best fidelity
+    #[default]
+    None,
+    /// Rounded to half pixels
+    Half,
+    /// Rounded to whole pixels, for the crispest glyphs and positions that repeat more often
+    Full,
+}
+
+impl GlyphSnap {
+    /// Grid x positions snap to, 0 for none.
+    pub fn grid(&self) -> f32 {
+        match self {
+            GlyphSnap::None => 0.0,
+            GlyphSnap::Half => 0.5,
+            GlyphSnap::Full => 1.0,
+        }
+    }
+}
+
+// Colors of the shaping annotations: cluster boundaries, tightening and loosening kerning
+const CLUSTER_MARK_COLOR: &str = "#0ea5e9";
+const KERN_TIGHTER_COLOR: &str = "#dc2626";
+const KERN_LOOSER_COLOR: &str = "#16a34a";
+
+// SVG's default miter limit
+const DEFAULT_MITER_LIMIT: f32 = 4.0;
+
+/// Parses a stroke line join: "round", "miter" or "bevel".
+pub fn parse_line_join(join: &str) -> Result<StrokeLineJoin, String> {
+    match join.trim() {
+        "round" => Ok(StrokeLineJoin::Round),
+        "miter" => Ok(StrokeLineJoin::Miter),
+        "bevel" => Ok(StrokeLineJoin::Bevel),
+        _ => Err(format!("Invalid line join '{}': expected round, miter or bevel", join)),
+    }
+}
+
+/// Dash pattern of the glyph strokes, in lengths relative to the font size
+/// (a bare number or "em") or in pixels ("px"), so patterns scale with the text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrokeDash {
+    pub lengths: Vec<Length>,
+    pub offset: Length,
+}
+
+impl std::str::FromStr for StrokeDash {
+    type Err = String;
+
+    /// Parses "dashed", "dotted" or alternating dash and gap lengths, e.g. "0.2,0.1" or "6px 3px".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = match s.trim() {
+            "dashed" => "0.15 0.08",
+            // Zero length dashes with round caps draw dots
+            "dotted" => "0 0.12",
+            pattern => pattern,
+        };
+        let lengths = pattern
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|length| !length.is_empty())
+            .map(str::parse::<Length>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid dash pattern '{}': {}", s, e))?;
+        let negative = lengths.iter().any(|length| length.value() < 0.0);
+        if lengths.is_empty() || negative {
+            return Err(format!("Invalid dash pattern '{}': expected dashed, dotted or dash and gap lengths, e.g. \"0.2,0.1\"", s));
+        }
+        Ok(StrokeDash { lengths, offset: Length::default() })
+    }
+}
+
+impl StrokeDash {
+    pub fn set_offset(&mut self, offset: Length) -> &mut Self {
+        self.offset = offset;
+        self
+    }
+
+    /// The dash array and offset in pixels for text of `font_size`.
+    pub fn to_pixels(&self, font_size: f32) -> (Vec<f32>, f32) {
+        (self.lengths.iter().map(|length| length.to_pixels(font_size)).collect(), self.offset.to_pixels(font_size))
+    }
+}
 
 /// path configuration for SVG1.1 https://www.w3.org/TR/SVG11/painting.html
 #[derive(Clone, Debug)]
@@ -29,6 +481,9 @@ pub struct PathConfig {
     pub stroke_width: f32, // Store as f32 for easier use with svg crate
     pub stroke_linecap: StrokeLineCap,
     pub stroke_linejoin: StrokeLineJoin,
+    pub stroke_miterlimit: f32, // Only applies to miter joins
+    pub paint_order: PaintOrder,
+    pub stroke_dash: Option<StrokeDash>,
 }
 
 impl PathConfig {
@@ -41,6 +496,14 @@ impl PathConfig {
         }
     }
 
+    /// How far the stroke reaches beyond the outlines, beyond the default
+    /// hairline which the glyph boxes already make room for.
+    pub fn stroke_reach(&self) -> f32 {
+        let miter = matches!(self.stroke_linejoin, StrokeLineJoin::Miter | StrokeLineJoin::MiterClip);
+        let reach = self.stroke_width / 2.0 * if miter { self.stroke_miterlimit } else { 1.0 };
+        (reach - 0.5).max(0.0).ceil()
+    }
+
     pub fn get_stroke_linecap(&self) -> String {
         match self.stroke_linecap {
             StrokeLineCap::Round => "round".to_string(),
@@ -57,6 +520,9 @@ impl Default for PathConfig {
             stroke_width: 1.0,
             stroke_linejoin: StrokeLineJoin::Round,
             stroke_linecap: StrokeLineCap::Round,
+            stroke_miterlimit: DEFAULT_MITER_LIMIT,
+            paint_order: PaintOrder::Normal,
+            stroke_dash: None,
         }
     }
 }
@@ -66,6 +532,13 @@ impl Default for PathConfig {
 pub struct TextBuilder {
     pub origin: Point, // Top-left origin for the start of the text block
     pub path_config: PathConfig,
+    pub letter_space: Option<Length>, // Overrides the font config's letter spacing
+    pub continues_run: bool, // Space the first glyph from preceding text built separately
+    pub cluster_colors: Vec<(usize, Option<Rgba>)>, // Colors by the byte offset of the cluster they start at
+    pub cluster_backgrounds: Vec<(Range<usize>, Rgba)>, // Backgrounds behind the clusters starting in each byte range
+    pub box_cells: Vec<(usize, u8)>, // Box-drawing arms by the byte offset of their cluster, drawn as lines
+    pub box_cell_height: f32,
+    pub cluster_ids: Option<ClusterIds>, // Ids of the <use> elements, naming the text of their clusters
 }
 
 impl Default for TextBuilder {
@@ -73,6 +546,13 @@ impl Default for TextBuilder {
         Self {
             origin: Point { x: 0.0, y: 0.0 },
             path_config: PathConfig::default(),
+            letter_space: None,
+            continues_run: false,
+            cluster_colors: Vec::new(),
+            cluster_backgrounds: Vec::new(),
+            box_cells: Vec::new(),
+            box_cell_height: 0.0,
+            cluster_ids: None,
         }
     }
 }
@@ -87,6 +567,52 @@ impl TextBuilder {
         self
     }
 
+    pub fn set_letter_space(&mut self, letter_space: Option<Length>) -> &mut Self {
+        self.letter_space = letter_space;
+        self
+    }
+
+    pub fn set_path_config(&mut self, path_config: PathConfig) -> &mut Self {
+        self.path_config = path_config;
+        self
+    }
+
+    /// Whether the glyphs continue a run built separately before them, in which
+    /// case letter spacing also goes before the first glyph.
+    pub fn set_continues_run(&mut self, continues_run: bool) -> &mut Self {
+        self.continues_run = continues_run;
+        self
+    }
+
+    /// Colors glyphs by the cluster they belong to, overriding the group's fill
+    /// (or its stroke when the text isn't filled).
+    /// `None` goes back to the group's color.
+    pub fn set_cluster_colors(&mut self, cluster_colors: Vec<(usize, Option<Rgba>)>) -> &mut Self {
+        self.cluster_colors = cluster_colors;
+        self
+    }
+
+    /// Draws a rectangle of the line's height behind the clusters of each byte range.
+    pub fn set_cluster_backgrounds(&mut self, cluster_backgrounds: Vec<(Range<usize>, Rgba)>) -> &mut Self {
+        self.cluster_backgrounds = cluster_backgrounds;
+        self
+    }
+
+    /// Draws the clusters at the byte offsets of `box_cells` as lines reaching
+    /// out to the cell edges given by their arms, instead of as glyphs. Cells are
+    /// `height` tall from the origin, the distance between lines, so they join across lines.
+    pub fn set_box_cells(&mut self, box_cells: Vec<(usize, u8)>, height: f32) -> &mut Self {
+        self.box_cells = box_cells;
+        self.box_cell_height = height;
+        self
+    }
+
+    /// Gives every `<use>` element an id naming the text of its cluster, for glyph maps.
+    pub fn set_cluster_ids(&mut self, cluster_ids: Option<ClusterIds>) -> &mut Self {
+        self.cluster_ids = cluster_ids;
+        self
+    }
+
     // Removed set_color and set_fill_color, as these are applied later
     // to the group containing the <use> elements.
 
@@ -97,28 +623,43 @@ impl TextBuilder {
         &self,
         font_config: &FontConfig,
         font_style: &FontStyle,
-        glyphs: &GlyphBuffer,
+        glyphs: &ShapedText,
         glyph_cache: &mut GlyphCache,
         glyph_defs: &mut GlyphDefs, // Takes mutable reference to HashMap<String, Box<dyn Node>>
     ) -> (Group, Rect) { // Rect uses i16
+        let (group, bbox, _) = self.build_with_advance(font_config, font_style, glyphs, glyph_cache, glyph_defs);
+        (group, bbox)
+    }
+
+    /// Like `build`, also returning the x position where text following these glyphs starts.
+    pub fn build_with_advance(
+        &self,
+        font_config: &FontConfig,
+        font_style: &FontStyle,
+        glyphs: &ShapedText,
+        glyph_cache: &mut GlyphCache,
+        glyph_defs: &mut GlyphDefs,
+    ) -> (Group, Rect, f32) {
         let ft_face = font_config.get_font_by_style(font_style)
             .or_else(|| font_config.get_font_by_style(&FontStyle::Regular))
             .expect("Font face (style or regular) not found during build"); // Should have been checked earlier
 
-        let metrics = ft_face.metrics();
-        let origin_glyph_height = metrics.ascent - metrics.descent;
+        let metrics = font_config.face_metrics(ft_face);
         let target_glyph_height = font_config.get_size() as f32;
-        let scale_factor = target_glyph_height / origin_glyph_height.max(1.0); // Avoid division by zero
+        let scale_factor = font_config.pixel_scale(metrics);
+        // The line's box runs from the ascent to the descent
+        let line_box_height = (metrics.ascent - metrics.descent) * scale_factor;
 
         if font_config.get_debug() {
-            println!(
-                "Build Scale: origin_h={:?}, target_h={:?}, scale_factor={:?}, units/em={:?}",
-                origin_glyph_height, target_glyph_height, scale_factor, metrics.units_per_em
+            crate::log_debug!(
+                "Build Scale: size={:?}, size mode={:?}, scale_factor={:?}, units/em={:?}",
+                target_glyph_height, font_config.get_size_mode(), scale_factor, metrics.units_per_em
             );
         }
 
-        let ft_face_data = ft_face.copy_font_data().expect("Failed to copy font data");
-        let hb_face = Face::from_slice(&ft_face_data, 0).expect("Failed to create rustybuzz::Face");
+        let hb_face = font_config.get_shaping_face_by_style(font_style)
+            .or_else(|| font_config.get_shaping_face_by_style(&FontStyle::Regular))
+            .expect("Shaping face (style or regular) not found during build");
 
         let glyph_num = glyphs.len();
         let glyph_positions = glyphs.glyph_positions();
@@ -127,72 +668,179 @@ impl TextBuilder {
         let mut current_x = self.origin.x;
         // The y origin for <use> should account for the font's ascent scaled to the target size.
         // This positions the baseline correctly.
-        let base_y = self.origin.y + metrics.ascent * scale_factor;
-        let mut use_group = Group::new();
+        let hint = font_config.get_hinting().unwrap_or(0.0);
+        // A stroke of odd width is crisp along pixel centers rather than pixel edges
+        let stroked = *font_config.get_color() != Color::None && self.path_config.stroke_width > 0.0;
+        let hint_offset = match stroked && self.path_config.stroke_width.round() as i64 % 2 == 1 {
+            true => hint / 2.0,
+            false => 0.0,
+        };
+        let base_y = quantize_coord(self.origin.y + metrics.ascent * scale_factor, hint);
+        let mut uses: Vec<Use> = Vec::with_capacity(glyph_num);
+        // Cluster boundaries and kerning drawn over the glyphs when annotating shaping
+        let annotate = font_config.get_annotate_shaping();
+        let mut annotations = Group::new().set("class", "shaping-annotations").set("stroke-dasharray", "none");
+        // Box-drawing cells met so far: x, advance and arms
+        let mut box_lines: Vec<(f32, f32, u8)> = Vec::new();
+        // Cluster starts in text order, where each cluster's text ends
+        let mut cluster_starts: Vec<u32> = match self.cluster_ids {
+            Some(_) => glyph_infos.iter().map(|info| info.cluster).collect(),
+            None => Vec::new(),
+        };
+        cluster_starts.sort_unstable();
+        cluster_starts.dedup();
+        // Glyphs of the current cluster so far, to tell their ids apart
+        let mut cluster_glyph = 0;
+        // Horizontal extent of the glyphs under each background
+        let mut background_extents: Vec<Option<(f32, f32)>> = vec![None; self.cluster_backgrounds.len()];
 
-        let letter_space =
-            scale_factor * font_config.get_letter_space() * metrics.units_per_em as f32; // Use default if 0
+        let letter_space = self
+            .letter_space
+            .unwrap_or_else(|| font_config.get_letter_space())
+            .to_pixels(target_glyph_height);
 
         let mut min_x = current_x;
         let mut max_x = current_x;
         let mut min_y = base_y; // Start with baseline
         let mut max_y = base_y; // Start with baseline
 
-        let mut prev_space_glyph = true; // Add letter spacing except before the first glyph
+        // Letter spacing goes between clusters, so ligatures and combining marks
+        // are not pulled apart, and never after the last one
+        let mut prev_cluster = None;
+
+        let precision = font_config.get_path_precision();
+        let grid = font_config.get_quantize().unwrap_or(0.0);
+        // Path data buffer reused for every glyph outlined by this call
+        let mut path_data = String::new();
+
+        let color_attribute = if *font_config.get_fill_color() == Color::None { "stroke" } else { "fill" };
+
+        // Faces missing their style fall back to Regular, so key definitions by the face actually used
+        let face_style = if font_config.get_font_by_style(font_style).is_some() {
+            font_style
+        } else {
+            &FontStyle::Regular
+        };
 
         for i in 0..glyph_num {
             let glyph_id = glyph_infos[i].glyph_id;
             let glyph_pos = glyph_positions[i];
             let glyph_id_u16 = glyph_id as u16;
-            let svg_id = format!("g{}", glyph_id_u16);
 
-            // Add letter spacing before rendering the glyph (if not the first char)
-            current_x += if !prev_space_glyph { letter_space } else { 0.0 };
-            prev_space_glyph = false; // Reset after potentially adding space
+            // Glyphs from a fallback font are outlined from its face at its own scale
+            let face_index = glyphs.face_index(i);
+            let fallback_face = match face_index {
+                0 => None,
+                _ => font_config.get_face_by_index(face_index, font_style),
+            };
+            let (face_index, glyph_face, glyph_scale) = match fallback_face {
+                Some(face) => (face_index, face.face(), font_config.pixel_scale(font_config.face_metrics(face))),
+                None => (0, hb_face, scale_factor),
+            };
+            // Fallbacks pick their faces by the requested style
+            let face_style = if face_index > 0 { font_style } else { face_style };
 
-            // --- Manage Glyph Definition ---
-            if let std::collections::hash_map::Entry::Vacant(e) = glyph_cache.entry(glyph_id_u16) {
-                let mut d_str = String::new();
-                // Build path at origin (0,0) with scaling
+            // Add letter spacing before the glyph if it starts a new cluster
+            let cluster = glyph_infos[i].cluster;
+            let starts_cluster = match prev_cluster {
+                Some(prev) => prev != cluster,
+                None => self.continues_run,
+            };
+            if starts_cluster {
+                current_x += letter_space;
+            }
+            if annotate && (starts_cluster || prev_cluster.is_none()) {
+                annotations = annotations.add(
+                    Line::new()
+                        .set("x1", format_coord(current_x, precision))
+                        .set("y1", format_coord(self.origin.y, precision))
+                        .set("x2", format_coord(current_x, precision))
+                        .set("y2", format_coord(self.origin.y + line_box_height, precision))
+                        .set("stroke", CLUSTER_MARK_COLOR)
+                        .set("stroke-width", 0.5)
+                        .set("stroke-dasharray", "2 2"),
+                );
+            }
+            cluster_glyph = if prev_cluster == Some(cluster) { cluster_glyph + 1 } else { 0 };
+            prev_cluster = Some(cluster);
+
+            // Build path at origin (0,0) with scaling, once per face/glyph/scale
+            let d_str = font_config.get_outline_cache().get_or_insert_with(face_style, face_index, glyph_id_u16, glyph_scale, precision, grid, (hint, hint_offset), || {
+                path_data.clear();
                 let mut path_builder = GlyphPathBuilder::new(
-                    scale_factor,
-                    -scale_factor, // Negative Y scale to flip vertically
+                    glyph_scale,
+                    -glyph_scale, // Negative Y scale to flip vertically
                     0.0,           // X origin for definition path
                     0.0,           // Y origin for definition path
-                    &mut d_str,
-                );
+                    precision,
+                    grid,
+                    &mut path_data,
+                )
+                .hint(hint, hint_offset);
+                // Outline the glyph to generate the path data
+                let _bbox_def = glyph_face.outline_glyph(GlyphId(glyph_id_u16), &mut path_builder);
+                Arc::from(path_data.as_str())
+            });
 
-                // Outline the glyph to generate the path data 'd_str'
-                let _bbox_def = hb_face.outline_glyph(GlyphId(glyph_id_u16), &mut path_builder);
+            // --- Manage Glyph Definition ---
+            let svg_id = match glyph_cache.entry((face_style.clone(), face_index, glyph_id_u16)) {
+                std::collections::hash_map::Entry::Occupied(e) => e.get().clone(),
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    // Glyphs without outlines may be bitmaps, as color emoji are
+                    let bitmap = d_str.is_empty().then(|| bitmap_glyph(glyph_face, GlyphId(glyph_id_u16), glyph_scale, precision)).flatten();
+                    // Quantized outlines often coincide, so glyphs with identical
+                    // path data share one definition
+                    let svg_id = if grid > 0.0 && bitmap.is_none() {
+                        outline_id(&d_str)
+                    } else if face_index > 0 {
+                        format!("g{}-{}-f{}", glyph_id_u16, face_style, face_index)
+                    } else {
+                        format!("g{}-{}{}", glyph_id_u16, face_style, font_config.variations_id_suffix())
+                    };
 
-                // Create the <path> node for <defs>
-                // No fill/stroke here; apply to <use> or parent group
-                let def_path = Path::new()
-                    .set("id", svg_id.clone())
-                    .set("d", d_str);
+                    // Create the <path> node for <defs>
+                    // No fill/stroke here; apply to <use> or parent group
+                    let def_node: Box<dyn Node> = match bitmap {
+                        Some(image) => Box::new(image.set("id", svg_id.clone())),
+                        None => Box::new(Path::new().set("id", svg_id.clone()).set("d", &*d_str)),
+                    };
 
-                // Insert the Boxed node into glyph_defs
-                glyph_defs.insert(svg_id.clone(), Box::new(def_path));
-                e.insert(svg_id.clone());
+                    // Insert the Boxed node into glyph_defs
+                    glyph_defs.entry(svg_id.clone()).or_insert(def_node);
+                    e.insert(svg_id.clone());
 
-                if font_config.get_debug() {
-                    println!("Defined glyph: id={}, svg_id={}", glyph_id_u16, svg_id);
+                    if font_config.get_debug() {
+                        crate::log_debug!("Defined glyph: id={}, svg_id={}", glyph_id_u16, svg_id);
+                    }
+                    svg_id
                 }
-            }
+            };
 
             // --- Create <use> Element ---
-            let use_x = current_x + (glyph_pos.x_offset as f32 * scale_factor);
+            // Only the placement snaps; the advances add up exactly
+            let use_x = quantize_coord(current_x + (glyph_pos.x_offset as f32 * scale_factor), font_config.get_glyph_snap().grid());
             let use_y = base_y - (glyph_pos.y_offset as f32 * scale_factor); // Adjust y based on rustybuzz offset
 
-            let use_node = Use::new()
+            let mut use_node = Use::new()
                 .set("href", format!("#{}", svg_id)) // Use href (SVG 2 standard)
-                .set("x", use_x)
-                .set("y", use_y);
+                .set("x", format_coord(quantize_coord(quantize_coord(use_x, grid), hint), precision))
+                .set("y", format_coord(quantize_coord(quantize_coord(use_y, grid), hint), precision));
+            if let Some(color) = cluster_color(&self.cluster_colors, cluster as usize).flatten() {
+                use_node = use_node.set(color_attribute, color.to_string());
+            }
+            if let Some(ids) = &self.cluster_ids {
+                let end = cluster_starts.iter().find(|start| **start > cluster).map_or(ids.len, |end| *end as usize);
+                use_node = use_node.set("id", ids.id(cluster as usize, end, cluster_glyph));
+            }
 
-            use_group = use_group.add(use_node);
+            let box_arms = self.box_cells.binary_search_by_key(&(cluster as usize), |(offset, _)| *offset).ok().map(|i| self.box_cells[i].1);
+            match box_arms {
+                Some(arms) => box_lines.push((current_x, glyph_pos.x_advance as f32 * scale_factor, arms)),
+                None => uses.push(use_node),
+            }
 
             if font_config.get_debug() {
-                println!(
+                crate::log_debug!(
                     "Used glyph: id={}, svg_id={}, use_x={}, use_y={}, x_adv={}",
                     glyph_id_u16, svg_id, use_x, use_y, glyph_pos.x_advance
                 );
@@ -213,50 +861,137 @@ impl TextBuilder {
             min_y = min_y.min(estimated_glyph_min_y);
             max_y = max_y.max(estimated_glyph_max_y);
 
+            for ((range, _), extent) in self.cluster_backgrounds.iter().zip(background_extents.iter_mut()) {
+                if range.contains(&(cluster as usize)) {
+                    let (start, end) = extent.get_or_insert((current_x, current_x));
+                    *start = start.min(current_x);
+                    *end = end.max(current_x + advance_width);
+                }
+            }
+
+            // Marks and the glyphs of a ligature's cluster aren't followed by a pair to kern
+            let ends_cluster = glyph_infos.get(i + 1).is_some_and(|next| next.cluster != cluster);
+            if annotate && ends_cluster {
+                // Kerning is what shaping added to the glyph's own advance, in units of the primary face
+                let nominal = glyph_face.glyph_hor_advance(GlyphId(glyph_id_u16)).unwrap_or(0) as f32 * glyph_scale / scale_factor;
+                let kerning = (glyph_pos.x_advance as f32 - nominal).round();
+                if kerning != 0.0 {
+                    annotations = annotations.add(kerning_marker(
+                        current_x + advance_width,
+                        base_y,
+                        kerning,
+                        target_glyph_height,
+                        precision,
+                    ));
+                }
+            }
 
             // --- Advance cursor for the next glyph ---
             current_x += advance_width;
-
-            // Check if glyph looks like whitespace (no outline, has advance)
-             if hb_face.outline_glyph(GlyphId(glyph_id_u16), &mut NullOutlineBuilder).is_none() && glyph_pos.x_advance > 0 {
-                 prev_space_glyph = true; // It's likely a space, don't add letter-spacing before next char
-             }
-
         }
 
-        // Add final letter spacing if the last char wasn't space-like
-        max_x += if !prev_space_glyph { letter_space } else { 0.0 };
-
-
         // Calculate final bounding box using i16 for consistency with Rect
         let bbox = Rect {
             x_min: min_x.floor() as i16,
             y_min: min_y.floor() as i16, // Use estimated min_y
             x_max: max_x.ceil() as i16,
             // Use line height for max_y relative to origin, adjust for scale
-            y_max: (self.origin.y + target_glyph_height).ceil() as i16,
+            y_max: (self.origin.y + line_box_height).ceil() as i16,
         };
 
 
         if font_config.get_debug() {
-            println!(
+            crate::log_debug!(
                 "TextBuilder BBox: x_min={:?} y_min={:?} x_max={:?} y_max={:?} width={:?} height={:?}",
                 bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max, bbox.width(), bbox.height()
             );
         }
 
+        // Backgrounds go first so the glyphs are drawn over them
+        let mut use_group = Group::new();
+        for ((_, color), extent) in self.cluster_backgrounds.iter().zip(background_extents) {
+            if let Some((start, end)) = extent {
+                use_group = use_group.add(
+                    Rectangle::new()
+                        .set("x", format_coord(start, precision))
+                        .set("y", format_coord(self.origin.y, precision))
+                        .set("width", format_coord(end - start, precision))
+                        .set("height", format_coord(line_box_height, precision))
+                        .set("fill", color.to_string())
+                        .set("stroke", "none"),
+                );
+            }
+        }
+        for use_node in uses {
+            use_group = use_group.add(use_node);
+        }
+        if !box_lines.is_empty() {
+            let stroke_width = target_glyph_height * BOX_STROKE_EM;
+            let mut path = box_path(&box_lines, self.origin.y, self.box_cell_height, stroke_width, precision);
+            // Filled text has no stroke color of its own to pass on to the lines
+            if *font_config.get_fill_color() != Color::None {
+                path = path.set("stroke", font_config.get_fill_color().to_string());
+            }
+            use_group = use_group.add(path);
+        }
+        if annotate {
+            use_group = use_group.add(annotations);
+        }
+
         // Apply common path attributes (stroke width etc.) to the group if needed,
         // although fill/stroke color should be applied higher up.
         use_group = use_group
             .set("stroke-width", self.path_config.stroke_width)
             .set("stroke-linecap", self.path_config.get_stroke_linecap())
             .set("stroke-linejoin", self.path_config.get_stroke_linejoin());
+        if self.path_config.get_stroke_linejoin() == "miter" && self.path_config.stroke_miterlimit != DEFAULT_MITER_LIMIT {
+            use_group = use_group.set("stroke-miterlimit", self.path_config.stroke_miterlimit);
+        }
+        if self.path_config.paint_order == PaintOrder::Stroke {
+            use_group = use_group.set("paint-order", "stroke");
+        }
+        if let Some(dash) = &self.path_config.stroke_dash {
+            let (lengths, offset) = dash.to_pixels(target_glyph_height);
+            let lengths: Vec<String> = lengths.into_iter().map(|length| format_coord(length, precision)).collect();
+            use_group = use_group.set("stroke-dasharray", lengths.join(" "));
+            if offset != 0.0 {
+                use_group = use_group.set("stroke-dashoffset", format_coord(offset, precision));
+            }
+        }
 
 
-        (use_group, bbox)
+        (use_group, bbox, current_x)
     }
 }
 
+// A tick between two glyphs at `x`, standing on the baseline and labelled below it with
+// the kerning in font units: red where the pair is pulled together, green where pushed apart
+fn kerning_marker(x: f32, baseline: f32, kerning: f32, size: f32, precision: u8) -> Group {
+    let color = if kerning < 0.0 { KERN_TIGHTER_COLOR } else { KERN_LOOSER_COLOR };
+    let label_size = (size / 4.0).max(4.0);
+    Group::new()
+        .set("class", "kerning")
+        .add(
+            Line::new()
+                .set("x1", format_coord(x, precision))
+                .set("y1", format_coord(baseline - label_size, precision))
+                .set("x2", format_coord(x, precision))
+                .set("y2", format_coord(baseline, precision))
+                .set("stroke", color)
+                .set("stroke-width", 1),
+        )
+        .add(
+            Text::new(format!("{}", kerning))
+                .set("x", format_coord(x, precision))
+                .set("y", format_coord(baseline + label_size, precision))
+                .set("font-family", "sans-serif")
+                .set("font-size", format_coord(label_size, precision))
+                .set("text-anchor", "middle")
+                .set("fill", color)
+                .set("stroke", "none"),
+        )
+}
+
 // --- GlyphPathBuilder ---
 // Used to convert ttf_parser outline commands into SVG path data string 'd'.
 pub struct GlyphPathBuilder<'a> {
@@ -264,63 +999,297 @@ pub struct GlyphPathBuilder<'a> {
     pub scale_y: f32,
     pub x_offset: f32, // Offset to apply to all points (used for positioning in <defs>)
     pub y_offset: f32, // Offset to apply to all points
+    pub precision: u8, // Decimal places kept per coordinate
+    pub grid: f32, // Coordinates snap to multiples of this; 0 keeps them as is
+    pub hint: f32, // Pixel size edges snap to; 0 keeps them as is
+    pub hint_offset: f32, // Where in a pixel edges snap to, e.g. half a pixel for its center
     pub d: &'a mut String,
+    contour: Vec<(char, Vec<(f32, f32)>)>, // Commands of the current contour, held back while hinting
 }
 
 impl<'a> GlyphPathBuilder<'a> {
-    fn new(scale_x: f32, scale_y: f32, x_offset: f32, y_offset: f32, d: &'a mut String) -> Self {
+    fn new(scale_x: f32, scale_y: f32, x_offset: f32, y_offset: f32, precision: u8, grid: f32, d: &'a mut String) -> Self {
         Self {
             scale_x,
             scale_y,
             x_offset,
             y_offset,
+            precision,
+            grid,
+            hint: 0.0,
+            hint_offset: 0.0,
             d,
+            contour: Vec::new(),
+        }
+    }
+
+    fn hint(mut self, pixel: f32, offset: f32) -> Self {
+        self.hint = pixel;
+        self.hint_offset = offset;
+        self
+    }
+
+    // Appends a path command followed by its transformed points
+    fn command(&mut self, command: char, points: &[(f32, f32)]) {
+        if self.hint > 0.0 {
+            let points = points.iter().map(|&(x, y)| (self.x_offset + x * self.scale_x, self.y_offset + y * self.scale_y)).collect();
+            self.contour.push((command, points));
+            return;
+        }
+        let points: Vec<(f32, f32)> = points.iter().map(|&(x, y)| (self.tx(x), self.ty(y))).collect();
+        self.write_command(command, &points);
+    }
+
+    fn write_command(&mut self, command: char, points: &[(f32, f32)]) {
+        self.d.push(command);
+        for &(x, y) in points {
+            self.d.push(' ');
+            write_coord(self.d, x, self.precision);
+            self.d.push(' ');
+            write_coord(self.d, y, self.precision);
+        }
+    }
+
+    // Writes the held back contour with the coordinates of its horizontal and vertical
+    // features snapped to whole pixels: a point sharing its y with the point before or
+    // after it is on a horizontal stem edge or a curve's top or bottom, and likewise for x
+    fn write_hinted_contour(&mut self) {
+        let contour = std::mem::take(&mut self.contour);
+        let points: Vec<(f32, f32)> = contour.iter().flat_map(|(_, points)| points.iter().copied()).collect();
+        let count = points.len();
+        // The nearest point before or after `i` that isn't in the same place
+        let neighbor = |i: usize, step: usize| {
+            (1..count).map(|k| points[(i + k * step) % count]).find(|&point| point != points[i])
+        };
+        let same = |a: f32, b: f32| (a - b).abs() < 1e-3;
+        let mut hinted = Vec::with_capacity(count);
+        for (i, &(x, y)) in points.iter().enumerate() {
+            let neighbors = [neighbor(i, 1), neighbor(i, count - 1)];
+            let vertical = neighbors.iter().flatten().any(|&(nx, _)| same(nx, x));
+            let horizontal = neighbors.iter().flatten().any(|&(_, ny)| same(ny, y));
+            let snap = |value: f32| quantize_coord(value - self.hint_offset, self.hint) + self.hint_offset;
+            let x = if vertical { snap(x) } else { x };
+            let y = if horizontal { snap(y) } else { y };
+            hinted.push((quantize_coord(x, self.grid), quantize_coord(y, self.grid)));
+        }
+        let mut hinted = hinted.into_iter();
+        for (command, points) in contour {
+            let points: Vec<(f32, f32)> = hinted.by_ref().take(points.len()).collect();
+            self.write_command(command, &points);
         }
     }
 
     // Helper to apply scale and offset
     #[inline]
     fn tx(&self, x: f32) -> f32 {
-        self.x_offset + x * self.scale_x
+        quantize_coord(self.x_offset + x * self.scale_x, self.grid)
     }
 
     #[inline]
     fn ty(&self, y: f32) -> f32 {
-        self.y_offset + y * self.scale_y
+        quantize_coord(self.y_offset + y * self.scale_y, self.grid)
     }
 }
 
 impl ttf_parser::OutlineBuilder for GlyphPathBuilder<'_> {
     fn move_to(&mut self, x: f32, y: f32) {
-        write!(self.d, "M {} {}", self.tx(x), self.ty(y)).unwrap();
+        self.command('M', &[(x, y)]);
     }
 
     fn line_to(&mut self, x: f32, y: f32) {
-        write!(self.d, "L {} {}", self.tx(x), self.ty(y)).unwrap();
+        self.command('L', &[(x, y)]);
     }
 
     fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
-        write!(self.d, "Q {} {} {} {}", self.tx(x1), self.ty(y1), self.tx(x), self.ty(y)).unwrap();
+        self.command('Q', &[(x1, y1), (x, y)]);
     }
 
     fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
-        write!(self.d, "C {} {} {} {} {} {}", self.tx(x1), self.ty(y1), self.tx(x2), self.ty(y2), self.tx(x), self.ty(y)).unwrap();
+        self.command('C', &[(x1, y1), (x2, y2), (x, y)]);
     }
 
     fn close(&mut self) {
-        write!(self.d, "Z ").unwrap();
+        if self.hint > 0.0 {
+            self.write_hinted_contour();
+        }
+        self.d.push_str("Z ");
     }
 }
 
-// --- NullOutlineBuilder ---
-// Used to check if a glyph has an outline without generating path data.
-struct NullOutlineBuilder;
+#[cfg(test)]
+mod test_svg {
+    use super::*;
+    use crate::test_support;
 
-impl ttf_parser::OutlineBuilder for NullOutlineBuilder {
-    fn move_to(&mut self, _x: f32, _y: f32) {}
-    fn line_to(&mut self, _x: f32, _y: f32) {}
-    fn quad_to(&mut self, _x1: f32, _y1: f32, _x: f32, _y: f32) {}
-    fn curve_to(&mut self, _x1: f32, _y1: f32, _x2: f32, _y2: f32, _x: f32, _y: f32) {}
-    fn close(&mut self) {}
-}
+    #[test]
+    fn test_format_coord_rounds_and_trims() {
+        assert_eq!(format_coord(12.345678, 2), "12.35");
+        assert_eq!(format_coord(12.5, 2), "12.5");
+        assert_eq!(format_coord(3.0, 2), "3");
+        assert_eq!(format_coord(-0.05, 2), "-0.05");
+        assert_eq!(format_coord(-0.001, 2), "0");
+        assert_eq!(format_coord(7.6, 0), "8");
+        assert_eq!(format_coord(1200.0, 3), "1200");
+    }
+
+    #[test]
+    fn test_format_coord_matches_std_formatting() {
+        for value in [0.0f32, 1.26, -17.126, 999.999, 0.0626, -1234.5] {
+            let expected: f32 = format!("{:.3}", value).parse().unwrap();
+            let actual: f32 = format_coord(value, 3).parse().unwrap();
+            assert_eq!(actual, expected, "value {}", value);
+        }
+    }
 
+    #[test]
+    fn test_path_config_stroke() {
+        let mut path_config = PathConfig { stroke_width: 6.0, paint_order: PaintOrder::Stroke, ..PathConfig::default() };
+        assert_eq!(path_config.stroke_reach(), 3.0);
+        assert_eq!(PathConfig::default().stroke_reach(), 0.0);
+        path_config.stroke_linejoin = parse_line_join("miter").unwrap();
+        path_config.stroke_miterlimit = 2.0;
+        assert_eq!(path_config.stroke_reach(), 6.0);
+        assert!(parse_line_join("square").is_err());
+    }
+
+    #[test]
+    fn test_stroke_dash() {
+        let mut dash: StrokeDash = "0.25, 4px".parse().unwrap();
+        dash.set_offset("0.5em".parse().unwrap());
+        assert_eq!(dash.to_pixels(20.0), (vec![5.0, 4.0], 10.0));
+        assert_eq!("dotted".parse::<StrokeDash>().unwrap().to_pixels(50.0).0, vec![0.0, 6.0]);
+        assert!("".parse::<StrokeDash>().is_err());
+        assert!("2 -1".parse::<StrokeDash>().is_err());
+        assert!("2 wide".parse::<StrokeDash>().is_err());
+    }
+
+    #[test]
+    fn test_size_estimate_is_close_to_output() {
+        use crate::render::{build_line_layouts_document, layout_lines, RenderConfig};
+
+        let font_config = test_support::font_config(16);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+
+        let lines: Vec<String> = (0..40).map(|i| format!("Line {} of the quick brown fox jumps over the lazy dog", i)).collect();
+        let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+        let doc = build_line_layouts_document(line_layouts, &lines, &[], &glyph_defs, &font_config, &render_config).unwrap();
+        let uses = count_glyph_uses(&doc);
+        assert!(uses >= 40 * 40);
+
+        let estimated = estimate_svg_size(uses, glyph_defs.len(), font_config.get_path_precision());
+        let actual = doc.to_string().len();
+        assert!(estimated > actual / 2 && estimated < actual * 2, "estimated {} for {} bytes", estimated, actual);
+    }
+
+    #[test]
+    fn test_svgz_round_trip() {
+        use crate::render::build_text_svg;
+        use crate::render::RenderConfig;
+
+        let font_config = test_support::font_config(20);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let text = "the quick brown fox jumps over the lazy dog\n".repeat(20);
+        let (doc, _) = build_text_svg(&text, &font_config, &render_config).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("text.svgz");
+        let format = OutputFormat::from_path(&path);
+        assert_eq!(format, OutputFormat::Svgz);
+        assert_eq!(OutputFormat::from_path(std::path::Path::new("out.svg")), OutputFormat::Svg);
+        let written = save_document(&path, &doc, 0, format, &RasterOptions::default(), None).unwrap();
+        let xml = to_xml(&doc);
+        assert_eq!(written as u64, std::fs::metadata(&path).unwrap().len());
+        assert!(written < xml.len() / 4, "{} compressed bytes for {}", written, xml.len());
+        assert_eq!(read_svg_file(&path).unwrap(), xml);
+    }
+
+    #[test]
+    fn test_quantize_snaps_coordinates_and_shares_definitions() {
+        use crate::render::{build_line_layouts_document, layout_lines, RenderConfig};
+
+        assert_eq!(quantize_coord(1.13, 0.25), 1.25);
+        assert_eq!(quantize_coord(-0.6, 0.5), -0.5);
+        assert_eq!(quantize_coord(1.13, 0.0), 1.13);
+
+        let mut font_config = test_support::font_config(16);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let lines = vec!["Quantized outlines, quantized places".to_string(), "a b  c".to_string()];
+
+        let (_, exact_defs) = layout_lines(&lines, &font_config, &render_config);
+        font_config.set_quantize(Some(0.25));
+        let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+        assert!(glyph_defs.len() <= exact_defs.len());
+
+        let doc = build_line_layouts_document(line_layouts, &lines, &[], &glyph_defs, &font_config, &render_config).unwrap().to_string();
+        let path_data = doc.split(" d=\"").skip(1).map(|rest| &rest[..rest.find('"').unwrap()]);
+        for d in path_data {
+            for coord in d.split(|c: char| c.is_ascii_alphabetic() || c == ' ').filter(|c| !c.is_empty()) {
+                let value: f32 = coord.parse().unwrap();
+                assert_eq!((value * 4.0).fract(), 0.0, "{} in {}", coord, d);
+            }
+        }
+    }
+    #[test]
+    fn test_hinting_snaps_stems_and_baselines() {
+        use crate::render::{build_line_layouts_document, layout_lines, RenderConfig};
+
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        // Filled text snaps its stems to pixel edges, and text with a 1px stroke to pixel centers
+        for (color, edge) in [(Color::None, 0.0), (Color::BLACK, 0.5)] {
+            let mut font_config = FontConfig::from_file(test_support::FONT_PATH, 13, Color::BLACK, color, false).unwrap();
+            font_config.set_hinting(Some(1.0));
+            let lines = ["HIL Tell".to_string()];
+            let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+            let doc = build_line_layouts_document(line_layouts, &lines, &[], &glyph_defs, &font_config, &render_config).unwrap().to_string();
+            // Glyphs made of straight stems only have every corner snapped
+            let path_data: Vec<&str> = doc.split(" d=\"").skip(1).map(|rest| &rest[..rest.find('"').unwrap()]).collect();
+            let snapped = path_data.iter().filter(|d| {
+                d.split(|c: char| c.is_ascii_alphabetic() || c == ' ')
+                    .filter(|c| !c.is_empty())
+                    .all(|c| c.parse::<f32>().unwrap().rem_euclid(1.0) == edge)
+            });
+            assert!(snapped.count() >= 4, "{:?}", path_data);
+            for place in doc.split(" x=\"").skip(1).chain(doc.split(" y=\"").skip(1)) {
+                let value: f32 = place[..place.find('"').unwrap()].parse().unwrap();
+                assert_eq!(value.fract(), 0.0, "{}", doc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_glyph_snap_rounds_x_positions() {
+        use crate::render::{build_line_layouts_document, layout_lines, RenderConfig};
+
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        for snap in [GlyphSnap::Half, GlyphSnap::Full] {
+            let mut font_config = test_support::font_config(13);
+            font_config.set_glyph_snap(snap);
+            let lines = ["Wavy text, ok?".to_string()];
+            let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+            let doc = build_line_layouts_document(line_layouts, &lines, &[], &glyph_defs, &font_config, &render_config).unwrap().to_string();
+            let xs: Vec<f32> = doc.split("<use ").skip(1).filter_map(|rest| {
+                let x = &rest[rest.find(" x=\"")? + 4..];
+                x[..x.find('"')?].parse().ok()
+            }).collect();
+            assert!(!xs.is_empty(), "{}", doc);
+            assert!(xs.iter().all(|x| (x / snap.grid()).fract() == 0.0), "{:?}", xs);
+        }
+    }
+
+    #[test]
+    fn test_annotate_shaping_marks_kerning_and_clusters() {
+        use crate::render::{build_line_layouts_document, layout_lines, RenderConfig};
+
+        let mut font_config = test_support::font_config(32);
+        font_config.set_annotate_shaping(true);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let lines = ["AVo office".to_string()];
+        let (line_layouts, glyph_defs) = layout_lines(&lines, &font_config, &render_config);
+        let doc = build_line_layouts_document(line_layouts, &lines, &[], &glyph_defs, &font_config, &render_config).unwrap().to_string();
+        // A and V, and V and o are kerned together, nothing else is
+        assert_eq!(doc.matches("class=\"kerning\"").count(), 2, "{}", doc);
+        assert!(doc.contains("-131\n</text>") && doc.contains("-159\n</text>"), "{}", doc);
+        // The ffi ligature is one cluster: A V o space o ffi c e
+        assert_eq!(doc.matches("stroke-dasharray=\"2 2\"").count(), 8, "{}", doc);
+    }
+}
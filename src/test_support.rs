@@ -0,0 +1,20 @@
+// fixtures shared by the unit tests: the DejaVu Sans bundled in tests/fonts,
+// so tests don't depend on the fonts installed
+use crate::color::Color;
+use crate::font::FontConfig;
+
+/// Path of the bundled DejaVu Sans.
+pub const FONT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/DejaVuSans.ttf");
+
+/// Path of the bundled DejaVu Sans Mono.
+pub const MONO_FONT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/DejaVuSansMono.ttf");
+
+/// The bundled font at `size` pixels, filled and stroked black.
+pub fn font_config(size: u32) -> FontConfig {
+    FontConfig::from_file(FONT_PATH, size, Color::BLACK, Color::BLACK, false).unwrap()
+}
+
+/// Contents of the bundled font file.
+pub fn font_data() -> Vec<u8> {
+    std::fs::read(FONT_PATH).unwrap()
+}
@@ -0,0 +1,190 @@
+// writing the text as <text> elements in the font embedded in the svg, so it
+// stays selectable, searchable and editable instead of becoming outlines
+use clap::ValueEnum;
+use rustybuzz::ttf_parser::{name_id, Face};
+use svg::node::element::{Group, Style};
+use svg::node::Blob;
+
+use crate::font::{FontConfig, FontFace, FontStyle};
+use crate::markup::{parse_markup, plain_text, Span};
+use crate::preview::base64;
+use crate::render::RenderConfig;
+use crate::subset::subset_tables;
+use crate::woff::{face_woff, woff};
+use crate::xml::escape_text;
+
+/// How the text is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TextMode {
+    /// Glyph outlines, looking the same everywhere
+    #[default]
+    Paths,
+    /// `<text>` elements in the font embedded with `@font-face`, selectable and editable
+    Text,
+}
+
+// Face the text is set in: that of the rendered style, or the regular one
+fn text_face<'a>(font_config: &'a FontConfig, render_config: &RenderConfig) -> Option<&'a FontFace> {
+    font_config
+        .get_font_by_style(render_config.get_font_style())
+        .or_else(|| font_config.get_font_by_style(&FontStyle::Regular))
+}
+
+/// Family name of `face`, which the embedded font is declared under.
+pub fn family_name(face: &Face) -> String {
+    let names = face.names();
+    let family = [name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]
+        .into_iter()
+        .find_map(|id| names.into_iter().find(|name| name.name_id == id && name.is_unicode()).and_then(|name| name.to_string()));
+    // Quotes would end the CSS string the family is given in
+    family.map_or_else(|| "text2svg".to_string(), |family| family.replace(['"', '\'', '\\'], ""))
+}
+
+// CSS font-stretch of `face`, None when it has the normal width
+fn font_stretch(face: &Face) -> Option<&'static str> {
+    const STRETCHES: [&str; 9] = [
+        "ultra-condensed", "extra-condensed", "condensed", "semi-condensed", "normal",
+        "semi-expanded", "expanded", "extra-expanded", "ultra-expanded",
+    ];
+    let stretch = STRETCHES[(face.width().to_number() as usize).clamp(1, 9) - 1];
+    (stretch != "normal").then_some(stretch)
+}
+
+/// `line` as a `<text>` element, its baseline where the glyphs would sit and
+/// its markup spans as `<tspan>`s keeping their letter spacing.
+pub fn text_line(line: &str, spans: Option<&[Span]>, font_config: &FontConfig, render_config: &RenderConfig) -> Group {
+    let Some(face) = text_face(font_config, render_config) else {
+        return Group::new();
+    };
+    let metrics = font_config.face_metrics(face);
+    let scale = font_config.pixel_scale(metrics);
+    let font_size = font_config.get_size() as f32;
+    let mut attributes = format!(
+        r#" font-family="'{}'" font-size="{}""#,
+        escape_text(&family_name(face.face())),
+        metrics.units_per_em as f32 * scale
+    );
+    if let Some(stretch) = font_stretch(face.face()) {
+        attributes.push_str(&format!(r#" font-stretch="{}""#, stretch));
+    }
+    if face.face().is_italic() {
+        attributes.push_str(r#" font-style="italic""#);
+    }
+    attributes.push_str(&format!(r#" font-weight="{}""#, face.face().weight().to_number()));
+    // The browser shapes the text, so it's given the features the glyphs were chosen with
+    if let Some(settings) = font_config.css_feature_settings() {
+        attributes.push_str(&format!(r#" style="font-feature-settings: {}""#, settings));
+    }
+    let letter_space = font_config.get_letter_space().to_pixels(font_size);
+    if letter_space != 0.0 {
+        attributes.push_str(&format!(r#" letter-spacing="{}""#, letter_space));
+    }
+    let content = match spans {
+        Some(spans) => spans
+            .iter()
+            .map(|span| match span.letter_space {
+                Some(spacing) => format!(r#"<tspan letter-spacing="{}">{}</tspan>"#, spacing.to_pixels(font_size), escape_text(&span.text)),
+                None => format!("<tspan>{}</tspan>", escape_text(&span.text)),
+            })
+            .collect(),
+        None => escape_text(line),
+    };
+    // Written out as is: the svg crate would put a newline between the spans,
+    // which preserved whitespace shows as a space
+    Group::new().add(Blob::new(format!(
+        r#"<text{} x="0" xml:space="preserve" y="{}">{}</text>"#,
+        attributes,
+        metrics.ascent * scale,
+        content
+    )))
+}
+
+/// `<style>` with the `@font-face` rule embedding the text's face as WOFF,
+/// cut down to the glyphs of `lines` when it has TrueType outlines.
+pub fn font_face_style(lines: &[String], font_config: &FontConfig, render_config: &RenderConfig) -> Option<Style> {
+    let face = text_face(font_config, render_config)?.face();
+    // Markup tags aren't written as text
+    let lines: Vec<String> = match render_config.get_markup() {
+        true => lines.iter().map(|line| parse_markup(line).map_or_else(|_| line.clone(), |spans| plain_text(&spans))).collect(),
+        false => lines.to_vec(),
+    };
+    let (font, subset) = match subset_tables(face, &lines, font_config.get_features()) {
+        Some(tables) => (woff(&tables, false), true),
+        None => (face_woff(face), false),
+    };
+    if font_config.get_verbose() {
+        crate::log_verbose!(
+            "Embedding {}: {} bytes{}",
+            family_name(face),
+            font.len(),
+            if subset { ", with only the glyphs of the text" } else { ", whole as it has no TrueType outlines" }
+        );
+    }
+    let style = if face.is_italic() { "italic" } else { "normal" };
+    Some(Style::new(format!(
+        "@font-face {{ font-family: \"{}\"; font-weight: {}; font-style: {}; font-stretch: {}; src: url(\"data:font/woff;base64,{}\") format(\"woff\"); }}",
+        family_name(face),
+        face.weight().to_number(),
+        style,
+        font_stretch(face).unwrap_or("normal"),
+        base64(&font)
+    )))
+}
+
+#[cfg(test)]
+mod test_textmode {
+    use super::*;
+    use crate::test_support;
+    use crate::render::build_text_svg;
+    use crate::xml::to_xml;
+
+    #[test]
+    fn test_text_mode() {
+        let font_config = test_support::font_config(20);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_text_mode(TextMode::Text).set_markup(true);
+        let (doc, glyph_defs) = build_text_svg("Fish &amp; <span letter-spacing=\"0.1em\">chips</span>\nsecond  line", &font_config, &render_config).unwrap();
+        let xml = to_xml(&doc);
+
+        assert!(glyph_defs.is_empty() && !xml.contains("<use"), "{}", xml);
+        assert!(xml.contains("@font-face { font-family: \"DejaVu Sans\"; font-weight: 400;"));
+        assert!(xml.contains("data:font/woff;base64,d09GR"));
+        // Only the glyphs of the text are embedded
+        assert!(xml.len() < 60_000, "{} bytes", xml.len());
+        assert!(xml.contains(r#"<text font-family="'DejaVu Sans'" font-size="20" font-weight="400" x="0" xml:space="preserve""#));
+        // Nothing comes between the spans, as it would show as a space
+        assert!(xml.contains(r#"><tspan>Fish &amp; </tspan><tspan letter-spacing="2">chips</tspan></text>"#), "{}", xml);
+        assert!(xml.contains("><tspan>second  line</tspan></text>"));
+    }
+
+    #[test]
+    fn test_font_face_subset_of_lines() {
+        let font_config = test_support::font_config(20);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_text_mode(TextMode::Text);
+        let style = |lines: &[&str], render_config: &RenderConfig| {
+            let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+            font_face_style(&lines, &font_config, render_config).unwrap().to_string()
+        };
+        assert!(style(&["ab"], &render_config).len() < style(&["ab", "xyz"], &render_config).len());
+        // Markup tags aren't part of the text
+        let plain = style(&["ab"], &render_config);
+        render_config.set_markup(true);
+        assert_eq!(style(&[r#"<span letter-spacing="1px">a</span>b"#], &render_config), plain);
+    }
+
+    #[test]
+    fn test_text_mode_keeps_features() {
+        let mut font_config = test_support::font_config(20);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_text_mode(TextMode::Text);
+        let default_xml = to_xml(&build_text_svg("Salt", &font_config, &render_config).unwrap().0);
+        assert!(!default_xml.contains("font-feature-settings"));
+
+        font_config.set_features_from_string("salt,liga=0").unwrap();
+        let xml = to_xml(&build_text_svg("Salt", &font_config, &render_config).unwrap().0);
+        assert!(xml.contains(r#" style="font-feature-settings: 'liga' 0, 'salt' 1""#), "{}", xml);
+        // The alternates are embedded along with the plain glyphs
+        assert!(xml.len() > default_xml.len());
+    }
+}
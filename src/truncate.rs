@@ -0,0 +1,108 @@
+// cutting lines short with an ellipsis, to a number of characters and to the
+// pixel width, the ellipsis counted in both
+use crate::font::{FontConfig, FontStyle};
+use crate::markup::{parse_markup, plain_text, slice_spans, to_markup, Span};
+use crate::utils::{calculate_text_width, text_columns};
+
+pub const DEFAULT_ELLIPSIS: &str = "…";
+
+/// Limits lines are cut to, and what marks a cut line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Truncation<'a> {
+    pub max_chars: Option<usize>,
+    /// Whether East Asian wide characters count as two towards `max_chars`
+    pub east_asian: bool,
+    pub max_pixel_width: Option<f32>,
+    pub ellipsis: &'a str,
+}
+
+impl Truncation<'_> {
+    /// Whether `text` is within both limits.
+    pub fn fits(&self, text: &str, font_config: &FontConfig, font_style: &FontStyle) -> bool {
+        self.max_chars.is_none_or(|max_chars| text_columns(text, self.east_asian) <= max_chars)
+            && self.max_pixel_width.is_none_or(|max_width| {
+                calculate_text_width(text, font_config, font_style).is_none_or(|width| width <= max_width)
+            })
+    }
+
+    /// `line` as it is if it fits, else cut to fit with the ellipsis.
+    /// Markup lines are cut by their text, keeping the styles of what is left.
+    pub fn truncate(&self, line: &str, markup: bool, font_config: &FontConfig, font_style: &FontStyle) -> String {
+        self.edit(line, markup, |text| match self.fits(text, font_config, font_style) {
+            true => None,
+            false => Some(self.kept(text, font_config, font_style)),
+        })
+    }
+
+    /// `line` ending with the ellipsis, cut as needed for both to fit, e.g.
+    /// the last line kept when the lines after it are dropped.
+    pub fn ellipsize(&self, line: &str, markup: bool, font_config: &FontConfig, font_style: &FontStyle) -> String {
+        self.edit(line, markup, |text| Some(self.kept(text, font_config, font_style)))
+    }
+
+    // Replaces the text of `line` past the byte length `kept` returns with the ellipsis
+    fn edit(&self, line: &str, markup: bool, kept: impl Fn(&str) -> Option<usize>) -> String {
+        let spans = match markup {
+            true => parse_markup(line).ok(),
+            false => None,
+        };
+        match spans {
+            Some(mut spans) => {
+                let Some(end) = kept(&plain_text(&spans)) else {
+                    return line.to_string();
+                };
+                spans = slice_spans(&spans, 0, end);
+                spans.push(Span::new(self.ellipsis, None));
+                to_markup(&spans)
+            }
+            None => match kept(line) {
+                Some(end) => format!("{}{}", &line[..end], self.ellipsis),
+                None => line.to_string(),
+            },
+        }
+    }
+
+    // Byte length of the longest start of `text` that fits with the ellipsis
+    // after it, without the spaces before the ellipsis
+    fn kept(&self, text: &str, font_config: &FontConfig, font_style: &FontStyle) -> usize {
+        let ends: Vec<usize> = text.char_indices().map(|(i, _)| i).skip(1).chain([text.len()]).collect();
+        let kept_end = |end: usize| text[..end].trim_end().len();
+        let fitting = ends.partition_point(|&end| {
+            self.fits(&format!("{}{}", &text[..kept_end(end)], self.ellipsis), font_config, font_style)
+        });
+        match fitting {
+            0 => 0,
+            n => kept_end(ends[n - 1]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_truncate {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_truncate_lines() {
+        let font_config = test_support::font_config(20);
+        let style = FontStyle::Regular;
+        let chars = Truncation { max_chars: Some(8), east_asian: true, max_pixel_width: None, ellipsis: "…" };
+        assert_eq!(chars.truncate("Short", false, &font_config, &style), "Short");
+        assert_eq!(chars.truncate("Longer words here", false, &font_config, &style), "Longer…");
+        assert_eq!(chars.ellipsize("Short", false, &font_config, &style), "Short…");
+        assert_eq!(
+            chars.truncate(r#"Lo<span letter-spacing="2px">nger &amp; more</span>"#, true, &font_config, &style),
+            r#"Lo<span letter-spacing="2px">nger</span>…"#
+        );
+        // Wide characters count as two columns
+        assert_eq!(chars.truncate("漢字かなカナ", false, &font_config, &style), "漢字か…");
+
+        // A wide ellipsis leaves less of the text within the pixel width
+        let width = calculate_text_width("Longer words here", &font_config, &style).unwrap() - 1.0;
+        let pixels = Truncation { max_chars: None, east_asian: true, max_pixel_width: Some(width), ellipsis: " (more)" };
+        let truncated = pixels.truncate("Longer words here", false, &font_config, &style);
+        let kept = truncated.strip_suffix(" (more)").unwrap();
+        assert!(kept.len() < "Longer words".len() && "Longer words here".starts_with(kept));
+        assert!(pixels.fits(&truncated, &font_config, &style));
+    }
+}
@@ -0,0 +1,248 @@
+// interactive tuning: the render is previewed in the terminal while keys
+// adjust the font, size, spacing and colors, then the matching options are printed
+use std::fs::File;
+use std::io::{Read, Write};
+use std::panic::PanicHookInfo;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use svg::Document;
+
+use crate::color::Color;
+use crate::preview::{rasterize, ImageProtocol};
+
+/// Colors the fill and stroke cycle through.
+pub const PALETTE: &[&str] = &["none", "#000", "#fff", "#e63946", "#f4a261", "#e9c46a", "#2a9d8f", "#264653", "#6a4c93"];
+
+/// Key bindings shown under the preview.
+pub const HELP: &str = "f/F font  +/- size  ]/[ spacing  b/B fill  c/C color  q done";
+
+// Steps of one key press
+const SIZE_STEP: u32 = 2;
+const SPACE_STEP: f32 = 0.01;
+
+// Bytes after Esc read at most, longer than any key's sequence
+const MAX_ESCAPE_SEQUENCE: usize = 16;
+
+/// The settings being tuned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuneState {
+    pub fonts: Vec<String>,
+    /// Index into `fonts`
+    pub font: usize,
+    pub size: u32,
+    /// Letter spacing in em
+    pub space: f32,
+    pub fill: Color,
+    pub color: Color,
+}
+
+/// What a key press did to the state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Changed,
+    Done,
+    Ignored,
+}
+
+impl TuneState {
+    /// Starts from `font` among the installed `fonts`, which it is added to if missing.
+    pub fn new(mut fonts: Vec<String>, font: &str, size: u32, space: f32, fill: Color, color: Color) -> Self {
+        let index = fonts.iter().position(|name| name.eq_ignore_ascii_case(font)).unwrap_or_else(|| {
+            fonts.insert(0, font.to_string());
+            0
+        });
+        TuneState { fonts, font: index, size, space, fill, color }
+    }
+
+    pub fn get_font(&self) -> &str {
+        &self.fonts[self.font]
+    }
+
+    /// Applies one key press.
+    pub fn apply(&mut self, key: u8) -> KeyAction {
+        let count = self.fonts.len();
+        match key {
+            b'f' => self.font = (self.font + 1) % count,
+            b'F' => self.font = (self.font + count - 1) % count,
+            b'+' | b'=' => self.size += SIZE_STEP,
+            b'-' | b'_' => self.size = self.size.saturating_sub(SIZE_STEP).max(SIZE_STEP),
+            b']' => self.space = round_space(self.space + SPACE_STEP),
+            b'[' => self.space = round_space(self.space - SPACE_STEP),
+            b'b' => self.fill = cycle(self.fill, 1),
+            b'B' => self.fill = cycle(self.fill, PALETTE.len() - 1),
+            b'c' => self.color = cycle(self.color, 1),
+            b'C' => self.color = cycle(self.color, PALETTE.len() - 1),
+            b'q' | b'Q' | b'\r' | b'\n' => return KeyAction::Done,
+            _ => return KeyAction::Ignored,
+        }
+        KeyAction::Changed
+    }
+
+    /// Command line options reproducing the state.
+    pub fn options(&self) -> String {
+        format!(
+            "--font {} --size {} --space {}em --fill {} --color {}",
+            quote(self.get_font()),
+            self.size,
+            self.space,
+            quote(&self.fill.to_string()),
+            quote(&self.color.to_string())
+        )
+    }
+
+    /// One line summary shown under the preview.
+    pub fn status(&self) -> String {
+        format!(
+            "{} ({}/{})  size {}  space {}em  fill {}  color {}",
+            self.get_font(),
+            self.font + 1,
+            self.fonts.len(),
+            self.size,
+            self.space,
+            self.fill,
+            self.color
+        )
+    }
+}
+
+// Keeps the spacing free of float noise, e.g. 0.03 rather than 0.030000001
+fn round_space(space: f32) -> f32 {
+    (space * 100.0).round() / 100.0
+}
+
+// The palette color `step` places after `color`, the first if it isn't in the palette
+fn cycle(color: Color, step: usize) -> Color {
+    let index = PALETTE.iter().position(|c| c.parse() == Ok(color)).map_or(0, |i| (i + step) % PALETTE.len());
+    PALETTE[index].parse().unwrap()
+}
+
+/// `value` quoted for a POSIX shell.
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+type PanicHook = Box<dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+// The terminal in unbuffered, silent input mode, restored when dropped or on a panic
+struct RawMode {
+    saved: String,
+    previous_hook: Arc<PanicHook>,
+}
+
+impl RawMode {
+    fn enter() -> Result<Self> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["-icanon", "-echo", "min", "1", "time", "0"])?;
+        // The panic message is printed to a usable terminal, even when panics abort
+        let previous_hook: Arc<PanicHook> = Arc::new(std::panic::take_hook());
+        let (hook_saved, hook_previous) = (saved.clone(), Arc::clone(&previous_hook));
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = stty(&[&hook_saved]);
+            hook_previous(info);
+        }));
+        Ok(RawMode { saved, previous_hook })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+        if !std::thread::panicking() {
+            let previous_hook = Arc::clone(&self.previous_hook);
+            std::panic::set_hook(Box::new(move |info| previous_hook(info)));
+        }
+    }
+}
+
+// Reads the rest of an escape sequence such as an arrow key. A lone Esc has
+// nothing after it, so reads give up after a tenth of a second.
+fn skip_escape_sequence(tty: &mut File) -> Result<()> {
+    stty(&["min", "0", "time", "1"])?;
+    let mut byte = [0u8; 1];
+    let mut skipped = Ok(());
+    for _ in 0..MAX_ESCAPE_SEQUENCE {
+        match tty.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                skipped = Err(e);
+                break;
+            }
+        }
+    }
+    stty(&["min", "1", "time", "0"])?;
+    Ok(skipped?)
+}
+
+fn stty(args: &[&str]) -> Result<String> {
+    let tty = File::open("/dev/tty").map_err(|e| anyhow!("No terminal to tune in: {}", e))?;
+    let output = Command::new("stty").args(args).stdin(Stdio::from(tty)).stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("stty {} failed", args.join(" ")));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Shows `render` of the state after every key press until the user is done,
+/// and returns the final state.
+pub fn run(
+    mut state: TuneState,
+    protocol: ImageProtocol,
+    mut render: impl FnMut(&TuneState) -> Result<Document>,
+) -> Result<TuneState> {
+    let mut tty = File::open("/dev/tty").map_err(|e| anyhow!("No terminal to tune in: {}", e))?;
+    let _raw = RawMode::enter()?;
+    let mut out = std::io::stdout();
+    let mut key = [0u8; 1];
+    loop {
+        let preview = render(&state).and_then(|doc| rasterize(&doc, 1.0)).map(|png| protocol.escape(&png));
+        // Clear the screen and, for kitty, the images on it
+        write!(out, "\x1b[2J\x1b[H{}", protocol.clear())?;
+        match preview {
+            Ok(image) => writeln!(out, "{}", image)?,
+            Err(e) => writeln!(out, "Error: {}", e)?,
+        }
+        writeln!(out, "\n{}\n{}", state.status(), HELP)?;
+        out.flush()?;
+
+        loop {
+            if tty.read(&mut key)? == 0 {
+                return Ok(state);
+            }
+            if key[0] == 0x1b {
+                // Skip escape sequences such as arrow keys, whose "[" would adjust the spacing
+                skip_escape_sequence(&mut tty)?;
+                continue;
+            }
+            match state.apply(key[0]) {
+                KeyAction::Changed => break,
+                KeyAction::Done => {
+                    write!(out, "\x1b[2J\x1b[H{}", protocol.clear())?;
+                    return Ok(state);
+                }
+                KeyAction::Ignored => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_tune {
+    use super::*;
+
+    #[test]
+    fn test_apply_keys() {
+        let mut state = TuneState::new(vec!["A".to_string(), "B".to_string()], "Mono", 20, 0.0, Color::None, Color::BLACK);
+        assert_eq!(state.fonts, vec!["Mono", "A", "B"]);
+        for key in b"FF++]]]bC" {
+            assert_eq!(state.apply(*key), KeyAction::Changed);
+        }
+        assert_eq!(state.apply(b'x'), KeyAction::Ignored);
+        assert_eq!(state.apply(b'q'), KeyAction::Done);
+        assert_eq!(state.get_font(), "A");
+        assert_eq!(state.options(), "--font 'A' --size 24 --space 0.03em --fill '#000000' --color 'none'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
@@ -1,37 +1,62 @@
 // some useful utility functions
-use anyhow::{Result, anyhow};
+use anyhow::Result;
+use clap::ValueEnum;
 use std::iter::Iterator;
 use std::path::Path;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use crate::font::{FontConfig, FontStyle};
-use rustybuzz::Face;
+use std::io::{BufRead, BufReader, Cursor};
+use crate::error::io_error;
+use crate::font::{FontConfig, FontStyle, TabMode};
+use crate::shape::ShapedText;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// How lines longer than the width limit are broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WrapMode {
+    /// Keep every input line whole
+    None,
+    /// Break after exactly `--width` characters, even inside words
+    Char,
+    /// Break at the last whitespace within `--width` characters
+    Word,
+    /// Break at whitespace by the measured width, within `--pixel-width`
+    Pixel,
+}
 
 // Reads file line by line, splitting lines longer than `max_chars_per_line`.
-// Tries to wrap at whitespace for ASCII text.
-pub fn open_file_by_lines_width<P: AsRef<Path>>(path: P, max_chars_per_line: usize) -> Result<Vec<String>> {
+// With `words` it tries to wrap at whitespace for ASCII text, and with
+// `east_asian` wide characters count as two.
+pub fn open_file_by_lines_width<P: AsRef<Path>>(path: P, max_chars_per_line: usize, words: bool, east_asian: bool) -> Result<Vec<String>> {
     let path = path.as_ref();
     if path.exists() && path.is_file() {
         match File::open(path) {
             Ok(file) => {
                 let reader = BufReader::new(file);
                 let width_iter = WidthLineIterator::new(reader, max_chars_per_line);
-                Ok(width_iter.collect())
+                Ok(width_iter.words(words).east_asian(east_asian).collect())
             },
-            Err(err) => Err(anyhow!("{}: {}", path.display(), err)),
+            Err(err) => Err(io_error(path, err)),
         }
     } else {
-        Err(anyhow!(
-            "{}: doesn't exist or is not a regular file", path.display()))
+        Err(io_error(path, "doesn't exist or is not a regular file"))
     }
 }
 
+// Wraps a single paragraph by character count, the same way files are wrapped.
+pub fn wrap_text_by_width(text: &str, max_chars_per_line: usize, words: bool, east_asian: bool) -> Vec<String> {
+    if text.is_empty() {
+        return vec![String::new()];
+    }
+    WidthLineIterator::new(Cursor::new(text), max_chars_per_line).words(words).east_asian(east_asian).collect()
+}
+
 // Reads file line by line, splitting lines based on pixel width.
 // Uses font metrics to determine actual text width for wrapping.
 pub fn open_file_by_lines_pixel_width<P: AsRef<Path>>(
     path: P, 
     max_pixel_width: f32,
-    font_config: &mut FontConfig,
+    font_config: &FontConfig,
     font_style: &FontStyle
 ) -> Result<Vec<String>> {
     let path = path.as_ref();
@@ -39,14 +64,14 @@ pub fn open_file_by_lines_pixel_width<P: AsRef<Path>>(
         match File::open(path) {
             Ok(file) => {
                 let reader = BufReader::new(file);
-                let pixel_width_iter = PixelWidthLineIterator::new(reader, max_pixel_width, font_config, font_style);
-                Ok(pixel_width_iter.collect())
+                let lines = reader.lines().collect::<Result<Vec<String>, _>>()
+                    .map_err(|e| io_error(path, e))?;
+                Ok(wrap_lines_by_pixel_width(&lines, max_pixel_width, font_config, font_style))
             },
-            Err(err) => Err(anyhow!("{}: {}", path.display(), err)),
+            Err(err) => Err(io_error(path, err)),
         }
     } else {
-        Err(anyhow!(
-            "{}: doesn't exist or is not a regular file", path.display()))
+        Err(io_error(path, "doesn't exist or is not a regular file"))
     }
 }
 
@@ -58,14 +83,13 @@ pub fn open_file_by_lines<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
             Ok(file) => {
                 let reader = BufReader::new(file);
                 let lines = reader.lines().collect::<Result<Vec<String>, _>>()
-                    .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+                    .map_err(|e| io_error(path, e))?;
                 Ok(lines)
             },
-            Err(err) => Err(anyhow!("{}: {}", path.display(), err)),
+            Err(err) => Err(io_error(path, err)),
         }
     } else {
-        Err(anyhow!(
-            "{}: doesn't exist or is not a regular file", path.display()))
+        Err(io_error(path, "doesn't exist or is not a regular file"))
     }
 }
 
@@ -73,11 +97,16 @@ pub fn open_file_by_lines<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
 // --- WidthLineIterator ---
 // Iterator that reads lines from a BufReader, but splits lines exceeding
 // a specified character width, attempting word wrapping for ASCII.
+// The read buffer is reused between lines; wrapped parts are byte ranges into it.
 
 struct WidthLineIterator<R: BufRead> {
     reader: R,
     max_width: usize,
-    buffer: String, // Holds leftover part of a line for the next iteration
+    words: bool, // Whether to break at whitespace rather than after exactly max_width chars
+    east_asian: bool, // Whether wide characters take two columns
+    line: String, // Current input line, reused for every read
+    offset: usize, // Byte offset of the part of `line` not returned yet
+    pending: bool, // Whether `line` still has a part to return
 }
 
 impl<R: BufRead> WidthLineIterator<R> {
@@ -85,135 +114,114 @@ impl<R: BufRead> WidthLineIterator<R> {
         WidthLineIterator {
             reader,
             max_width,
-            buffer: String::new(),
+            words: true,
+            east_asian: true,
+            line: String::new(),
+            offset: 0,
+            pending: false,
         }
     }
+
+    fn words(mut self, words: bool) -> Self {
+        self.words = words;
+        self
+    }
+
+    fn east_asian(mut self, east_asian: bool) -> Self {
+        self.east_asian = east_asian;
+        self
+    }
 }
 
 impl<R: BufRead> Iterator for WidthLineIterator<R> {
     type Item = String;
 
-        fn next(&mut self) -> Option<Self::Item> {
-        // Process buffer first if exceeding max_width
-        if self.buffer.chars().count() > self.max_width {
-            let (line_part, remaining_part) = split_line(&self.buffer, self.max_width);
-            self.buffer = remaining_part;
-            return Some(line_part);
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.pending && !read_trimmed_line(&mut self.reader, &mut self.line) {
+            return None;
         }
-
-        // If buffer has content within max_width, return it
-        if !self.buffer.is_empty() {
-            let buffer_content = std::mem::take(&mut self.buffer);
-            return Some(buffer_content);
+        if !self.pending {
+            self.offset = 0;
+            self.pending = true;
         }
 
-        // Buffer empty, read a new line
-        let mut line = String::new();
-        match self.reader.read_line(&mut line) {
-            Ok(0) => None, // EOF
-            Ok(_) => { // Successfully read a line
-                let trimmed_line = line.trim_end_matches(['\r', '\n']).to_string();
-
-                // If line exceeds max_width, split it
-                if trimmed_line.chars().count() > self.max_width {
-                    let (line_part, remaining_part) = split_line(&trimmed_line, self.max_width);
-                    self.buffer = remaining_part;
-                    Some(line_part)
-                } else {
-                    // Line fits within max_width
-                    Some(trimmed_line)
-                }
-            }
-            Err(e) => {
-                eprintln!("Error reading line: {}", e);
-                None
-            }
-        }
+        let rest = &self.line[self.offset..];
+        let (end, next) = if self.words {
+            split_line_indices(rest, self.max_width, self.east_asian)
+        } else {
+            split_line_indices_by_chars(rest, self.max_width, self.east_asian)
+        };
+        let part = rest[..end].to_string();
+        self.offset += next;
+        self.pending = self.offset < self.line.len();
+        Some(part)
     }
 }
 
-// --- PixelWidthLineIterator ---
-// Iterator that reads lines from a BufReader, but splits lines based on pixel width
-// using actual font metrics for precise text measurement.
-
-struct PixelWidthLineIterator<'a, R: BufRead> {
-    reader: R,
-    max_pixel_width: f32,
-    font_config: &'a mut FontConfig,
-    font_style: &'a FontStyle,
-    buffer: String, // Holds leftover part of a line for the next iteration
-}
-
-impl<'a, R: BufRead> PixelWidthLineIterator<'a, R> {
-    fn new(reader: R, max_pixel_width: f32, font_config: &'a mut FontConfig, font_style: &'a FontStyle) -> Self {
-        PixelWidthLineIterator {
-            reader,
-            max_pixel_width,
-            font_config,
-            font_style,
-            buffer: String::new(),
+// Reads the next line into `line` without its line ending.
+// Returns false at EOF or on a read error.
+fn read_trimmed_line<R: BufRead>(reader: &mut R, line: &mut String) -> bool {
+    line.clear();
+    match reader.read_line(line) {
+        Ok(0) => false, // EOF
+        Ok(_) => {
+            let len = line.trim_end_matches(['\r', '\n']).len();
+            line.truncate(len);
+            true
+        }
+        Err(e) => {
+            crate::log_warn!("Failed to read a line: {}", e);
+            false
         }
     }
 }
 
-impl<R: BufRead> Iterator for PixelWidthLineIterator<'_, R> {
-    type Item = String;
+/// Byte index and text of each grapheme cluster of `line` (UAX #29 extended
+/// clusters): a base with its combining marks, an Indic conjunct, a ZWJ emoji
+/// sequence or a flag.
+pub fn cluster_starts(line: &str) -> impl Iterator<Item = (usize, &str)> + '_ {
+    line.grapheme_indices(true)
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // Process buffer first if exceeding max_pixel_width
-        if let Some(text_width) = calculate_text_width(&self.buffer, self.font_config, self.font_style) {
-            if text_width > self.max_pixel_width {
-                let (line_part, remaining_part) = split_line_by_pixel_width(&self.buffer, self.max_pixel_width, self.font_config, self.font_style);
-                self.buffer = remaining_part;
-                return Some(line_part);
-            }
-        }
+// Columns of `cluster` (UAX #11 via unicode-width, at most two), or one for each
+// cluster that takes room when not counting East Asian widths
+fn cluster_columns(cluster: &str, east_asian: bool) -> usize {
+    match cluster.width() {
+        0 => 0,
+        width if east_asian => width.min(2),
+        _ => 1,
+    }
+}
 
-        // If buffer has content within max_pixel_width, return it
-        if !self.buffer.is_empty() {
-            let buffer_content = std::mem::take(&mut self.buffer);
-            return Some(buffer_content);
-        }
+/// Columns `text` takes, each cluster one or, with `east_asian`, two when it is wide.
+pub fn text_columns(text: &str, east_asian: bool) -> usize {
+    cluster_starts(text).map(|(_, cluster)| cluster_columns(cluster, east_asian)).sum()
+}
 
-        // Buffer empty, read a new line
-        let mut line = String::new();
-        match self.reader.read_line(&mut line) {
-            Ok(0) => None, // EOF
-            Ok(_) => { // Successfully read a line
-                let trimmed_line = line.trim_end_matches(['\r', '\n']).to_string();
-
-                // If line exceeds max_pixel_width, split it
-                if let Some(text_width) = calculate_text_width(&trimmed_line, self.font_config, self.font_style) {
-                    if text_width > self.max_pixel_width {
-                        let (line_part, remaining_part) = split_line_by_pixel_width(&trimmed_line, self.max_pixel_width, self.font_config, self.font_style);
-                        self.buffer = remaining_part;
-                        return Some(line_part);
-                    }
-                }
-                // Line fits within max_pixel_width
-                Some(trimmed_line)
-            }
-            Err(e) => {
-                eprintln!("Error reading line: {}", e);
-                None
-            }
+/// Byte index of the first cluster of `line` that doesn't fit in `columns`, or None
+/// when the whole line does. The first cluster always fits, so a line is never
+/// split before its first character.
+pub fn column_split_index(line: &str, columns: usize, east_asian: bool) -> Option<usize> {
+    let mut used = 0;
+    for (n, (i, cluster)) in cluster_starts(line).enumerate() {
+        let width = cluster_columns(cluster, east_asian);
+        if n > 0 && used + width > columns {
+            return Some(i);
         }
+        used += width;
     }
+    None
 }
 
-// Helper function to split a line at max_width, trying to wrap at whitespace.
-fn split_line(line: &str, max_width: usize) -> (String, String) {
-    if line.chars().count() <= max_width {
-        return (line.trim_end().to_string(), String::new());
-    }
-
-    // Find the character index corresponding to max_width
-    let split_char_index = if let Some((idx, _)) = line.char_indices().nth(max_width) {
-        idx
-    } else if line.chars().count() > max_width {
-        line.char_indices().nth(max_width).map(|(i, _)| i).unwrap_or(line.len())
-    } else {
-        0
+// Find where to split a line at max_width chars, trying to wrap at whitespace.
+// Returns the byte index where the first part ends (trailing whitespace trimmed)
+// and the byte index where the rest starts (leading whitespace skipped).
+// A line that fits is returned whole: (line.len(), line.len()).
+fn split_line_indices(line: &str, max_width: usize, east_asian: bool) -> (usize, usize) {
+    // Find the byte index of the first character past max_width
+    let split_char_index = match column_split_index(line, max_width, east_asian) {
+        Some(idx) => idx,
+        None => return (line.len(), line.len()),
     };
 
     // Look backwards from the split point for whitespace
@@ -224,53 +232,145 @@ fn split_line(line: &str, max_width: usize) -> (String, String) {
         .find(|&(_, c)| c.is_ascii_whitespace())
         .map(|(i, _)| i);
 
-    if let Some(idx) = wrap_index {
-        // Found whitespace: split before it, trim whitespace
-        let first_part = potential_split_point[..idx].trim_end().to_string();
-        let second_part = line[idx..].trim_start().to_string();
-        (first_part, second_part)
-    } else {
-        // No whitespace found: hard break at max_width chars
-        let (first_part, second_part) = line.split_at(split_char_index);
-        (first_part.to_string(), second_part.trim_start().to_string()) // Added trim_start() here
-    }
+    // Split before the whitespace if found, otherwise hard break at max_width chars
+    let split_point = wrap_index.unwrap_or(split_char_index);
+    trim_split(line, split_point)
 }
 
-// Calculate the pixel width of text using font metrics
-fn calculate_text_width(text: &str, font_config: &mut FontConfig, font_style: &FontStyle) -> Option<f32> {
-    if text.is_empty() {
-        return Some(0.0);
+// Like `split_line_indices`, but always breaks after exactly max_width chars
+fn split_line_indices_by_chars(line: &str, max_width: usize, east_asian: bool) -> (usize, usize) {
+    match column_split_index(line, max_width.max(1), east_asian) {
+        Some(split_point) => trim_split(line, split_point),
+        None => (line.len(), line.len()),
     }
+}
+
+// Split indices around `split_point`, trimming whitespace on both sides of the break
+fn trim_split(line: &str, split_point: usize) -> (usize, usize) {
+    let end = line[..split_point].trim_end().len();
+    let next = line.len() - line[split_point..].trim_start().len();
+    (end, next)
+}
 
+// Scale factor from font units to pixels and the letter spacing in pixels
+fn text_scale(font_config: &FontConfig, font_style: &FontStyle) -> Option<(f32, f32)> {
     // Get the font face for the specified style, fallback to regular
     let ft_face = font_config.get_font_by_style(font_style)
         .or_else(|| font_config.get_font_by_style(&FontStyle::Regular))?;
 
-    let font_data = ft_face.copy_font_data()?;
-    let hb_face = Face::from_slice(&font_data, 0)?;
+    // Get font metrics for scaling
+    let metrics = font_config.face_metrics(ft_face);
+    let target_size = font_config.get_size() as f32;
+    let scale_factor = font_config.pixel_scale(metrics);
+    let letter_space = font_config.get_letter_space().to_pixels(target_size);
+
+    Some((scale_factor, letter_space))
+}
+
+/// Size of a line of text in pixels, for laying it out before rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextMetrics {
+    /// Advance width, letter spacing included
+    pub width: f32,
+    /// Ascent plus descent: the height of the line without its gap
+    pub height: f32,
+    /// Distance from the baseline up to the top of the line
+    pub ascent: f32,
+    /// Distance from the baseline down to the bottom of the line, positive
+    pub descent: f32,
+    /// Extra space the font puts between lines
+    pub line_gap: f32,
+}
+
+/// Metrics of `text` as one line set in `font_style` (or the regular face).
+/// Fails when no face can be loaded or the text can't be shaped.
+pub fn measure_text(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Result<TextMetrics> {
+    let face = font_config.get_font_by_style(font_style)
+        .or_else(|| font_config.get_font_by_style(&FontStyle::Regular))
+        .ok_or_else(|| anyhow::anyhow!("Font has no {:?} or regular face", font_style))?;
+    let metrics = font_config.face_metrics(face);
+    let scale = font_config.pixel_scale(metrics);
+    let (ascent, descent) = (metrics.ascent * scale, -metrics.descent * scale);
+    let width = calculate_text_width(text, font_config, font_style)
+        .ok_or_else(|| anyhow::anyhow!("Failed to measure {:?}", text))?;
+    Ok(TextMetrics {
+        width,
+        height: ascent + descent,
+        ascent,
+        descent,
+        line_gap: metrics.line_gap * scale,
+    })
+}
+
+// Calculate the pixel width of text using font metrics
+pub fn calculate_text_width(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<f32> {
+    if text.is_empty() {
+        return Some(0.0);
+    }
+
+    if font_config.get_fast_measure() {
+        return approximate_text_width(text, font_config, font_style);
+    }
 
-    let mut buffer = rustybuzz::UnicodeBuffer::new();
-    buffer.push_str(text);
+    let (scale_factor, letter_space) = text_scale(font_config, font_style)?;
 
-    let glyph_buffer = rustybuzz::shape(&hb_face, font_config.get_features(), buffer);
+    if let Some(width) = monospace_text_width(text, font_config, font_style) {
+        return Some(width);
+    }
 
-    // Calculate total advance width
+    // Calculate total advance width, skipping shaping for plain ASCII when the face allows it.
+    // Plain ASCII the fast path accepts shapes to one cluster per char.
     let mut total_width = 0.0;
-    let glyph_positions = glyph_buffer.glyph_positions();
-    
-    // Get font metrics for scaling
-    let metrics = ft_face.metrics();
-    let target_size = font_config.get_size() as f32;
-    let origin_glyph_height = metrics.ascent - metrics.descent;
-    let scale_factor = target_size / origin_glyph_height.max(1.0);
+    let cluster_count;
+    if let Some(width) = font_config.ascii_width(text, font_style) {
+        total_width = width as f32 * scale_factor;
+        cluster_count = text.len();
+    } else {
+        let glyph_buffer = font_config.shape(text, font_style)?;
+        for glyph_pos in glyph_buffer.glyph_positions() {
+            total_width += glyph_pos.x_advance as f32 * scale_factor;
+        }
+        cluster_count = count_clusters(&glyph_buffer);
+    }
 
-    for glyph_pos in glyph_positions {
-        total_width += glyph_pos.x_advance as f32 * scale_factor;
+    // Letter spacing goes between clusters, never after the last one
+    if cluster_count > 1 {
+        total_width += letter_space * (cluster_count - 1) as f32;
     }
 
-    // Add letter spacing
-    let letter_space = scale_factor * font_config.get_letter_space() * metrics.units_per_em as f32;
-    let char_count = text.chars().count();
+    Some(total_width)
+}
+
+// Number of distinct clusters in shaped text; letter spacing is applied between them
+fn count_clusters(shaped: &ShapedText) -> usize {
+    let mut clusters: Vec<u32> = shaped.glyph_infos().iter().map(|info| info.cluster).collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+    clusters.len()
+}
+
+// Estimate the pixel width of text from nominal advances in cmap/hmtx, without shaping.
+// Kerning, ligatures and contextual forms are ignored.
+pub fn approximate_text_width(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<f32> {
+    let (scale_factor, letter_space) = text_scale(font_config, font_style)?;
+    let face = font_config.get_shaping_face_by_style(font_style)
+        .or_else(|| font_config.get_shaping_face_by_style(&FontStyle::Regular))?;
+
+    let space_advance = face.glyph_index(' ').and_then(|space| face.glyph_hor_advance(space)).unwrap_or(0) as f32;
+    let mut total_width = 0.0;
+    let mut char_count = 0;
+    for c in text.chars() {
+        if c == '\t' {
+            total_width += font_config.tab_advance(total_width / scale_factor, space_advance) * scale_factor;
+        } else if let Some(glyph_id) = face.glyph_index(c) {
+            total_width += face.glyph_hor_advance(glyph_id).unwrap_or(0) as f32 * scale_factor;
+        } else {
+            // Missing characters come from a fallback, or else render as .notdef
+            total_width += fallback_advance(c, font_config, font_style)
+                .unwrap_or_else(|| face.glyph_hor_advance(Default::default()).unwrap_or(0) as f32 * scale_factor);
+        }
+        char_count += 1;
+    }
     if char_count > 1 {
         total_width += letter_space * (char_count - 1) as f32;
     }
@@ -278,84 +378,215 @@ fn calculate_text_width(text: &str, font_config: &mut FontConfig, font_style: &F
     Some(total_width)
 }
 
-// Split a line based on pixel width, trying to wrap at whitespace
-fn split_line_by_pixel_width(
-    line: &str, 
-    max_pixel_width: f32, 
-    font_config: &mut FontConfig, 
-    font_style: &FontStyle
-) -> (String, String) {
-    if let Some(text_width) = calculate_text_width(line, font_config, font_style) {
-        if text_width <= max_pixel_width {
-            return (line.trim_end().to_string(), String::new());
+// Pixel advance of `c` in the first fallback font, or else the emoji font, that has it
+fn fallback_advance(c: char, font_config: &FontConfig, font_style: &FontStyle) -> Option<f32> {
+    (1..=font_config.get_fallbacks().len() + 1).find_map(|index| {
+        let face = font_config.get_face_by_index(index, font_style)?;
+        let advance = face.face().glyph_hor_advance(face.face().glyph_index(c)?)?;
+        Some(advance as f32 * font_config.pixel_scale(font_config.face_metrics(face)))
+    })
+}
+
+// Pixel width of one column of a monospace face and the letter spacing in pixels,
+// when `text` is printable ASCII and so laid out exactly one column per char
+fn monospace_columns(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<(f32, f32)> {
+    if !text.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+        return None;
+    }
+    let advance = font_config.monospace_advance(font_style)?;
+    let (scale_factor, letter_space) = text_scale(font_config, font_style)?;
+    Some((advance as f32 * scale_factor, letter_space))
+}
+
+// Width of printable ASCII text in a monospace face: columns × advance, no shaping
+fn monospace_text_width(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<f32> {
+    let (column_width, letter_space) = monospace_columns(text, font_config, font_style)?;
+    let columns = text.len() as f32;
+    Some(columns * column_width + letter_space * (columns - 1.0).max(0.0))
+}
+
+// Split indices for a monospace line: the number of columns that fit is known
+// up front, so the break is the last space within them, or a hard break.
+fn split_line_indices_by_columns(line: &str, columns: usize) -> (usize, usize) {
+    let columns = columns.max(1);
+    if line.len() <= columns {
+        return (line.len(), line.len());
+    }
+    // A space right after the last fitting column is a break point too
+    let wrap_index = line.as_bytes()[..=columns]
+        .iter()
+        .rposition(|&b| b == b' ')
+        .filter(|&i| i > 0);
+    trim_split(line, wrap_index.unwrap_or(columns))
+}
+
+// Shape the text once and return the pixel width of every prefix that ends on a
+// cluster boundary, as (byte index of the boundary, prefix width) in ascending order.
+// Splitting only at these boundaries never breaks a ligature or a combining sequence,
+// nor an emoji sequence or flag the font has no glyph for and shapes char by char.
+fn cluster_prefix_widths(text: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<Vec<(usize, f32)>> {
+    let (scale_factor, letter_space) = text_scale(font_config, font_style)?;
+    let glyph_buffer = font_config.shape(text, font_style)?;
+    let glyph_infos = glyph_buffer.glyph_infos();
+    let glyph_positions = glyph_buffer.glyph_positions();
+
+    // Sum advances per cluster, keyed by the byte index where the cluster starts
+    let mut clusters: Vec<(usize, f32)> = Vec::new();
+    for (info, pos) in glyph_infos.iter().zip(glyph_positions.iter()) {
+        let advance = pos.x_advance as f32 * scale_factor;
+        let start = info.cluster as usize;
+        match clusters.last_mut() {
+            Some((last_start, width)) if *last_start == start => *width += advance,
+            _ => clusters.push((start, advance)),
         }
-    } else {
-        // Fallback to character-based splitting if width calculation fails
-        return split_line(line, 50); // Arbitrary fallback
-    }
-
-    // Find the optimal split point using binary search approach
-    let chars: Vec<char> = line.chars().collect();
-    let mut best_split = 0;
-    let mut wrap_split = None;
-
-    // First pass: find the maximum characters that fit
-    for i in 1..=chars.len() {
-        let substring: String = chars[..i].iter().collect();
-        if let Some(width) = calculate_text_width(&substring, font_config, font_style) {
-            if width <= max_pixel_width {
-                best_split = i;
-                // Check if this position is at a word boundary
-                if i < chars.len() && chars[i-1].is_ascii_whitespace() {
-                    wrap_split = Some(i-1);
-                }
-            } else {
-                break;
-            }
+    }
+    clusters.sort_by_key(|&(start, _)| start);
+
+    // Character cluster starts, so the shaper's clusters never split one
+    let cluster_starts: Vec<usize> = cluster_starts(text).map(|(i, _)| i).collect();
+    let mut boundaries = Vec::with_capacity(clusters.len());
+    let mut prefix_width = 0.0;
+    for (i, &(_, advance)) in clusters.iter().enumerate() {
+        let end = clusters.get(i + 1).map(|&(next, _)| next).unwrap_or(text.len());
+        prefix_width += advance;
+        if end < text.len() && cluster_starts.binary_search(&end).is_err() {
+            continue;
         }
+        // Letter spacing goes between clusters, never after the last one
+        let width = prefix_width + letter_space * i as f32;
+        boundaries.push((end, width));
     }
 
-    // Use word boundary if we found one within reasonable distance
-    let mut split_point = if let Some(wrap_pos) = wrap_split {
-        // Only use word boundary if it's not too far from the optimal split
-        let distance = best_split.saturating_sub(wrap_pos);
-        if distance <= best_split / 4 { // Within 25% of optimal
-            wrap_pos
-        } else {
-            best_split
+    Some(boundaries)
+}
+
+// Width of a single word or whitespace run, memoized in the font config
+fn word_width(word: &str, font_config: &FontConfig, font_style: &FontStyle) -> Option<f32> {
+    let cache = font_config.get_word_width_cache();
+    if let Some(width) = cache.get(font_style, word) {
+        return Some(width);
+    }
+    let width = calculate_text_width(word, font_config, font_style)?;
+    cache.insert(font_style, word, width);
+    Some(width)
+}
+
+// Byte ranges of the words and ASCII whitespace runs of a line, in order
+fn word_ranges(line: &str) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        let rest = &line[start..];
+        let is_space = rest.starts_with(|c: char| c.is_ascii_whitespace());
+        let len = rest
+            .find(|c: char| c.is_ascii_whitespace() != is_space)
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return None;
         }
-    } else {
-        // Look backwards from best_split for whitespace
-        let mut search_pos = best_split;
-        while search_pos > 0 {
-            search_pos -= 1;
-            if chars[search_pos].is_ascii_whitespace() {
-                break;
-            }
+        let range = (start, start + len, is_space);
+        start += len;
+        Some(range)
+    })
+}
+
+// Split indices for a line wrapped word by word, using memoized word widths.
+// Returns None when the line has to be broken inside its first word.
+fn split_line_indices_by_words(
+    line: &str,
+    max_pixel_width: f32,
+    font_config: &FontConfig,
+    font_style: &FontStyle
+) -> Option<(usize, usize)> {
+    let (_, letter_space) = text_scale(font_config, font_style)?;
+    let mut width = 0.0;
+    let mut last_space = None;
+
+    for (start, end, is_space) in word_ranges(line) {
+        let word_width = word_width(&line[start..end], font_config, font_style)?;
+        // Letter spacing also goes between the last char of one word and the next
+        width += if start == 0 { word_width } else { letter_space + word_width };
+        if width > max_pixel_width {
+            let split_point = if is_space { Some(start) } else { last_space };
+            return split_point.filter(|&i| i > 0).map(|i| trim_split(line, i));
         }
-        if search_pos > 0 && chars[search_pos].is_ascii_whitespace() {
-            search_pos
-        } else {
-            best_split
+        if is_space {
+            last_space = Some(start);
         }
+    }
+
+    Some((line.len(), line.len()))
+}
+
+// Find where to split a line based on pixel width, trying to wrap at whitespace.
+// Returns split indices like `split_line_indices`; a line that fits is returned whole.
+fn split_line_indices_by_pixel_width(
+    line: &str,
+    max_pixel_width: f32,
+    font_config: &FontConfig,
+    font_style: &FontStyle
+) -> (usize, usize) {
+    if line.is_empty() {
+        return (0, 0);
+    }
+    let columns = monospace_columns(line, font_config, font_style)
+        .filter(|&(column_width, letter_space)| column_width + letter_space > 0.0);
+    if let Some((column_width, letter_space)) = columns {
+        // n columns take n × column + (n - 1) × spacing
+        let columns = ((max_pixel_width + letter_space) / (column_width + letter_space)).floor();
+        return split_line_indices_by_columns(line, columns.max(0.0) as usize);
+    }
+    // Where a tab stop falls depends on everything before it, so words can't be measured alone
+    let tab_stops = font_config.get_tab_mode() == TabMode::Stops && line.contains('\t');
+    if let Some(split) = split_line_indices_by_words(line, max_pixel_width, font_config, font_style).filter(|_| !tab_stops) {
+        return split;
+    }
+
+    // The first word alone is too wide: break it at the widest fitting cluster
+    let boundaries = match cluster_prefix_widths(line, font_config, font_style) {
+        Some(boundaries) => boundaries,
+        // Fallback to character-based splitting if width calculation fails
+        None => return split_line_indices(line, 50, true), // Arbitrary fallback
     };
 
-    if split_point == 0 {
-        // Emergency fallback: at least take one character
-        split_point = 1.min(chars.len());
+    let total_width = boundaries.last().map(|&(_, width)| width).unwrap_or(0.0);
+    if total_width <= max_pixel_width {
+        return (line.len(), line.len());
     }
 
-    let first_part: String = chars[..split_point].iter().collect();
-    let second_part: String = chars[split_point..].iter().collect();
+    // Prefix widths grow monotonically, so binary search for the longest prefix that fits
+    let fitting = boundaries.partition_point(|&(_, width)| width <= max_pixel_width);
+    let best_split = if fitting == 0 {
+        // Emergency fallback: at least take one cluster
+        boundaries[0].0
+    } else {
+        boundaries[fitting - 1].0
+    };
 
-    (first_part.trim_end().to_string(), second_part.trim_start().to_string())
+    // Prefer breaking at the last whitespace within the fitting prefix
+    let wrap_split = line[..best_split]
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| i > 0 && c.is_ascii_whitespace())
+        .map(|(i, _)| i);
+    trim_split(line, wrap_split.unwrap_or(best_split))
 }
 
 // Convenience function to wrap a single text string by pixel width
 pub fn wrap_text_by_pixel_width(
     text: &str,
     max_pixel_width: f32,
-    font_config: &mut FontConfig,
+    font_config: &FontConfig,
+    font_style: &FontStyle
+) -> Vec<String> {
+    wrap_text_by_pixel_widths(text, |_| max_pixel_width, font_config, font_style)
+}
+
+/// Like `wrap_text_by_pixel_width`, with the width of each line given by its index,
+/// e.g. to leave room for something beside the first few lines.
+pub fn wrap_text_by_pixel_widths(
+    text: &str,
+    line_width: impl Fn(usize) -> f32,
+    font_config: &FontConfig,
     font_style: &FontStyle
 ) -> Vec<String> {
     if text.is_empty() {
@@ -363,33 +594,55 @@ pub fn wrap_text_by_pixel_width(
     }
 
     let mut lines = Vec::new();
-    let mut remaining = text.to_string();
-
-    while !remaining.is_empty() {
-        if let Some(text_width) = calculate_text_width(&remaining, font_config, font_style) {
-            if text_width <= max_pixel_width {
-                lines.push(remaining);
-                break;
-            }
-        }
+    let mut offset = 0;
 
-        let (line_part, remaining_part) = split_line_by_pixel_width(&remaining, max_pixel_width, font_config, font_style);
-        if line_part.is_empty() {
+    while offset < text.len() {
+        let remaining = &text[offset..];
+        let (end, next) = split_line_indices_by_pixel_width(remaining, line_width(lines.len()), font_config, font_style);
+        if end == 0 && next == 0 {
             // Prevent infinite loop
             break;
         }
-        lines.push(line_part);
-        remaining = remaining_part;
+        lines.push(remaining[..end].to_string());
+        offset += next;
     }
 
     lines
 }
 
+// Wrap each paragraph by pixel width, in parallel with the "parallel" feature,
+// keeping the original order
+pub fn wrap_lines_by_pixel_width(
+    lines: &[String],
+    max_pixel_width: f32,
+    font_config: &FontConfig,
+    font_style: &FontStyle
+) -> Vec<String> {
+    #[cfg(feature = "parallel")]
+    let wrapped = {
+        use rayon::prelude::*;
+        lines
+            .par_iter()
+            .map(|line| wrap_text_by_pixel_width(line, max_pixel_width, font_config, font_style))
+            .collect::<Vec<Vec<String>>>()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let wrapped = lines
+        .iter()
+        .map(|line| wrap_text_by_pixel_width(line, max_pixel_width, font_config, font_style))
+        .collect::<Vec<Vec<String>>>();
+
+    wrapped.into_iter().flatten().collect()
+}
+
 
 #[cfg(test)]
 mod test_utils{
   use super::*;
+  use crate::test_support;
+  use crate::color::Color;
   use std::io::Cursor;
+  use std::collections::HashMap;
 
   #[test]
   fn test_open_file_not_found() {
@@ -397,7 +650,7 @@ mod test_utils{
             Ok(_) => panic!("Should have failed"),
             Err(e) => assert!(e.to_string().contains("doesn't exist or is not a regular file")),
         }
-         match open_file_by_lines_width("/tmp/file-does-not-exist-hopefully", 80) {
+         match open_file_by_lines_width("/tmp/file-does-not-exist-hopefully", 80, true, true) {
             Ok(_) => panic!("Should have failed"),
             Err(e) => assert!(e.to_string().contains("doesn't exist or is not a regular file")),
         }
@@ -414,35 +667,45 @@ mod test_utils{
 
    #[test]
     fn test_split_line_simple() {
-        let (l, r) = split_line("abcdefghijkl", 5);
+        let line = "abcdefghijkl";
+        let (end, next) = split_line_indices(line, 5, true);
+        let (l, r) = (&line[..end], &line[next..]);
         assert_eq!(l, "abcde");
         assert_eq!(r, "fghijkl");
     }
 
     #[test]
     fn test_split_line_with_whitespace_wrap() {
-        let (l, r) = split_line("abcde fghijkl", 8);
+        let line = "abcde fghijkl";
+        let (end, next) = split_line_indices(line, 8, true);
+        let (l, r) = (&line[..end], &line[next..]);
         assert_eq!(l, "abcde"); // Wraps before 'f' at the space
         assert_eq!(r, "fghijkl");
     }
 
      #[test]
     fn test_split_line_with_whitespace_at_end() {
-        let (l, r) = split_line("abcde ", 5); // Space is exactly at width limit
+        let line = "abcde ";
+        let (end, next) = split_line_indices(line, 5, true);
+        let (l, r) = (&line[..end], &line[next..]); // Space is exactly at width limit
         assert_eq!(l, "abcde"); // Space is trimmed
         assert_eq!(r, "");
     }
 
     #[test]
     fn test_split_line_no_whitespace() {
-        let (l, r) = split_line("abcdefghijkl", 5);
+        let line = "abcdefghijkl";
+        let (end, next) = split_line_indices(line, 5, true);
+        let (l, r) = (&line[..end], &line[next..]);
         assert_eq!(l, "abcde"); // Hard break
         assert_eq!(r, "fghijkl");
     }
 
      #[test]
     fn test_split_line_non_ascii() {
-        let (l, r) = split_line("你好世界你好世界", 3); // Split after 3 chars
+        let line = "你好世界你好世界";
+        let (end, next) = split_line_indices(line, 6, true);
+        let (l, r) = (&line[..end], &line[next..]); // Split after 3 wide chars
         assert_eq!(l, "你好世");
         assert_eq!(r, "界你好世界");
     }
@@ -458,12 +721,20 @@ mod test_utils{
         assert_eq!(lines, vec!["123", "123", "123"]);
   }
 
+  #[test]
+  fn test_width_iter_keeps_empty_lines_and_strips_crlf() {
+        let data = "abc def\r\n\r\nghijk\n";
+        let width_iter = WidthLineIterator::new(Cursor::new(data), 4);
+        let lines: Vec<String> = width_iter.collect();
+        assert_eq!(lines, vec!["abc", "def", "", "ghij", "k"]);
+  }
+
   #[test]
   fn test_width_iter_non_ascii_wrap() {
         let data = "当我发现我童年和少年时期的旧日记时，它们已经被尘埃所覆盖。";
         let cursor = Cursor::new(data);
         let reader = BufReader::new(cursor);
-        let width_iter = WidthLineIterator::new(reader, 26);
+        let width_iter = WidthLineIterator::new(reader, 26).east_asian(false);
         let lines: Vec<String> = width_iter.collect();
         // Should hard break as no ASCII whitespace involved
         assert_eq!(lines, vec!["当我发现我童年和少年时期的旧日记时，它们已经被尘埃所", "覆盖。"]);
@@ -518,32 +789,48 @@ mod test_utils{
         assert_eq!(lines, vec!["12345", "67890"]);
   }
 
-  // Helper function to create a font config with system fonts for testing
-  fn create_test_font_config() -> FontConfig {
-        use crate::font::fonts;
-        
-        // Try to get system fonts and use the first available one
-        let available_fonts = fonts();
-        let font_name = if !available_fonts.is_empty() {
-            available_fonts[0].clone()
-        } else {
-            // Fallback to common system fonts
-            #[cfg(target_os = "macos")]
-            let fallback = "Arial";
-            #[cfg(target_os = "windows")]
-            let fallback = "Arial";
-            #[cfg(target_os = "linux")]
-            let fallback = "DejaVu Sans";
-            fallback.to_string()
-        };
+   #[test]
+  fn test_wrap_text_by_chars() {
+        let text = "This is the first line";
+        assert_eq!(wrap_text_by_width(text, 10, false, true), vec!["This is th", "e first li", "ne"]);
+        assert_eq!(wrap_text_by_width(text, 10, true, true), vec!["This is", "the first", "line"]);
+  }
 
-        FontConfig::new(
-            font_name,
-            16,
-            "#000".to_string(),
-            "#000".to_string(),
-            false
-        ).expect("Failed to create font config with system font")
+   #[test]
+  fn test_wrap_keeps_clusters_whole() {
+        // e + combining acute, a ZWJ family, a flag and a zero width space are one column each or none
+        let text = "cafe\u{301} \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} \u{1F1EF}\u{1F1F5}x\u{200B}y";
+        assert_eq!(text_columns(text, false), 10);
+        assert_eq!(wrap_text_by_width("cafe\u{301}s", 4, false, true), vec!["cafe\u{301}", "s"]);
+        assert_eq!(wrap_text_by_width("a\u{200B}b\u{200B}c d", 3, false, true), vec!["a\u{200B}b\u{200B}c", "d"]);
+        assert_eq!(
+            wrap_text_by_width("\u{1F468}\u{200D}\u{1F469} \u{1F1EF}\u{1F1F5}\u{1F1EB}\u{1F1F7}", 3, true, false),
+            vec!["\u{1F468}\u{200D}\u{1F469}", "\u{1F1EF}\u{1F1F5}\u{1F1EB}\u{1F1F7}"]
+        );
+  }
+
+   #[test]
+  fn test_wrap_keeps_marks_and_conjuncts() {
+        // Hebrew points stay on their letter: shin with qamats and shin dot is one column
+        let shalom = "\u{5E9}\u{5B8}\u{5C1}\u{5DC}\u{5D5}\u{5B9}\u{5DD}";
+        assert_eq!(text_columns(shalom, false), 4);
+        assert_eq!(wrap_text_by_width(shalom, 2, false, true), vec!["\u{5E9}\u{5B8}\u{5C1}\u{5DC}", "\u{5D5}\u{5B9}\u{5DD}"]);
+        // A Devanagari conjunct (sa, virama, ta, vowel sign e) never splits
+        let namaste = "\u{928}\u{92E}\u{938}\u{94D}\u{924}\u{947}";
+        assert_eq!(text_columns(namaste, false), 3);
+        assert_eq!(wrap_text_by_width(namaste, 2, false, true), vec!["\u{928}\u{92E}", "\u{938}\u{94D}\u{924}\u{947}"]);
+        assert_eq!(wrap_text_by_width(namaste, 1, false, true), vec!["\u{928}", "\u{92E}", "\u{938}\u{94D}\u{924}\u{947}"]);
+  }
+
+  #[test]
+  fn test_wrap_east_asian_width() {
+        // Ideographs take two columns, so mixed lines come out visually even
+        let text = "漢字かなMixedテキスト";
+        assert_eq!(text_columns(text, true), 21);
+        assert_eq!(wrap_text_by_width(text, 8, false, true), vec!["漢字かな", "Mixedテ", "キスト"]);
+        assert_eq!(wrap_text_by_width(text, 8, false, false), vec!["漢字かなMixe", "dテキスト"]);
+        // A wide character never splits, even when it is wider than the line
+        assert_eq!(wrap_text_by_width("漢字", 1, false, true), vec!["漢", "字"]);
   }
 
   #[test]
@@ -551,8 +838,8 @@ mod test_utils{
         // Test empty string - this should always work
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
-        let result = wrap_text_by_pixel_width("", 100.0, &mut font_config, &FontStyle::Regular);
+        let font_config = test_support::font_config(16);
+        let result = wrap_text_by_pixel_width("", 100.0, &font_config, &FontStyle::Regular);
         assert_eq!(result, vec![""]);
   }
 
@@ -561,18 +848,31 @@ mod test_utils{
         // Test empty string width calculation
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
-        let width = calculate_text_width("", &mut font_config, &FontStyle::Regular);
+        let font_config = test_support::font_config(16);
+        let width = calculate_text_width("", &font_config, &FontStyle::Regular);
         assert_eq!(width, Some(0.0));
   }
 
+  #[test]
+  fn test_measure_text() {
+        let font_config = test_support::font_config(20);
+        let metrics = measure_text("Hello", &font_config, &FontStyle::Regular).unwrap();
+        assert_eq!(Some(metrics.width), calculate_text_width("Hello", &font_config, &FontStyle::Regular));
+        assert!(metrics.ascent > 0.0 && metrics.descent > 0.0 && metrics.line_gap >= 0.0);
+        assert_eq!(metrics.height, metrics.ascent + metrics.descent);
+        // The em square falls between the ascent and the whole line
+        assert!(metrics.ascent < 20.0 && metrics.height > 20.0, "{:?}", metrics);
+        // An empty font has nothing to measure with
+        assert!(measure_text("Hello", &FontConfig::default(), &FontStyle::Regular).is_err());
+  }
+
   #[test]
   fn test_calculate_text_width_simple() {
         // Test width calculation for simple text
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
-        let width = calculate_text_width("Hello", &mut font_config, &FontStyle::Regular);
+        let font_config = test_support::font_config(16);
+        let width = calculate_text_width("Hello", &font_config, &FontStyle::Regular);
         
         // Width should be Some positive value for non-empty text
         assert!(width.is_some());
@@ -584,15 +884,14 @@ mod test_utils{
         // Test splitting when text fits within pixel width
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
+        let font_config = test_support::font_config(16);
         let text = "Short";
         
         // Use a very large pixel width - text should not be split
-        let (first, second) = split_line_by_pixel_width(text, 10000.0, &mut font_config, &FontStyle::Regular);
+        let (end, next) = split_line_indices_by_pixel_width(text, 10000.0, &font_config, &FontStyle::Regular);
         
         // Should not split - all text in first part
-        assert_eq!(first.trim(), text);
-        assert!(second.is_empty());
+        assert_eq!((end, next), (text.len(), text.len()));
   }
 
   #[test]
@@ -600,27 +899,66 @@ mod test_utils{
         // Test splitting when text exceeds pixel width
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
+        let font_config = test_support::font_config(16);
         let text = "This is a longer text that should be split";
         
         // Use a small pixel width to force splitting
-        let (first, second) = split_line_by_pixel_width(text, 50.0, &mut font_config, &FontStyle::Regular);
+        let (end, next) = split_line_indices_by_pixel_width(text, 50.0, &font_config, &FontStyle::Regular);
         
         // Should have split the line
-        assert!(!first.is_empty());
-        
-        // Verify the split preserves the original text
-        let combined = if second.is_empty() {
-            first.trim().to_string()
-        } else {
-            format!("{} {}", first.trim(), second.trim()).trim().to_string()
-        };
+        assert!(end > 0 && next < text.len());
         
-        // Remove multiple spaces that might occur during splitting and joining
-        let normalized_combined = combined.split_whitespace().collect::<Vec<_>>().join(" ");
-        let normalized_original = text.split_whitespace().collect::<Vec<_>>().join(" ");
-        
-        assert_eq!(normalized_combined, normalized_original);
+        // Only the whitespace at the break is left out
+        assert!(text[end..next].chars().all(|c| c.is_ascii_whitespace()));
+  }
+
+  #[test]
+  fn test_pixel_split_keeps_interior_whitespace() {
+        use crate::font::FontStyle;
+
+        let font_config = test_support::font_config(16);
+        let text = "  let  x   =  1;\t// aligned    note";
+        for width in [30.0, 60.0, 90.0, 120.0, 10000.0] {
+            let lines = wrap_text_by_pixel_width(text, width, &font_config, &FontStyle::Regular);
+            // Each line is a verbatim slice of the text, with only whitespace between them
+            let mut rest = text;
+            for (i, line) in lines.iter().enumerate() {
+                let gap = rest.find(line.as_str()).unwrap();
+                assert!(rest[..gap].chars().all(|c| c.is_ascii_whitespace()));
+                assert!(i == 0 || !line.starts_with(' '), "{:?}", lines);
+                assert!(!line.ends_with(' '), "{:?}", lines);
+                rest = &rest[gap + line.len()..];
+            }
+            assert!(rest.is_empty());
+        }
+        let (end, next) = split_line_indices_by_pixel_width(text, 90.0, &font_config, &FontStyle::Regular);
+        assert!(text[..end].starts_with("  let  x   ="), "{:?}", &text[..end]);
+        assert!(text[next..].ends_with("aligned    note"), "{:?}", &text[next..]);
+  }
+
+  #[test]
+  fn test_split_astral_plane_characters() {
+        use crate::font::FontStyle;
+        use crate::length::Length;
+
+        let mut font_config = test_support::font_config(16);
+        // Letter spacing makes the shaper's cluster for a zero width space take room
+        font_config.set_letter_space(Length::Px(3.0));
+        // Emoji sequences and a flag the test font has no glyphs for, and CJK extension B
+        // ideographs around a zero width space, which the shaper gives a cluster of its own
+        let text = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{1F1EF}\u{1F1F5}\u{20000}\u{200B}\u{20001}\u{1F44B}\u{1F3FD}a\u{1D11E}";
+        let clusters: Vec<usize> = cluster_starts(text).map(|(i, _)| i).collect();
+        for width in (1..80).map(|n| n as f32 * 2.5) {
+            let (end, next) = split_line_indices_by_pixel_width(text, width, &font_config, &FontStyle::Regular);
+            assert!(end > 0);
+            assert_eq!(end, next);
+            assert!(end == text.len() || clusters.contains(&end), "{:?} at {}", &text[..end], width);
+        }
+        for columns in 1..12 {
+            let (end, next) = split_line_indices(text, columns, true);
+            assert_eq!(end, next);
+            assert!(end == text.len() || clusters.contains(&end), "{:?} at {}", &text[..end], columns);
+        }
   }
 
   #[test]
@@ -628,10 +966,10 @@ mod test_utils{
         // Test wrapping text that fits in one line
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
+        let font_config = test_support::font_config(16);
         let text = "Short text";
         
-        let result = wrap_text_by_pixel_width(text, 10000.0, &mut font_config, &FontStyle::Regular);
+        let result = wrap_text_by_pixel_width(text, 10000.0, &font_config, &FontStyle::Regular);
         
         // Should return single line
         assert_eq!(result.len(), 1);
@@ -643,10 +981,10 @@ mod test_utils{
         // Test wrapping text that needs multiple lines
         use crate::font::FontStyle;
         
-        let mut font_config = create_test_font_config();
+        let font_config = test_support::font_config(16);
         let text = "This is a very long text that should definitely be wrapped into multiple lines when using a small pixel width";
         
-        let result = wrap_text_by_pixel_width(text, 100.0, &mut font_config, &FontStyle::Regular);
+        let result = wrap_text_by_pixel_width(text, 100.0, &font_config, &FontStyle::Regular);
         
         // Should return multiple lines
         assert!(result.len() > 1);
@@ -657,6 +995,131 @@ mod test_utils{
         assert_eq!(combined, original);
   }
 
+  #[test]
+  fn test_cluster_prefix_widths_match_full_width() {
+        use crate::font::FontStyle;
+
+        let font_config = test_support::font_config(16);
+        let text = "Wrap me at a cluster";
+        let boundaries = cluster_prefix_widths(text, &font_config, &FontStyle::Regular).unwrap();
+
+        // Prefix widths must be monotonic for the binary search to be valid
+        assert!(boundaries.windows(2).all(|w| w[0].1 <= w[1].1));
+        // The last boundary covers the whole text
+        let (end, width) = *boundaries.last().unwrap();
+        assert_eq!(end, text.len());
+        let full_width = calculate_text_width(text, &font_config, &FontStyle::Regular).unwrap();
+        assert!((width - full_width).abs() < 0.01);
+  }
+
+  #[test]
+  fn test_word_widths_are_memoized_until_settings_change() {
+        use crate::font::FontStyle;
+
+        let mut font_config = test_support::font_config(16);
+        let text = "the cat and the dog and the bird";
+        wrap_text_by_pixel_width(text, 60.0, &font_config, &FontStyle::Regular);
+
+        let cache = font_config.get_word_width_cache();
+        let the_width = cache.get(&FontStyle::Regular, "the").expect("word was not memoized");
+        let measured = calculate_text_width("the", &font_config, &FontStyle::Regular).unwrap();
+        assert!((the_width - measured).abs() < 0.01);
+
+        // Letter spacing changes every width, so memoized widths are dropped
+        font_config.set_letter_space(0.1);
+        assert!(font_config.get_word_width_cache().is_empty());
+  }
+
+  #[test]
+  fn test_fast_measure_is_close_to_shaped_width() {
+        use crate::font::FontStyle;
+
+        let mut font_config = test_support::font_config(16);
+        let text = "Approximate widths skip shaping";
+        let shaped = calculate_text_width(text, &font_config, &FontStyle::Regular).unwrap();
+
+        font_config.set_fast_measure(true);
+        let approximate = calculate_text_width(text, &font_config, &FontStyle::Regular).unwrap();
+        // Only kerning and ligatures differ, a few percent at most
+        assert!((approximate - shaped).abs() / shaped < 0.05);
+  }
+
+  #[test]
+  fn test_ascii_fast_path_matches_shaping() {
+        use crate::font::FontStyle;
+
+        // Words are measured one at a time while wrapping
+        let words = ["AVATAR", "Ty.", "We'll", "wax,", "42", "(x)", "{y};", "LTAVAW"];
+        for font_path in [test_support::FONT_PATH, test_support::MONO_FONT_PATH] {
+            let font_config = FontConfig::from_file(font_path, 16, Color::BLACK, Color::BLACK, false).unwrap();
+            for word in words {
+                let Some(ascii_width) = font_config.ascii_width(word, &FontStyle::Regular) else {
+                    continue;
+                };
+                let shaped = font_config.shape(word, &FontStyle::Regular).unwrap();
+                let shaped_width: i32 = shaped.glyph_positions().iter().map(|pos| pos.x_advance).sum();
+                assert_eq!(ascii_width, shaped_width, "{} in {}", word, font_config.get_font_name());
+            }
+        }
+  }
+
+  #[test]
+  fn test_letter_spacing_only_between_clusters() {
+        use crate::font::FontStyle;
+        use crate::length::Length;
+        use crate::svg::TextBuilder;
+
+        let mut font_config = test_support::font_config(16);
+        for text in ["spaced out", "ffi ligature", "e\u{301}"] {
+            font_config.set_letter_space(Length::Px(0.0));
+            let unspaced = calculate_text_width(text, &font_config, &FontStyle::Regular).unwrap();
+            font_config.set_letter_space(Length::Px(3.0));
+            let spaced = calculate_text_width(text, &font_config, &FontStyle::Regular).unwrap();
+
+            // Rendering advances exactly as far as measuring says, with no spacing after the last cluster
+            let shaped = font_config.shape(text, &FontStyle::Regular).unwrap();
+            let (_, _, end_x) = TextBuilder::new().build_with_advance(
+                &font_config, &FontStyle::Regular, &shaped, &mut HashMap::new(), &mut HashMap::new());
+            assert!((end_x - spaced).abs() < 0.01, "{}: rendered {} measured {}", text, end_x, spaced);
+            let clusters = count_clusters(&shaped);
+            assert!((spaced - unspaced - 3.0 * (clusters - 1) as f32).abs() < 0.01, "{}", text);
+        }
+        assert_eq!("0.25".parse::<Length>(), Ok(Length::Em(0.25)));
+        assert_eq!("0.25em".parse::<Length>(), Ok(Length::Em(0.25)));
+        assert_eq!("2px".parse::<Length>(), Ok(Length::Px(2.0)));
+
+        // An em is the font size, whatever the font's units per em
+        assert_eq!(Length::Em(0.25).to_pixels(16.0), 4.0);
+        font_config.set_letter_space(Length::Em(0.25));
+        let em_spaced = calculate_text_width("abcd", &font_config, &FontStyle::Regular).unwrap();
+        font_config.set_letter_space(Length::Px(4.0));
+        assert!((em_spaced - calculate_text_width("abcd", &font_config, &FontStyle::Regular).unwrap()).abs() < 0.01);
+  }
+
+  #[test]
+  fn test_monospace_fast_path_matches_shaping() {
+        use crate::font::FontStyle;
+
+        let font_config = FontConfig::from_file(test_support::MONO_FONT_PATH, 16, Color::BLACK, Color::BLACK, false).unwrap();
+        assert!(font_config.monospace_advance(&FontStyle::Regular).is_some());
+
+        let line = "fn main() { println!(\"{}\", 42); } // a   longer comment";
+        let (scale_factor, _) = text_scale(&font_config, &FontStyle::Regular).unwrap();
+        let shaped = font_config.shape(line, &FontStyle::Regular).unwrap();
+        let shaped_width: f32 = shaped.glyph_positions().iter().map(|pos| pos.x_advance as f32 * scale_factor).sum();
+        let width = calculate_text_width(line, &font_config, &FontStyle::Regular).unwrap();
+        assert!((width - shaped_width).abs() < 0.01);
+
+        // Ten columns fit; the break falls on the space right after them
+        let column = width / line.len() as f32;
+        assert_eq!(split_line_indices_by_columns("0123456789 abc", 10), (10, 11));
+        let (end, next) = split_line_indices_by_pixel_width(line, column * 20.5, &font_config, &FontStyle::Regular);
+        assert_eq!(&line[..end], "fn main() {");
+        assert_eq!(&line[next..next + 7], "println");
+
+        assert!(test_support::font_config(16).monospace_advance(&FontStyle::Regular).is_none());
+  }
+
   // Test the basic functionality without requiring actual fonts
   #[test]
   fn test_pixel_width_api_exists() {
@@ -0,0 +1,178 @@
+// checks of command line values before anything is rendered, with messages
+// naming the accepted range or the closest valid value
+use crate::font::parse_feature_list;
+use crate::length::Length;
+
+/// Largest font size in pixels: glyph bounds are kept in 16 bit coordinates,
+/// which a few lines of larger text would overflow.
+pub const MAX_FONT_SIZE: u32 = 2048;
+
+/// Letter spacing range in em; below -1em the letters run backwards.
+pub const SPACING_RANGE: (f32, f32) = (-1.0, 10.0);
+
+/// Parses a font size in whole pixels from 1 to `MAX_FONT_SIZE`.
+pub fn parse_font_size(s: &str) -> Result<u32, String> {
+    let size: f64 = s
+        .trim()
+        .strip_suffix("px")
+        .unwrap_or(s.trim())
+        .parse()
+        .map_err(|_| format!("'{}' is not a size: expected a whole number of pixels, e.g. 64", s))?;
+    if size.fract() != 0.0 {
+        return Err(format!("expected a whole number of pixels, the nearest is {}", size.round()));
+    }
+    if size < 1.0 {
+        return Err("the size must be at least 1 pixel".to_string());
+    }
+    if size > MAX_FONT_SIZE as f64 {
+        return Err(format!("the largest size is {} pixels", MAX_FONT_SIZE));
+    }
+    Ok(size as u32)
+}
+
+/// Parses a length in pixels of zero or more, e.g. a stroke width.
+pub fn parse_non_negative(s: &str) -> Result<f32, String> {
+    match parse_number(s)? {
+        value if value < 0.0 => Err(format!("{} is negative; the smallest value is 0", value)),
+        value => Ok(value),
+    }
+}
+
+/// Parses a number above zero, e.g. a duration or a scale.
+pub fn parse_positive(s: &str) -> Result<f32, String> {
+    match parse_number(s)? {
+        value if value <= 0.0 => Err(format!("{} is not above 0", value)),
+        value => Ok(value),
+    }
+}
+
+/// Parses an opacity from 0 to 1.
+pub fn parse_opacity(s: &str) -> Result<f32, String> {
+    match parse_number(s)? {
+        value if !(0.0..=1.0).contains(&value) => Err(format!("expected 0 to 1, the nearest is {}", value.clamp(0.0, 1.0))),
+        value => Ok(value),
+    }
+}
+
+// A finite number, optionally in "px"
+fn parse_number(s: &str) -> Result<f32, String> {
+    s.trim()
+        .strip_suffix("px")
+        .unwrap_or(s.trim())
+        .parse::<f32>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("'{}' is not a number", s))
+}
+
+/// Parses a line height above zero: a multiple of the font size such as 1.4
+/// or 1.2em, or pixels such as 24px.
+pub fn parse_line_height(s: &str) -> Result<Length, String> {
+    match parse_length(s)? {
+        length if length.value() <= 0.0 => Err(format!("the line height {} is not above 0", length)),
+        length => Ok(length),
+    }
+}
+
+/// Parses a paragraph spacing of zero or more: a multiple of the font size
+/// such as 0.5 or 0.5em, or pixels such as 12px.
+pub fn parse_paragraph_spacing(s: &str) -> Result<Length, String> {
+    match parse_length(s)? {
+        length if length.value() < 0.0 => Err(format!("{} is negative; the smallest spacing is 0", length)),
+        length => Ok(length),
+    }
+}
+
+// A length relative to the font size, bare numbers being multiples of it
+fn parse_length(s: &str) -> Result<Length, String> {
+    s.parse::<Length>()
+        .map_err(|_| format!("'{}' is not a length: expected e.g. 1.4, 1.2em or 24px", s))
+}
+
+/// Checks a feature list such as "liga=0,ss01" (or "help"), keeping it as given.
+pub fn parse_features(s: &str) -> Result<String, String> {
+    if s != "help" {
+        parse_feature_list(s)?;
+    }
+    Ok(s.to_string())
+}
+
+/// Checks `space` against `SPACING_RANGE` at `font_size` pixels.
+pub fn validate_spacing(space: Length, font_size: u32) -> Result<(), String> {
+    let em = space.to_em(font_size as f32);
+    let (min, max) = SPACING_RANGE;
+    if (min..=max).contains(&em) {
+        return Ok(());
+    }
+    let nearest = em.clamp(min, max);
+    let nearest = match space {
+        Length::Em(_) => Length::Em(nearest),
+        Length::Px(_) => Length::Px((nearest * font_size as f32).round()),
+    };
+    Err(format!(
+        "Invalid value '{}' for '--space': spacing must be from {}em to {}em of the font size, the nearest is {}",
+        space, min, max, nearest
+    ))
+}
+
+/// The candidate closest to `value` by edit distance, if any is close enough to be a likely typo.
+pub fn closest<'a>(value: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let value = value.to_ascii_lowercase();
+    let limit = (value.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&value, &candidate.to_ascii_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance, counting a swap of neighbours as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            let mut distance = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test_validate {
+    use super::*;
+
+    #[test]
+    fn test_validate_values() {
+        assert_eq!(parse_font_size("48px"), Ok(48));
+        assert!(parse_font_size("0").unwrap_err().contains("at least 1"));
+        assert!(parse_font_size("100000").unwrap_err().contains("2048"));
+        assert!(parse_font_size("12.5").unwrap_err().contains("13"));
+        assert!(parse_non_negative("-3").is_err());
+        assert_eq!(parse_opacity("1.5").unwrap_err(), "expected 0 to 1, the nearest is 1");
+        assert!(parse_features("liga=0, ss01").is_ok());
+        assert!(parse_features("ligaa").unwrap_err().contains("did you mean 'liga'"));
+        assert_eq!(parse_line_height("1.4"), Ok(Length::Em(1.4)));
+        assert_eq!(parse_line_height("24px"), Ok(Length::Px(24.0)));
+        assert!(parse_line_height("0em").is_err());
+        assert!(parse_paragraph_spacing("-2px").unwrap_err().contains("negative"));
+        assert!(parse_paragraph_spacing("tall").is_err());
+        assert!(validate_spacing(Length::Px(-3.0), 16).is_ok());
+        assert!(validate_spacing(Length::Px(-30.0), 16).unwrap_err().ends_with("the nearest is -16px"));
+
+        assert_eq!(closest("tomatoe", ["tomato", "teal"]), Some("tomato"));
+        assert_eq!(closest("lgia", ["liga", "kern"]), Some("liga"));
+        assert_eq!(closest("xyz", ["liga", "kern"]), None);
+    }
+}
@@ -0,0 +1,136 @@
+// variable font axis animation: the text is outlined at interpolated axis
+// values and the keyframes are shown one after another with SMIL
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use rustybuzz::ttf_parser::Tag;
+use rustybuzz::Variation;
+use svg::node::element::{Animate, Group};
+
+use crate::a11y::label_document;
+use crate::font::FontConfig;
+use crate::output::OutputConfig;
+use crate::render::{build_text_document, layout_lines, save_svg, stack_line_layouts, RenderConfig};
+use crate::svg::GlyphDefs;
+
+/// An axis pulsing between two values, e.g. weight from 100 to 900 and back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisAnimation {
+    pub tag: Tag,
+    pub from: f32,
+    pub to: f32,
+}
+
+impl std::str::FromStr for AxisAnimation {
+    type Err = String;
+
+    /// Parses "axis=from:to", e.g. "wght=100:900".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid axis animation '{}': expected \"axis=from:to\", e.g. \"wght=100:900\"", s);
+        let (axis, range) = s.split_once('=').ok_or_else(invalid)?;
+        let (from, to) = range.split_once(':').ok_or_else(invalid)?;
+        let axis = axis.trim();
+        if axis.len() != 4 || !axis.is_ascii() {
+            return Err(invalid());
+        }
+        let parse = |value: &str| value.trim().parse::<f32>().ok().filter(|value| value.is_finite());
+        Ok(AxisAnimation {
+            tag: Tag::from_bytes_lossy(axis.as_bytes()),
+            from: parse(from).ok_or_else(invalid)?,
+            to: parse(to).ok_or_else(invalid)?,
+        })
+    }
+}
+
+impl AxisAnimation {
+    /// Axis values of `keyframes` evenly spaced keyframes going from `from`
+    /// to `to` and back, so the animation loops smoothly.
+    pub fn keyframe_values(&self, keyframes: usize) -> Vec<f32> {
+        (0..keyframes)
+            .map(|i| {
+                let t = 1.0 - (1.0 - 2.0 * i as f32 / keyframes as f32).abs();
+                self.from + (self.to - self.from) * t
+            })
+            .collect()
+    }
+}
+
+/// Animation showing keyframe `index` of `keyframes` for its share of `duration` seconds, looping.
+pub fn keyframe_visibility(index: usize, keyframes: usize, duration: f32) -> Animate {
+    let values: Vec<&str> = (0..keyframes)
+        .map(|i| if i == index { "visible" } else { "hidden" })
+        .collect();
+    let key_times: Vec<String> = (0..keyframes)
+        .map(|i| format!("{}", i as f32 / keyframes as f32))
+        .collect();
+    Animate::new()
+        .set("attributeName", "visibility")
+        .set("values", values.join(";"))
+        .set("keyTimes", key_times.join(";"))
+        .set("calcMode", "discrete")
+        .set("dur", format!("{}s", duration))
+        .set("repeatCount", "indefinite")
+}
+
+/// Renders `lines`, paragraphs starting at `paragraph_starts`, once per keyframe of the axis animation and saves them
+/// as a looping animation. Renderers without SMIL show the first keyframe.
+#[allow(clippy::too_many_arguments)]
+pub fn render_axis_animation(
+    lines: &[String],
+    paragraph_starts: &[usize],
+    font_config: &mut FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    animation: &AxisAnimation,
+    keyframes: usize,
+    duration: f32,
+    output: &Path,
+) -> Result<()> {
+    if !font_config.has_variation_axis(animation.tag) {
+        return Err(anyhow!("Font '{}' has no '{}' variation axis", font_config.get_font_name(), animation.tag));
+    }
+
+    let mut glyph_defs: GlyphDefs = HashMap::new();
+    let mut content = Group::new();
+    let (mut width, mut height) = (0, 0);
+    for (i, value) in animation.keyframe_values(keyframes).into_iter().enumerate() {
+        font_config.set_variations(vec![Variation { tag: animation.tag, value }]);
+        let (line_layouts, defs) = layout_lines(lines, font_config, render_config);
+        // Glyph ids carry the axis values, so keyframes never share outlines by accident
+        glyph_defs.extend(defs);
+        let (keyframe, keyframe_width, keyframe_height) = stack_line_layouts(line_layouts, paragraph_starts, font_config, render_config);
+        width = width.max(keyframe_width);
+        height = height.max(keyframe_height);
+        content = content.add(
+            keyframe
+                .set("visibility", if i == 0 { "visible" } else { "hidden" })
+                .add(keyframe_visibility(i, keyframes, duration)),
+        );
+    }
+    font_config.set_variations(Vec::new());
+
+    let doc = build_text_document(content, lines, &glyph_defs, (0.0, 0.0, width as f32, height as f32), font_config, render_config)?;
+    let doc = label_document(doc, lines, render_config.get_markup(), render_config.get_accessibility());
+    save_svg(output, &doc, &glyph_defs, font_config, output_config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_variation {
+    use super::*;
+
+    #[test]
+    fn test_axis_keyframes() {
+        let animation: AxisAnimation = "wght=100:900".parse().unwrap();
+        assert_eq!(animation.tag, Tag::from_bytes(b"wght"));
+        assert_eq!(animation.keyframe_values(4), vec![100.0, 500.0, 900.0, 500.0]);
+        assert!("wght=100".parse::<AxisAnimation>().is_err());
+        assert!("weight=1:2".parse::<AxisAnimation>().is_err());
+
+        let animate = keyframe_visibility(1, 4, 2.0).to_string();
+        assert!(animate.contains(r#"values="hidden;visible;hidden;hidden""#));
+        assert!(animate.contains(r#"keyTimes="0;0.25;0.5;0.75""#));
+        assert!(animate.contains(r#"dur="2s""#));
+    }
+}
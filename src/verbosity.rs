@@ -0,0 +1,131 @@
+// how much the command line tool reports, and where: messages go to stdout
+// unless the SVG itself is written there (pipe mode), then to stderr
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+static LEVEL: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static PIPE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Output path that writes the SVG to stdout.
+pub const STDOUT_PATH: &str = "-";
+
+/// Log levels, from `--quiet` to `-vv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only
+    Quiet = 0,
+    /// Progress messages and warnings
+    Normal = 1,
+    /// Output statistics such as the size of the written file
+    Verbose = 2,
+    /// Font selection, caches and shaping details
+    Debug = 3,
+}
+
+impl Verbosity {
+    /// Level of `-v` given `count` times, or of `--quiet`.
+    pub fn from_flags(count: u8, quiet: bool) -> Self {
+        match (quiet, count) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        }
+    }
+
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Verbosity::Quiet,
+            1 => Verbosity::Normal,
+            2 => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+}
+
+/// Sets the level for the whole process.
+pub fn set_verbosity(level: Verbosity) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    Verbosity::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Whether messages of `level` are shown.
+pub fn enabled(level: Verbosity) -> bool {
+    level != Verbosity::Quiet && verbosity() >= level
+}
+
+/// In pipe mode stdout carries only the SVG, and messages go to stderr.
+pub fn set_pipe_mode(pipe: bool) {
+    PIPE_MODE.store(pipe, Ordering::Relaxed);
+}
+
+pub fn pipe_mode() -> bool {
+    PIPE_MODE.load(Ordering::Relaxed)
+}
+
+/// Whether `path` asks for the SVG on stdout.
+pub fn is_stdout(path: &std::path::Path) -> bool {
+    path.as_os_str() == STDOUT_PATH
+}
+
+/// Prints a message of `level`, if shown; used by the `log_*` macros.
+pub fn message(level: Verbosity, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    if pipe_mode() {
+        eprintln!("{}", args);
+    } else {
+        println!("{}", args);
+    }
+}
+
+/// Prints a warning on stderr unless quiet.
+pub fn warning(args: fmt::Arguments) {
+    if enabled(Verbosity::Normal) {
+        eprintln!("Warning: {}", args);
+    }
+}
+
+/// Progress message, hidden by `--quiet`.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::verbosity::message($crate::verbosity::Verbosity::Normal, format_args!($($arg)*)) };
+}
+
+/// Statistics shown with `-v`.
+#[macro_export]
+macro_rules! log_verbose {
+    ($($arg:tt)*) => { $crate::verbosity::message($crate::verbosity::Verbosity::Verbose, format_args!($($arg)*)) };
+}
+
+/// Details shown with `-vv` or `--debug`.
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::verbosity::message($crate::verbosity::Verbosity::Debug, format_args!($($arg)*)) };
+}
+
+/// Warning on stderr, hidden by `--quiet`.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::verbosity::warning(format_args!($($arg)*)) };
+}
+
+#[cfg(test)]
+mod test_verbosity {
+    use super::*;
+
+    #[test]
+    fn test_levels_from_flags() {
+        assert_eq!(Verbosity::from_flags(0, false), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(1, false), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(2, false), Verbosity::Debug);
+        assert_eq!(Verbosity::from_flags(5, false), Verbosity::Debug);
+        assert_eq!(Verbosity::from_flags(2, true), Verbosity::Quiet);
+        assert!(is_stdout(std::path::Path::new("-")));
+        assert!(!is_stdout(std::path::Path::new("out.svg")));
+    }
+}
@@ -0,0 +1,287 @@
+// WordArt style warps: after layout, every glyph is outlined in place and its
+// points are moved by a nonlinear function of where they sit in the text block
+use clap::ValueEnum;
+use svg::node::element::{Element, Group};
+use svg::Node;
+
+use crate::svg::{write_coord, GlyphDefs};
+
+/// Shape the text is bent into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WarpKind {
+    /// Bowed upwards in the middle, like a bridge
+    Arch,
+    /// One smooth wave from left to right
+    Wave,
+    /// A waving flag: the wave grows along the text and the letters swell with it
+    Flag,
+}
+
+/// Strongest warp either way: beyond it the letters of a flag turn inside out.
+pub const MAX_WARP_AMOUNT: f32 = 2.0;
+
+/// A warp with its strength, as a fraction of the text block's height.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Warp {
+    pub kind: WarpKind,
+    pub amount: f32,
+}
+
+impl Warp {
+    pub fn new(kind: WarpKind, amount: f32) -> Self {
+        Self { kind, amount }
+    }
+
+    /// Where the point (x, y) of a block covering `domain` (x, y, width, height) moves to.
+    pub fn apply(&self, point: (f32, f32), domain: (f32, f32, f32, f32)) -> (f32, f32) {
+        let (x, y) = point;
+        let (x0, y0, width, height) = domain;
+        let u = if width > 0.0 { ((x - x0) / width).clamp(0.0, 1.0) } else { 0.0 };
+        let lift = self.amount * height;
+        let y = match self.kind {
+            WarpKind::Arch => y - lift * (std::f32::consts::PI * u).sin(),
+            WarpKind::Wave => y - lift * 0.5 * (std::f32::consts::TAU * u).sin(),
+            WarpKind::Flag => {
+                let wave = (std::f32::consts::TAU * 1.5 * u).sin() * u;
+                let middle = y0 + height / 2.0;
+                // Letters stretch where the flag bulges towards the viewer
+                let swell = 1.0 + self.amount * 0.5 * (std::f32::consts::TAU * 1.5 * u).cos() * u;
+                middle + (y - middle) * swell - lift * 0.5 * wave
+            }
+        };
+        (x, y)
+    }
+
+    /// How far the warped text can reach above and below its block of `height`.
+    pub fn padding(&self, height: f32) -> (f32, f32) {
+        let lift = self.amount.abs() * height;
+        match self.kind {
+            WarpKind::Arch if self.amount >= 0.0 => (lift, 0.0),
+            WarpKind::Arch => (0.0, lift),
+            WarpKind::Wave => (lift * 0.5, lift * 0.5),
+            WarpKind::Flag => (lift * 0.75, lift * 0.75),
+        }
+    }
+}
+
+// Straight segments are split into this many pieces so they can bend
+const LINE_PIECES: usize = 8;
+// Curves are halved this many times before their control points are moved
+const CURVE_SPLITS: usize = 2;
+
+fn lerp(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+// Points of a bezier curve, its start included
+type Curve = Vec<(f32, f32)>;
+
+// Splits a bezier curve at t = 0.5
+fn split_curve(points: &[(f32, f32)]) -> (Curve, Curve) {
+    let mut left = vec![points[0]];
+    let mut right = vec![points[points.len() - 1]];
+    let mut level = points.to_vec();
+    while level.len() > 1 {
+        level = level.windows(2).map(|pair| lerp(pair[0], pair[1], 0.5)).collect();
+        left.push(level[0]);
+        right.push(level[level.len() - 1]);
+    }
+    right.reverse();
+    (left, right)
+}
+
+// Point mapping glyph outlines are bent by
+type PointMap<'a> = &'a dyn Fn((f32, f32)) -> (f32, f32);
+
+// Moves glyph path data (as written by GlyphPathBuilder) by `offset`, then maps its points
+fn warp_path_data(d: &str, offset: (f32, f32), map: PointMap, precision: u8) -> String {
+    let mut out = String::with_capacity(d.len() * 2);
+    let push = |out: &mut String, command: char, points: &[(f32, f32)]| {
+        out.push(command);
+        for &point in points {
+            let (x, y) = map(point);
+            out.push(' ');
+            write_coord(out, x, precision);
+            out.push(' ');
+            write_coord(out, y, precision);
+        }
+    };
+
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    let mut command = ' ';
+    let mut numbers: Vec<f32> = Vec::new();
+    // A trailing sentinel flushes the last command
+    for token in d.split_inclusive(|c: char| c.is_ascii_alphabetic()).chain(std::iter::once("E")) {
+        let (arguments, next) = match token.chars().last() {
+            Some(c) if c.is_ascii_alphabetic() => (&token[..token.len() - 1], c),
+            _ => (token, ' '),
+        };
+        numbers.extend(arguments.split_whitespace().filter_map(|n| n.parse::<f32>().ok()));
+        if next == ' ' {
+            continue;
+        }
+
+        let points: Vec<(f32, f32)> = numbers
+            .chunks_exact(2)
+            .map(|pair| (pair[0] + offset.0, pair[1] + offset.1))
+            .collect();
+        match (command, points.as_slice()) {
+            ('M', [to]) => {
+                push(&mut out, 'M', &[*to]);
+                current = *to;
+                start = *to;
+            }
+            ('L', [to]) => {
+                let pieces: Vec<(f32, f32)> = (1..=LINE_PIECES)
+                    .map(|i| lerp(current, *to, i as f32 / LINE_PIECES as f32))
+                    .collect();
+                for piece in pieces {
+                    push(&mut out, 'L', &[piece]);
+                }
+                current = *to;
+            }
+            ('Q', [_, to]) | ('C', [_, _, to]) => {
+                let mut curves: Vec<Curve> = vec![std::iter::once(current).chain(points.iter().copied()).collect()];
+                for _ in 0..CURVE_SPLITS {
+                    curves = curves
+                        .iter()
+                        .flat_map(|curve| {
+                            let (left, right) = split_curve(curve);
+                            [left, right]
+                        })
+                        .collect();
+                }
+                for curve in curves {
+                    push(&mut out, command, &curve[1..]);
+                }
+                current = *to;
+            }
+            ('Z', []) => {
+                out.push_str("Z ");
+                current = start;
+            }
+            _ => {}
+        }
+        command = next;
+        numbers.clear();
+    }
+    out
+}
+
+// "translate(x, y)" or "translate(x y)"
+fn parse_translate(transform: &str) -> Option<(f32, f32)> {
+    let arguments = transform.trim().strip_prefix("translate(")?.strip_suffix(')')?;
+    let mut numbers = arguments.split([',', ' ']).filter(|n| !n.is_empty()).map(|n| n.parse::<f32>().ok());
+    let x = numbers.next()??;
+    let y = numbers.next().unwrap_or(Some(0.0))?;
+    Some((x, y))
+}
+
+fn attribute(node: &dyn Node, name: &str) -> Option<String> {
+    node.get_attributes()?.get(name).map(|value| value.to_string())
+}
+
+fn warp_node(
+    node: &dyn Node,
+    offset: (f32, f32),
+    glyph_defs: &GlyphDefs,
+    map: PointMap,
+    precision: u8,
+) -> Option<Box<dyn Node>> {
+    match node.get_name() {
+        "use" => {
+            let id = attribute(node, "href")?;
+            let d = attribute(glyph_defs.get(id.trim_start_matches('#'))?.as_ref(), "d")?;
+            let x: f32 = attribute(node, "x").and_then(|x| x.parse().ok()).unwrap_or(0.0);
+            let y: f32 = attribute(node, "y").and_then(|y| y.parse().ok()).unwrap_or(0.0);
+            let mut path = Element::new("path");
+            for (name, value) in node.get_attributes()?.iter() {
+                if !matches!(name.as_str(), "href" | "x" | "y") {
+                    path.assign(name.as_str(), value.clone());
+                }
+            }
+            path.assign("d", warp_path_data(&d, (offset.0 + x, offset.1 + y), map, precision));
+            Some(Box::new(path))
+        }
+        "g" => {
+            let mut group = Element::new("g");
+            let mut offset = offset;
+            for (name, value) in node.get_attributes()?.iter() {
+                match parse_translate(value).filter(|_| name == "transform") {
+                    // Positions are baked into the warped outlines
+                    Some((dx, dy)) => offset = (offset.0 + dx, offset.1 + dy),
+                    None => group.assign(name.as_str(), value.clone()),
+                }
+            }
+            for child in node.get_children()?.iter() {
+                if let Some(child) = warp_node(child.as_ref(), offset, glyph_defs, map, precision) {
+                    group.append(child);
+                }
+            }
+            Some(Box::new(group))
+        }
+        _ => Some(node.clone()),
+    }
+}
+
+/// Replaces every glyph `<use>` of the laid out text by its own outline, warped
+/// over the block `domain` (x, y, width, height). The result no longer
+/// references `glyph_defs`.
+pub fn warp_text(content: &Group, glyph_defs: &GlyphDefs, warp: &Warp, domain: (f32, f32, f32, f32), precision: u8) -> Group {
+    map_text(content, glyph_defs, &|point| warp.apply(point, domain), precision)
+}
+
+/// Replaces every glyph `<use>` of the laid out text by its own outline, with
+/// each point moved by `map`. The result no longer references `glyph_defs`.
+pub fn map_text(content: &Group, glyph_defs: &GlyphDefs, map: PointMap, precision: u8) -> Group {
+    let mut warped = Group::new();
+    for (name, value) in Node::get_attributes(content).into_iter().flatten() {
+        warped = warped.set(name.as_str(), value.clone());
+    }
+    for child in Node::get_children(content).into_iter().flatten() {
+        if let Some(child) = warp_node(child.as_ref(), (0.0, 0.0), glyph_defs, map, precision) {
+            warped = warped.add(child);
+        }
+    }
+    warped
+}
+
+#[cfg(test)]
+mod test_warp {
+    use super::*;
+    use std::collections::HashMap;
+    use svg::node::element::{Path, Use};
+
+    #[test]
+    fn test_warp_moves_points() {
+        let domain = (0.0, 0.0, 100.0, 20.0);
+        let arch = Warp::new(WarpKind::Arch, 0.5);
+        assert_eq!(arch.apply((0.0, 10.0), domain), (0.0, 10.0));
+        assert_eq!(arch.apply((50.0, 10.0), domain), (50.0, 0.0));
+        assert_eq!(arch.padding(20.0), (10.0, 0.0));
+
+        let wave = Warp::new(WarpKind::Wave, 0.5);
+        let (_, y) = wave.apply((75.0, 10.0), domain);
+        assert!((y - 15.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_warp_text_outlines_each_glyph() {
+        let mut glyph_defs: GlyphDefs = HashMap::new();
+        glyph_defs.insert("g1".to_string(), Box::new(Path::new().set("id", "g1").set("d", "M 0 0L 10 0L 10 -10Z ")));
+        let content = Group::new().add(
+            Group::new()
+                .set("transform", "translate(0, 5)")
+                .add(Use::new().set("href", "#g1").set("x", 40).set("y", 5).set("fill", "#f00")),
+        );
+
+        let warped = warp_text(&content, &glyph_defs, &Warp::new(WarpKind::Arch, 0.0), (0.0, 0.0, 100.0, 20.0), 2).to_string();
+        assert!(!warped.contains("<use"));
+        assert!(!warped.contains("translate"));
+        assert!(warped.contains(r##"fill="#f00""##));
+        // With no bending, the outline is only moved into place, its lines split up
+        assert!(warped.contains("d=\"M 40 10L 41.25 10"));
+        assert!(warped.contains("L 50 0Z"));
+    }
+}
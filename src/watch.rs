@@ -0,0 +1,211 @@
+// watch mode: re-render a file whenever it changes, reusing the layout of
+// paragraphs that did not change since the previous run
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+
+use crate::a11y::label_document;
+use crate::error::io_error;
+use crate::font::FontConfig;
+use crate::output::OutputConfig;
+use crate::overflow::Overflow;
+use crate::render::{build_line_layouts_document, ellipsize_line, layout_lines, save_svg, wrap_paragraph, LineLayout, RenderConfig};
+use crate::svg::GlyphDefs;
+
+/// How often the watched file is checked for modifications.
+pub const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Wrapped and laid out lines of one input paragraph, plus the glyph definitions they use
+struct ParagraphLayout {
+    text: Vec<String>,
+    lines: Vec<LineLayout>,
+    glyph_defs: GlyphDefs,
+}
+
+/// Counters describing how much of the previous layout a render could reuse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IncrementalStats {
+    pub reused: usize,
+    pub laid_out: usize,
+}
+
+/// Renders text paragraph by paragraph, keeping each paragraph's layout
+/// around so unchanged paragraphs are not wrapped, shaped or outlined again.
+#[derive(Default)]
+pub struct IncrementalRenderer {
+    paragraphs: HashMap<String, Arc<ParagraphLayout>>,
+}
+
+impl IncrementalRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn render(
+        &mut self,
+        text: &str,
+        font_config: &FontConfig,
+        render_config: &RenderConfig,
+        output_config: &OutputConfig,
+        output: PathBuf,
+    ) -> Result<IncrementalStats> {
+        // Layouts don't depend on the direction, so it is resolved again on every render
+        let render_config = &*render_config.with_direction_of(text.lines());
+        let mut stats = IncrementalStats::default();
+        let mut paragraphs = HashMap::new();
+        let mut lines = Vec::new();
+        let mut line_layouts = Vec::new();
+        let mut paragraph_starts = Vec::new();
+        let mut glyph_defs: GlyphDefs = HashMap::new();
+
+        for paragraph in text.lines() {
+            let layout = match self.paragraphs.get(paragraph).or_else(|| paragraphs.get(paragraph)) {
+                Some(layout) => {
+                    stats.reused += 1;
+                    Arc::clone(layout)
+                }
+                None => {
+                    stats.laid_out += 1;
+                    Arc::new(layout_paragraph(paragraph, font_config, render_config))
+                }
+            };
+
+            paragraph_starts.push(line_layouts.len());
+            lines.extend(layout.text.iter().cloned());
+            line_layouts.extend(layout.lines.iter().cloned());
+            for (id, node) in layout.glyph_defs.iter() {
+                glyph_defs.entry(id.clone()).or_insert_with(|| node.clone());
+            }
+            paragraphs.insert(paragraph.to_string(), layout);
+        }
+
+        // The last line kept ends with the ellipsis; it is laid out again as it is
+        // only cut when lines after it are
+        let max_lines = render_config.get_max_lines().unwrap_or(usize::MAX);
+        if lines.len() > max_lines && max_lines > 0 && render_config.get_overflow() == Overflow::Ellipsis {
+            lines[max_lines - 1] = ellipsize_line(&lines[max_lines - 1], font_config, render_config);
+            let (mut last_layout, last_defs) = layout_lines(&lines[max_lines - 1..max_lines], font_config, render_config);
+            line_layouts[max_lines - 1] = last_layout.remove(0);
+            for (id, node) in last_defs {
+                glyph_defs.entry(id).or_insert(node);
+            }
+        }
+
+        // Only keep paragraphs that are still part of the document
+        self.paragraphs = paragraphs;
+
+        let doc = build_line_layouts_document(line_layouts, &lines, &paragraph_starts, &glyph_defs, font_config, render_config)?;
+        let paragraphs: Vec<String> = text.lines().map(str::to_string).collect();
+        let doc = label_document(doc, &paragraphs, render_config.get_markup(), render_config.get_accessibility());
+        save_svg(&output, &doc, &glyph_defs, font_config, output_config).map_err(|e| io_error(&output, e))?;
+        Ok(stats)
+    }
+}
+
+fn layout_paragraph(paragraph: &str, font_config: &FontConfig, render_config: &RenderConfig) -> ParagraphLayout {
+    let text = wrap_paragraph(paragraph, font_config, render_config);
+    let (lines, glyph_defs) = layout_lines(&text, font_config, render_config);
+    ParagraphLayout { text, lines, glyph_defs }
+}
+
+fn modified_time(path: &Path) -> Result<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| io_error(path, e))
+}
+
+/// Calls `render` once, then again every time `file` is modified.
+/// Runs until the process is interrupted or `render` fails.
+pub fn watch_file<F>(file: &Path, mut render: F) -> Result<()>
+where
+    F: FnMut() -> Result<()>,
+{
+    let mut last_modified = None;
+
+    loop {
+        let modified = modified_time(file)?;
+        if last_modified != Some(modified) {
+            last_modified = Some(modified);
+            render()?;
+        }
+        std::thread::sleep(WATCH_POLL_INTERVAL);
+    }
+}
+
+/// Watches a plain text file, re-laying out only the paragraphs that changed.
+pub fn watch_text_file(
+    file: &Path,
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    output: PathBuf,
+) -> Result<()> {
+    let mut renderer = IncrementalRenderer::new();
+
+    watch_file(file, || {
+        let started = Instant::now();
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| io_error(file, e))?;
+        let stats = renderer.render(&text, font_config, render_config, output_config, output.clone())?;
+        crate::log_info!(
+            "Rendered {} in {:.1}ms ({} paragraphs reused, {} laid out)",
+            output.display(),
+            started.elapsed().as_secs_f64() * 1000.0,
+            stats.reused,
+            stats.laid_out
+        );
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test_watch {
+    use super::*;
+    use crate::font::FontStyle;
+    use crate::test_support;
+
+    #[test]
+    fn test_incremental_render_reuses_unchanged_paragraphs() {
+        let font_config = test_support::font_config(16);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("watch.svg");
+        let mut renderer = IncrementalRenderer::new();
+
+        let stats = renderer.render("first\nsecond\nthird", &font_config, &render_config, &OutputConfig::new(), output.clone()).unwrap();
+        assert_eq!(stats, IncrementalStats { reused: 0, laid_out: 3 });
+
+        // Only the edited paragraph is laid out again
+        let stats = renderer.render("first\nsecond edited\nthird", &font_config, &render_config, &OutputConfig::new(), output.clone()).unwrap();
+        assert_eq!(stats, IncrementalStats { reused: 2, laid_out: 1 });
+
+        // Right to left text starts on the right, as in a normal render
+        renderer.render("שלום\nשלום עולם גדול", &font_config, &render_config, &OutputConfig::new(), output.clone()).unwrap();
+        let xml = std::fs::read_to_string(&output).unwrap();
+        assert!(!xml.contains("translate(0, 0)"), "{}", xml);
+        // Labelled like a normal render
+        assert!(xml.contains(r#"aria-label="שלום שלום עולם גדול""#) && xml.contains(r#"role="img""#), "{}", xml);
+        assert!(xml.contains("<title>שלום שלום עולם גדול</title>"), "{}", xml);
+    }
+
+    #[test]
+    fn test_incremental_render_limits_lines() {
+        let font_config = test_support::font_config(16);
+        let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+        render_config.set_max_lines(Some(2)).set_overflow(Overflow::Ellipsis);
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("watch.svg");
+        let text = "ab\ncd\nef";
+
+        // The same glyphs as a normal render: two lines, the last one ending with the ellipsis
+        IncrementalRenderer::new().render(text, &font_config, &render_config, &OutputConfig::new(), output.clone()).unwrap();
+        let watched = std::fs::read_to_string(&output).unwrap();
+        let (document, _) = crate::render::build_text_svg(text, &font_config, &render_config).unwrap();
+        let rendered = crate::xml::to_xml(&document);
+        assert_eq!(watched.matches("<use").count(), rendered.matches("<use").count());
+        assert_eq!(watched.matches("<use").count(), 5);
+    }
+}
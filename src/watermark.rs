@@ -0,0 +1,155 @@
+// `watermark` subcommand: the rendered text tiled diagonally over a canvas,
+// ready to overlay on images or pages
+use std::path::Path;
+
+use anyhow::Result;
+use svg::node::element::{Definitions, Pattern, Rectangle};
+use svg::Document;
+
+use crate::font::FontConfig;
+use crate::output::OutputConfig;
+use crate::render::{apply_effects, effects_view_box, layout_lines, save_svg, stack_line_layouts, style_text_group, RenderConfig};
+use crate::svg::GlyphDefs;
+
+/// Id of the pattern holding one watermark tile.
+pub const WATERMARK_TILE_ID: &str = "watermark-tile";
+
+/// Canvas size in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Canvas {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl std::str::FromStr for Canvas {
+    type Err = String;
+
+    /// Parses "WIDTHxHEIGHT", e.g. "1200x800".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid canvas size '{}': expected WIDTHxHEIGHT, e.g. 1200x800", s);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let parse = |value: &str| value.trim().parse::<f32>().ok().filter(|value| value.is_finite() && *value > 0.0);
+        Ok(Canvas {
+            width: parse(width).ok_or_else(invalid)?,
+            height: parse(height).ok_or_else(invalid)?,
+        })
+    }
+}
+
+/// How the text is tiled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatermarkConfig {
+    pub canvas: Canvas,
+    // degrees, clockwise
+    pub rotation: f32,
+    pub opacity: f32,
+    // pixels between neighbouring copies
+    pub gap: f32,
+}
+
+impl WatermarkConfig {
+    pub fn new(canvas: Canvas) -> Self {
+        Self {
+            canvas,
+            rotation: -30.0,
+            opacity: 0.15,
+            gap: 40.0,
+        }
+    }
+}
+
+/// Document covering the canvas with rotated copies of the laid out lines.
+/// Every other row is shifted by half a tile, like bricks.
+pub fn build_watermark_document(
+    lines: &[String],
+    paragraph_starts: &[usize],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    watermark: &WatermarkConfig,
+) -> (Document, GlyphDefs) {
+    let (line_layouts, glyph_defs) = layout_lines(lines, font_config, render_config);
+    let (content, width, height) = stack_line_layouts(line_layouts, paragraph_starts, font_config, render_config);
+
+    let mut defs = Definitions::new();
+    for node in glyph_defs.values() {
+        defs = defs.add(node.clone());
+    }
+    let text = style_text_group(content, font_config, render_config);
+    let text_box = effects_view_box((0.0, 0.0, width as f32, height as f32), render_config);
+    let (text, defs) = apply_effects(text, defs, text_box, render_config);
+
+    let (x, y, text_width, text_height) = text_box;
+    let tile_width = text_width + watermark.gap;
+    let row_height = text_height + watermark.gap;
+    let copy = |dx: f32, dy: f32| {
+        svg::node::element::Group::new()
+            .set("transform", format!("translate({} {})", dx - x, dy - y))
+            .add(text.clone())
+    };
+    let pattern = Pattern::new()
+        .set("id", WATERMARK_TILE_ID)
+        .set("patternUnits", "userSpaceOnUse")
+        .set("width", tile_width)
+        .set("height", row_height * 2.0)
+        .set("patternTransform", format!("rotate({})", watermark.rotation))
+        .add(copy(0.0, 0.0))
+        // The shifted row straddles the tile's edges, so it is drawn from both sides
+        .add(copy(tile_width / 2.0, row_height))
+        .add(copy(-tile_width / 2.0, row_height));
+
+    let canvas = watermark.canvas;
+    let doc = Document::new()
+        .set("width", canvas.width)
+        .set("height", canvas.height)
+        .set("viewBox", format!("0 0 {} {}", canvas.width, canvas.height))
+        .add(defs.add(pattern))
+        .add(
+            Rectangle::new()
+                .set("width", canvas.width)
+                .set("height", canvas.height)
+                .set("fill", format!("url(#{})", WATERMARK_TILE_ID))
+                .set("opacity", watermark.opacity),
+        );
+    (doc, glyph_defs)
+}
+
+/// Renders `lines`, paragraphs starting at `paragraph_starts`, as a watermark and saves it to `output`.
+pub fn render_watermark(
+    lines: &[String],
+    paragraph_starts: &[usize],
+    font_config: &FontConfig,
+    render_config: &RenderConfig,
+    output_config: &OutputConfig,
+    watermark: &WatermarkConfig,
+    output: &Path,
+) -> Result<()> {
+    let (doc, glyph_defs) = build_watermark_document(lines, paragraph_starts, font_config, render_config, watermark);
+    save_svg(output, &doc, &glyph_defs, font_config, output_config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_watermark {
+    use super::*;
+    use crate::test_support;
+    use crate::font::FontStyle;
+
+    #[test]
+    fn test_watermark_tiles_canvas() {
+        assert_eq!("1200x800".parse::<Canvas>(), Ok(Canvas { width: 1200.0, height: 800.0 }));
+        assert!("1200".parse::<Canvas>().is_err());
+        assert!("0x800".parse::<Canvas>().is_err());
+
+        let font_config = test_support::font_config(16);
+        let render_config = RenderConfig::new(false, FontStyle::Regular);
+        let mut watermark = WatermarkConfig::new(Canvas { width: 640.0, height: 480.0 });
+        watermark.rotation = 45.0;
+
+        let (doc, _) = build_watermark_document(&["DRAFT".to_string()], &[0], &font_config, &render_config, &watermark);
+        let doc = doc.to_string();
+        assert!(doc.contains(r#"viewBox="0 0 640 480""#));
+        assert!(doc.contains(r#"patternTransform="rotate(45)""#));
+        assert!(doc.contains(r#"opacity="0.15""#));
+        assert_eq!(doc.matches("<use").count(), 5 * 3);
+    }
+}
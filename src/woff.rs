@@ -0,0 +1,145 @@
+// packing a font face into a WOFF file, the web font container browsers read
+// from an @font-face rule, with each table zlib-compressed
+use std::borrow::Cow;
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rustybuzz::ttf_parser::{Face, Tag};
+
+const WOFF_HEADER_SIZE: usize = 44;
+const WOFF_TABLE_ENTRY_SIZE: usize = 20;
+const SFNT_HEADER_SIZE: usize = 12;
+const SFNT_TABLE_ENTRY_SIZE: usize = 16;
+
+/// One table of a face: its tag, checksum and data.
+#[derive(Debug, Clone)]
+pub struct FontTable<'a> {
+    pub tag: Tag,
+    pub checksum: u32,
+    pub data: Cow<'a, [u8]>,
+}
+
+/// The tables of `face`, in the order of its table directory.
+pub fn face_tables<'a>(face: &Face<'a>) -> Vec<FontTable<'a>> {
+    let raw = face.raw_face();
+    raw.table_records
+        .into_iter()
+        .filter_map(|record| {
+            let start = record.offset as usize;
+            let data = raw.data.get(start..start.checked_add(record.length as usize)?)?;
+            Some(FontTable { tag: record.tag, checksum: record.check_sum, data: Cow::Borrowed(data) })
+        })
+        .collect()
+}
+
+/// Checksum of table data as the sfnt directory records it.
+pub fn table_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    // Writing to a Vec can't fail
+    encoder.write_all(data).and_then(|_| encoder.finish()).unwrap_or_default()
+}
+
+/// A WOFF 1.0 file holding `tables`: tables that shrink are stored compressed.
+/// `cff` tells a CFF-flavored face from a TrueType one.
+pub fn woff(tables: &[FontTable], cff: bool) -> Vec<u8> {
+    let mut tables: Vec<&FontTable> = tables.iter().collect();
+    // The directory has to be sorted by tag
+    tables.sort_by_key(|table| table.tag);
+    let stored: Vec<Cow<[u8]>> = tables
+        .iter()
+        .map(|table| {
+            let compressed = zlib(&table.data);
+            match compressed.len() < table.data.len() {
+                true => Cow::Owned(compressed),
+                false => Cow::Borrowed(table.data.as_ref()),
+            }
+        })
+        .collect();
+
+    let directory_end = WOFF_HEADER_SIZE + WOFF_TABLE_ENTRY_SIZE * tables.len();
+    let total_size = directory_end + stored.iter().map(|data| padded(data.len())).sum::<usize>();
+    let sfnt_size = SFNT_HEADER_SIZE
+        + SFNT_TABLE_ENTRY_SIZE * tables.len()
+        + tables.iter().map(|table| padded(table.data.len())).sum::<usize>();
+
+    let mut out = Vec::with_capacity(total_size);
+    out.extend_from_slice(b"wOFF");
+    out.extend_from_slice(if cff { b"OTTO" } else { &[0, 1, 0, 0] });
+    out.extend_from_slice(&(total_size as u32).to_be_bytes());
+    out.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(&(sfnt_size as u32).to_be_bytes());
+    // Version 1.0, then no metadata or private data
+    out.extend_from_slice(&[0, 1, 0, 0]);
+    out.extend_from_slice(&[0; 20]);
+
+    let mut offset = directory_end;
+    for (table, data) in tables.iter().zip(&stored) {
+        out.extend_from_slice(&table.tag.to_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&(table.data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&table.checksum.to_be_bytes());
+        offset += padded(data.len());
+    }
+    for data in &stored {
+        out.extend_from_slice(data);
+        out.resize(padded(out.len()), 0);
+    }
+    out
+}
+
+/// `face` as a WOFF file.
+pub fn face_woff(face: &Face) -> Vec<u8> {
+    woff(&face_tables(face), face.tables().cff.is_some())
+}
+
+#[cfg(test)]
+mod test_woff {
+    use super::*;
+    use crate::test_support;
+    use std::io::Read;
+
+    #[test]
+    fn test_woff_tables_round_trip() {
+        let data = test_support::font_data();
+        let face = Face::parse(&data, 0).unwrap();
+        let tables = face_tables(&face);
+        let woff = face_woff(&face);
+        assert_eq!(&woff[..4], b"wOFF");
+        assert_eq!(u32::from_be_bytes(woff[8..12].try_into().unwrap()) as usize, woff.len());
+        assert!(woff.len() < data.len());
+
+        // Every table comes back from its directory entry
+        let count = u16::from_be_bytes(woff[12..14].try_into().unwrap()) as usize;
+        assert_eq!(count, tables.len());
+        for entry in woff[WOFF_HEADER_SIZE..WOFF_HEADER_SIZE + count * WOFF_TABLE_ENTRY_SIZE].chunks(WOFF_TABLE_ENTRY_SIZE) {
+            let field = |i: usize| u32::from_be_bytes(entry[i..i + 4].try_into().unwrap()) as usize;
+            let tag = Tag::from_bytes(entry[..4].try_into().unwrap());
+            let table = tables.iter().find(|table| table.tag == tag).unwrap();
+            let stored = &woff[field(4)..field(4) + field(8)];
+            let data = match field(8) < field(12) {
+                true => {
+                    let mut data = Vec::new();
+                    flate2::read::ZlibDecoder::new(stored).read_to_end(&mut data).unwrap();
+                    data
+                }
+                false => stored.to_vec(),
+            };
+            assert_eq!(data, table.data.as_ref(), "{}", tag);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// word color maps: callers color words programmatically, e.g. attention
+// weights over a sentence, by word or by the index of the word in the text
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::color::{split_colors, Rgba};
+
+/// Colors for words, looked up by the word itself or by its index among the
+/// whitespace separated words of the whole text, counting from 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WordColors {
+    /// Matched against words with surrounding punctuation removed
+    pub words: HashMap<String, Rgba>,
+    /// Indices win over words
+    pub tokens: HashMap<usize, Rgba>,
+}
+
+impl WordColors {
+    /// Loads a map from a `.json` file, or a CSV file otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let is_json = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let parsed = if is_json { Self::from_json(&content) } else { Self::from_csv(&content) };
+        parsed.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parses an object of words or indices to colors, e.g. `{"fox": "#f00", "3": "#0f0"}`,
+    /// or an array with a color (or null) for every word in turn.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let color = |value: &Value| match value {
+            Value::String(color) => color.parse::<Rgba>().map(Some),
+            Value::Null => Ok(None),
+            _ => Err(format!("Invalid color {}: expected a string", value)),
+        };
+        let mut word_colors = WordColors::default();
+        match serde_json::from_str::<Value>(json).map_err(|e| format!("Invalid JSON: {}", e))? {
+            Value::Object(entries) => {
+                for (key, value) in &entries {
+                    if let Some(color) = color(value)? {
+                        word_colors.insert(key, color)?;
+                    }
+                }
+            }
+            Value::Array(colors) => {
+                for (index, value) in colors.iter().enumerate() {
+                    if let Some(color) = color(value)? {
+                        word_colors.tokens.insert(index, color);
+                    }
+                }
+            }
+            _ => return Err("Expected an object of words to colors or an array of colors".to_string()),
+        }
+        Ok(word_colors)
+    }
+
+    /// Parses "word,color" rows, where the word may also be an index. Empty
+    /// rows and a "word,color" header are skipped.
+    pub fn from_csv(csv: &str) -> Result<Self, String> {
+        let mut word_colors = WordColors::default();
+        for (number, row) in csv.lines().enumerate() {
+            let row = row.trim();
+            if row.is_empty() || (number == 0 && row.eq_ignore_ascii_case("word,color")) {
+                continue;
+            }
+            // Words may contain commas, colors only inside rgb() or hsl()
+            let color = *split_colors(row, ',').last().unwrap();
+            let key = row
+                .strip_suffix(color)
+                .and_then(|key| key.strip_suffix(','))
+                .ok_or_else(|| format!("Invalid row {} '{}': expected \"word,color\"", number + 1, row))?;
+            let key = key.trim().trim_matches('"');
+            word_colors.insert(key, color.trim().parse()?)?;
+        }
+        Ok(word_colors)
+    }
+
+    fn insert(&mut self, key: &str, color: Rgba) -> Result<(), String> {
+        if key.is_empty() {
+            return Err("Empty word in word color map".to_string());
+        }
+        match key.parse::<usize>() {
+            Ok(index) => self.tokens.insert(index, color),
+            Err(_) => self.words.insert(key.to_string(), color),
+        };
+        Ok(())
+    }
+
+    pub fn has_tokens(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Colored byte ranges of `line`, whose first word is word `first_token` of the text.
+    /// Indexed words are colored whole, looked up words without their punctuation.
+    pub fn line_colors(&self, line: &str, first_token: usize) -> Vec<(Range<usize>, Rgba)> {
+        tokens(line)
+            .enumerate()
+            .filter_map(|(i, range)| {
+                if let Some(color) = self.tokens.get(&(first_token + i)) {
+                    return Some((range, *color));
+                }
+                let token = &line[range.clone()];
+                let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+                match self.words.get(word) {
+                    Some(color) if !word.is_empty() => {
+                        let start = range.start + token.find(word).unwrap_or(0);
+                        Some((start..start + word.len(), *color))
+                    }
+                    _ => self.words.get(token).map(|color| (range, *color)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Byte ranges of the whitespace separated words of `line`.
+pub fn tokens(line: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    line.split_whitespace().map(move |token| {
+        let start = token.as_ptr() as usize - line.as_ptr() as usize;
+        start..start + token.len()
+    })
+}
+
+#[cfg(test)]
+mod test_wordcolor {
+    use super::*;
+
+    #[test]
+    fn test_parse_word_colors() {
+        let red = Rgba(255, 0, 0, 255);
+        let green = Rgba(0, 255, 0, 255);
+        let json = WordColors::from_json(r##"{"fox": "#f00", "3": "#0f0", "dog": null}"##).unwrap();
+        assert_eq!(json.words, HashMap::from([("fox".to_string(), red)]));
+        assert_eq!(json.tokens, HashMap::from([(3, green)]));
+        let array = WordColors::from_json(r##"["#f00", null, "#0f0"]"##).unwrap();
+        assert_eq!(array.tokens, HashMap::from([(0, red), (2, green)]));
+        let csv = WordColors::from_csv("word,color\nfox,rgb(255, 0, 0)\n\n3, lime\n").unwrap();
+        assert_eq!(csv, json);
+        assert!(WordColors::from_json(r#"{"fox": 3}"#).is_err());
+        assert!(WordColors::from_csv("fox").is_err());
+    }
+
+    #[test]
+    fn test_line_colors() {
+        let red = Rgba(255, 0, 0, 255);
+        let green = Rgba(0, 255, 0, 255);
+        let word_colors = WordColors::from_json(r##"{"fox": "#f00", "6": "#0f0"}"##).unwrap();
+        // The line starts at word 4 of the text
+        assert_eq!(word_colors.line_colors("the fox, the dog", 4), vec![(4..7, red), (9..12, green)]);
+    }
+}
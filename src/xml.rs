@@ -0,0 +1,176 @@
+// keeping the written SVG well-formed whatever the input: the svg crate escapes
+// & < > but leaves quotes in attribute values and characters XML 1.0 forbids
+use std::borrow::Cow;
+use std::{fmt, io};
+
+use svg::node::Node;
+
+// Stands in for a quote in attribute values until the document is written out.
+// XML forbids the character, so no sanitized value holds one of its own
+const QUOTE_PLACEHOLDER: char = '\u{1}';
+
+/// Whether `c` may appear in an XML 1.0 document (the `Char` production).
+pub fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..='\u{10FFFF}')
+}
+
+/// `text` without the characters XML 1.0 forbids, e.g. control characters.
+pub fn strip_invalid(text: &str) -> Cow<'_, str> {
+    match text.chars().all(is_xml_char) {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(text.chars().filter(|&c| is_xml_char(c)).collect()),
+    }
+}
+
+/// `text` escaped for element content.
+pub fn escape_text(text: &str) -> String {
+    strip_invalid(text).replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// `value` escaped for a quoted attribute value, with whitespace kept as written.
+pub fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in strip_invalid(value).chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' => escaped.push_str("&#9;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Whether an attribute value would break the document as the svg crate writes it
+fn unsafe_value(value: &str) -> bool {
+    value.chars().any(|c| c == '"' || !is_xml_char(c))
+}
+
+fn has_unsafe_values(node: &dyn Node) -> bool {
+    node.get_attributes().is_some_and(|attributes| attributes.values().any(|value| unsafe_value(value)))
+        || node.get_children().is_some_and(|children| children.iter().any(|child| has_unsafe_values(child.as_ref())))
+}
+
+fn sanitize_values(node: &mut dyn Node) {
+    if let Some(attributes) = node.get_attributes_mut() {
+        for value in attributes.values_mut() {
+            if unsafe_value(value) {
+                *value = strip_invalid(value).replace('"', &QUOTE_PLACEHOLDER.to_string()).into();
+            }
+        }
+    }
+    if let Some(children) = node.get_children_mut() {
+        for child in children.iter_mut() {
+            sanitize_values(child.as_mut());
+        }
+    }
+}
+
+// `document` (or any node) with its unsafe attribute values replaced, when it has any
+fn sanitized<N: Node + Clone>(document: &N) -> Cow<'_, N> {
+    match has_unsafe_values(document) {
+        true => {
+            let mut document = Clone::clone(document);
+            sanitize_values(&mut document);
+            Cow::Owned(document)
+        }
+        false => Cow::Borrowed(document),
+    }
+}
+
+// Text content may hold characters XML forbids too, and the placeholders are left to restore
+fn push_xml(xml: &mut String, text: &str, restore_quotes: bool) {
+    for c in text.chars() {
+        if c == QUOTE_PLACEHOLDER && restore_quotes {
+            xml.push_str("&quot;");
+        } else if is_xml_char(c) {
+            xml.push(c);
+        }
+    }
+}
+
+/// `document` as well-formed XML: quotes in attribute values are escaped and
+/// characters XML 1.0 forbids are left out, wherever they came from.
+pub fn to_xml<N: Node + Clone + fmt::Display>(document: &N) -> String {
+    let sanitized = sanitized(document);
+    let out = sanitized.to_string();
+    if out.chars().all(is_xml_char) {
+        return out;
+    }
+    let mut xml = String::with_capacity(out.len());
+    push_xml(&mut xml, &out, matches!(sanitized, Cow::Owned(_)));
+    xml
+}
+
+// Passes the formatted document on piece by piece, cleaned up as in to_xml
+struct XmlWriter<W> {
+    out: W,
+    restore_quotes: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for XmlWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let written = match s.chars().all(is_xml_char) {
+            true => self.out.write_all(s.as_bytes()),
+            false => {
+                let mut xml = String::with_capacity(s.len());
+                push_xml(&mut xml, s, self.restore_quotes);
+                self.out.write_all(xml.as_bytes())
+            }
+        };
+        written.map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Writes `document` to `out` as [`to_xml`] gives it, without holding the
+/// whole text in memory.
+pub fn write_xml<N: Node + Clone + fmt::Display, W: io::Write>(document: &N, out: W) -> io::Result<()> {
+    let sanitized = sanitized(document);
+    let mut writer = XmlWriter { out, restore_quotes: matches!(sanitized, Cow::Owned(_)), error: None };
+    match fmt::write(&mut writer, format_args!("{}", sanitized)) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(writer.error.unwrap_or_else(|| io::Error::other("failed to format the document"))),
+    }
+}
+
+#[cfg(test)]
+mod test_xml {
+    use super::*;
+    use svg::node::element::{Group, Title};
+    use svg::Document;
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(escape_text("a < b & \u{1}c"), "a &lt; b &amp; c");
+        assert_eq!(escape_attribute("say \"hi\"\n'x'"), "say &quot;hi&quot;&#10;&apos;x&apos;");
+        assert_eq!(strip_invalid("ok\u{FFFE}\u{0}!"), "ok!");
+        assert!(matches!(strip_invalid("plain 漢字 \u{1F600}"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_document_stays_well_formed() {
+        let doc = Document::new()
+            .add(Title::new("</title><script>x & \"y\"\u{0}</script>"))
+            .add(Group::new().set("class", "a\" onload=\"alert(1)").set("data-note", "<b>\u{B}&"));
+        let xml = to_xml(&doc);
+        assert!(xml.contains(r#"class="a&quot; onload=&quot;alert(1)""#), "{}", xml);
+        assert!(xml.contains(r#"data-note="&lt;b&gt;&amp;""#), "{}", xml);
+        assert!(xml.contains("&lt;/title&gt;&lt;script&gt;x &amp; \"y\"&lt;/script&gt;"), "{}", xml);
+        assert!(xml.chars().all(is_xml_char));
+        // A document without anything to fix is written as it is
+        let plain = Document::new().add(Group::new().set("class", "text-line"));
+        assert_eq!(to_xml(&plain), plain.to_string());
+        let mut written = Vec::new();
+        write_xml(&doc, &mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), xml);
+    }
+}
@@ -0,0 +1,222 @@
+// Golden snapshot tests: renders a fixture suite with the fonts bundled in
+// tests/fonts and compares the output, normalized, with tests/snapshots/<name>.svg.
+// Run with UPDATE_SNAPSHOTS=1 to write the snapshots after an intended change.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+use resvg::usvg::roxmltree::{Document as XmlDocument, Node};
+use text2svg::background::{Background, Edges};
+use text2svg::color::Color;
+use text2svg::decoration::{Underline, UnderlineStyle};
+use text2svg::font::{FontConfig, FontStyle};
+use text2svg::render::{build_text_svg, RenderConfig};
+use text2svg::utils::WrapMode;
+use text2svg::xml::to_xml;
+
+const SANS: &str = "DejaVuSans.ttf";
+const MONO: &str = "DejaVuSansMono.ttf";
+
+struct Fixture {
+    name: &'static str,
+    font: &'static str,
+    text: &'static str,
+    configure: fn(&mut FontConfig, &mut RenderConfig),
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture { name: "plain", font: SANS, text: "Hello, world!", configure: |_, _| {} },
+    Fixture { name: "kerning", font: SANS, text: "AVATAR Wave To", configure: |_, _| {} },
+    Fixture {
+        name: "pixel-wrap",
+        font: SANS,
+        text: "The quick brown fox jumps over the lazy dog.",
+        configure: |_, render_config| {
+            render_config.set_max_pixel_width(Some(180.0));
+        },
+    },
+    Fixture {
+        name: "char-wrap",
+        font: MONO,
+        text: "0123456789abcdefghij klmno",
+        configure: |_, render_config| {
+            render_config.set_max_width(Some(8)).set_wrap(Some(WrapMode::Char));
+        },
+    },
+    Fixture {
+        name: "markup",
+        font: SANS,
+        text: r#"Plain <span letter-spacing="4px">spaced</span> text"#,
+        configure: |_, render_config| {
+            render_config.set_markup(true);
+        },
+    },
+    Fixture {
+        name: "decorated",
+        font: SANS,
+        text: "Boxed",
+        configure: |font_config, render_config| {
+            font_config.set_fill_color("#0ea5e9".parse().unwrap());
+            render_config
+                .set_underline(Some(Underline::new(UnderlineStyle::Solid)))
+                .set_background(Some("#fff".parse::<Background>().unwrap()))
+                .set_padding("8".parse::<Edges>().unwrap());
+        },
+    },
+];
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn render(fixture: &Fixture) -> String {
+    let font_path = tests_dir().join("fonts").join(fixture.font);
+    let mut font_config = FontConfig::from_file(font_path.to_str().unwrap(), 32, Color::BLACK, Color::BLACK, false)
+        .unwrap_or_else(|e| panic!("{}: can't load {}: {}", fixture.name, font_path.display(), e));
+    let mut render_config = RenderConfig::new(false, FontStyle::Regular);
+    (fixture.configure)(&mut font_config, &mut render_config);
+    let (document, _) = build_text_svg(fixture.text, &font_config, &render_config).unwrap();
+    to_xml(&document)
+}
+
+// Matches references to ids, href="#id" and url(#id), but not colors such as #fff
+fn reference_pattern() -> Regex {
+    Regex::new(r"^#([^\s)]+)$|url\(#([^)]+)\)").unwrap()
+}
+
+fn referenced_id<'a>(captures: &'a Captures) -> &'a str {
+    captures.get(1).or_else(|| captures.get(2)).unwrap().as_str()
+}
+
+fn id_references(name: &str, value: &str) -> Vec<String> {
+    reference_pattern()
+        .captures_iter(value)
+        .filter(|captures| captures.get(2).is_some() || name.ends_with("href"))
+        .map(|captures| referenced_id(&captures).to_string())
+        .collect()
+}
+
+// Ids in the order they are first referenced outside <defs>, whose order is arbitrary
+fn collect_references(node: Node, ids: &mut Vec<String>) {
+    if node.tag_name().name() == "defs" {
+        return;
+    }
+    for attribute in node.attributes() {
+        for id in id_references(attribute.name(), attribute.value()) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+    for child in node.children().filter(Node::is_element) {
+        collect_references(child, ids);
+    }
+}
+
+// One element per line, attributes sorted, numbers to a tenth of a pixel and
+// the children of <defs> sorted, so only changes to the rendering show
+fn write_canonical(node: Node, names: &HashMap<String, String>, depth: usize, out: &mut Vec<String>) {
+    let number = Regex::new(r"-?\d+\.\d+").unwrap();
+    let reference = reference_pattern();
+    let normalize = |value: &str| {
+        let value = reference.replace_all(value, |captures: &Captures| {
+            let id = referenced_id(captures);
+            let name = names.get(id).map_or(id, String::as_str);
+            match captures.get(1) {
+                Some(_) => format!("#{}", name),
+                None => format!("url(#{})", name),
+            }
+        });
+        number
+            .replace_all(&value, |captures: &Captures| {
+                let rounded = format!("{:.1}", captures[0].parse::<f64>().unwrap());
+                if rounded == "-0.0" { "0.0".to_string() } else { rounded }
+            })
+            .into_owned()
+    };
+    let mut attributes: Vec<String> = node
+        .attributes()
+        .map(|attribute| match attribute.name() {
+            "id" => format!("id=\"{}\"", names.get(attribute.value()).map_or(attribute.value(), String::as_str)),
+            name => format!("{}=\"{}\"", name, normalize(attribute.value())),
+        })
+        .collect();
+    attributes.sort();
+    let indent = "  ".repeat(depth);
+    let open = std::iter::once(node.tag_name().name().to_string()).chain(attributes).collect::<Vec<_>>().join(" ");
+    let text: String = node.children().filter(Node::is_text).filter_map(|child| child.text()).collect();
+    let mut children: Vec<Vec<String>> = node
+        .children()
+        .filter(Node::is_element)
+        .map(|child| {
+            let mut lines = Vec::new();
+            write_canonical(child, names, depth + 1, &mut lines);
+            lines
+        })
+        .collect();
+    if node.tag_name().name() == "defs" {
+        children.sort();
+    }
+    match (children.is_empty(), text.trim()) {
+        (true, "") => out.push(format!("{}<{}/>", indent, open)),
+        (true, text) => out.push(format!("{}<{}>{}</{}>", indent, open, text, node.tag_name().name())),
+        (false, _) => {
+            out.push(format!("{}<{}>", indent, open));
+            out.extend(children.into_iter().flatten());
+            out.push(format!("{}</{}>", indent, node.tag_name().name()));
+        }
+    }
+}
+
+/// `svg` in a canonical form: ids renamed in order of use, since glyph ids come
+/// from hashes, and the arbitrary order of definitions and float noise left out.
+fn normalize(svg: &str) -> String {
+    let document = XmlDocument::parse(svg).expect("the rendered SVG isn't well-formed");
+    let mut ids = Vec::new();
+    collect_references(document.root_element(), &mut ids);
+    let mut names: HashMap<String, String> = ids.into_iter().enumerate().map(|(i, id)| (id, format!("id{}", i))).collect();
+    // Definitions nothing outside <defs> refers to are named in the order of what they hold
+    let mut unreferenced: Vec<(String, String)> = document
+        .descendants()
+        .filter_map(|node| node.attribute("id").filter(|id| !names.contains_key(*id)).map(|id| (node, id.to_string())))
+        .map(|(node, id)| {
+            let mut lines = Vec::new();
+            write_canonical(node, &names, 0, &mut lines);
+            (lines.join("\n"), id)
+        })
+        .collect();
+    unreferenced.sort();
+    for (i, (_, id)) in unreferenced.into_iter().enumerate() {
+        names.insert(id, format!("def{}", i));
+    }
+    let mut lines = Vec::new();
+    write_canonical(document.root_element(), &names, 0, &mut lines);
+    lines.join("\n") + "\n"
+}
+
+#[test]
+fn test_snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = Vec::new();
+    for fixture in FIXTURES {
+        let actual = normalize(&render(fixture));
+        let path = tests_dir().join("snapshots").join(format!("{}.svg", fixture.name));
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(_) | Err(_) if update => std::fs::write(&path, &actual).unwrap(),
+            Ok(expected) => {
+                let line = expected.lines().zip(actual.lines()).position(|(a, b)| a != b).unwrap_or(0) + 1;
+                failures.push(format!("{}: differs from {} from line {}", fixture.name, path.display(), line));
+            }
+            Err(_) => failures.push(format!("{}: no snapshot at {}", fixture.name, path.display())),
+        }
+    }
+    assert!(failures.is_empty(), "{}\nrerun with UPDATE_SNAPSHOTS=1 to accept the new output", failures.join("\n"));
+}
+
+#[test]
+fn test_normalize_ignores_ids_and_definition_order() {
+    let a = r##"<svg xmlns="http://www.w3.org/2000/svg"><defs><path id="g9" d="M0 0"/><path id="g3" d="M1.04 1"/></defs><use href="#g3" x="1.0001"/><use href="#g9"/></svg>"##;
+    let b = r##"<svg xmlns="http://www.w3.org/2000/svg"><defs><path d="M1.0 1" id="x1"/><path id="x2" d="M0 0"/></defs><use x="1.0" href="#x1"/><use href="#x2"/></svg>"##;
+    assert_eq!(normalize(a), normalize(b));
+}